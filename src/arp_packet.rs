@@ -0,0 +1,263 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::parser::{array, require};
+use crate::{Layer, ParseError};
+
+/// An ARP or RARP packet.
+///
+/// Addresses are kept at the lengths given in the header, so they are 6-byte MAC addresses and
+/// 4-byte IPv4 addresses on Ethernet.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArpPacket {
+    pub hardware_type: u16,
+    pub protocol_type: u16,
+    pub opcode: u16,
+    pub sender_hardware_address: Vec<u8>,
+    pub sender_protocol_address: Vec<u8>,
+    pub target_hardware_address: Vec<u8>,
+    pub target_protocol_address: Vec<u8>,
+}
+
+impl Default for ArpPacket {
+    fn default() -> Self {
+        ArpPacket::new()
+    }
+}
+
+impl ArpPacket {
+    pub fn new() -> ArpPacket {
+        ArpPacket {
+            hardware_type: 0,
+            protocol_type: 0,
+            opcode: 0,
+            sender_hardware_address: vec![],
+            sender_protocol_address: vec![],
+            target_hardware_address: vec![],
+            target_protocol_address: vec![],
+        }
+    }
+
+    /// Parses an ARP or RARP packet.
+    ///
+    /// # Errors
+    /// Returns `ParseError::Truncated` if `data` ends before the addresses do.
+    pub fn parse(data: &[u8]) -> Result<ArpPacket, ParseError> {
+        require(data, 8, Layer::Arp)?;
+        let hardware_length = data[4] as usize;
+        let protocol_length = data[5] as usize;
+        require(data, 8 + 2 * (hardware_length + protocol_length), Layer::Arp)?;
+
+        let mut arp = ArpPacket::new();
+        arp.hardware_type = u16::from_be_bytes(array(data, 0));
+        arp.protocol_type = u16::from_be_bytes(array(data, 2));
+        arp.opcode = u16::from_be_bytes(array(data, 6));
+        let mut current = 8;
+        let mut next = |length: usize| {
+            current += length;
+            data[current - length..current].to_vec()
+        };
+        arp.sender_hardware_address = next(hardware_length);
+        arp.sender_protocol_address = next(protocol_length);
+        arp.target_hardware_address = next(hardware_length);
+        arp.target_protocol_address = next(protocol_length);
+        Ok(arp)
+    }
+
+    /// Returns whether this is a RARP request or reply.
+    pub fn is_rarp(&self) -> bool {
+        self.opcode == 3 || self.opcode == 4
+    }
+
+    /// Returns whether this is a gratuitous ARP, announcing the sender's own address.
+    pub fn is_gratuitous(&self) -> bool {
+        matches!(self.opcode, 1 | 2)
+            && self.sender_protocol_address == self.target_protocol_address
+            && self.sender_protocol_address.iter().any(|b| *b != 0)
+    }
+
+    /// Returns the name of the opcode.
+    pub fn opcode_name(&self) -> &'static str {
+        match self.opcode {
+            1 => "request",
+            2 => "reply",
+            3 => "RARP request",
+            4 => "RARP reply",
+            8 => "InARP request",
+            9 => "InARP reply",
+            _ => "unknown",
+        }
+    }
+}
+
+impl fmt::Display for ArpPacket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "ARP: -----{} Header-----", if self.is_rarp() { "RARP" } else { "ARP" })?;
+        writeln!(f, "ARP:")?;
+        writeln!(f, "ARP: Hardware type   = {}", self.hardware_type)?;
+        writeln!(f, "ARP: Protocol type   = 0x{:04x}", self.protocol_type)?;
+        writeln!(f, "ARP: Opcode          = {} ({})", self.opcode, self.opcode_name())?;
+        writeln!(f, "ARP: Sender MAC      = {}", hardware_address(&self.sender_hardware_address))?;
+        writeln!(f, "ARP: Sender IP       = {}", protocol_address(&self.sender_protocol_address))?;
+        writeln!(f, "ARP: Target MAC      = {}", hardware_address(&self.target_hardware_address))?;
+        writeln!(f, "ARP: Target IP       = {}", protocol_address(&self.target_protocol_address))?;
+        if self.is_gratuitous() {
+            writeln!(f, "ARP: [Gratuitous ARP]")?;
+        }
+        write!(f, "ARP:")
+    }
+}
+
+/// Collects the ARP traffic of a capture to report gratuitous ARPs and IP addresses claimed by
+/// more than one MAC address.
+#[derive(Clone, Debug, Default)]
+pub struct ArpMonitor {
+    packets: usize,
+    /// Packet number, sender IP and sender MAC of every gratuitous ARP.
+    gratuitous: Vec<(usize, Vec<u8>, Vec<u8>)>,
+    /// MAC addresses seen claiming each IP address, in first-seen order.
+    claims: BTreeMap<Vec<u8>, Vec<Vec<u8>>>,
+}
+
+impl ArpMonitor {
+    pub fn new() -> ArpMonitor {
+        ArpMonitor::default()
+    }
+
+    /// Records an ARP packet.
+    ///
+    /// # Arguments
+    /// * `arp` - The packet to record.
+    /// * `packet_number` - The position of the packet in the capture, counted from 1.
+    pub fn observe(&mut self, arp: &ArpPacket, packet_number: usize) {
+        self.packets += 1;
+        if arp.is_rarp() {
+            return;
+        }
+        if arp.is_gratuitous() {
+            self.gratuitous.push((packet_number, arp.sender_protocol_address.clone(),
+                                  arp.sender_hardware_address.clone()));
+        }
+        // Probes come from 0.0.0.0 and claim nothing.
+        if arp.sender_protocol_address.iter().all(|b| *b == 0) {
+            return;
+        }
+        let macs = self.claims.entry(arp.sender_protocol_address.clone()).or_default();
+        if !macs.contains(&arp.sender_hardware_address) {
+            macs.push(arp.sender_hardware_address.clone());
+        }
+    }
+
+    /// Returns whether any ARP packet was recorded.
+    pub fn is_empty(&self) -> bool {
+        self.packets == 0
+    }
+
+    /// Returns the IP addresses claimed by more than one MAC address, with those MAC addresses.
+    pub fn conflicts(&self) -> impl Iterator<Item = (&Vec<u8>, &Vec<Vec<u8>>)> {
+        self.claims.iter().filter(|(_, macs)| macs.len() > 1)
+    }
+}
+
+impl fmt::Display for ArpMonitor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "ARP: -----ARP Report-----")?;
+        writeln!(f, "ARP:")?;
+        writeln!(f, "ARP: ARP packets       = {}", self.packets)?;
+        writeln!(f, "ARP: Gratuitous ARPs   = {}", self.gratuitous.len())?;
+        for (packet_number, ip, mac) in &self.gratuitous {
+            writeln!(f, "ARP:   packet {}: {} is at {}", packet_number, protocol_address(ip), hardware_address(mac))?;
+        }
+        writeln!(f, "ARP: Conflicting IPs   = {}", self.conflicts().count())?;
+        for (ip, macs) in self.conflicts() {
+            let macs: Vec<String> = macs.iter().map(|mac| hardware_address(mac)).collect();
+            writeln!(f, "ARP:   {} claimed by {} [possible spoofing]", protocol_address(ip), macs.join(", "))?;
+        }
+        write!(f, "ARP:")
+    }
+}
+
+/// Formats a hardware address as colon-separated hex bytes.
+fn hardware_address(address: &[u8]) -> String {
+    let bytes: Vec<String> = address.iter().map(|b| format!("{:02x}", b)).collect();
+    bytes.join(":")
+}
+
+/// Formats a protocol address as dotted decimal, as IPv4 addresses are written.
+fn protocol_address(address: &[u8]) -> String {
+    let bytes: Vec<String> = address.iter().map(|b| b.to_string()).collect();
+    bytes.join(".")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds an Ethernet/IPv4 ARP packet.
+    fn arp(opcode: u16, sender_mac: u8, sender_ip: [u8; 4], target_ip: [u8; 4]) -> ArpPacket {
+        let mut data = vec![0x00, 0x01, 0x08, 0x00, 6, 4];
+        data.extend_from_slice(&opcode.to_be_bytes());
+        data.extend_from_slice(&[0x02, 0, 0, 0, 0, sender_mac]);
+        data.extend_from_slice(&sender_ip);
+        data.extend_from_slice(&[0; 6]);
+        data.extend_from_slice(&target_ip);
+        ArpPacket::parse(&data).unwrap()
+    }
+
+    #[test]
+    fn addresses_are_read_at_their_header_lengths() {
+        let request = arp(1, 1, [10, 0, 0, 1], [10, 0, 0, 2]);
+        assert_eq!(request.opcode_name(), "request");
+        assert_eq!(request.sender_hardware_address, [0x02, 0, 0, 0, 0, 1]);
+        assert_eq!(request.sender_protocol_address, [10, 0, 0, 1]);
+        assert_eq!(request.target_protocol_address, [10, 0, 0, 2]);
+        assert!(!request.is_gratuitous());
+
+        let data = [0x00, 0x01, 0x08, 0x00, 6, 4, 0x00, 0x01, 0x02, 0, 0, 0, 0, 1, 10, 0, 0, 1];
+        assert!(matches!(ArpPacket::parse(&data),
+                         Err(ParseError::Truncated { layer: Layer::Arp, needed: 28, available: 18 })));
+    }
+
+    #[test]
+    fn gratuitous_arps_announce_the_senders_own_address() {
+        assert!(arp(1, 1, [10, 0, 0, 1], [10, 0, 0, 1]).is_gratuitous());
+        assert!(arp(2, 1, [10, 0, 0, 1], [10, 0, 0, 1]).is_gratuitous());
+        // A probe asks about an address from 0.0.0.0.
+        assert!(!arp(1, 1, [0, 0, 0, 0], [0, 0, 0, 0]).is_gratuitous());
+        assert!(!arp(3, 1, [10, 0, 0, 1], [10, 0, 0, 1]).is_gratuitous());
+
+        let mut monitor = ArpMonitor::new();
+        assert!(monitor.is_empty());
+        monitor.observe(&arp(1, 1, [10, 0, 0, 1], [10, 0, 0, 2]), 1);
+        monitor.observe(&arp(2, 2, [10, 0, 0, 2], [10, 0, 0, 2]), 2);
+        assert!(!monitor.is_empty());
+        assert_eq!(monitor.gratuitous, [(2, vec![10, 0, 0, 2], vec![0x02, 0, 0, 0, 0, 2])]);
+        assert!(monitor.to_string().contains("ARP:   packet 2: 10.0.0.2 is at 02:00:00:00:00:02"));
+    }
+
+    #[test]
+    fn an_address_claimed_by_two_macs_is_a_conflict() {
+        let mut monitor = ArpMonitor::new();
+        monitor.observe(&arp(2, 1, [10, 0, 0, 1], [10, 0, 0, 2]), 1);
+        monitor.observe(&arp(2, 1, [10, 0, 0, 1], [10, 0, 0, 2]), 2);
+        assert_eq!(monitor.conflicts().count(), 0);
+
+        monitor.observe(&arp(2, 2, [10, 0, 0, 1], [10, 0, 0, 2]), 3);
+        let conflicts: Vec<_> = monitor.conflicts().collect();
+        assert_eq!(conflicts, [(&vec![10, 0, 0, 1], &vec![vec![0x02, 0, 0, 0, 0, 1], vec![0x02, 0, 0, 0, 0, 2]])]);
+        assert!(monitor.to_string()
+            .contains("ARP:   10.0.0.1 claimed by 02:00:00:00:00:01, 02:00:00:00:00:02 [possible spoofing]"));
+    }
+
+    #[test]
+    fn probes_and_rarp_claim_no_address() {
+        let mut monitor = ArpMonitor::new();
+        monitor.observe(&arp(1, 1, [0, 0, 0, 0], [10, 0, 0, 1]), 1);
+        monitor.observe(&arp(1, 2, [0, 0, 0, 0], [10, 0, 0, 1]), 2);
+        monitor.observe(&arp(4, 3, [10, 0, 0, 1], [10, 0, 0, 1]), 3);
+        monitor.observe(&arp(2, 4, [10, 0, 0, 1], [10, 0, 0, 2]), 4);
+        assert_eq!(monitor.packets, 4);
+        assert!(monitor.gratuitous.is_empty());
+        assert_eq!(monitor.conflicts().count(), 0);
+    }
+}
@@ -0,0 +1,495 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::bpf_compiler::compile;
+use crate::{Filter, FilterExpr, LinkType, PcapBlock};
+
+// Instruction classes, sizes, modes and operations, with the values of <linux/filter.h>.
+pub(crate) const BPF_LD: u16 = 0x00;
+pub(crate) const BPF_LDX: u16 = 0x01;
+pub(crate) const BPF_ST: u16 = 0x02;
+pub(crate) const BPF_STX: u16 = 0x03;
+pub(crate) const BPF_ALU: u16 = 0x04;
+pub(crate) const BPF_JMP: u16 = 0x05;
+pub(crate) const BPF_RET: u16 = 0x06;
+pub(crate) const BPF_MISC: u16 = 0x07;
+
+pub(crate) const BPF_W: u16 = 0x00;
+pub(crate) const BPF_H: u16 = 0x08;
+pub(crate) const BPF_B: u16 = 0x10;
+
+pub(crate) const BPF_IMM: u16 = 0x00;
+pub(crate) const BPF_ABS: u16 = 0x20;
+pub(crate) const BPF_IND: u16 = 0x40;
+pub(crate) const BPF_MEM: u16 = 0x60;
+pub(crate) const BPF_LEN: u16 = 0x80;
+pub(crate) const BPF_MSH: u16 = 0xa0;
+
+pub(crate) const BPF_ADD: u16 = 0x00;
+pub(crate) const BPF_SUB: u16 = 0x10;
+pub(crate) const BPF_MUL: u16 = 0x20;
+pub(crate) const BPF_DIV: u16 = 0x30;
+pub(crate) const BPF_OR: u16 = 0x40;
+pub(crate) const BPF_AND: u16 = 0x50;
+pub(crate) const BPF_LSH: u16 = 0x60;
+pub(crate) const BPF_RSH: u16 = 0x70;
+pub(crate) const BPF_NEG: u16 = 0x80;
+pub(crate) const BPF_MOD: u16 = 0x90;
+pub(crate) const BPF_XOR: u16 = 0xa0;
+
+pub(crate) const BPF_JA: u16 = 0x00;
+pub(crate) const BPF_JEQ: u16 = 0x10;
+pub(crate) const BPF_JGT: u16 = 0x20;
+pub(crate) const BPF_JGE: u16 = 0x30;
+pub(crate) const BPF_JSET: u16 = 0x40;
+
+pub(crate) const BPF_K: u16 = 0x00;
+pub(crate) const BPF_X: u16 = 0x08;
+pub(crate) const BPF_A: u16 = 0x10;
+
+pub(crate) const BPF_TAX: u16 = 0x00;
+pub(crate) const BPF_TXA: u16 = 0x80;
+
+/// Number of words of scratch memory, `M[0]` to `M[15]`.
+const MEMORY_WORDS: usize = 16;
+
+/// Longest program the kernel accepts.
+const MAX_INSTRUCTIONS: usize = 4096;
+
+/// A classic BPF instruction, laid out as the kernel's `struct sock_filter`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BpfInstruction {
+    pub code: u16,
+    /// Instructions to skip when a conditional jump is taken.
+    pub jt: u8,
+    /// Instructions to skip when a conditional jump is not taken.
+    pub jf: u8,
+    pub k: u32,
+}
+
+impl BpfInstruction {
+    pub fn new(code: u16, jt: u8, jf: u8, k: u32) -> BpfInstruction {
+        BpfInstruction { code, jt, jf, k }
+    }
+
+    fn class(&self) -> u16 {
+        self.code & 0x07
+    }
+
+    /// Returns whether the instruction is a jump that depends on a comparison.
+    fn is_conditional(&self) -> bool {
+        self.class() == BPF_JMP && self.code & 0xf0 != BPF_JA
+    }
+
+    /// Formats the instruction at position `n` as `tcpdump -d` does, with jump targets given as
+    /// instruction positions.
+    fn image(&self, n: usize) -> String {
+        let k = self.k;
+        let (op, operand) = match self.code {
+            c if c == BPF_RET | BPF_K => ("ret", format!("#{}", k)),
+            c if c == BPF_RET | BPF_A => ("ret", "a".to_string()),
+            c if c == BPF_RET | BPF_X => ("ret", "x".to_string()),
+            c if c == BPF_LD | BPF_W | BPF_ABS => ("ld", format!("[{}]", k)),
+            c if c == BPF_LD | BPF_H | BPF_ABS => ("ldh", format!("[{}]", k)),
+            c if c == BPF_LD | BPF_B | BPF_ABS => ("ldb", format!("[{}]", k)),
+            c if c == BPF_LD | BPF_W | BPF_LEN => ("ld", "#pktlen".to_string()),
+            c if c == BPF_LD | BPF_W | BPF_IND => ("ld", format!("[x + {}]", k)),
+            c if c == BPF_LD | BPF_H | BPF_IND => ("ldh", format!("[x + {}]", k)),
+            c if c == BPF_LD | BPF_B | BPF_IND => ("ldb", format!("[x + {}]", k)),
+            c if c == BPF_LD | BPF_IMM => ("ld", format!("#0x{:x}", k)),
+            c if c == BPF_LDX | BPF_IMM => ("ldx", format!("#0x{:x}", k)),
+            c if c == BPF_LDX | BPF_W | BPF_LEN => ("ldx", "#pktlen".to_string()),
+            c if c == BPF_LDX | BPF_MSH | BPF_B => ("ldxb", format!("4*([{}]&0xf)", k)),
+            c if c == BPF_LD | BPF_MEM => ("ld", format!("M[{}]", k)),
+            c if c == BPF_LDX | BPF_MEM => ("ldx", format!("M[{}]", k)),
+            c if c == BPF_ST => ("st", format!("M[{}]", k)),
+            c if c == BPF_STX => ("stx", format!("M[{}]", k)),
+            c if c == BPF_JMP | BPF_JA => ("ja", format!("{}", n + 1 + k as usize)),
+            c if c == BPF_MISC | BPF_TAX => ("tax", String::new()),
+            c if c == BPF_MISC | BPF_TXA => ("txa", String::new()),
+            c if c == BPF_ALU | BPF_NEG => ("neg", String::new()),
+            c if c & 0x07 == BPF_JMP || c & 0x07 == BPF_ALU => {
+                let op = match (c & 0x07, c & 0xf0) {
+                    (BPF_JMP, BPF_JEQ) => "jeq",
+                    (BPF_JMP, BPF_JGT) => "jgt",
+                    (BPF_JMP, BPF_JGE) => "jge",
+                    (BPF_JMP, BPF_JSET) => "jset",
+                    (BPF_ALU, BPF_ADD) => "add",
+                    (BPF_ALU, BPF_SUB) => "sub",
+                    (BPF_ALU, BPF_MUL) => "mul",
+                    (BPF_ALU, BPF_DIV) => "div",
+                    (BPF_ALU, BPF_MOD) => "mod",
+                    (BPF_ALU, BPF_AND) => "and",
+                    (BPF_ALU, BPF_OR) => "or",
+                    (BPF_ALU, BPF_XOR) => "xor",
+                    (BPF_ALU, BPF_LSH) => "lsh",
+                    (BPF_ALU, BPF_RSH) => "rsh",
+                    _ => "unimp",
+                };
+                let hex = c & 0x07 == BPF_JMP || matches!(c & 0xf0, BPF_AND | BPF_OR | BPF_XOR);
+                let operand = match (c & BPF_X, hex) {
+                    (BPF_X, _) => "x".to_string(),
+                    (_, true) => format!("#0x{:x}", k),
+                    (_, false) => format!("#{}", k),
+                };
+                (op, operand)
+            }
+            _ => ("unimp", format!("0x{:x}", self.code)),
+        };
+        if self.is_conditional() {
+            format!("({:03}) {:<8} {:<16} jt {}\tjf {}", n, op, operand, n + 1 + self.jt as usize,
+                    n + 1 + self.jf as usize)
+        } else {
+            format!("({:03}) {:<8} {}", n, op, operand).trim_end().to_string()
+        }
+    }
+}
+
+/// Error returned when a filter cannot be compiled to BPF, or a BPF program cannot be loaded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BpfError {
+    /// The filter cannot be expressed in BPF, as `bad-checksum`, which needs the checksums of the
+    /// packet to be computed.
+    Unsupported(Filter),
+    /// A line of `tcpdump -ddd` output could not be read; lines are counted from 1.
+    Syntax { line: usize, message: String },
+    /// An instruction could jump out of the program, use an unknown opcode or memory it has not
+    /// got, or divide by zero; instructions are counted from 0.
+    Invalid { instruction: usize, message: String },
+}
+
+impl fmt::Display for BpfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BpfError::Unsupported(filter) => write!(f, "`{}` cannot be compiled to BPF", filter),
+            BpfError::Syntax { line, message } => write!(f, "line {}: {}", line, message),
+            BpfError::Invalid { instruction, message } => write!(f, "instruction {}: {}", instruction, message),
+        }
+    }
+}
+
+impl Error for BpfError {}
+
+/// A classic BPF program, as the kernel runs on sockets and tcpdump compiles its filters to.
+///
+/// A program returns the number of bytes of the packet to keep, with 0 rejecting it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BpfProgram {
+    /// Checked by `validate`: the jumps stay inside the program, which ends with a return.
+    instructions: Vec<BpfInstruction>,
+}
+
+impl BpfProgram {
+    /// Builds a program from its instructions, checking it can be run safely.
+    ///
+    /// # Errors
+    /// Returns `BpfError::Invalid` for the first instruction the kernel would refuse.
+    pub fn new(instructions: Vec<BpfInstruction>) -> Result<BpfProgram, BpfError> {
+        let program = BpfProgram { instructions };
+        program.validate()?;
+        Ok(program)
+    }
+
+    /// Returns the instructions of the program.
+    pub fn instructions(&self) -> &[BpfInstruction] {
+        &self.instructions
+    }
+
+    /// Compiles a filter expression to a program over the raw bytes of packets with the given
+    /// link-layer type.
+    ///
+    /// Unlike tcpdump's, the program finds the network and transport headers as the decoder does,
+    /// past VLAN tags, MPLS labels, PPPoE and IPv6 extension headers, so that it accepts the
+    /// packets `FilterExpr::matches` accepts once they are decoded. Programs cannot loop, so only
+    /// up to four of each repeated header are followed. As in the decoder, fragments have no
+    /// ports until they are reassembled.
+    ///
+    /// # Errors
+    /// Returns `BpfError::Unsupported` for a primitive that BPF cannot express, or
+    /// `BpfError::Invalid` if the program is longer than the kernel accepts.
+    pub fn compile(filter: &FilterExpr, link_type: LinkType) -> Result<BpfProgram, BpfError> {
+        BpfProgram::new(compile(filter, link_type)?)
+    }
+
+    /// Loads a program from the output of `tcpdump -ddd`: the number of instructions, then each
+    /// instruction as its decimal code, jt, jf and k. Lines may also be separated by commas, as
+    /// in the bytecode `iptables` and `tc` take.
+    ///
+    /// # Errors
+    /// Returns `BpfError::Syntax` if the text is malformed, or `BpfError::Invalid` if the program
+    /// cannot be run safely.
+    pub fn from_ddd(text: &str) -> Result<BpfProgram, BpfError> {
+        let mut lines = text
+            .split(['\n', ','])
+            .enumerate()
+            .map(|(number, line)| (number + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty());
+        let (line, count) = lines.next().ok_or(BpfError::Syntax { line: 1, message: "empty program".to_string() })?;
+        let count = usize::from_str(count).map_err(|_| {
+            BpfError::Syntax { line, message: format!("expected the number of instructions, found `{}`", count) }
+        })?;
+        let mut instructions = vec![];
+        for (line, text) in lines {
+            let fields: Vec<u32> = text.split_whitespace().filter_map(|field| u32::from_str(field).ok()).collect();
+            match fields[..] {
+                [code, jt, jf, k] if text.split_whitespace().count() == 4 && code <= 0xffff && jt <= 0xff
+                    && jf <= 0xff => {
+                    instructions.push(BpfInstruction::new(code as u16, jt as u8, jf as u8, k))
+                }
+                _ => {
+                    let message = format!("expected an instruction as `code jt jf k`, found `{}`", text);
+                    return Err(BpfError::Syntax { line, message });
+                }
+            }
+        }
+        if instructions.len() != count {
+            let message = format!("{} instructions announced, {} found", count, instructions.len());
+            return Err(BpfError::Syntax { line: 1, message });
+        }
+        BpfProgram::new(instructions)
+    }
+
+    /// Formats the program as `tcpdump -ddd` does, to be read back by `from_ddd`.
+    pub fn to_ddd(&self) -> String {
+        let mut text = format!("{}\n", self.instructions.len());
+        for instruction in &self.instructions {
+            text.push_str(&format!("{} {} {} {}\n", instruction.code, instruction.jt, instruction.jf, instruction.k));
+        }
+        text
+    }
+
+    /// Runs the program over the captured bytes of a packet, returning the number of bytes to keep,
+    /// or 0 if the packet is rejected. A load past the captured bytes rejects the packet.
+    ///
+    /// # Arguments
+    /// * `packet` - The captured bytes, from the start of the link-layer header.
+    /// * `wire_length` - The length of the packet on the wire, which `ld #pktlen` loads.
+    pub fn run(&self, packet: &[u8], wire_length: u32) -> u32 {
+        self.execute(packet, wire_length).unwrap_or(0)
+    }
+
+    /// Returns whether the program accepts the packet, without decoding it.
+    pub fn matches(&self, block: &PcapBlock) -> bool {
+        self.run(&block.data, block.original_length) != 0
+    }
+
+    /// Runs the program, returning `None` when it aborts.
+    fn execute(&self, packet: &[u8], wire_length: u32) -> Option<u32> {
+        let mut a: u32 = 0;
+        let mut x: u32 = 0;
+        let mut memory = [0_u32; MEMORY_WORDS];
+        let mut pc = 0;
+        loop {
+            let instruction = self.instructions.get(pc)?;
+            pc += 1;
+            let k = instruction.k;
+            let code = instruction.code;
+            match instruction.class() {
+                BPF_LD | BPF_LDX => {
+                    let size = code & 0x18;
+                    let value = match code & 0xe0 {
+                        BPF_IMM => k,
+                        BPF_LEN => wire_length,
+                        BPF_MEM => *memory.get(k as usize)?,
+                        BPF_ABS => load(packet, k, size)?,
+                        BPF_IND => load(packet, x.checked_add(k)?, size)?,
+                        BPF_MSH => 4 * (load(packet, k, BPF_B)? & 0x0f),
+                        _ => return None,
+                    };
+                    if instruction.class() == BPF_LD {
+                        a = value;
+                    } else {
+                        x = value;
+                    }
+                }
+                BPF_ST => *memory.get_mut(k as usize)? = a,
+                BPF_STX => *memory.get_mut(k as usize)? = x,
+                BPF_ALU => {
+                    let operand = if code & BPF_X == BPF_X { x } else { k };
+                    a = match code & 0xf0 {
+                        BPF_ADD => a.wrapping_add(operand),
+                        BPF_SUB => a.wrapping_sub(operand),
+                        BPF_MUL => a.wrapping_mul(operand),
+                        BPF_DIV => a.checked_div(operand)?,
+                        BPF_MOD => a.checked_rem(operand)?,
+                        BPF_OR => a | operand,
+                        BPF_AND => a & operand,
+                        BPF_XOR => a ^ operand,
+                        BPF_LSH => a.checked_shl(operand).unwrap_or(0),
+                        BPF_RSH => a.checked_shr(operand).unwrap_or(0),
+                        BPF_NEG => a.wrapping_neg(),
+                        _ => return None,
+                    };
+                }
+                BPF_JMP => {
+                    let operand = if code & BPF_X == BPF_X { x } else { k };
+                    let taken = match code & 0xf0 {
+                        BPF_JA => {
+                            pc = pc.checked_add(k as usize)?;
+                            continue;
+                        }
+                        BPF_JEQ => a == operand,
+                        BPF_JGT => a > operand,
+                        BPF_JGE => a >= operand,
+                        BPF_JSET => a & operand != 0,
+                        _ => return None,
+                    };
+                    pc += if taken { instruction.jt } else { instruction.jf } as usize;
+                }
+                BPF_RET => {
+                    return match code & 0x18 {
+                        BPF_K => Some(k),
+                        BPF_A => Some(a),
+                        BPF_X => Some(x),
+                        _ => None,
+                    };
+                }
+                BPF_MISC => match code & 0xf8 {
+                    BPF_TAX => x = a,
+                    BPF_TXA => a = x,
+                    _ => return None,
+                },
+                _ => return None,
+            }
+        }
+    }
+
+    /// Checks the program as the kernel does before attaching it: every opcode is known, every
+    /// jump lands inside the program, memory is within `M[0]` to `M[15]`, no division is by a
+    /// constant 0, and the program ends with a return.
+    fn validate(&self) -> Result<(), BpfError> {
+        let invalid = |instruction: usize, message: &str| BpfError::Invalid { instruction, message: message.to_string() };
+        let length = self.instructions.len();
+        if length == 0 || length > MAX_INSTRUCTIONS {
+            return Err(invalid(0, &format!("a program has 1 to {} instructions, not {}", MAX_INSTRUCTIONS, length)));
+        }
+        for (n, instruction) in self.instructions.iter().enumerate() {
+            let code = instruction.code;
+            let k = instruction.k;
+            let remaining = length - n - 1;
+            let known = match instruction.class() {
+                BPF_LD => {
+                    let size_known = code & 0x18 != 0x18;
+                    match code & 0xe0 {
+                        BPF_IMM | BPF_LEN | BPF_MEM => code & 0x18 == BPF_W,
+                        BPF_ABS | BPF_IND => size_known,
+                        _ => false,
+                    }
+                }
+                BPF_LDX => matches!(code & 0xf8, c if c == BPF_IMM | BPF_W || c == BPF_LEN | BPF_W
+                    || c == BPF_MEM | BPF_W || c == BPF_MSH | BPF_B),
+                BPF_ST | BPF_STX => code & 0xf8 == 0,
+                BPF_ALU => code & 0xf0 <= BPF_XOR && (code & 0xf0 != BPF_NEG || code & BPF_X == 0),
+                BPF_JMP => code & 0xf0 <= BPF_JSET && (code & 0xf0 != BPF_JA || code & BPF_X == 0),
+                BPF_RET => matches!(code & 0xf8, BPF_K | BPF_X | BPF_A),
+                _ => matches!(code & 0xf8, BPF_TAX | BPF_TXA),
+            };
+            if !known {
+                return Err(invalid(n, &format!("unknown opcode 0x{:02x}", code)));
+            }
+            let uses_memory = matches!(instruction.class(), BPF_ST | BPF_STX)
+                || (matches!(instruction.class(), BPF_LD | BPF_LDX) && code & 0xe0 == BPF_MEM);
+            if uses_memory && k as usize >= MEMORY_WORDS {
+                return Err(invalid(n, &format!("memory M[{}] out of range", k)));
+            }
+            if instruction.class() == BPF_ALU && matches!(code & 0xf0, BPF_DIV | BPF_MOD) && code & BPF_X == 0 && k == 0 {
+                return Err(invalid(n, "division by zero"));
+            }
+            if instruction.class() == BPF_JMP {
+                let too_far = if code & 0xf0 == BPF_JA {
+                    k as usize >= remaining
+                } else {
+                    instruction.jt as usize >= remaining || instruction.jf as usize >= remaining
+                };
+                if too_far {
+                    return Err(invalid(n, "jump past the end of the program"));
+                }
+            }
+        }
+        if self.instructions[length - 1].class() != BPF_RET {
+            return Err(invalid(length - 1, "the program does not end with a return"));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for BpfProgram {
+    /// Formats the program as `tcpdump -d` does, one instruction per line.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let lines: Vec<String> = self.instructions.iter().enumerate().map(|(n, instruction)| instruction.image(n)).collect();
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+/// Loads a big-endian word, half-word or byte from the packet at `offset`.
+fn load(packet: &[u8], offset: u32, size: u16) -> Option<u32> {
+    let offset = offset as usize;
+    let length = match size {
+        BPF_W => 4,
+        BPF_H => 2,
+        BPF_B => 1,
+        _ => return None,
+    };
+    let bytes = packet.get(offset..offset.checked_add(length)?)?;
+    Some(bytes.iter().fold(0, |value, byte| (value << 8) | *byte as u32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instruction(code: u16, jt: u8, jf: u8, k: u32) -> BpfInstruction {
+        BpfInstruction::new(code, jt, jf, k)
+    }
+
+    fn invalid(instructions: Vec<BpfInstruction>) -> (usize, String) {
+        match BpfProgram::new(instructions) {
+            Err(BpfError::Invalid { instruction, message }) => (instruction, message),
+            result => panic!("expected an invalid program, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn validation_rejects_unsafe_programs() {
+        let ret = instruction(BPF_RET | BPF_K, 0, 0, 0);
+        let jump = |jt, jf| instruction(BPF_JMP | BPF_JEQ | BPF_K, jt, jf, 0);
+        assert_eq!(invalid(vec![jump(1, 0), ret]), (0, "jump past the end of the program".to_string()));
+        assert_eq!(invalid(vec![jump(0, 1), ret]), (0, "jump past the end of the program".to_string()));
+        assert_eq!(invalid(vec![instruction(BPF_JMP | BPF_JA, 0, 0, 1), ret]),
+                   (0, "jump past the end of the program".to_string()));
+        assert_eq!(invalid(vec![instruction(BPF_ALU | BPF_DIV | BPF_K, 0, 0, 0), ret]), (0, "division by zero".to_string()));
+        assert_eq!(invalid(vec![instruction(BPF_ALU | BPF_MOD | BPF_K, 0, 0, 0), ret]), (0, "division by zero".to_string()));
+        assert_eq!(invalid(vec![ret, instruction(BPF_LD | BPF_IMM, 0, 0, 1)]),
+                   (1, "the program does not end with a return".to_string()));
+        assert_eq!(invalid(vec![instruction(BPF_ST, 0, 0, 16), ret]), (0, "memory M[16] out of range".to_string()));
+        assert_eq!(invalid(vec![instruction(0xff, 0, 0, 0), ret]), (0, "unknown opcode 0xff".to_string()));
+        assert_eq!(invalid(vec![]).0, 0);
+
+        // Division by the index register is checked when the program runs.
+        let divide = BpfProgram::new(vec![instruction(BPF_ALU | BPF_DIV | BPF_X, 0, 0, 0), ret]).unwrap();
+        assert_eq!(divide.run(&[], 0), 0);
+    }
+
+    #[test]
+    fn ddd_text_round_trips() {
+        let filter = FilterExpr::parse("tcp port 80").unwrap();
+        let program = BpfProgram::compile(&filter, LinkType::Ethernet).unwrap();
+        assert_eq!(BpfProgram::from_ddd(&program.to_ddd()), Ok(program));
+        assert_eq!(BpfProgram::from_ddd("2\n6 0 0 65535\n"),
+                   Err(BpfError::Syntax { line: 1, message: "2 instructions announced, 1 found".to_string() }));
+        assert_eq!(BpfProgram::from_ddd("1,6 0 0"),
+                   Err(BpfError::Syntax { line: 2, message: "expected an instruction as `code jt jf k`, found `6 0 0`".to_string() }));
+    }
+
+    #[test]
+    fn loads_past_the_captured_bytes_reject_the_packet() {
+        let program = BpfProgram::new(vec![
+            instruction(BPF_LD | BPF_H | BPF_ABS, 0, 0, 12),
+            instruction(BPF_RET | BPF_A, 0, 0, 0),
+        ]).unwrap();
+        assert_eq!(program.run(&[0; 14], 14), 0);
+        assert_eq!(program.run(&[0xff; 14], 14), 0xffff);
+        assert_eq!(program.run(&[0xff; 13], 14), 0);
+    }
+}
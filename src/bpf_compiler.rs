@@ -0,0 +1,828 @@
+use std::net::IpAddr;
+
+use crate::bpf::*;
+use crate::{BpfError, BpfInstruction, EndpointQualifier, Filter, FilterExpr, IpNetwork, LinkType};
+
+/// Number of bytes an accepting program keeps, the default snap length of tcpdump.
+const ACCEPT_LENGTH: u32 = 262144;
+
+// Words of scratch memory in which the prologue of a program leaves what it found in the packet.
+/// EtherType of the network layer, past VLAN tags, MPLS and PPPoE.
+const TYPE: u32 = 0;
+/// Offset of the network header.
+const NETWORK: u32 = 1;
+/// IP protocol of the packet as the decoder gives it, or `NO_PROTOCOL`.
+const PROTOCOL: u32 = 2;
+/// Offset of the TCP or UDP header, or 0 when the datagram is not decoded, as in a fragment.
+const TRANSPORT: u32 = 3;
+/// Number of VLAN tags.
+const TAGS: u32 = 4;
+/// The accumulator, kept while the index register is moved.
+const SAVED: u32 = 5;
+
+/// The protocol of an IPv6 packet whose upper layer is not decoded, which no IP protocol equals.
+const NO_PROTOCOL: u32 = 0x100;
+
+// Programs cannot loop, so the prologue follows up to this many of each repeated header. The
+// decoder has no such limit.
+const MAX_VLAN_TAGS: u32 = 4;
+const MAX_MPLS_LABELS: usize = 4;
+const MAX_IPV6_EXTENSION_HEADERS: usize = 4;
+
+/// Where a value is loaded from.
+#[derive(Copy, Clone, Debug)]
+enum Load {
+    /// From a word of scratch memory set by the prologue.
+    Memory(u32),
+    /// From a fixed offset, with the size given as `BPF_W`, `BPF_H` or `BPF_B`.
+    Absolute(u16, u32),
+    /// From an offset into the network header.
+    Network(u16, u32),
+    /// From an offset into the TCP or UDP header.
+    Transport(u16, u32),
+}
+
+impl Load {
+    /// Returns the load of the field `by` bytes before this one.
+    fn before(self, by: u32) -> Load {
+        match self {
+            Load::Memory(word) => Load::Memory(word),
+            Load::Absolute(size, offset) => Load::Absolute(size, offset - by),
+            Load::Network(size, offset) => Load::Network(size, offset - by),
+            Load::Transport(size, offset) => Load::Transport(size, offset - by),
+        }
+    }
+}
+
+/// A boolean test on the bytes of a packet, the intermediate form filters are compiled through.
+#[derive(Clone, Debug)]
+enum Test {
+    Always(bool),
+    /// Loads a value, masks it if asked, and compares it with `value` by a jump operation.
+    Compare { load: Load, mask: Option<u32>, jump: u16, value: u32 },
+    Not(Box<Test>),
+    All(Vec<Test>),
+    Any(Vec<Test>),
+}
+
+impl Test {
+    /// Returns whether the test loads the IP protocol or the transport header, which the
+    /// prologue then has to find.
+    fn needs_transport(&self) -> bool {
+        match self {
+            Test::Always(_) => false,
+            Test::Compare { load, .. } => {
+                matches!(load, Load::Memory(PROTOCOL) | Load::Memory(TRANSPORT) | Load::Transport(..))
+            }
+            Test::Not(test) => test.needs_transport(),
+            Test::All(tests) | Test::Any(tests) => tests.iter().any(Test::needs_transport),
+        }
+    }
+}
+
+fn equal(load: Load, value: u32) -> Test {
+    Test::Compare { load, mask: None, jump: BPF_JEQ, value }
+}
+
+fn masked(load: Load, mask: u32, value: u32) -> Test {
+    Test::Compare { load, mask: Some(mask), jump: BPF_JEQ, value }
+}
+
+fn any_bit(load: Load, bits: u32) -> Test {
+    Test::Compare { load, mask: None, jump: BPF_JSET, value: bits }
+}
+
+fn not(test: Test) -> Test {
+    match test {
+        Test::Always(value) => Test::Always(!value),
+        Test::Not(test) => *test,
+        test => Test::Not(Box::new(test)),
+    }
+}
+
+/// Returns a test passing when all of `tests` do, leaving out those that always pass.
+fn all(tests: Vec<Test>) -> Test {
+    let mut tests: Vec<Test> = tests.into_iter().filter(|test| !matches!(test, Test::Always(true))).collect();
+    if tests.iter().any(|test| matches!(test, Test::Always(false))) {
+        return Test::Always(false);
+    }
+    match tests.len() {
+        0 => Test::Always(true),
+        1 => tests.remove(0),
+        _ => Test::All(tests),
+    }
+}
+
+/// Returns a test passing when any of `tests` does, leaving out those that never pass.
+fn any(tests: Vec<Test>) -> Test {
+    let mut tests: Vec<Test> = tests.into_iter().filter(|test| !matches!(test, Test::Always(false))).collect();
+    if tests.iter().any(|test| matches!(test, Test::Always(true))) {
+        return Test::Always(true);
+    }
+    match tests.len() {
+        0 => Test::Always(false),
+        1 => tests.remove(0),
+        _ => Test::Any(tests),
+    }
+}
+
+/// Combines the tests of a packet's source and destination as the qualifier asks.
+fn qualify(qualifier: EndpointQualifier, source: Test, destination: Test) -> Test {
+    match qualifier {
+        EndpointQualifier::Src => source,
+        EndpointQualifier::Dst => destination,
+        EndpointQualifier::SrcOrDst => any(vec![source, destination]),
+        EndpointQualifier::SrcAndDst => all(vec![source, destination]),
+    }
+}
+
+/// How the link-layer header of a capture tells the network protocol, and where its payload
+/// starts.
+#[derive(Copy, Clone, Debug)]
+enum LinkLayer {
+    /// An EtherType at the given offset, which VLAN tags may follow.
+    EtherType { type_offset: u32, payload_offset: u32 },
+    /// No link-layer header; the IP version is read from the first nibble.
+    Raw,
+    /// A 4-byte address family, in network byte order or, when `host_order`, in either.
+    Family { host_order: bool },
+}
+
+const ETHERTYPE_IPV4: u32 = 0x0800;
+const ETHERTYPE_IPV6: u32 = 0x86dd;
+const ETHERTYPE_ARP: u32 = 0x0806;
+const ETHERTYPE_RARP: u32 = 0x8035;
+const ETHERTYPE_MPLS: u32 = 0x8847;
+const ETHERTYPE_PPPOE_SESSION: u32 = 0x8864;
+/// Tag protocol identifiers of 802.1Q and QinQ VLAN tags.
+const TPIDS: [u32; 3] = [0x8100, 0x88a8, 0x9100];
+
+/// Address families that loopback captures give IPv4 and IPv6 packets.
+const FAMILY_IPV4: [u32; 1] = [2];
+const FAMILY_IPV6: [u32; 4] = [10, 24, 28, 30];
+
+impl LinkLayer {
+    fn new(link_type: LinkType) -> LinkLayer {
+        match link_type {
+            LinkType::Ethernet => LinkLayer::EtherType { type_offset: 12, payload_offset: 14 },
+            LinkType::LinuxSll => LinkLayer::EtherType { type_offset: 14, payload_offset: 16 },
+            LinkType::LinuxSll2 => LinkLayer::EtherType { type_offset: 0, payload_offset: 20 },
+            LinkType::Raw | LinkType::IPv4 | LinkType::IPv6 => LinkLayer::Raw,
+            LinkType::Null => LinkLayer::Family { host_order: true },
+            LinkType::Loop => LinkLayer::Family { host_order: false },
+        }
+    }
+}
+
+/// IP protocol numbers.
+const TCP: u32 = 6;
+const UDP: u32 = 17;
+const ICMP: u32 = 1;
+const ICMPV6: u32 = 58;
+
+/// Lowers the primitives of a filter expression to tests on the bytes of a packet and on what the
+/// prologue found in it.
+struct Lowering {
+    link: LinkLayer,
+}
+
+impl Lowering {
+    fn expression(&self, filter: &FilterExpr) -> Result<Test, BpfError> {
+        Ok(match filter {
+            FilterExpr::Primitive(filter) => self.primitive(filter)?,
+            FilterExpr::Not(operand) => not(self.expression(operand)?),
+            FilterExpr::And(left, right) => all(vec![self.expression(left)?, self.expression(right)?]),
+            FilterExpr::Or(left, right) => any(vec![self.expression(left)?, self.expression(right)?]),
+        })
+    }
+
+    /// Returns a test for a network layer with one of the given EtherTypes.
+    fn network_type(&self, ether_types: &[u32]) -> Test {
+        any(ether_types.iter().map(|ether_type| equal(Load::Memory(TYPE), *ether_type)).collect())
+    }
+
+    /// Returns a test for an IP packet carrying one of the given protocols.
+    fn protocol(&self, protocols: &[u32]) -> Test {
+        any(protocols.iter().map(|protocol| equal(Load::Memory(PROTOCOL), *protocol)).collect())
+    }
+
+    /// Returns a test on a field of a decoded TCP or UDP header, whether carried by IPv4 or IPv6.
+    ///
+    /// # Arguments
+    /// * `protocols` - The transport protocols the field belongs to.
+    /// * `offset` - Offset of the field in the transport header.
+    /// * `test` - Builds the test from the load of the field.
+    fn transport_field(&self, protocols: &[u32], offset: u32, size: u16, test: impl Fn(Load) -> Test) -> Test {
+        all(vec![
+            self.protocol(protocols),
+            not(equal(Load::Memory(TRANSPORT), 0)),
+            test(Load::Transport(size, offset)),
+        ])
+    }
+
+    /// Returns a test on the source and destination ports, as the qualifier asks.
+    fn ports(&self, qualifier: EndpointQualifier, test: impl Fn(Load) -> Test) -> Test {
+        let source = self.transport_field(&[TCP, UDP], 0, BPF_H, &test);
+        let destination = self.transport_field(&[TCP, UDP], 2, BPF_H, &test);
+        qualify(qualifier, source, destination)
+    }
+
+    /// Returns a test on the source and destination addresses, masked by the prefix length.
+    fn addresses(&self, qualifier: EndpointQualifier, network: IpNetwork) -> Test {
+        let prefix_length = network.prefix_length() as u32;
+        // Compares each word of the address that the prefix covers at least in part.
+        let words = |offset: u32, address: &[u8]| {
+            let tests = address
+                .chunks(4)
+                .enumerate()
+                .map(|(i, word)| {
+                    let value = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+                    let bits = prefix_length.saturating_sub(i as u32 * 32).min(32);
+                    let mask = if bits == 0 { 0 } else { u32::MAX << (32 - bits) };
+                    let load = Load::Network(BPF_W, offset + i as u32 * 4);
+                    match mask {
+                        0 => Test::Always(true),
+                        u32::MAX => equal(load, value),
+                        mask => masked(load, mask, value & mask),
+                    }
+                })
+                .collect();
+            all(tests)
+        };
+        match network.address() {
+            IpAddr::V4(address) => {
+                let octets = address.octets();
+                let test = qualify(qualifier, words(12, &octets), words(16, &octets));
+                all(vec![self.network_type(&[ETHERTYPE_IPV4]), test])
+            }
+            IpAddr::V6(address) => {
+                let octets = address.octets();
+                let test = qualify(qualifier, words(8, &octets), words(24, &octets));
+                all(vec![self.network_type(&[ETHERTYPE_IPV6]), test])
+            }
+        }
+    }
+
+    fn primitive(&self, filter: &Filter) -> Result<Test, BpfError> {
+        Ok(match filter {
+            Filter::Host(qualifier, address) => {
+                let prefix_length = if address.is_ipv4() { 32 } else { 128 };
+                let network = IpNetwork::new(*address, prefix_length).ok_or(BpfError::Unsupported(filter.clone()))?;
+                self.addresses(*qualifier, network)
+            }
+            Filter::Net(qualifier, network) => self.addresses(*qualifier, *network),
+            Filter::Port(qualifier, port) => {
+                self.ports(*qualifier, |load| Test::Compare { load, mask: None, jump: BPF_JEQ, value: *port as u32 })
+            }
+            Filter::PortRange(qualifier, first, last) => self.ports(*qualifier, |load| {
+                all(vec![
+                    Test::Compare { load, mask: None, jump: BPF_JGE, value: *first as u32 },
+                    not(Test::Compare { load, mask: None, jump: BPF_JGT, value: *last as u32 }),
+                ])
+            }),
+            Filter::Ip => self.network_type(&[ETHERTYPE_IPV4]),
+            Filter::Ip6 => self.network_type(&[ETHERTYPE_IPV6]),
+            // As in the decoded filter, ARP includes RARP.
+            Filter::Arp => self.network_type(&[ETHERTYPE_ARP, ETHERTYPE_RARP]),
+            Filter::Tcp => self.protocol(&[TCP]),
+            Filter::Udp => self.protocol(&[UDP]),
+            Filter::Icmp => all(vec![self.network_type(&[ETHERTYPE_IPV4]), self.protocol(&[ICMP])]),
+            Filter::Icmp6 => all(vec![self.network_type(&[ETHERTYPE_IPV6]), self.protocol(&[ICMPV6])]),
+            Filter::Vlan(vlan_id) => match (self.link, vlan_id) {
+                (LinkLayer::EtherType { .. }, None) => {
+                    Test::Compare { load: Load::Memory(TAGS), mask: None, jump: BPF_JGT, value: 0 }
+                }
+                // Any of the tags may have the VLAN ID.
+                (LinkLayer::EtherType { payload_offset, .. }, Some(vlan_id)) => any((0..MAX_VLAN_TAGS)
+                    .map(|i| all(vec![
+                        Test::Compare { load: Load::Memory(TAGS), mask: None, jump: BPF_JGT, value: i },
+                        masked(Load::Absolute(BPF_H, payload_offset + 4 * i), 0x0fff, *vlan_id as u32),
+                    ]))
+                    .collect()),
+                _ => Test::Always(false),
+            },
+            Filter::TcpFlags(mask) => {
+                // The flags are byte 13 of the TCP header, and NS is the low bit of byte 12.
+                let low = (mask.bits() & 0xff) as u32;
+                let ns = (mask.bits() >> 8) as u32;
+                let flags = |load: Load| {
+                    let mut tests = vec![];
+                    if low != 0 {
+                        tests.push(any_bit(load, low));
+                    }
+                    if ns != 0 {
+                        tests.push(any_bit(load.before(1), ns));
+                    }
+                    any(tests)
+                };
+                self.transport_field(&[TCP], 13, BPF_B, flags)
+            }
+            Filter::BadChecksum => return Err(BpfError::Unsupported(filter.clone())),
+        })
+    }
+}
+
+/// A jump target, resolved to an instruction position once the program is laid out.
+type Label = usize;
+
+/// An instruction whose jump targets are still labels.
+struct Pending {
+    code: u16,
+    k: u32,
+    /// Target of `ja`, or of a conditional jump when taken.
+    jt: Option<Label>,
+    jf: Option<Label>,
+}
+
+/// Emits the instructions of a program, then resolves its jumps.
+struct Generator {
+    code: Vec<Pending>,
+    /// Position of each label, once placed.
+    labels: Vec<usize>,
+}
+
+impl Generator {
+    fn label(&mut self) -> Label {
+        self.labels.push(usize::MAX);
+        self.labels.len() - 1
+    }
+
+    /// Places the label at the next instruction.
+    fn place(&mut self, label: Label) {
+        self.labels[label] = self.code.len();
+    }
+
+    fn emit(&mut self, code: u16, k: u32) {
+        self.code.push(Pending { code, k, jt: None, jf: None });
+    }
+
+    fn jump(&mut self, code: u16, k: u32, jt: Label, jf: Option<Label>) {
+        self.code.push(Pending { code, k, jt: Some(jt), jf });
+    }
+
+    fn goto(&mut self, label: Label) {
+        self.jump(BPF_JMP | BPF_JA, 0, label, None);
+    }
+
+    /// Emits jumps to `target` when the accumulator equals one of `values`, falling through
+    /// otherwise.
+    fn jump_if_any(&mut self, values: &[u32], target: Label) {
+        for value in values {
+            let next = self.label();
+            self.jump(BPF_JMP | BPF_JEQ | BPF_K, *value, target, Some(next));
+            self.place(next);
+        }
+    }
+
+    /// Emits code moving the index register `by` bytes further, keeping the accumulator.
+    fn advance(&mut self, by: u32) {
+        self.emit(BPF_ST, SAVED);
+        self.emit(BPF_MISC | BPF_TXA, 0);
+        self.emit(BPF_ALU | BPF_ADD | BPF_K, by);
+        self.emit(BPF_MISC | BPF_TAX, 0);
+        self.emit(BPF_LD | BPF_MEM, SAVED);
+    }
+
+    /// Emits code moving the index register past an IPv6 extension header whose length field is
+    /// in units of `1 << shift` bytes, not counting the first `units`, leaving the header's next
+    /// header in the accumulator.
+    fn skip_extension_header(&mut self, units: u32, shift: u32) {
+        self.emit(BPF_LD | BPF_B | BPF_IND, 0);
+        self.emit(BPF_ST, PROTOCOL);
+        self.emit(BPF_LD | BPF_B | BPF_IND, 1);
+        self.emit(BPF_ALU | BPF_ADD | BPF_K, units);
+        self.emit(BPF_ALU | BPF_LSH | BPF_K, shift);
+        self.emit(BPF_ALU | BPF_ADD | BPF_X, 0);
+        self.emit(BPF_MISC | BPF_TAX, 0);
+        self.emit(BPF_LD | BPF_MEM, PROTOCOL);
+    }
+
+    /// Emits the prologue of a program, which finds the network header as the decoder does and
+    /// leaves its EtherType in `M[TYPE]`, its offset in `M[NETWORK]` and, for an EtherType link
+    /// layer, the number of VLAN tags in `M[TAGS]`. With `transport`, it also walks the IP header
+    /// to leave the IP protocol in `M[PROTOCOL]` and the offset of the TCP or UDP header in
+    /// `M[TRANSPORT]`.
+    fn prologue(&mut self, link: LinkLayer, transport: bool) {
+        let network = self.label();
+        let ipv4 = self.label();
+        let ipv6 = self.label();
+        let unknown = self.label();
+        // The index register holds the offset of the next header, and the accumulator its type.
+        match link {
+            LinkLayer::EtherType { type_offset, payload_offset } => {
+                self.emit(BPF_LDX | BPF_IMM, payload_offset);
+                self.emit(BPF_LD | BPF_H | BPF_ABS, type_offset);
+                let untagged = self.label();
+                for _ in 0..MAX_VLAN_TAGS {
+                    let tag = self.label();
+                    self.jump_if_any(&TPIDS, tag);
+                    self.goto(untagged);
+                    self.place(tag);
+                    self.emit(BPF_LD | BPF_H | BPF_IND, 2);
+                    self.advance(4);
+                }
+                self.place(untagged);
+                self.emit(BPF_ST, SAVED);
+                self.emit(BPF_MISC | BPF_TXA, 0);
+                self.emit(BPF_ALU | BPF_SUB | BPF_K, payload_offset);
+                self.emit(BPF_ALU | BPF_RSH | BPF_K, 2);
+                self.emit(BPF_ST, TAGS);
+                self.emit(BPF_LD | BPF_MEM, SAVED);
+
+                let mpls = self.label();
+                let pppoe = self.label();
+                self.jump_if_any(&[ETHERTYPE_MPLS], mpls);
+                self.jump_if_any(&[ETHERTYPE_PPPOE_SESSION], pppoe);
+                self.goto(network);
+
+                // MPLS does not name the protocol under the label stack, so the decoder guesses
+                // it from the IP version.
+                self.place(mpls);
+                let bottom = self.label();
+                for _ in 0..MAX_MPLS_LABELS {
+                    let last = self.label();
+                    let more = self.label();
+                    self.emit(BPF_LD | BPF_W | BPF_IND, 0);
+                    self.jump(BPF_JMP | BPF_JSET | BPF_K, 0x100, last, Some(more));
+                    self.place(last);
+                    self.advance(4);
+                    self.goto(bottom);
+                    self.place(more);
+                    self.advance(4);
+                }
+                self.goto(unknown);
+                self.place(bottom);
+                self.emit(BPF_LD | BPF_B | BPF_IND, 0);
+                self.emit(BPF_ALU | BPF_AND | BPF_K, 0xf0);
+                self.jump_if_any(&[0x40], ipv4);
+                self.jump_if_any(&[0x60], ipv6);
+                self.goto(unknown);
+
+                self.place(pppoe);
+                self.emit(BPF_LD | BPF_H | BPF_IND, 6);
+                self.advance(8);
+                self.jump_if_any(&[0x0021], ipv4);
+                self.jump_if_any(&[0x0057], ipv6);
+                self.goto(unknown);
+            }
+            LinkLayer::Raw => {
+                // The decoder takes any version but 6 for IPv4.
+                self.emit(BPF_LDX | BPF_IMM, 0);
+                self.emit(BPF_LD | BPF_B | BPF_ABS, 0);
+                self.emit(BPF_ALU | BPF_AND | BPF_K, 0xf0);
+                self.jump_if_any(&[0x60], ipv6);
+                self.goto(ipv4);
+            }
+            LinkLayer::Family { host_order } => {
+                let swapped = |families: &[u32]| families.iter().map(|family| family.swap_bytes()).collect::<Vec<u32>>();
+                self.emit(BPF_LDX | BPF_IMM, 4);
+                self.emit(BPF_LD | BPF_W | BPF_ABS, 0);
+                self.jump_if_any(&FAMILY_IPV4, ipv4);
+                self.jump_if_any(&FAMILY_IPV6, ipv6);
+                if host_order {
+                    self.jump_if_any(&swapped(&FAMILY_IPV4), ipv4);
+                    self.jump_if_any(&swapped(&FAMILY_IPV6), ipv6);
+                }
+                self.goto(unknown);
+            }
+        }
+        self.place(unknown);
+        self.emit(BPF_LD | BPF_IMM, 0);
+        self.goto(network);
+        self.place(ipv4);
+        self.emit(BPF_LD | BPF_IMM, ETHERTYPE_IPV4);
+        self.goto(network);
+        self.place(ipv6);
+        self.emit(BPF_LD | BPF_IMM, ETHERTYPE_IPV6);
+
+        self.place(network);
+        self.emit(BPF_ST, TYPE);
+        self.emit(BPF_STX, NETWORK);
+        if !transport {
+            return;
+        }
+
+        let done = self.label();
+        let ipv4 = self.label();
+        let ipv6 = self.label();
+        let undecoded = self.label();
+        let fragment = self.label();
+        self.jump_if_any(&[ETHERTYPE_IPV4], ipv4);
+        self.jump_if_any(&[ETHERTYPE_IPV6], ipv6);
+        self.goto(undecoded);
+
+        // Only an IPv4 packet that is not a fragment has its datagram decoded.
+        self.place(ipv4);
+        let whole = self.label();
+        self.emit(BPF_LD | BPF_B | BPF_IND, 9);
+        self.emit(BPF_ST, PROTOCOL);
+        self.emit(BPF_LD | BPF_H | BPF_IND, 6);
+        self.jump(BPF_JMP | BPF_JSET | BPF_K, 0x3fff, fragment, Some(whole));
+        self.place(whole);
+        self.emit(BPF_LD | BPF_B | BPF_IND, 0);
+        self.emit(BPF_ALU | BPF_AND | BPF_K, 0x0f);
+        self.emit(BPF_ALU | BPF_LSH | BPF_K, 2);
+        self.emit(BPF_ALU | BPF_ADD | BPF_X, 0);
+        self.emit(BPF_ST, TRANSPORT);
+        self.goto(done);
+
+        // The extension headers are walked as the decoder does: Hop-by-Hop, Routing and
+        // Destination Options give their length in 8-byte units, Authentication in 4-byte units,
+        // and only an atomic fragment is decoded further. ESP ends the walk undecoded.
+        self.place(ipv6);
+        let upper = self.label();
+        self.emit(BPF_LD | BPF_B | BPF_IND, 6);
+        self.advance(40);
+        for _ in 0..MAX_IPV6_EXTENSION_HEADERS {
+            let options = self.label();
+            let fragment_header = self.label();
+            let atomic = self.label();
+            let authentication = self.label();
+            let next = self.label();
+            self.jump_if_any(&[0, 43, 60], options);
+            self.jump_if_any(&[44], fragment_header);
+            self.jump_if_any(&[51], authentication);
+            self.jump_if_any(&[50], undecoded);
+            self.goto(upper);
+            self.place(options);
+            self.skip_extension_header(1, 3);
+            self.goto(next);
+            self.place(fragment_header);
+            self.emit(BPF_LD | BPF_H | BPF_IND, 2);
+            self.emit(BPF_ALU | BPF_AND | BPF_K, 0xfff9);
+            self.jump(BPF_JMP | BPF_JEQ | BPF_K, 0, atomic, Some(undecoded));
+            self.place(atomic);
+            self.emit(BPF_LD | BPF_B | BPF_IND, 0);
+            self.advance(8);
+            self.goto(next);
+            self.place(authentication);
+            self.skip_extension_header(2, 2);
+            self.place(next);
+        }
+        self.goto(undecoded);
+        self.place(upper);
+        self.emit(BPF_ST, PROTOCOL);
+        self.emit(BPF_STX, TRANSPORT);
+        self.goto(done);
+
+        self.place(undecoded);
+        self.emit(BPF_LD | BPF_IMM, NO_PROTOCOL);
+        self.emit(BPF_ST, PROTOCOL);
+        self.place(fragment);
+        self.emit(BPF_LD | BPF_IMM, 0);
+        self.emit(BPF_ST, TRANSPORT);
+        self.place(done);
+    }
+
+    /// Emits the code of a test that continues at `on_true` if it passes, `on_false` otherwise.
+    fn test(&mut self, test: &Test, on_true: Label, on_false: Label) {
+        match test {
+            Test::Always(value) => self.jump(BPF_JMP | BPF_JA, 0, if *value { on_true } else { on_false }, None),
+            Test::Compare { load, mask, jump, value } => {
+                match *load {
+                    Load::Memory(word) => self.emit(BPF_LD | BPF_MEM, word),
+                    Load::Absolute(size, offset) => self.emit(BPF_LD | size | BPF_ABS, offset),
+                    Load::Network(size, offset) => {
+                        self.emit(BPF_LDX | BPF_MEM, NETWORK);
+                        self.emit(BPF_LD | size | BPF_IND, offset);
+                    }
+                    Load::Transport(size, offset) => {
+                        self.emit(BPF_LDX | BPF_MEM, TRANSPORT);
+                        self.emit(BPF_LD | size | BPF_IND, offset);
+                    }
+                }
+                if let Some(mask) = mask {
+                    self.emit(BPF_ALU | BPF_AND | BPF_K, *mask);
+                }
+                self.jump(BPF_JMP | *jump | BPF_K, *value, on_true, Some(on_false));
+            }
+            Test::Not(test) => self.test(test, on_false, on_true),
+            Test::All(tests) | Test::Any(tests) => {
+                let every = matches!(test, Test::All(_));
+                for (i, test) in tests.iter().enumerate() {
+                    if i == tests.len() - 1 {
+                        self.test(test, on_true, on_false);
+                    } else {
+                        let next = self.label();
+                        if every {
+                            self.test(test, next, on_false);
+                        } else {
+                            self.test(test, on_true, next);
+                        }
+                        self.place(next);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Makes every conditional jump reach its targets within the 255 instructions that `jt` and
+    /// `jf` can skip, as libpcap does: a jump that would go further goes instead to a `ja` placed
+    /// right after it, which jumps the rest of the way.
+    fn add_trampolines(&mut self) {
+        loop {
+            let labels = &self.labels;
+            let far = |n: usize, label: Option<Label>| label.filter(|label| labels[*label] - (n + 1) > u8::MAX as usize);
+            let Some((n, jt, jf)) = self
+                .code
+                .iter()
+                .enumerate()
+                .filter(|(_, pending)| pending.jf.is_some())
+                .map(|(n, pending)| (n, far(n, pending.jt), far(n, pending.jf)))
+                .find(|(_, jt, jf)| jt.is_some() || jf.is_some())
+            else {
+                return;
+            };
+            let count = jt.iter().chain(&jf).count();
+            for position in self.labels.iter_mut().filter(|position| **position > n) {
+                *position += count;
+            }
+            let mut next = n + 1;
+            let mut trampoline = |generator: &mut Generator, target: Label| {
+                let label = generator.label();
+                generator.labels[label] = next;
+                generator.code.insert(next, Pending { code: BPF_JMP | BPF_JA, k: 0, jt: Some(target), jf: None });
+                next += 1;
+                label
+            };
+            if let Some(target) = jt {
+                self.code[n].jt = Some(trampoline(self, target));
+            }
+            if let Some(target) = jf {
+                self.code[n].jf = Some(trampoline(self, target));
+            }
+        }
+    }
+
+    /// Resolves the labels into jump offsets.
+    fn resolve(mut self) -> Vec<BpfInstruction> {
+        self.add_trampolines();
+        let labels = self.labels;
+        let offset = |n: usize, label: Option<Label>| label.map_or(0, |label| labels[label] - (n + 1));
+        self.code
+            .iter()
+            .enumerate()
+            .map(|(n, pending)| {
+                if pending.code == BPF_JMP | BPF_JA {
+                    let k = pending.jt.map_or(pending.k, |label| offset(n, Some(label)) as u32);
+                    return BpfInstruction::new(pending.code, 0, 0, k);
+                }
+                BpfInstruction::new(pending.code, offset(n, pending.jt) as u8, offset(n, pending.jf) as u8, pending.k)
+            })
+            .collect()
+    }
+}
+
+/// Compiles a filter expression to the instructions of a BPF program over packets of the given
+/// link-layer type, returning the packet when it passes and rejecting it otherwise.
+pub(crate) fn compile(filter: &FilterExpr, link_type: LinkType) -> Result<Vec<BpfInstruction>, BpfError> {
+    let link = LinkLayer::new(link_type);
+    let test = Lowering { link }.expression(filter)?;
+    if let Test::Always(accept) = test {
+        return Ok(vec![BpfInstruction::new(BPF_RET | BPF_K, 0, 0, if accept { ACCEPT_LENGTH } else { 0 })]);
+    }
+
+    let mut generator = Generator { code: vec![], labels: vec![] };
+    let accept = generator.label();
+    let reject = generator.label();
+    generator.prologue(link, test.needs_transport());
+    generator.test(&test, accept, reject);
+    generator.place(accept);
+    generator.emit(BPF_RET | BPF_K, ACCEPT_LENGTH);
+    generator.place(reject);
+    generator.emit(BPF_RET | BPF_K, 0);
+    Ok(generator.resolve())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::tests::{ethernet, ipv4, ipv6, tcp, udp};
+    use crate::{parse_frame, BpfProgram};
+
+    /// Returns the bytes of an IPv6 extension header followed by `payload`.
+    fn extension(header: &[u8], payload: &[u8]) -> Vec<u8> {
+        [header, payload].concat()
+    }
+
+    /// Returns frames of every encapsulation the decoder knows, carrying various datagrams.
+    fn frames() -> Vec<Vec<u8>> {
+        let icmp = [8, 0, 0, 0, 0, 1, 0, 1];
+        let icmpv6 = [128, 0, 0, 0, 0, 1, 0, 1];
+        let hop_by_hop = [6, 0, 1, 4, 0, 0, 0, 0];
+        let options_to_udp = [17, 1, 1, 12, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        let mut options_ipv4 = ipv4([10, 0, 0, 2], 6, 0, &[[1, 1, 1, 0].as_slice(), &tcp(80, 0x02)].concat());
+        options_ipv4[0] = 0x46;
+        options_ipv4[3] += 4;
+        let arp = [0, 1, 8, 0, 6, 4, 0, 1, 0, 0, 0, 0, 0, 1, 10, 0, 0, 1, 0, 0, 0, 0, 0, 0, 10, 0, 0, 2];
+        let mpls = [[0, 0x10, 0, 64].as_slice(), &[0, 0x20, 0x01, 64], &ipv4([10, 0, 0, 2], 17, 0, &udp(53))].concat();
+        let pppoe = [[0x11, 0, 0, 1, 0, 62, 0, 0x57].as_slice(), &ipv6(6, &tcp(443, 0x12))].concat();
+        vec![
+            ethernet(&[], 0x0800, &ipv4([10, 0, 0, 2], 17, 0, &udp(53))),
+            ethernet(&[], 0x0800, &ipv4([192, 168, 0, 1], 6, 0, &tcp(80, 0x02))),
+            ethernet(&[], 0x0800, &ipv4([10, 0, 0, 2], 1, 0, &icmp)),
+            ethernet(&[], 0x0800, &options_ipv4),
+            // The first and a later fragment of a datagram, neither of which has its ports decoded.
+            ethernet(&[], 0x0800, &ipv4([10, 0, 0, 2], 17, 0x2000, &udp(53))),
+            ethernet(&[], 0x0800, &ipv4([10, 0, 0, 2], 17, 0x0001, &udp(53))),
+            ethernet(&[100], 0x0800, &ipv4([10, 0, 0, 2], 17, 0, &udp(53))),
+            ethernet(&[10, 20], 0x0800, &ipv4([10, 0, 0, 2], 6, 0, &tcp(80, 0x10))),
+            ethernet(&[], 0x86dd, &ipv6(17, &udp(53))),
+            ethernet(&[], 0x86dd, &ipv6(0, &extension(&hop_by_hop, &tcp(80, 0x02)))),
+            ethernet(&[], 0x86dd, &ipv6(0, &extension(&[60, 0, 1, 4, 0, 0, 0, 0], &extension(&[58, 0, 1, 4, 0, 0, 0, 0], &icmpv6)))),
+            ethernet(&[], 0x86dd, &ipv6(60, &extension(&options_to_udp, &udp(53)))),
+            ethernet(&[], 0x86dd, &ipv6(44, &extension(&[17, 0, 0, 1, 0, 0, 0, 7], &udp(53)))),
+            ethernet(&[], 0x86dd, &ipv6(44, &extension(&[17, 0, 0, 0, 0, 0, 0, 7], &udp(53)))),
+            ethernet(&[], 0x86dd, &ipv6(51, &extension(&[6, 1, 0, 0, 0, 0, 0, 1, 0, 0, 0, 1], &tcp(22, 0x18)))),
+            ethernet(&[], 0x86dd, &ipv6(50, &[0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 0])),
+            ethernet(&[30], 0x86dd, &ipv6(0, &extension(&[17, 0, 1, 4, 0, 0, 0, 0], &udp(123)))),
+            ethernet(&[], 0x8847, &mpls),
+            ethernet(&[40], 0x8864, &pppoe),
+            ethernet(&[], 0x0806, &arp),
+        ]
+    }
+
+    /// Asserts that the program compiled from `expression` accepts exactly the frames that the
+    /// expression matches once decoded, and returns the program.
+    fn check(expression: &str, frames: &[Vec<u8>]) -> BpfProgram {
+        let filter = FilterExpr::parse(expression).unwrap();
+        let program = BpfProgram::compile(&filter, LinkType::Ethernet).unwrap();
+        for frame in frames {
+            let decoded = filter.matches(&parse_frame(frame).unwrap());
+            assert_eq!(program.run(frame, frame.len() as u32) != 0, decoded, "{} on {:02x?}", expression, frame);
+        }
+        program
+    }
+
+    #[test]
+    fn programs_match_as_the_decoded_filter_does() {
+        let frames = frames();
+        let expressions = [
+            "ip", "ip6", "arp", "tcp", "udp", "icmp", "icmp6", "not icmp", "port 53", "tcp port 80", "udp dst port 53",
+            "src port 40000", "portrange 50-100", "host 10.0.0.2", "net 10.0.0.0/8", "dst net 192.168.0.0/16",
+            "host 2001:db8::2", "src net 2001:db8::/32", "vlan", "not vlan", "vlan 20", "vlan 30 and udp",
+            "vlan and udp", "tcp-syn", "tcp[flags] & (syn|ack) != 0", "ip6 and not tcp", "udp port 53 or tcp port 22",
+        ];
+        for expression in expressions {
+            check(expression, &frames);
+        }
+    }
+
+    #[test]
+    fn programs_pass_the_expected_frames() {
+        let frames = frames();
+        let cases: [(&str, &[usize]); 5] = [
+            ("tcp port 80", &[1, 3, 7, 9]),
+            ("dst net 10.0.0.0/8", &[0, 2, 3, 4, 5, 6, 7, 17]),
+            ("vlan and udp", &[6, 16]),
+            ("not icmp", &[0, 1, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19]),
+            // Neither fragment has its ports decoded, though the first holds the UDP header.
+            ("udp port 53", &[0, 6, 8, 11, 13, 17]),
+        ];
+        for (expression, expected) in cases {
+            let program = check(expression, &frames);
+            let passed: Vec<usize> = (0..frames.len()).filter(|i| program.run(&frames[*i], 0) != 0).collect();
+            assert_eq!(passed, expected, "{}", expression);
+        }
+    }
+
+    #[test]
+    fn programs_read_every_link_layer() {
+        let packets = [
+            ipv4([10, 0, 0, 2], 17, 0, &udp(53)),
+            ipv4([10, 0, 0, 2], 6, 0, &tcp(80, 0x02)),
+            ipv6(0, &extension(&[6, 0, 1, 4, 0, 0, 0, 0], &tcp(80, 0x02))),
+        ];
+        let headers = [
+            (LinkType::Raw, vec![], vec![]),
+            (LinkType::Null, vec![2, 0, 0, 0], vec![30, 0, 0, 0]),
+            (LinkType::Loop, vec![0, 0, 0, 2], vec![0, 0, 0, 24]),
+            (LinkType::LinuxSll, [[0, 0, 0, 1, 0, 6].as_slice(), &[0; 8], &[8, 0]].concat(),
+             [[0, 0, 0, 1, 0, 6].as_slice(), &[0; 8], &[0x86, 0xdd]].concat()),
+        ];
+        for (link_type, ipv4_header, ipv6_header) in headers {
+            for expression in ["ip", "ip6", "tcp port 80", "udp", "not host 10.0.0.2"] {
+                let filter = FilterExpr::parse(expression).unwrap();
+                let program = BpfProgram::compile(&filter, link_type).unwrap();
+                for (i, packet) in packets.iter().enumerate() {
+                    let header = if i < 2 { &ipv4_header } else { &ipv6_header };
+                    let bytes = [header.as_slice(), packet].concat();
+                    let decoded = filter.matches(&crate::parse_packet(link_type, &bytes).unwrap());
+                    assert_eq!(program.run(&bytes, 0) != 0, decoded, "{} on {} packet {}", expression, link_type, i);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn long_jumps_go_through_trampolines() {
+        let expression = (0..40).map(|port| format!("udp port {}", port)).collect::<Vec<String>>().join(" or ");
+        let frames: Vec<Vec<u8>> = [0, 1, 20, 39, 40]
+            .iter()
+            .map(|port| ethernet(&[], 0x0800, &ipv4([10, 0, 0, 2], 17, 0, &udp(*port))))
+            .collect();
+        let program = check(&expression, &frames);
+        let instructions = program.instructions();
+        assert!(instructions.len() > 256);
+    }
+}
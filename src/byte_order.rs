@@ -0,0 +1,24 @@
+/// Byte order in which a capture file stores its header and record fields.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ByteOrder {
+    LittleEndian,
+    BigEndian,
+}
+
+impl ByteOrder {
+    /// Decodes a 16-bit field stored in this byte order.
+    pub fn u16(&self, bytes: [u8; 2]) -> u16 {
+        match self {
+            ByteOrder::LittleEndian => u16::from_le_bytes(bytes),
+            ByteOrder::BigEndian => u16::from_be_bytes(bytes),
+        }
+    }
+
+    /// Decodes a 32-bit field stored in this byte order.
+    pub fn u32(&self, bytes: [u8; 4]) -> u32 {
+        match self {
+            ByteOrder::LittleEndian => u32::from_le_bytes(bytes),
+            ByteOrder::BigEndian => u32::from_be_bytes(bytes),
+        }
+    }
+}
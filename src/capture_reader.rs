@@ -0,0 +1,67 @@
+use std::io::Read;
+
+use crate::pcapng_reader::SECTION_HEADER_BLOCK;
+use crate::{LinkType, ParseError, PcapBlock, PcapNgReader, PcapReader};
+
+/// Reader over a pcap or pcapng capture, chosen from the first four bytes of the input.
+pub enum CaptureReader<R: Read> {
+    Pcap(PcapReader<R>),
+    PcapNg(PcapNgReader<R>),
+}
+
+impl<R: Read> CaptureReader<R> {
+    /// Detects the capture format and parses its file or section header.
+    ///
+    /// # Arguments
+    /// * `reader` - The source of the capture bytes.
+    ///
+    /// # Errors
+    /// Returns the error of `PcapReader::new` or `PcapNgReader::new`.
+    pub fn new(mut reader: R) -> Result<CaptureReader<R>, ParseError> {
+        let mut magic_number = [0_u8; 4];
+        reader.read_exact(&mut magic_number)?;
+        if magic_number == SECTION_HEADER_BLOCK {
+            Ok(CaptureReader::PcapNg(PcapNgReader::with_magic(magic_number, reader)?))
+        } else {
+            Ok(CaptureReader::Pcap(PcapReader::with_magic(magic_number, reader)?))
+        }
+    }
+
+    /// Returns the link-layer type of the capture, or of the first interface of a pcapng capture
+    /// once it has been described.
+    pub fn link_type(&self) -> Option<LinkType> {
+        match self {
+            CaptureReader::Pcap(reader) => Some(reader.link_type()),
+            CaptureReader::PcapNg(reader) => {
+                reader.interfaces().first().and_then(|interface| LinkType::from_number(interface.link_type).ok())
+            }
+        }
+    }
+
+    /// Sets a test run on each packet before its frame is decoded; packets it rejects are skipped.
+    pub fn set_prefilter(&mut self, prefilter: impl FnMut(&PcapBlock) -> bool + 'static) {
+        match self {
+            CaptureReader::Pcap(reader) => reader.set_prefilter(prefilter),
+            CaptureReader::PcapNg(reader) => reader.set_prefilter(prefilter),
+        }
+    }
+
+    /// Returns the number of packets the prefilter skipped so far.
+    pub fn filtered(&self) -> usize {
+        match self {
+            CaptureReader::Pcap(reader) => reader.filtered(),
+            CaptureReader::PcapNg(reader) => reader.filtered(),
+        }
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = Result<PcapBlock, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            CaptureReader::Pcap(reader) => reader.next(),
+            CaptureReader::PcapNg(reader) => reader.next(),
+        }
+    }
+}
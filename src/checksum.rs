@@ -0,0 +1,198 @@
+use std::fmt;
+
+/// Length of the IPv4 pseudo-header covered by TCP and UDP checksums.
+pub(crate) const IPV4_PSEUDO_HEADER_LENGTH: usize = 12;
+
+/// Result of verifying a checksum against the bytes it covers.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ChecksumStatus {
+    Correct,
+    /// The checksum does not match; `expected` is the value it should hold.
+    Incorrect { expected: u16 },
+    /// The checksum is zero or holds only the pseudo-header sum, as left by a sending host that
+    /// offloads checksumming to its network card. Only checksums covering a pseudo-header, those of
+    /// TCP, UDP and ICMPv6, are offloaded.
+    Offloaded,
+    /// A UDP datagram over IPv4 with a zero checksum, meaning the sender did not compute one.
+    Absent,
+    /// The checksum could not be verified, because the packet was cut short by the snap length or
+    /// is a fragment of a larger one.
+    #[default]
+    Unverified,
+}
+
+impl ChecksumStatus {
+    /// Verifies the checksum stored at `checksum_offset` in `data`.
+    ///
+    /// # Arguments
+    /// * `pseudo_header` - The IP pseudo-header covered by the checksum, or an empty slice.
+    /// * `data` - The bytes covered by the checksum, including the checksum field.
+    /// * `checksum_offset` - The offset of the checksum field in `data`.
+    pub(crate) fn verify(pseudo_header: &[u8], data: &[u8], checksum_offset: usize) -> ChecksumStatus {
+        if data.len() < checksum_offset + 2 {
+            return ChecksumStatus::Unverified;
+        }
+        let stored = u16::from_be_bytes([data[checksum_offset], data[checksum_offset + 1]]);
+        let expected = expected(pseudo_header, data, checksum_offset);
+        // A computed checksum of zero is sent as 0xffff, which means the same in ones' complement.
+        if stored == expected || (expected == 0 && stored == 0xffff) {
+            ChecksumStatus::Correct
+        } else if !pseudo_header.is_empty() && (stored == 0 || stored == fold(sum(0, pseudo_header))) {
+            ChecksumStatus::Offloaded
+        } else {
+            ChecksumStatus::Incorrect { expected }
+        }
+    }
+
+    /// Returns whether the checksum was verified and found to be wrong.
+    pub fn is_incorrect(&self) -> bool {
+        matches!(self, ChecksumStatus::Incorrect { .. })
+    }
+}
+
+/// Computes the checksum of `data`, with the pseudo-header, leaving out the checksum field at
+/// `checksum_offset`, which `data` must hold.
+pub(crate) fn expected(pseudo_header: &[u8], data: &[u8], checksum_offset: usize) -> u16 {
+    !fold(sum(sum(sum(0, pseudo_header), &data[..checksum_offset]), &data[checksum_offset + 2..]))
+}
+
+/// Builds the IPv4 pseudo-header covered by TCP and UDP checksums.
+pub(crate) fn ipv4_pseudo_header(source: [u8; 4], destination: [u8; 4], protocol: u8, length: usize) -> [u8; 12] {
+    let mut pseudo_header = [0_u8; IPV4_PSEUDO_HEADER_LENGTH];
+    pseudo_header[0..4].copy_from_slice(&source);
+    pseudo_header[4..8].copy_from_slice(&destination);
+    pseudo_header[9] = protocol;
+    pseudo_header[10..12].copy_from_slice(&(length as u16).to_be_bytes());
+    pseudo_header
+}
+
+/// Builds the IPv6 pseudo-header covered by TCP, UDP and ICMPv6 checksums.
+pub(crate) fn ipv6_pseudo_header(source: [u8; 16], destination: [u8; 16], next_header: u8, length: usize) -> [u8; 40] {
+    let mut pseudo_header = [0_u8; 40];
+    pseudo_header[0..16].copy_from_slice(&source);
+    pseudo_header[16..32].copy_from_slice(&destination);
+    pseudo_header[32..36].copy_from_slice(&(length as u32).to_be_bytes());
+    pseudo_header[39] = next_header;
+    pseudo_header
+}
+
+/// Adds the 16-bit big-endian words of `data` to `sum`, padding an odd last byte with zero.
+fn sum(sum: u32, data: &[u8]) -> u32 {
+    data.chunks(2).fold(sum, |sum, word| {
+        let word = if word.len() == 2 { u16::from_be_bytes([word[0], word[1]]) } else { (word[0] as u16) << 8 };
+        let (sum, overflow) = sum.overflowing_add(word as u32);
+        sum + overflow as u32
+    })
+}
+
+/// Folds the carries of a 32-bit sum back into 16 bits.
+fn fold(mut sum: u32) -> u16 {
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    sum as u16
+}
+
+impl fmt::Display for ChecksumStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChecksumStatus::Correct => write!(f, "[correct]"),
+            ChecksumStatus::Incorrect { expected } => write!(f, "[incorrect, should be 0x{:04x}]", expected),
+            ChecksumStatus::Offloaded => write!(f, "[unverified, checksum offloaded?]"),
+            ChecksumStatus::Absent => write!(f, "[none]"),
+            ChecksumStatus::Unverified => write!(f, "[unverified]"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{IPProtocol, IPacket, ProtocolDatagram};
+
+    /// Builds a UDP datagram from port 1024 to port 53 carrying `payload`, with `checksum` stored.
+    fn udp(checksum: u16, payload: &[u8]) -> Vec<u8> {
+        let mut datagram = vec![0x04, 0x00, 0x00, 0x35];
+        datagram.extend_from_slice(&(8 + payload.len() as u16).to_be_bytes());
+        datagram.extend_from_slice(&checksum.to_be_bytes());
+        datagram.extend_from_slice(payload);
+        datagram
+    }
+
+    fn udp_status(datagram: &[u8], pseudo_header: &[u8]) -> ChecksumStatus {
+        let mut parsed = ProtocolDatagram::parse(&IPProtocol::UDP, datagram).unwrap();
+        parsed.verify_checksum(datagram, pseudo_header);
+        parsed.checksum_status().unwrap()
+    }
+
+    #[test]
+    fn a_matching_checksum_is_correct() {
+        let header = [0x45, 0x00, 0x00, 0x73, 0x00, 0x00, 0x40, 0x00, 0x40, 0x11, 0xb8, 0x61, 0xc0, 0xa8, 0x00, 0x01,
+                      0xc0, 0xa8, 0x00, 0xc7];
+        assert_eq!(ChecksumStatus::verify(&[], &header, 10), ChecksumStatus::Correct);
+
+        let mut wrong = header;
+        wrong[11] = 0x62;
+        assert_eq!(ChecksumStatus::verify(&[], &wrong, 10), ChecksumStatus::Incorrect { expected: 0xb861 });
+    }
+
+    #[test]
+    fn a_computed_zero_is_sent_as_all_ones() {
+        // The words other than the checksum add up to 0xffff, so the checksum computes to zero.
+        let data = [0xff, 0x00, 0x00, 0xff, 0xff, 0xff];
+        assert_eq!(expected(&[], &data, 4), 0);
+        assert_eq!(ChecksumStatus::verify(&[], &data, 4), ChecksumStatus::Correct);
+    }
+
+    #[test]
+    fn only_pseudo_header_checksums_are_offloaded() {
+        let pseudo_header = ipv4_pseudo_header([10, 0, 0, 1], [10, 0, 0, 2], 6, 20);
+        let mut segment = [0_u8; 20];
+        segment[16..18].copy_from_slice(&fold(sum(0, &pseudo_header)).to_be_bytes());
+        assert_eq!(ChecksumStatus::verify(&pseudo_header, &segment, 16), ChecksumStatus::Offloaded);
+        segment[16..18].copy_from_slice(&[0, 0]);
+        assert_eq!(ChecksumStatus::verify(&pseudo_header, &segment, 16), ChecksumStatus::Offloaded);
+
+        // ICMP over IPv4 covers no pseudo-header, so a zero checksum there is just wrong.
+        let icmp = [0x08, 0x00, 0x00, 0x00, 0x12, 0x34, 0x00, 0x01];
+        assert_eq!(ChecksumStatus::verify(&[], &icmp, 2), ChecksumStatus::Incorrect { expected: 0xe5ca });
+    }
+
+    #[test]
+    fn a_zero_udp_checksum_is_absent_over_ipv4() {
+        let pseudo_header = ipv4_pseudo_header([10, 0, 0, 1], [10, 0, 0, 2], 17, 12);
+        assert_eq!(udp_status(&udp(0, b"ping"), &pseudo_header), ChecksumStatus::Absent);
+
+        let mut datagram = udp(0, b"ping");
+        let checksum = expected(&pseudo_header, &datagram, 6);
+        datagram[6..8].copy_from_slice(&checksum.to_be_bytes());
+        assert_eq!(udp_status(&datagram, &pseudo_header), ChecksumStatus::Correct);
+    }
+
+    #[test]
+    fn a_zero_udp_checksum_is_incorrect_over_ipv6() {
+        let mut source = [0_u8; 16];
+        source[..2].copy_from_slice(&[0x20, 0x01]);
+        source[15] = 1;
+        let mut destination = source;
+        destination[15] = 2;
+        let pseudo_header = ipv6_pseudo_header(source, destination, 17, 12);
+        let datagram = udp(0, b"ping");
+        let expected = expected(&pseudo_header, &datagram, 6);
+        assert_eq!(udp_status(&datagram, &pseudo_header), ChecksumStatus::Incorrect { expected });
+    }
+
+    #[test]
+    fn a_truncated_datagram_is_unverified() {
+        assert_eq!(ChecksumStatus::verify(&[], &[0x08, 0x00, 0x00], 2), ChecksumStatus::Unverified);
+
+        // The total length claims four more payload bytes than were captured.
+        let datagram = udp(0x1234, b"ping");
+        let mut packet = vec![0x45, 0x00, 0x00, 20 + datagram.len() as u8 + 4, 0x00, 0x00, 0x00, 0x00, 0x40, 0x11, 0x00,
+                              0x00, 10, 0, 0, 1, 10, 0, 0, 2];
+        packet.extend_from_slice(&datagram);
+        let packet = IPacket::parse(&packet).unwrap();
+        assert!(packet.truncated);
+        assert_eq!(packet.datagram.checksum_status(), Some(ChecksumStatus::Unverified));
+    }
+}
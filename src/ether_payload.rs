@@ -0,0 +1,402 @@
+use std::fmt;
+
+use crate::parser::{array, require};
+use crate::{ArpPacket, IPProtocol, IPacket, Ipv6Packet, Layer, ParseError, ProtocolDatagram};
+
+pub const ETHERTYPE_IPV4: u16 = 0x0800;
+pub const ETHERTYPE_ARP: u16 = 0x0806;
+pub const ETHERTYPE_RARP: u16 = 0x8035;
+pub const ETHERTYPE_VLAN: u16 = 0x8100;
+pub const ETHERTYPE_IPV6: u16 = 0x86dd;
+pub const ETHERTYPE_MPLS: u16 = 0x8847;
+pub const ETHERTYPE_PPPOE_SESSION: u16 = 0x8864;
+pub const ETHERTYPE_QINQ: u16 = 0x88a8;
+pub const ETHERTYPE_LLDP: u16 = 0x88cc;
+pub const ETHERTYPE_QINQ_LEGACY: u16 = 0x9100;
+
+/// Returns the name of an EtherType, or an empty string if it is not known.
+pub fn ether_type_name(ether_type: u16) -> &'static str {
+    match ether_type {
+        ETHERTYPE_IPV4 => "IPv4",
+        ETHERTYPE_ARP => "ARP",
+        ETHERTYPE_RARP => "RARP",
+        ETHERTYPE_VLAN => "802.1Q VLAN",
+        ETHERTYPE_IPV6 => "IPv6",
+        0x8809 => "Slow protocols",
+        ETHERTYPE_MPLS => "MPLS",
+        0x8848 => "MPLS multicast",
+        0x8863 => "PPPoE discovery",
+        ETHERTYPE_PPPOE_SESSION => "PPPoE session",
+        0x888e => "802.1X",
+        ETHERTYPE_QINQ => "802.1ad QinQ",
+        ETHERTYPE_LLDP => "LLDP",
+        0x88e5 => "MACsec",
+        0x88f7 => "PTP",
+        ETHERTYPE_QINQ_LEGACY => "QinQ",
+        0..=0x05ff => "802.3 length",
+        _ => "",
+    }
+}
+
+/// The payload of a frame, decoded according to its EtherType.
+pub enum EtherPayload {
+    IPv4(IPacket),
+    IPv6(Ipv6Packet),
+    /// An ARP or RARP packet.
+    Arp(ArpPacket),
+    /// An MPLS label stack, followed by the packet under the bottom label.
+    Mpls { labels: Vec<MplsLabel>, payload: Box<EtherPayload> },
+    /// A PPPoE session header and the PPP frame it carries.
+    PppoeSession { session_id: u16, ppp_protocol: u16, payload: Box<EtherPayload> },
+    Lldp(Vec<LldpTlv>),
+    /// A payload whose EtherType is not decoded. `ether_type` is 0 when the enclosing header
+    /// names its protocol with something other than an EtherType, such as a PPP protocol number.
+    Unknown { ether_type: u16, bytes: Vec<u8> },
+}
+
+impl EtherPayload {
+    /// Parses a payload according to its EtherType.
+    ///
+    /// # Arguments
+    /// * `ether_type` - The EtherType, or protocol type of a cooked capture, naming the payload.
+    /// * `data` - The raw bytes following the link-layer header.
+    ///
+    /// # Errors
+    /// Returns the error of the decoder selected by `ether_type`.
+    pub fn parse(ether_type: u16, data: &[u8]) -> Result<EtherPayload, ParseError> {
+        match ether_type {
+            ETHERTYPE_IPV4 => Ok(EtherPayload::IPv4(IPacket::parse(data)?)),
+            ETHERTYPE_IPV6 => Ok(EtherPayload::IPv6(Ipv6Packet::parse(data)?)),
+            ETHERTYPE_ARP | ETHERTYPE_RARP => Ok(EtherPayload::Arp(ArpPacket::parse(data)?)),
+            ETHERTYPE_MPLS => {
+                let mut labels = vec![];
+                let mut current = 0;
+                loop {
+                    require(data, current + 4, Layer::Mpls)?;
+                    let label = MplsLabel::from(u32::from_be_bytes(array(data, current)));
+                    current += 4;
+                    let bottom = label.bottom_of_stack;
+                    labels.push(label);
+                    if bottom {
+                        break;
+                    }
+                }
+                // MPLS does not name the protocol under the stack, so guess it from the IP version.
+                let inner = match data.get(current).map(|b| b >> 4) {
+                    Some(4) => ETHERTYPE_IPV4,
+                    Some(6) => ETHERTYPE_IPV6,
+                    _ => 0,
+                };
+                Ok(EtherPayload::Mpls { labels, payload: Box::new(EtherPayload::parse(inner, &data[current..])?) })
+            }
+            ETHERTYPE_PPPOE_SESSION => {
+                require(data, 8, Layer::Pppoe)?;
+                let ppp_protocol = u16::from_be_bytes(array(data, 6));
+                let inner = match ppp_protocol {
+                    0x0021 => ETHERTYPE_IPV4,
+                    0x0057 => ETHERTYPE_IPV6,
+                    _ => 0,
+                };
+                Ok(EtherPayload::PppoeSession {
+                    session_id: u16::from_be_bytes(array(data, 2)),
+                    ppp_protocol,
+                    payload: Box::new(EtherPayload::parse(inner, &data[8..])?),
+                })
+            }
+            ETHERTYPE_LLDP => Ok(EtherPayload::Lldp(LldpTlv::parse_all(data)?)),
+            _ => Ok(EtherPayload::Unknown { ether_type, bytes: data.to_vec() }),
+        }
+    }
+
+    /// Returns the network-layer payload, looking through MPLS and PPPoE encapsulation.
+    pub fn network(&self) -> &EtherPayload {
+        match self {
+            EtherPayload::Mpls { payload, .. }
+            | EtherPayload::PppoeSession { payload, .. } => payload.network(),
+            _ => self,
+        }
+    }
+
+    /// Returns the network-layer payload for modification, looking through MPLS and PPPoE.
+    pub fn network_mut(&mut self) -> &mut EtherPayload {
+        match self {
+            EtherPayload::Mpls { payload, .. } | EtherPayload::PppoeSession { payload, .. } => payload.network_mut(),
+            _ => self,
+        }
+    }
+
+    /// Returns the IPv4 packet carried by this payload, if any.
+    pub fn ipv4(&self) -> Option<&IPacket> {
+        match self.network() {
+            EtherPayload::IPv4(packet) => Some(packet),
+            _ => None,
+        }
+    }
+
+    /// Returns the IPv4 packet carried by this payload for modification, if any.
+    pub fn ipv4_mut(&mut self) -> Option<&mut IPacket> {
+        match self.network_mut() {
+            EtherPayload::IPv4(packet) => Some(packet),
+            _ => None,
+        }
+    }
+
+    /// Returns the ARP or RARP packet carried by this payload, if any.
+    pub fn arp(&self) -> Option<&ArpPacket> {
+        match self.network() {
+            EtherPayload::Arp(packet) => Some(packet),
+            _ => None,
+        }
+    }
+
+    /// Returns the IPv6 packet carried by this payload, if any.
+    pub fn ipv6(&self) -> Option<&Ipv6Packet> {
+        match self.network() {
+            EtherPayload::IPv6(packet) => Some(packet),
+            _ => None,
+        }
+    }
+
+    /// Returns the IPv6 packet carried by this payload for modification, if any.
+    pub fn ipv6_mut(&mut self) -> Option<&mut Ipv6Packet> {
+        match self.network_mut() {
+            EtherPayload::IPv6(packet) => Some(packet),
+            _ => None,
+        }
+    }
+
+    /// Returns the upper-layer protocol of the IPv4 or IPv6 packet carried by this payload.
+    pub fn ip_protocol(&self) -> Option<IPProtocol> {
+        match self.network() {
+            EtherPayload::IPv4(packet) => Some(packet.protocol),
+            EtherPayload::IPv6(packet) => Some(packet.protocol),
+            _ => None,
+        }
+    }
+
+    /// Returns the TCP, UDP or ICMP datagram of the IPv4 or IPv6 packet carried by this payload.
+    pub fn datagram(&self) -> Option<&ProtocolDatagram> {
+        match self.network() {
+            EtherPayload::IPv4(packet) => Some(&packet.datagram),
+            EtherPayload::IPv6(packet) => Some(&packet.datagram),
+            _ => None,
+        }
+    }
+
+    /// Returns the TCP, UDP or ICMP datagram of the IPv4 or IPv6 packet carried by this payload
+    /// for modification.
+    pub fn datagram_mut(&mut self) -> Option<&mut ProtocolDatagram> {
+        match self.network_mut() {
+            EtherPayload::IPv4(packet) => Some(&mut packet.datagram),
+            EtherPayload::IPv6(packet) => Some(&mut packet.datagram),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for EtherPayload {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EtherPayload::IPv4(packet) => write!(f, "{}", packet),
+            EtherPayload::IPv6(packet) => write!(f, "{}", packet),
+            EtherPayload::Arp(packet) => write!(f, "{}", packet),
+            EtherPayload::Mpls { labels, payload } => {
+                writeln!(f, "MPLS: -----MPLS Label Stack-----")?;
+                writeln!(f, "MPLS:")?;
+                for label in labels {
+                    writeln!(f, "MPLS: Label = {}, Traffic class = {}, Bottom of stack = {}, TTL = {}", label.label,
+                             label.traffic_class, label.bottom_of_stack as u8, label.ttl)?;
+                }
+                writeln!(f, "MPLS:")?;
+                write!(f, "{}", payload)
+            }
+            EtherPayload::PppoeSession { session_id, ppp_protocol, payload } => {
+                writeln!(f, "PPPoE: -----PPPoE Session Header-----")?;
+                writeln!(f, "PPPoE:")?;
+                writeln!(f, "PPPoE: Session ID   = 0x{:04x}", session_id)?;
+                writeln!(f, "PPPoE: PPP protocol = 0x{:04x}", ppp_protocol)?;
+                writeln!(f, "PPPoE:")?;
+                write!(f, "{}", payload)
+            }
+            EtherPayload::Lldp(tlvs) => {
+                writeln!(f, "LLDP: -----LLDP Data Unit-----")?;
+                writeln!(f, "LLDP:")?;
+                for tlv in tlvs {
+                    writeln!(f, "LLDP: {}", tlv)?;
+                }
+                write!(f, "LLDP:")
+            }
+            EtherPayload::Unknown { ether_type, bytes } => {
+                write!(f, "Unsupported EtherType 0x{:04x}, {} bytes not decoded", ether_type, bytes.len())
+            }
+        }
+    }
+}
+
+/// One entry of an MPLS label stack.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MplsLabel {
+    pub label: u32,
+    pub traffic_class: u8,
+    pub bottom_of_stack: bool,
+    pub ttl: u8,
+}
+
+impl From<u32> for MplsLabel {
+    fn from(entry: u32) -> MplsLabel {
+        MplsLabel {
+            label: entry >> 12,
+            traffic_class: ((entry >> 9) & 0x7) as u8,
+            bottom_of_stack: (entry >> 8) & 1 == 1,
+            ttl: entry as u8,
+        }
+    }
+}
+
+/// A type-length-value entry of an LLDP data unit.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LldpTlv {
+    pub tlv_type: u8,
+    pub value: Vec<u8>,
+}
+
+impl LldpTlv {
+    /// Parses the TLVs of an LLDP data unit up to the End of LLDPDU TLV.
+    ///
+    /// # Errors
+    /// Returns `ParseError::Truncated` if a TLV runs past the end of `data`.
+    pub fn parse_all(data: &[u8]) -> Result<Vec<LldpTlv>, ParseError> {
+        let mut tlvs = vec![];
+        let mut current = 0;
+        while current < data.len() {
+            require(data, current + 2, Layer::Lldp)?;
+            let header = u16::from_be_bytes(array(data, current));
+            let tlv_type = (header >> 9) as u8;
+            let length = (header & 0x01ff) as usize;
+            require(data, current + 2 + length, Layer::Lldp)?;
+            if tlv_type == 0 {
+                break;
+            }
+            tlvs.push(LldpTlv { tlv_type, value: data[current + 2..current + 2 + length].to_vec() });
+            current += 2 + length;
+        }
+        Ok(tlvs)
+    }
+}
+
+impl fmt::Display for LldpTlv {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = |bytes: &[u8]| String::from_utf8_lossy(bytes).into_owned();
+        match self.tlv_type {
+            1 | 2 if !self.value.is_empty() => {
+                // Chassis and port IDs start with a subtype, and MAC address subtypes are shown as hex.
+                let (name, mac_subtype) = if self.tlv_type == 1 { ("Chassis ID", 4) } else { ("Port ID", 3) };
+                if self.value[0] == mac_subtype && self.value.len() == 7 {
+                    let mac: Vec<String> = self.value[1..].iter().map(|b| format!("{:x}", b)).collect();
+                    write!(f, "{:<18} = {}", name, mac.join(":"))
+                } else {
+                    write!(f, "{:<18} = {}", name, text(&self.value[1..]))
+                }
+            }
+            3 if self.value.len() == 2 => {
+                write!(f, "Time to live       = {} seconds", u16::from_be_bytes(array(&self.value, 0)))
+            }
+            4 => write!(f, "Port description   = {}", text(&self.value)),
+            5 => write!(f, "System name        = {}", text(&self.value)),
+            6 => write!(f, "System description = {}", text(&self.value)),
+            7 if self.value.len() == 4 => {
+                write!(f, "Capabilities       = 0x{:04x}, enabled 0x{:04x}", u16::from_be_bytes(array(&self.value, 0)),
+                       u16::from_be_bytes(array(&self.value, 2)))
+            }
+            127 if self.value.len() >= 4 => {
+                write!(f, "Organization specific, OUI {:02x}:{:02x}:{:02x} subtype {}, {} bytes", self.value[0],
+                       self.value[1], self.value[2], self.value[3], self.value.len() - 4)
+            }
+            _ => write!(f, "TLV type {:<9} = {} bytes", self.tlv_type, self.value.len()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 28-byte IPv4 packet carrying an empty UDP datagram.
+    const PACKET: [u8; 28] = [0x45, 0x00, 0x00, 0x1c, 0x00, 0x01, 0x00, 0x00, 0x40, 0x11, 0x00, 0x00, 10, 0, 0, 1, 10, 0,
+                              0, 2, 0x04, 0x00, 0x00, 0x35, 0x00, 0x08, 0x00, 0x00];
+
+    #[test]
+    fn mpls_stacks_end_at_the_bottom_label() {
+        // Label 16 with traffic class 5, then label 18 at the bottom of the stack.
+        let data = [[0x00, 0x01, 0x0a, 0xff].as_slice(), &[0x00, 0x01, 0x21, 0x40], &PACKET].concat();
+        let payload = EtherPayload::parse(ETHERTYPE_MPLS, &data).unwrap();
+        let EtherPayload::Mpls { labels, .. } = &payload else { panic!("not MPLS") };
+        assert_eq!(labels, &[
+            MplsLabel { label: 16, traffic_class: 5, bottom_of_stack: false, ttl: 255 },
+            MplsLabel { label: 18, traffic_class: 0, bottom_of_stack: true, ttl: 64 },
+        ]);
+        assert_eq!(payload.ipv4().map(|packet| packet.destination_add), Some([10, 0, 0, 2]));
+
+        // Nothing names what is under the stack but the IP version.
+        let data = [0x00, 0x01, 0x01, 0x40, 0xaa, 0xbb];
+        let payload = EtherPayload::parse(ETHERTYPE_MPLS, &data).unwrap();
+        assert!(matches!(payload.network(), EtherPayload::Unknown { ether_type: 0, bytes } if bytes == &[0xaa, 0xbb]));
+
+        // A stack without a bottom label runs off the end of the frame.
+        let error = EtherPayload::parse(ETHERTYPE_MPLS, &[0x00, 0x01, 0x00, 0x40]).err();
+        assert!(matches!(error, Some(ParseError::Truncated { layer: Layer::Mpls, needed: 8, available: 4 })));
+    }
+
+    #[test]
+    fn pppoe_sessions_carry_ppp_protocols() {
+        let data = [[0x11, 0x00, 0x12, 0x34, 0x00, 0x1e, 0x00, 0x21].as_slice(), &PACKET].concat();
+        let payload = EtherPayload::parse(ETHERTYPE_PPPOE_SESSION, &data).unwrap();
+        assert!(matches!(payload, EtherPayload::PppoeSession { session_id: 0x1234, ppp_protocol: 0x0021, .. }));
+        assert!(payload.ipv4().is_some());
+
+        // LCP is not an IP protocol, so it is left undecoded.
+        let data = [0x11, 0x00, 0x12, 0x34, 0x00, 0x02, 0xc0, 0x21, 0x09, 0x01];
+        let payload = EtherPayload::parse(ETHERTYPE_PPPOE_SESSION, &data).unwrap();
+        assert!(payload.ipv4().is_none());
+        assert!(matches!(payload.network(), EtherPayload::Unknown { ether_type: 0, .. }));
+
+        let error = EtherPayload::parse(ETHERTYPE_PPPOE_SESSION, &data[..7]).err();
+        assert!(matches!(error, Some(ParseError::Truncated { layer: Layer::Pppoe, .. })));
+    }
+
+    #[test]
+    fn lldp_tlvs_are_read_up_to_the_end_tlv() {
+        let data = [
+            [0x02, 0x07, 0x04, 0x02, 0, 0, 0, 0, 1].as_slice(),
+            &[0x04, 0x05, 0x05, b'e', b't', b'h', b'0'],
+            &[0x06, 0x02, 0x00, 0x78],
+            &[0x0a, 0x03, b's', b'w', b'1'],
+            &[0x00, 0x00],
+            // Padding after the end TLV is ignored.
+            &[0xff, 0xff],
+        ].concat();
+        let payload = EtherPayload::parse(ETHERTYPE_LLDP, &data).unwrap();
+        let EtherPayload::Lldp(tlvs) = payload else { panic!("not LLDP") };
+        let lines: Vec<String> = tlvs.iter().map(LldpTlv::to_string).collect();
+        assert_eq!(lines, [
+            "Chassis ID         = 2:0:0:0:0:1",
+            "Port ID            = eth0",
+            "Time to live       = 120 seconds",
+            "System name        = sw1",
+        ]);
+
+        // A TLV longer than what is left of the frame is an error.
+        assert!(matches!(LldpTlv::parse_all(&[0x0a, 0x05, b's']), Err(ParseError::Truncated { layer: Layer::Lldp, .. })));
+    }
+
+    #[test]
+    fn other_ether_types_are_kept_as_bytes() {
+        let payload = EtherPayload::parse(0x88b5, &[1, 2, 3]).unwrap();
+        assert!(matches!(&payload, EtherPayload::Unknown { ether_type: 0x88b5, bytes } if bytes == &[1, 2, 3]));
+        assert!(payload.ipv4().is_none());
+        assert_eq!(payload.to_string(), "Unsupported EtherType 0x88b5, 3 bytes not decoded");
+        assert_eq!(ether_type_name(ETHERTYPE_LLDP), "LLDP");
+        assert_eq!(ether_type_name(0x05dc), "802.3 length");
+        assert_eq!(ether_type_name(0x88b5), "");
+    }
+}
@@ -1,40 +1,137 @@
 use std::fmt;
-use crate::{IPacket, IPVersion};
+use crate::ether_payload::ether_type_name;
+use crate::{CookedHeader, EtherPayload, IPacket, Ipv6Packet, LinkType};
 
-pub(crate) struct EthernetFrame {
-    pub(crate) packet_size: u32,
-    pub(crate) destination_address: [u8; 6],
-    pub(crate) source_address: [u8; 6],
-    pub(crate) ether_type: [u8; 2],
-    pub(crate) version: IPVersion,
-    pub(crate) packet: IPacket,
+pub struct EthernetFrame {
+    pub packet_size: u32,
+    pub link_type: LinkType,
+    /// Header of a Linux cooked capture, which replaces the Ethernet addresses.
+    pub cooked: Option<CookedHeader>,
+    pub destination_address: [u8; 6],
+    pub source_address: [u8; 6],
+    /// VLAN tags in the order they appear, the outer (service) tag first.
+    pub vlan_tags: Vec<VlanTag>,
+    /// EtherType of the payload, following any VLAN tags.
+    pub ether_type: [u8; 2],
+    pub payload: EtherPayload,
+}
+
+/// An 802.1Q or 802.1ad VLAN tag.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct VlanTag {
+    /// Tag protocol identifier: 0x8100 for 802.1Q, 0x88a8 or 0x9100 for a QinQ service tag.
+    pub tpid: u16,
+    /// Priority code point.
+    pub priority: u8,
+    pub drop_eligible: bool,
+    pub vlan_id: u16,
+}
+
+impl VlanTag {
+    /// Splits the tag control information of a tag with the given protocol identifier.
+    pub fn from_tci(tpid: u16, tci: u16) -> VlanTag {
+        VlanTag {
+            tpid,
+            priority: (tci >> 13) as u8,
+            drop_eligible: (tci >> 12) & 1 == 1,
+            vlan_id: tci & 0x0fff,
+        }
+    }
+}
+
+impl fmt::Display for VlanTag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "VID {}, PCP {}, DEI {} ({})", self.vlan_id, self.priority, self.drop_eligible as u8,
+               ether_type_name(self.tpid))
+    }
+}
+
+impl Default for EthernetFrame {
+    fn default() -> Self {
+        EthernetFrame::new()
+    }
 }
 
 impl EthernetFrame {
-    pub(crate) fn new() -> EthernetFrame {
+    pub fn new() -> EthernetFrame {
         EthernetFrame {
             packet_size: 0,
+            link_type: LinkType::Ethernet,
+            cooked: None,
             destination_address: [0; 6],
             source_address: [0; 6],
+            vlan_tags: vec![],
             ether_type: [0; 2],
-            version: IPVersion::V4,
-            packet: IPacket::new(),
+            payload: EtherPayload::Unknown { ether_type: 0, bytes: vec![] },
         }
     }
+
+    /// Returns the IPv4 packet carried by the frame, if any.
+    pub fn ipv4(&self) -> Option<&IPacket> {
+        self.payload.ipv4()
+    }
+
+    /// Returns the IPv6 packet carried by the frame, if any.
+    pub fn ipv6(&self) -> Option<&Ipv6Packet> {
+        self.payload.ipv6()
+    }
 }
 
 impl fmt::Display for EthernetFrame {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
-        write!(f, "ETHER: -----Ether Header-----\n")?;
-        write!(f, "ETHER:\n")?;
-        write!(f, "ETHER: Packet size= {} bytes\n", self.packet_size)?;
-        write!(f, "ETHER: Destination= {:x}:{:x}:{:x}:{:x}:{:x}:{:x}\n", self.destination_address[0],
+        if self.link_type != LinkType::Ethernet {
+            writeln!(f, "ETHER: -----Link Header-----")?;
+            writeln!(f, "ETHER:")?;
+            writeln!(f, "ETHER: Packet size= {} bytes", self.packet_size)?;
+            writeln!(f, "ETHER: Link type  = {}", self.link_type)?;
+            if let Some(cooked) = &self.cooked {
+                let direction = match cooked.packet_type {
+                    0 => "to us",
+                    1 => "broadcast",
+                    2 => "multicast",
+                    3 => "to another host",
+                    4 => "sent by us",
+                    _ => "unknown",
+                };
+                writeln!(f, "ETHER: Packet type= {} ({})", cooked.packet_type, direction)?;
+                let source: Vec<String> = cooked.source_address.iter().map(|b| format!("{:x}", b)).collect();
+                writeln!(f, "ETHER: Source     = {}", source.join(":"))?;
+                if let Some(index) = cooked.interface_index {
+                    writeln!(f, "ETHER: Interface  = {}", index)?;
+                }
+            }
+            for tag in &self.vlan_tags {
+                writeln!(f, "ETHER: VLAN tag   = {}", tag)?;
+            }
+            writeln!(f, "ETHER: Ethertype  = {}", EtherTypeDisplay(self.ether_type))?;
+            return write!(f, "ETHER:");
+        }
+        writeln!(f, "ETHER: -----Ether Header-----")?;
+        writeln!(f, "ETHER:")?;
+        writeln!(f, "ETHER: Packet size= {} bytes", self.packet_size)?;
+        writeln!(f, "ETHER: Destination= {:x}:{:x}:{:x}:{:x}:{:x}:{:x}", self.destination_address[0],
                self.destination_address[1], self.destination_address[2], self.destination_address[3],
                self.destination_address[4], self.destination_address[5])?;
-        write!(f, "ETHER: Source     = {:x}:{:x}:{:x}:{:x}:{:x}:{:x}\n", self.source_address[0],
+        writeln!(f, "ETHER: Source     = {:x}:{:x}:{:x}:{:x}:{:x}:{:x}", self.source_address[0],
                self.source_address[1], self.source_address[2], self.source_address[3],
                self.source_address[4], self.source_address[5])?;
-        write!(f, "ETHER: Ethertype  = 0x{:x}{:x}\n", self.ether_type[0], self.ether_type[1])?;
+        for tag in &self.vlan_tags {
+            writeln!(f, "ETHER: VLAN tag   = {}", tag)?;
+        }
+        writeln!(f, "ETHER: Ethertype  = {}", EtherTypeDisplay(self.ether_type))?;
         write!(f, "ETHER:")
     }
-}
\ No newline at end of file
+}
+
+/// Formats an EtherType with its name, as in `0x0800 (IPv4)`.
+struct EtherTypeDisplay([u8; 2]);
+
+impl fmt::Display for EtherTypeDisplay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ether_type = u16::from_be_bytes(self.0);
+        match ether_type_name(ether_type) {
+            "" => write!(f, "0x{:04x}", ether_type),
+            name => write!(f, "0x{:04x} ({})", ether_type, name),
+        }
+    }
+}
@@ -0,0 +1,1079 @@
+use std::error::Error;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr};
+use std::str::FromStr;
+
+use crate::{EthernetFrame, IPProtocol, ProtocolDatagram, TcpFlags};
+
+/// Which end of a packet an address or port is compared with, as the `src` and `dst` qualifiers
+/// of a primitive choose.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EndpointQualifier {
+    Src,
+    Dst,
+    /// Either end, as when no qualifier or `src or dst` is given.
+    SrcOrDst,
+    /// Both ends, as `src and dst` asks.
+    SrcAndDst,
+}
+
+impl EndpointQualifier {
+    /// Returns whether the source, the destination or both pass `test`, as the qualifier asks.
+    fn test<T>(&self, source: T, destination: T, test: impl Fn(T) -> bool) -> bool {
+        match self {
+            EndpointQualifier::Src => test(source),
+            EndpointQualifier::Dst => test(destination),
+            EndpointQualifier::SrcOrDst => test(source) || test(destination),
+            EndpointQualifier::SrcAndDst => test(source) && test(destination),
+        }
+    }
+}
+
+impl fmt::Display for EndpointQualifier {
+    /// Formats the qualifier as it is written before a primitive, followed by a space, or as
+    /// nothing for the default of either end.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EndpointQualifier::Src => write!(f, "src "),
+            EndpointQualifier::Dst => write!(f, "dst "),
+            EndpointQualifier::SrcOrDst => Ok(()),
+            EndpointQualifier::SrcAndDst => write!(f, "src and dst "),
+        }
+    }
+}
+
+/// Enum representing the primitive filters that can be applied to network packets, the operands
+/// of a `FilterExpr`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Filter {
+    Host(EndpointQualifier, IpAddr),
+    Port(EndpointQualifier, u16),
+    /// TCP or UDP ports from the first to the second, both included.
+    PortRange(EndpointQualifier, u16, u16),
+    Ip,
+    Ip6,
+    Arp,
+    Tcp,
+    Udp,
+    /// ICMP over IPv4.
+    Icmp,
+    /// ICMPv6, over IPv6.
+    Icmp6,
+    /// Packets with an IPv4 or IPv6 address inside the network.
+    Net(EndpointQualifier, IpNetwork),
+    /// Frames with a VLAN tag, or with a tag of the given VLAN ID.
+    Vlan(Option<u16>),
+    /// TCP segments with any of the given flags set.
+    TcpFlags(TcpFlags),
+    /// Packets with an IPv4 header or datagram checksum that was verified and found wrong.
+    BadChecksum,
+}
+
+impl Filter {
+    /// Returns whether the given frame passes this filter.
+    ///
+    /// # Arguments
+    /// * `frame` - The decoded frame to test.
+    pub fn matches(&self, frame: &EthernetFrame) -> bool {
+        match self {
+            Filter::Host(qualifier, address) => {
+                addresses(frame).is_some_and(|(source, destination)| {
+                    qualifier.test(source, destination, |end| end == *address)
+                })
+            }
+            Filter::Net(qualifier, network) => {
+                addresses(frame).is_some_and(|(source, destination)| {
+                    qualifier.test(source, destination, |end| network.contains(end))
+                })
+            }
+            Filter::Port(qualifier, port) => {
+                ports(frame).is_some_and(|(source, destination)| qualifier.test(source, destination, |end| end == *port))
+            }
+            Filter::PortRange(qualifier, first, last) => {
+                ports(frame).is_some_and(|(source, destination)| {
+                    qualifier.test(source, destination, |end| (*first..=*last).contains(&end))
+                })
+            }
+            Filter::Vlan(vlan_id) => frame.vlan_tags.iter().any(|tag| vlan_id.is_none_or(|id| tag.vlan_id == id)),
+            Filter::Ip => frame.ipv4().is_some(),
+            Filter::Ip6 => frame.ipv6().is_some(),
+            Filter::Arp => frame.payload.arp().is_some(),
+            Filter::Tcp => frame.payload.ip_protocol() == Some(IPProtocol::TCP),
+            Filter::TcpFlags(mask) => {
+                matches!(frame.payload.datagram(), Some(ProtocolDatagram::TCP(tcp)) if tcp.flags.intersects(*mask))
+            }
+            Filter::Udp => frame.payload.ip_protocol() == Some(IPProtocol::UDP),
+            Filter::Icmp => frame.ipv4().is_some_and(|packet| packet.protocol == IPProtocol::ICMP),
+            Filter::Icmp6 => frame.ipv6().is_some_and(|packet| packet.protocol == IPProtocol::ICMPv6),
+            Filter::BadChecksum => {
+                let datagram_status = frame.payload.datagram().and_then(ProtocolDatagram::checksum_status);
+                frame.ipv4().is_some_and(|packet| packet.header_checksum_status.is_incorrect())
+                    || datagram_status.is_some_and(|status| status.is_incorrect())
+            }
+        }
+    }
+}
+
+impl fmt::Display for Filter {
+    /// Formats the filter in the syntax it is parsed from.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Filter::Host(qualifier, address) => write!(f, "{}host {}", qualifier, address),
+            Filter::Port(qualifier, port) => write!(f, "{}port {}", qualifier, port),
+            Filter::PortRange(qualifier, first, last) => write!(f, "{}portrange {}-{}", qualifier, first, last),
+            Filter::Ip => write!(f, "ip"),
+            Filter::Ip6 => write!(f, "ip6"),
+            Filter::Arp => write!(f, "arp"),
+            Filter::Tcp => write!(f, "tcp"),
+            Filter::Udp => write!(f, "udp"),
+            Filter::Icmp => write!(f, "icmp"),
+            Filter::Icmp6 => write!(f, "icmp6"),
+            Filter::Net(qualifier, network) => write!(f, "{}net {}", qualifier, network),
+            Filter::Vlan(None) => write!(f, "vlan"),
+            Filter::Vlan(Some(vlan_id)) => write!(f, "vlan {}", vlan_id),
+            Filter::TcpFlags(mask) => {
+                let names: Vec<String> = TcpFlags::ALL
+                    .iter()
+                    .filter(|(flag, _)| mask.contains(*flag))
+                    .map(|(_, name)| name.to_ascii_lowercase())
+                    .collect();
+                write!(f, "tcp[tcpflags] & ({}) != 0", names.join("|"))
+            }
+            Filter::BadChecksum => write!(f, "bad-checksum"),
+        }
+    }
+}
+
+/// An IPv4 or IPv6 network, given by its address and the length of its prefix.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct IpNetwork {
+    address: IpAddr,
+    prefix_length: u8,
+}
+
+impl IpNetwork {
+    /// Returns the network with the given address and prefix length, or `None` when the prefix is
+    /// longer than the address or the address has bits set past the prefix. As in tcpdump, such an
+    /// address is rejected rather than masked, since `10.1.2.3/8` is more likely a typo than a way
+    /// of writing `10.0.0.0/8`.
+    pub fn new(address: IpAddr, prefix_length: u8) -> Option<IpNetwork> {
+        let (bits, width) = address_bits(address);
+        if prefix_length > width || bits & !prefix_mask(width, prefix_length) != 0 {
+            return None;
+        }
+        Some(IpNetwork { address, prefix_length })
+    }
+
+    pub fn address(&self) -> IpAddr {
+        self.address
+    }
+
+    pub fn prefix_length(&self) -> u8 {
+        self.prefix_length
+    }
+
+    /// Returns whether the address is inside the network. An IPv4 address is never inside an IPv6
+    /// network, nor the other way round: an IPv4-mapped IPv6 address such as `::ffff:10.0.0.1` is
+    /// only inside IPv6 networks, like `::ffff:0:0/96`.
+    pub fn contains(&self, address: IpAddr) -> bool {
+        let (network, width) = address_bits(self.address);
+        let (bits, address_width) = address_bits(address);
+        width == address_width && bits & prefix_mask(width, self.prefix_length) == network
+    }
+}
+
+impl fmt::Display for IpNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.address, self.prefix_length)
+    }
+}
+
+/// Returns the bits of an address, with its width: 32 for IPv4 and 128 for IPv6.
+fn address_bits(address: IpAddr) -> (u128, u8) {
+    match address {
+        IpAddr::V4(address) => (u32::from(address) as u128, 32),
+        IpAddr::V6(address) => (u128::from(address), 128),
+    }
+}
+
+/// Returns the mask of the first `prefix_length` bits of an address `width` bits wide.
+fn prefix_mask(width: u8, prefix_length: u8) -> u128 {
+    let address = u128::MAX >> (128 - width as u32);
+    let host = width - prefix_length;
+    if host == 0 {
+        address
+    } else {
+        address & !(u128::MAX >> (128 - host as u32))
+    }
+}
+
+/// Returns the source and destination addresses of the frame's IPv4 or IPv6 header.
+fn addresses(frame: &EthernetFrame) -> Option<(IpAddr, IpAddr)> {
+    if let Some(packet) = frame.ipv4() {
+        Some((packet.source_add.into(), packet.destination_add.into()))
+    } else {
+        frame.ipv6().map(|packet| (packet.source_add.into(), packet.destination_add.into()))
+    }
+}
+
+/// Returns the source and destination ports of the frame's TCP segment or UDP datagram.
+fn ports(frame: &EthernetFrame) -> Option<(u16, u16)> {
+    match frame.payload.datagram()? {
+        ProtocolDatagram::TCP(tcp) => Some((u16::from_be_bytes(tcp.source_port), u16::from_be_bytes(tcp.destination_port))),
+        ProtocolDatagram::UDP(udp) => Some((u16::from_be_bytes(udp.source_port), u16::from_be_bytes(udp.destination_port))),
+        _ => None,
+    }
+}
+
+/// A filter expression in the style of tcpdump: primitives combined with `and`, `or` and `not`,
+/// grouped by parentheses.
+///
+/// As in tcpdump, `not` binds tightest, and `and` and `or` have the same precedence and group
+/// from left to right, so `a or b and c` means `(a or b) and c`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterExpr {
+    Primitive(Filter),
+    Not(Box<FilterExpr>),
+    And(Box<FilterExpr>, Box<FilterExpr>),
+    Or(Box<FilterExpr>, Box<FilterExpr>),
+}
+
+impl FilterExpr {
+    /// Parses a filter expression, such as `tcp and port 443 and not host 10.0.0.1`.
+    ///
+    /// Besides the primitives of `Filter`, an expression may qualify `host`, `net`, `port` and
+    /// `portrange` with `src`, `dst`, `src or dst` or `src and dst`, and `port` and `portrange`
+    /// with `tcp` or `udp`. `&&`, `||` and `!` may be written for `and`, `or` and `not`, and a
+    /// bare value repeats the primitive before it, as in `port 80 or 443`.
+    ///
+    /// # Errors
+    /// Returns a `FilterError` giving the position of the first token that does not fit the
+    /// syntax.
+    pub fn parse(expression: &str) -> Result<FilterExpr, FilterError> {
+        let tokens = tokenize(expression)?;
+        let mut parser = Parser { tokens, next: 0, end: expression.len(), last: None };
+        let filter = parser.expression()?;
+        match parser.peek() {
+            None => Ok(filter),
+            Some(Token::RightParen) => Err(parser.error("unmatched `)`")),
+            Some(_) => Err(parser.error(&format!("expected `and` or `or`, found {}", parser.describe()))),
+        }
+    }
+
+    /// Returns whether the given frame passes this expression.
+    ///
+    /// # Arguments
+    /// * `frame` - The decoded frame to test.
+    pub fn matches(&self, frame: &EthernetFrame) -> bool {
+        match self {
+            FilterExpr::Primitive(filter) => filter.matches(frame),
+            FilterExpr::Not(operand) => !operand.matches(frame),
+            FilterExpr::And(left, right) => left.matches(frame) && right.matches(frame),
+            FilterExpr::Or(left, right) => left.matches(frame) || right.matches(frame),
+        }
+    }
+
+    /// Formats an operand of `not`, `and` or `or`, in parentheses unless it is a primitive, a
+    /// negation, or the left operand of the same operator.
+    fn fmt_operand(&self, f: &mut fmt::Formatter, same_operator: bool) -> fmt::Result {
+        match self {
+            FilterExpr::And(..) | FilterExpr::Or(..) if !same_operator => write!(f, "({})", self),
+            _ => write!(f, "{}", self),
+        }
+    }
+}
+
+impl FromStr for FilterExpr {
+    type Err = FilterError;
+
+    fn from_str(expression: &str) -> Result<FilterExpr, FilterError> {
+        FilterExpr::parse(expression)
+    }
+}
+
+impl fmt::Display for FilterExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FilterExpr::Primitive(filter) => write!(f, "{}", filter),
+            FilterExpr::Not(operand) => {
+                write!(f, "not ")?;
+                operand.fmt_operand(f, false)
+            }
+            FilterExpr::And(left, right) => {
+                left.fmt_operand(f, matches!(**left, FilterExpr::And(..)))?;
+                write!(f, " and ")?;
+                right.fmt_operand(f, false)
+            }
+            FilterExpr::Or(left, right) => {
+                left.fmt_operand(f, matches!(**left, FilterExpr::Or(..)))?;
+                write!(f, " or ")?;
+                right.fmt_operand(f, false)
+            }
+        }
+    }
+}
+
+/// Error returned when a filter expression cannot be parsed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FilterError {
+    /// Byte offset in the expression of the token the error was found at; the length of the
+    /// expression when it ended too early.
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl Error for FilterError {}
+
+/// A token of a filter expression.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    /// A primitive name, qualifier, address or number.
+    Word(String),
+    LeftParen,
+    RightParen,
+    And,
+    Or,
+    Not,
+    /// `&` of a `tcp[flags]` test.
+    Ampersand,
+    /// `|` between the flags of a `tcp[flags]` test.
+    Pipe,
+    /// `!=` of a `tcp[flags]` test.
+    NotEqual,
+}
+
+/// Splits an expression into tokens, each with its byte offset.
+fn tokenize(expression: &str) -> Result<Vec<(Token, usize)>, FilterError> {
+    let is_word = |c: char| c.is_ascii_alphanumeric() || ".:-_[]/".contains(c);
+    let mut tokens = vec![];
+    let mut chars = expression.char_indices().peekable();
+    while let Some((position, c)) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::LeftParen,
+            ')' => Token::RightParen,
+            '&' if chars.next_if(|(_, c)| *c == '&').is_some() => Token::And,
+            '&' => Token::Ampersand,
+            '|' if chars.next_if(|(_, c)| *c == '|').is_some() => Token::Or,
+            '|' => Token::Pipe,
+            '!' if chars.next_if(|(_, c)| *c == '=').is_some() => Token::NotEqual,
+            '!' => Token::Not,
+            c if is_word(c) => {
+                let mut end = position + c.len_utf8();
+                while let Some((next, c)) = chars.next_if(|(_, c)| is_word(*c)) {
+                    end = next + c.len_utf8();
+                }
+                match &expression[position..end] {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    word => Token::Word(word.to_string()),
+                }
+            }
+            c => return Err(FilterError { position, message: format!("unexpected character `{}`", c) }),
+        };
+        tokens.push((token, position));
+    }
+    Ok(tokens)
+}
+
+/// The kind of primitive that takes a value, which a bare value after it repeats.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum ValueKind {
+    Host,
+    Net,
+    Port,
+    PortRange,
+}
+
+impl ValueKind {
+    fn from_name(name: &str) -> Option<ValueKind> {
+        match name {
+            "host" => Some(ValueKind::Host),
+            "net" => Some(ValueKind::Net),
+            "port" => Some(ValueKind::Port),
+            "portrange" => Some(ValueKind::PortRange),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            ValueKind::Host => "host",
+            ValueKind::Net => "net",
+            ValueKind::Port => "port",
+            ValueKind::PortRange => "portrange",
+        }
+    }
+}
+
+/// The qualifiers and kind of a primitive that takes a value: `tcp src port` in `tcp src port 80`.
+#[derive(Clone, Debug)]
+struct ValueTemplate {
+    protocol: Option<Filter>,
+    qualifier: EndpointQualifier,
+    kind: ValueKind,
+}
+
+/// A recursive descent parser over the tokens of an expression.
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    next: usize,
+    /// Length of the expression, the position reported when it ends too early.
+    end: usize,
+    /// The last primitive that took a value, which a bare value repeats.
+    last: Option<ValueTemplate>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(token, _)| token)
+    }
+
+    fn peek_word(&self, offset: usize) -> Option<&str> {
+        match self.tokens.get(self.next + offset) {
+            Some((Token::Word(word), _)) => Some(word),
+            _ => None,
+        }
+    }
+
+    fn advance(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.next).map(|(token, _)| token.clone());
+        self.next += 1;
+        token
+    }
+
+    /// Returns an error at the next token.
+    fn error(&self, message: &str) -> FilterError {
+        let position = self.tokens.get(self.next).map_or(self.end, |(_, position)| *position);
+        FilterError { position, message: message.to_string() }
+    }
+
+    /// Describes the next token for an error message.
+    fn describe(&self) -> String {
+        match self.peek() {
+            None => "end of expression".to_string(),
+            Some(Token::Word(word)) => format!("`{}`", word),
+            Some(Token::LeftParen) => "`(`".to_string(),
+            Some(Token::RightParen) => "`)`".to_string(),
+            Some(Token::And) => "`and`".to_string(),
+            Some(Token::Or) => "`or`".to_string(),
+            Some(Token::Not) => "`not`".to_string(),
+            Some(Token::Ampersand) => "`&`".to_string(),
+            Some(Token::Pipe) => "`|`".to_string(),
+            Some(Token::NotEqual) => "`!=`".to_string(),
+        }
+    }
+
+    /// expression = unary { ("and" | "or") unary }
+    fn expression(&mut self) -> Result<FilterExpr, FilterError> {
+        let mut left = self.unary()?;
+        loop {
+            left = match self.peek() {
+                Some(Token::And) => {
+                    self.advance();
+                    FilterExpr::And(Box::new(left), Box::new(self.unary()?))
+                }
+                Some(Token::Or) => {
+                    self.advance();
+                    FilterExpr::Or(Box::new(left), Box::new(self.unary()?))
+                }
+                _ => return Ok(left),
+            };
+        }
+    }
+
+    /// unary = "not" unary | "(" expression ")" | primitive
+    fn unary(&mut self) -> Result<FilterExpr, FilterError> {
+        match self.peek() {
+            Some(Token::Not) => {
+                self.advance();
+                Ok(FilterExpr::Not(Box::new(self.unary()?)))
+            }
+            Some(Token::LeftParen) => {
+                self.advance();
+                let expression = self.expression()?;
+                if self.peek() != Some(&Token::RightParen) {
+                    return Err(self.error(&format!("expected `)`, found {}", self.describe())));
+                }
+                self.advance();
+                Ok(expression)
+            }
+            Some(Token::Word(_)) => self.primitive(),
+            _ => Err(self.error(&format!("expected a filter, found {}", self.describe()))),
+        }
+    }
+
+    fn primitive(&mut self) -> Result<FilterExpr, FilterError> {
+        let word = self.peek_word(0).unwrap_or_default().to_string();
+        let filter = match word.as_str() {
+            "tcp[flags]" | "tcp[tcpflags]" => {
+                self.advance();
+                return self.flags_test().map(|mask| FilterExpr::Primitive(Filter::TcpFlags(mask)));
+            }
+            "tcp" | "udp" if self.peek_word(1).is_some_and(|next| next.starts_with("port")
+                || next == "src" || next == "dst") => {
+                self.advance();
+                let protocol = if word == "tcp" { Filter::Tcp } else { Filter::Udp };
+                return self.qualified(Some(protocol));
+            }
+            "src" | "dst" | "host" | "net" | "port" | "portrange" => return self.qualified(None),
+            "ip" => Filter::Ip,
+            "ip6" => Filter::Ip6,
+            "arp" => Filter::Arp,
+            "tcp" => Filter::Tcp,
+            "udp" => Filter::Udp,
+            "icmp" => Filter::Icmp,
+            "icmp6" => Filter::Icmp6,
+            "bad-checksum" => Filter::BadChecksum,
+            "vlan" => {
+                self.advance();
+                // The VLAN ID is optional, but a number after `vlan` has to be one.
+                if !self.peek_word(0).is_some_and(|id| id.starts_with(|c: char| c.is_ascii_digit())) {
+                    return Ok(FilterExpr::Primitive(Filter::Vlan(None)));
+                }
+                let vlan_id = self.peek_word(0).and_then(|id| u16::from_str(id).ok()).filter(|id| *id < 4096);
+                let vlan_id = vlan_id.ok_or_else(|| self.error(&format!("expected a VLAN ID after `vlan`, found {}",
+                                                                        self.describe())))?;
+                Filter::Vlan(Some(vlan_id))
+            }
+            name if name.starts_with("tcp-") && TcpFlags::from_name(name).is_some() => {
+                Filter::TcpFlags(TcpFlags::from_name(name).unwrap_or_default())
+            }
+            value if value.starts_with(|c: char| c.is_ascii_digit()) || value.contains(':') => {
+                // A bare value repeats the primitive before it.
+                let template = self.last.clone().ok_or_else(|| self.error(&format!("unknown filter `{}`", value)))?;
+                return self.value(template);
+            }
+            name => return Err(self.error(&format!("unknown filter `{}`", name))),
+        };
+        self.advance();
+        Ok(FilterExpr::Primitive(filter))
+    }
+
+    /// Parses a `host`, `net`, `port` or `portrange` primitive with its `src` or `dst` qualifier,
+    /// the protocol qualifier having been read already.
+    fn qualified(&mut self, protocol: Option<Filter>) -> Result<FilterExpr, FilterError> {
+        let qualifier = match self.peek_word(0) {
+            Some(first @ ("src" | "dst")) => {
+                let first = first == "src";
+                self.advance();
+                // `src or dst` and `src and dst`, in either order, are one qualifier.
+                let combined = self.peek_word(1) == Some(if first { "dst" } else { "src" });
+                match self.peek() {
+                    Some(Token::Or) if combined => {
+                        self.next += 2;
+                        EndpointQualifier::SrcOrDst
+                    }
+                    Some(Token::And) if combined => {
+                        self.next += 2;
+                        EndpointQualifier::SrcAndDst
+                    }
+                    _ if first => EndpointQualifier::Src,
+                    _ => EndpointQualifier::Dst,
+                }
+            }
+            _ => EndpointQualifier::SrcOrDst,
+        };
+        let kind = match self.peek_word(0).and_then(ValueKind::from_name) {
+            Some(kind) => {
+                self.advance();
+                kind
+            }
+            // A qualified address needs no `host`, as in `src 10.0.0.1`.
+            None if qualifier != EndpointQualifier::SrcOrDst && protocol.is_none() => ValueKind::Host,
+            None => {
+                return Err(self.error(&format!("expected `host`, `net`, `port` or `portrange`, found {}",
+                                               self.describe())))
+            }
+        };
+        if protocol.is_some() && !matches!(kind, ValueKind::Port | ValueKind::PortRange) {
+            return Err(self.error(&format!("`tcp` and `udp` only qualify ports, not `{}`", kind.name())));
+        }
+        let template = ValueTemplate { protocol, qualifier, kind };
+        self.last = Some(template.clone());
+        self.value(template)
+    }
+
+    /// Parses the value of a primitive that takes one.
+    fn value(&mut self, template: ValueTemplate) -> Result<FilterExpr, FilterError> {
+        let ValueTemplate { protocol, qualifier, kind } = template;
+        let word = self.peek_word(0).unwrap_or_default();
+        let filter = match kind {
+            ValueKind::Net => return Ok(FilterExpr::Primitive(Filter::Net(qualifier, self.network()?))),
+            ValueKind::Host => IpAddr::from_str(word).ok().map(|address| Filter::Host(qualifier, address)),
+            ValueKind::Port => u16::from_str(word).ok().map(|port| Filter::Port(qualifier, port)),
+            ValueKind::PortRange => word
+                .split_once('-')
+                .and_then(|(first, last)| Some((u16::from_str(first).ok()?, u16::from_str(last).ok()?)))
+                .filter(|(first, last)| first <= last)
+                .map(|(first, last)| Filter::PortRange(qualifier, first, last)),
+        };
+        let filter = filter.ok_or_else(|| {
+            let expected = match kind {
+                ValueKind::Host => "an IPv4 or IPv6 address",
+                ValueKind::Net => "a network address",
+                ValueKind::Port => "a port number",
+                ValueKind::PortRange => "a port range such as `1000-2000`",
+            };
+            self.error(&format!("expected {} after `{}`, found {}", expected, kind.name(), self.describe()))
+        })?;
+        self.advance();
+        Ok(match protocol {
+            Some(protocol) => FilterExpr::And(Box::new(FilterExpr::Primitive(protocol)),
+                                              Box::new(FilterExpr::Primitive(filter))),
+            None => FilterExpr::Primitive(filter),
+        })
+    }
+
+    /// Parses the value of a `net` primitive: an IPv4 or IPv6 address with an optional prefix
+    /// length, as in `10.0.0.0/8` or `2001:db8::/32`, an IPv4 address followed by `mask` and a
+    /// netmask, or the first one to three bytes of an IPv4 network, as in `192.168`.
+    fn network(&mut self) -> Result<IpNetwork, FilterError> {
+        let word = self.peek_word(0).unwrap_or_default().to_string();
+        let (address, length) = match word.split_once('/') {
+            Some((address, length)) => (address, Some(length)),
+            None => (word.as_str(), None),
+        };
+        let (address, implied_length) = network_address(address).ok_or_else(|| {
+            self.error(&format!("expected a network address after `net`, found {}", self.describe()))
+        })?;
+        let prefix_length = match length {
+            Some(length) => u8::from_str(length)
+                .ok()
+                .filter(|length| *length <= address_bits(address).1)
+                .ok_or_else(|| self.error(&format!("bad prefix length in `{}`", word)))?,
+            None => implied_length,
+        };
+        let mut error = self.error(&format!("`{}` has bits set past the network prefix", word));
+        self.advance();
+        let prefix_length = if length.is_none() && self.peek_word(0) == Some("mask") {
+            self.advance();
+            if address.is_ipv6() {
+                return Err(self.error("`mask` only applies to IPv4 networks, give an IPv6 prefix length instead"));
+            }
+            let mask = self.peek_word(0).and_then(|mask| Ipv4Addr::from_str(mask).ok());
+            let mask = u32::from(mask.ok_or_else(|| {
+                self.error(&format!("expected a netmask after `mask`, found {}", self.describe()))
+            })?);
+            if mask.leading_ones() + mask.trailing_zeros() != 32 {
+                return Err(self.error(&format!("netmask {} is not contiguous", Ipv4Addr::from(mask))));
+            }
+            error = self.error(&format!("`{}` has bits set past netmask {}", word, Ipv4Addr::from(mask)));
+            self.advance();
+            mask.leading_ones() as u8
+        } else {
+            prefix_length
+        };
+        IpNetwork::new(address, prefix_length).ok_or(error)
+    }
+
+    /// Parses the right-hand side of a `tcp[flags]` test, such as `& syn`, `& (syn|ack) != 0` or
+    /// `& tcp-rst`, into the mask of flags it names.
+    fn flags_test(&mut self) -> Result<TcpFlags, FilterError> {
+        if self.advance() != Some(Token::Ampersand) {
+            self.next -= 1;
+            return Err(self.error(&format!("expected `&` after `tcp[flags]`, found {}", self.describe())));
+        }
+        let grouped = self.peek() == Some(&Token::LeftParen);
+        if grouped {
+            self.advance();
+        }
+        let mut mask = TcpFlags::default();
+        loop {
+            let flag = self.peek_word(0).and_then(TcpFlags::from_name);
+            let flag = flag.ok_or_else(|| self.error(&format!("expected a TCP flag, found {}", self.describe())))?;
+            self.advance();
+            mask = mask | flag;
+            if !grouped || self.peek() != Some(&Token::Pipe) {
+                break;
+            }
+            self.advance();
+        }
+        if grouped {
+            if self.peek() != Some(&Token::RightParen) {
+                return Err(self.error(&format!("expected `|` or `)`, found {}", self.describe())));
+            }
+            self.advance();
+        }
+        if self.peek() == Some(&Token::NotEqual) {
+            self.advance();
+            if self.peek_word(0) != Some("0") {
+                return Err(self.error(&format!("expected `0` after `!=`, found {}", self.describe())));
+            }
+            self.advance();
+        }
+        Ok(mask)
+    }
+}
+
+/// Parses the address of a network with the prefix length it implies: all of an IPv4 or IPv6
+/// address, or the first one to three bytes of an IPv4 address, as in `10` or `172.16`.
+fn network_address(text: &str) -> Option<(IpAddr, u8)> {
+    if let Ok(address) = IpAddr::from_str(text) {
+        return Some((address, address_bits(address).1));
+    }
+    let bytes = text.split('.').map(|byte| u8::from_str(byte).ok()).collect::<Option<Vec<u8>>>()?;
+    if bytes.len() > 3 {
+        return None;
+    }
+    let mut octets = [0; 4];
+    octets[..bytes.len()].copy_from_slice(&bytes);
+    Some((Ipv4Addr::from(octets).into(), bytes.len() as u8 * 8))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::checksum;
+    use crate::parse_frame;
+
+    fn primitive(filter: Filter) -> FilterExpr {
+        FilterExpr::Primitive(filter)
+    }
+
+    fn not(operand: FilterExpr) -> FilterExpr {
+        FilterExpr::Not(Box::new(operand))
+    }
+
+    fn and(left: FilterExpr, right: FilterExpr) -> FilterExpr {
+        FilterExpr::And(Box::new(left), Box::new(right))
+    }
+
+    fn or(left: FilterExpr, right: FilterExpr) -> FilterExpr {
+        FilterExpr::Or(Box::new(left), Box::new(right))
+    }
+
+    fn host(qualifier: EndpointQualifier, address: &str) -> FilterExpr {
+        primitive(Filter::Host(qualifier, address.parse().unwrap()))
+    }
+
+    fn port(qualifier: EndpointQualifier, port: u16) -> FilterExpr {
+        primitive(Filter::Port(qualifier, port))
+    }
+
+    #[test]
+    fn operators_group_from_left_to_right() {
+        let (tcp, udp, arp) = (primitive(Filter::Tcp), primitive(Filter::Udp), primitive(Filter::Arp));
+        let cases = [
+            ("not tcp and udp or arp", or(and(not(tcp.clone()), udp.clone()), arp.clone())),
+            ("tcp or udp and arp", and(or(tcp.clone(), udp.clone()), arp.clone())),
+            ("tcp or (udp and arp)", or(tcp.clone(), and(udp.clone(), arp.clone()))),
+            ("not (tcp or udp)", not(or(tcp.clone(), udp.clone()))),
+            ("! tcp && udp || arp", or(and(not(tcp.clone()), udp.clone()), arp.clone())),
+            ("not not tcp", not(not(tcp.clone()))),
+        ];
+        for (expression, expected) in cases {
+            assert_eq!(FilterExpr::parse(expression), Ok(expected), "{}", expression);
+        }
+    }
+
+    #[test]
+    fn bare_values_repeat_the_primitive_before_them() {
+        use EndpointQualifier::*;
+        let tcp = primitive(Filter::Tcp);
+        let cases = [
+            ("host 10.0.0.1 or 10.0.0.2", or(host(SrcOrDst, "10.0.0.1"), host(SrcOrDst, "10.0.0.2"))),
+            ("src host 10.0.0.1 or 10.0.0.2", or(host(Src, "10.0.0.1"), host(Src, "10.0.0.2"))),
+            ("src or dst host 10.0.0.1", host(SrcOrDst, "10.0.0.1")),
+            ("dst and src host ::1", host(SrcAndDst, "::1")),
+            ("dst 10.0.0.1", host(Dst, "10.0.0.1")),
+            ("port 80 or 443 and not 8080", and(or(port(SrcOrDst, 80), port(SrcOrDst, 443)), not(port(SrcOrDst, 8080)))),
+            ("tcp dst port 80 or 443",
+             or(and(tcp.clone(), port(Dst, 80)), and(tcp.clone(), port(Dst, 443)))),
+            ("port 53 or udp or 123",
+             or(or(port(SrcOrDst, 53), primitive(Filter::Udp)), port(SrcOrDst, 123))),
+        ];
+        for (expression, expected) in cases {
+            assert_eq!(FilterExpr::parse(expression), Ok(expected), "{}", expression);
+        }
+    }
+
+    #[test]
+    fn display_parses_back_to_the_same_expression() {
+        let cases = [
+            "not tcp and udp or arp",
+            "tcp or (udp and arp)",
+            "src and dst net 10.0.0.0/8 and not portrange 1000-2000",
+            "tcp[flags] & (syn|fin) != 0 or tcp-rst",
+            "vlan 100 and (icmp or icmp6)",
+            "vlan and not vlan 5",
+        ];
+        for expression in cases {
+            let filter = FilterExpr::parse(expression).unwrap();
+            assert_eq!(FilterExpr::parse(&filter.to_string()), Ok(filter), "{}", expression);
+        }
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_token() {
+        let cases = [
+            ("tcp and", 7, "expected a filter, found end of expression"),
+            ("tcp udp", 4, "expected `and` or `or`, found `udp`"),
+            ("(tcp or udp", 11, "expected `)`, found end of expression"),
+            ("tcp)", 3, "unmatched `)`"),
+            ("tcp and foo", 8, "unknown filter `foo`"),
+            ("80", 0, "unknown filter `80`"),
+            ("port 65536", 5, "expected a port number after `port`, found `65536`"),
+            ("portrange 2000-1000", 10, "expected a port range such as `1000-2000` after `portrange`, found `2000-1000`"),
+            ("host 10.0.0", 5, "expected an IPv4 or IPv6 address after `host`, found `10.0.0`"),
+            ("tcp src host 10.0.0.1", 13, "`tcp` and `udp` only qualify ports, not `host`"),
+            ("vlan 4096", 5, "expected a VLAN ID after `vlan`, found `4096`"),
+            ("net 10.0.0.0/33", 4, "bad prefix length in `10.0.0.0/33`"),
+            ("net 10.0.0.0 mask 255.0.255.0", 18, "netmask 255.0.255.0 is not contiguous"),
+            ("tcp[flags] syn", 11, "expected `&` after `tcp[flags]`, found `syn`"),
+            ("tcp[flags] & (syn|foo)", 18, "expected a TCP flag, found `foo`"),
+            ("tcp[flags] & syn != 1", 20, "expected `0` after `!=`, found `1`"),
+            ("tcp # udp", 4, "unexpected character `#`"),
+        ];
+        for (expression, position, message) in cases {
+            let error = FilterExpr::parse(expression).unwrap_err();
+            assert_eq!((error.position, error.message.as_str()), (position, message), "{}", expression);
+        }
+    }
+
+    fn network(address: &str, prefix_length: u8) -> Option<IpNetwork> {
+        IpNetwork::new(address.parse().unwrap(), prefix_length)
+    }
+
+    #[test]
+    fn networks_contain_the_addresses_under_their_prefix() {
+        let cases = [
+            ("0.0.0.0", 0, "255.255.255.255", true),
+            ("0.0.0.0", 0, "::", false),
+            ("10.0.0.0", 8, "10.255.0.1", true),
+            ("10.0.0.0", 8, "11.0.0.0", false),
+            ("10.1.2.3", 32, "10.1.2.3", true),
+            ("10.1.2.3", 32, "10.1.2.4", false),
+            ("::", 0, "2001:db8::1", true),
+            ("::", 0, "10.0.0.1", false),
+            ("2001:db8::", 32, "2001:db8:ffff::1", true),
+            ("2001:db8::", 32, "2001:db9::", false),
+            ("2001:db8::1", 128, "2001:db8::1", true),
+            ("2001:db8::1", 128, "2001:db8::2", false),
+            // IPv4-mapped IPv6 addresses are IPv6 addresses.
+            ("10.0.0.0", 8, "::ffff:10.0.0.1", false),
+            ("::ffff:0:0", 96, "::ffff:10.0.0.1", true),
+            ("::ffff:10.0.0.0", 104, "::ffff:10.0.0.1", true),
+            ("::ffff:10.0.0.0", 104, "10.0.0.1", false),
+        ];
+        for (address, prefix_length, inside, expected) in cases {
+            let network = network(address, prefix_length).unwrap();
+            assert_eq!(network.contains(inside.parse().unwrap()), expected, "{} in {}", inside, network);
+        }
+    }
+
+    #[test]
+    fn networks_reject_host_bits_and_long_prefixes() {
+        assert_eq!(network("10.1.2.3", 8), None);
+        assert_eq!(network("10.1.2.3", 31), None);
+        assert_eq!(network("10.0.0.0", 33), None);
+        assert_eq!(network("2001:db8::1", 64), None);
+        assert_eq!(network("2001:db8::", 129), None);
+        assert!(network("10.1.2.3", 32).is_some());
+        assert!(network("2001:db8::1", 128).is_some());
+
+        let error = FilterExpr::parse("net 10.1.2.3/8").unwrap_err();
+        assert_eq!((error.position, error.message.as_str()), (4, "`10.1.2.3/8` has bits set past the network prefix"));
+        let error = FilterExpr::parse("net 10.1.0.0 mask 255.0.0.0").unwrap_err();
+        assert_eq!((error.position, error.message.as_str()), (18, "`10.1.0.0` has bits set past netmask 255.0.0.0"));
+    }
+
+    #[test]
+    fn net_values_imply_their_prefix() {
+        let cases = [
+            ("net 10", "10.0.0.0", 8),
+            ("net 172.16", "172.16.0.0", 16),
+            ("net 192.168.1", "192.168.1.0", 24),
+            ("net 10.1.2.3", "10.1.2.3", 32),
+            ("net 0.0.0.0/0", "0.0.0.0", 0),
+            ("net 10.0.0.0 mask 255.255.0.0", "10.0.0.0", 16),
+            ("net 2001:db8::/32", "2001:db8::", 32),
+            ("net ::/0", "::", 0),
+            ("net 2001:db8::1", "2001:db8::1", 128),
+        ];
+        for (expression, address, prefix_length) in cases {
+            let expected = primitive(Filter::Net(EndpointQualifier::SrcOrDst, network(address, prefix_length).unwrap()));
+            assert_eq!(FilterExpr::parse(expression), Ok(expected), "{}", expression);
+        }
+    }
+
+    /// Returns an Ethernet frame with the given VLAN tags, EtherType and payload.
+    pub(crate) fn ethernet(vlan_ids: &[u16], ether_type: u16, payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 1];
+        for vlan_id in vlan_ids {
+            frame.extend_from_slice(&[0x81, 0]);
+            frame.extend_from_slice(&vlan_id.to_be_bytes());
+        }
+        frame.extend_from_slice(&ether_type.to_be_bytes());
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    /// Returns an IPv4 packet from 10.0.0.1 to `destination`, with a correct header checksum.
+    pub(crate) fn ipv4(destination: [u8; 4], protocol: u8, flags_and_offset: u16, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x45, 0];
+        packet.extend_from_slice(&(20 + payload.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&[0x12, 0x34]);
+        packet.extend_from_slice(&flags_and_offset.to_be_bytes());
+        packet.extend_from_slice(&[64, protocol, 0, 0, 10, 0, 0, 1]);
+        packet.extend_from_slice(&destination);
+        let header_checksum = checksum::expected(&[], &packet, 10);
+        packet[10..12].copy_from_slice(&header_checksum.to_be_bytes());
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    /// Returns a UDP header from port 1000 to `destination_port`, with a zero checksum.
+    pub(crate) fn udp(destination_port: u16) -> Vec<u8> {
+        let mut datagram = vec![0x03, 0xe8];
+        datagram.extend_from_slice(&destination_port.to_be_bytes());
+        datagram.extend_from_slice(&[0, 8, 0, 0]);
+        datagram
+    }
+
+    /// Returns a TCP header from port 40000 to `destination_port` with the given flags and a zero
+    /// checksum.
+    pub(crate) fn tcp(destination_port: u16, flags: u8) -> Vec<u8> {
+        let mut segment = vec![0x9c, 0x40];
+        segment.extend_from_slice(&destination_port.to_be_bytes());
+        segment.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 0, 0x50, flags, 0xff, 0xff, 0, 0, 0, 0]);
+        segment
+    }
+
+    /// Returns an IPv6 packet from 2001:db8::1 to 2001:db8::2.
+    pub(crate) fn ipv6(next_header: u8, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x60, 0, 0, 0];
+        packet.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&[next_header, 64]);
+        packet.extend_from_slice(&[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        packet.extend_from_slice(&[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]);
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    /// Returns decoded frames of the kinds the filters tell apart.
+    fn frames() -> Vec<EthernetFrame> {
+        let mut bad_header = ipv4([10, 0, 0, 2], 17, 0, &udp(123));
+        bad_header[11] ^= 0xff;
+        let mut bad_segment = tcp(443, 0x12);
+        bad_segment[16] = 0x12;
+        let arp = [0, 1, 8, 0, 6, 4, 0, 1, 0, 0, 0, 0, 0, 1, 10, 0, 0, 1, 0, 0, 0, 0, 0, 0, 10, 0, 0, 2];
+        let frames = [
+            ethernet(&[], 0x0800, &ipv4([10, 0, 0, 2], 17, 0, &udp(53))),
+            ethernet(&[], 0x0800, &ipv4([192, 168, 0, 1], 6, 0, &tcp(80, 0x02))),
+            ethernet(&[], 0x0800, &ipv4([10, 0, 0, 2], 1, 0, &[8, 0, 0xf7, 0xfd, 0, 1, 0, 1])),
+            // A zero UDP checksum is not allowed over IPv6.
+            ethernet(&[], 0x86dd, &ipv6(17, &udp(53))),
+            ethernet(&[], 0x86dd, &ipv6(58, &[128, 0, 0, 0, 0, 1, 0, 1])),
+            ethernet(&[100], 0x0800, &ipv4([10, 0, 0, 2], 17, 0, &udp(53))),
+            ethernet(&[10, 20], 0x0800, &ipv4([10, 0, 0, 2], 6, 0, &tcp(80, 0x10))),
+            // The first fragment of a datagram, whose ports are not decoded.
+            ethernet(&[], 0x0800, &ipv4([10, 0, 0, 2], 17, 0x2000, &udp(53))),
+            ethernet(&[], 0x0806, &arp),
+            ethernet(&[], 0x0800, &bad_header),
+            ethernet(&[], 0x0800, &ipv4([10, 0, 0, 2], 6, 0, &bad_segment)),
+        ];
+        frames.iter().map(|frame| parse_frame(frame).unwrap()).collect()
+    }
+
+    /// Returns the indexes of the frames the expression matches.
+    fn matching(expression: &str, frames: &[EthernetFrame]) -> Vec<usize> {
+        let filter = FilterExpr::parse(expression).unwrap();
+        (0..frames.len()).filter(|&index| filter.matches(&frames[index])).collect()
+    }
+
+    fn check(cases: &[(&str, &[usize])]) {
+        let frames = frames();
+        for (expression, expected) in cases {
+            assert_eq!(matching(expression, &frames), *expected, "{}", expression);
+        }
+    }
+
+    #[test]
+    fn bad_checksum_matches_headers_and_datagrams_found_wrong() {
+        check(&[
+            ("bad-checksum", &[3, 9, 10]),
+            ("not bad-checksum and ip", &[0, 1, 2, 5, 6, 7]),
+            ("bad-checksum and ip6", &[3]),
+        ]);
+        let frames = frames();
+        // A zero TCP or ICMPv6 checksum may have been offloaded, so it is not taken as wrong.
+        assert!(!Filter::BadChecksum.matches(&frames[1]));
+        assert!(!Filter::BadChecksum.matches(&frames[4]));
+        assert!(Filter::BadChecksum.matches(&frames[9]));
+    }
+
+    #[test]
+    fn net_matches_addresses_under_the_prefix_or_mask() {
+        check(&[
+            ("net 192.168.0.0/16", &[1]),
+            ("dst net 10.0.0.0 mask 255.255.255.0", &[0, 2, 5, 6, 7, 9, 10]),
+            ("src net 10.0.0.0/8", &[0, 1, 2, 5, 6, 7, 9, 10]),
+            ("net 10", &[0, 1, 2, 5, 6, 7, 9, 10]),
+            ("src and dst net 10.0.0.0/8", &[0, 2, 5, 6, 7, 9, 10]),
+            ("net 2001:db8::/32", &[3, 4]),
+            ("dst net 2001:db8::2/128", &[3, 4]),
+            ("net 0.0.0.0/0", &[0, 1, 2, 5, 6, 7, 9, 10]),
+            ("not net 10.0.0.0/8", &[3, 4, 8]),
+        ]);
+    }
+
+    #[test]
+    fn port_matches_decoded_tcp_and_udp_ports() {
+        check(&[
+            // The fragment carries a UDP header but its ports are not decoded.
+            ("port 53", &[0, 3, 5]),
+            ("src port 1000", &[0, 3, 5, 9]),
+            ("dst port 80", &[1, 6]),
+            ("tcp port 80", &[1, 6]),
+            ("udp port 80", &[]),
+            ("portrange 100-500", &[9, 10]),
+            ("src or dst port 40000 and not port 80", &[10]),
+        ]);
+        let frames = frames();
+        assert!(Filter::Port(EndpointQualifier::Dst, 53).matches(&frames[0]));
+        assert!(!Filter::Port(EndpointQualifier::Src, 53).matches(&frames[0]));
+        assert!(!Filter::Port(EndpointQualifier::SrcAndDst, 53).matches(&frames[0]));
+    }
+
+    #[test]
+    fn icmp_and_icmp6_are_told_apart() {
+        check(&[
+            ("icmp", &[2]),
+            ("icmp6", &[4]),
+            ("icmp or icmp6", &[2, 4]),
+            ("ip6 and not icmp6", &[3]),
+        ]);
+    }
+
+    #[test]
+    fn primitives_see_through_vlan_tags() {
+        check(&[
+            ("vlan", &[5, 6]),
+            ("vlan 100", &[5]),
+            // Any tag of a stacked frame matches, the inner as well as the outer.
+            ("vlan 10", &[6]),
+            ("vlan 20 and tcp", &[6]),
+            ("vlan and port 53", &[5]),
+            ("not vlan and port 53", &[0, 3]),
+            ("vlan and host 10.0.0.2", &[5, 6]),
+        ]);
+    }
+
+    #[test]
+    fn tcp_flags_match_any_of_the_flags_named() {
+        check(&[
+            ("tcp-syn", &[1, 10]),
+            ("tcp[tcpflags] & (ack|rst) != 0", &[6, 10]),
+            ("tcp-rst", &[]),
+            ("tcp and not tcp-syn", &[6]),
+        ]);
+    }
+}
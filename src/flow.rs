@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+
+use crate::{EthernetFrame, IPProtocol, ProtocolDatagram, TcpFlags};
+
+/// Identifies one direction of a TCP or UDP conversation by its protocol and endpoints.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FlowKey {
+    pub protocol: IPProtocol,
+    pub source: SocketAddr,
+    pub destination: SocketAddr,
+}
+
+/// The direction of a packet within a conversation.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    /// From the endpoint that opened the conversation.
+    ClientToServer,
+    ServerToClient,
+}
+
+impl FlowKey {
+    /// Builds the key of the TCP segment or UDP datagram carried by the frame, if any.
+    pub fn from_frame(frame: &EthernetFrame) -> Option<FlowKey> {
+        let (source, destination): (IpAddr, IpAddr) = if let Some(packet) = frame.ipv4() {
+            (packet.source_add.into(), packet.destination_add.into())
+        } else if let Some(packet) = frame.ipv6() {
+            (packet.source_add.into(), packet.destination_add.into())
+        } else {
+            return None;
+        };
+        let (protocol, source_port, destination_port) = match frame.payload.datagram()? {
+            ProtocolDatagram::TCP(tcp) => (IPProtocol::TCP, tcp.source_port, tcp.destination_port),
+            ProtocolDatagram::UDP(udp) => (IPProtocol::UDP, udp.source_port, udp.destination_port),
+            _ => return None,
+        };
+        Some(FlowKey {
+            protocol,
+            source: SocketAddr::new(source, u16::from_be_bytes(source_port)),
+            destination: SocketAddr::new(destination, u16::from_be_bytes(destination_port)),
+        })
+    }
+
+    /// Returns the key of the opposite direction.
+    pub fn reversed(&self) -> FlowKey {
+        FlowKey { protocol: self.protocol, source: self.destination, destination: self.source }
+    }
+}
+
+impl fmt::Display for FlowKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} > {}", self.source, self.destination)
+    }
+}
+
+/// A conversation with the key of its client-to-server direction.
+struct Flow<T> {
+    client: FlowKey,
+    /// Initial sequence number of a TCP client, once its SYN was seen.
+    client_isn: Option<u32>,
+    value: T,
+}
+
+/// Conversations in the order they were first seen, each found from either direction's key.
+///
+/// The position of a conversation is its stream index, as Wireshark numbers streams. TCP streams
+/// are only ever started by `find_or_start_tcp`, so every consumer of TCP segments numbers them
+/// the same way.
+pub(crate) struct FlowTable<T> {
+    indexes: HashMap<FlowKey, usize>,
+    flows: Vec<Flow<T>>,
+}
+
+impl<T> FlowTable<T> {
+    pub(crate) fn new() -> FlowTable<T> {
+        FlowTable { indexes: HashMap::new(), flows: vec![] }
+    }
+
+    /// Returns the stream index of the conversation `key` belongs to and the direction of `key`.
+    pub(crate) fn find(&self, key: &FlowKey) -> Option<(usize, Direction)> {
+        let index = *self.indexes.get(key)?;
+        let client = self.flows[index].client == *key;
+        Some((index, if client { Direction::ClientToServer } else { Direction::ServerToClient }))
+    }
+
+    /// Starts a new conversation with `client` as its client-to-server key, at the next stream
+    /// index. An earlier conversation between the same endpoints keeps its index but can no
+    /// longer be found by key.
+    pub(crate) fn insert(&mut self, client: FlowKey, value: T) {
+        let index = self.flows.len();
+        self.indexes.insert(client, index);
+        self.indexes.insert(client.reversed(), index);
+        self.flows.push(Flow { client, client_isn: None, value });
+    }
+
+    /// Returns the stream index and direction of a TCP segment, starting a new stream when the
+    /// segment belongs to none, or when it is a SYN with a new initial sequence number between the
+    /// endpoints of an earlier stream, as happens when a client reuses its port.
+    ///
+    /// The client of a new stream is the sender of its first segment, or the receiver when that
+    /// segment is a SYN/ACK. Only a SYN without ACK from the client sets its initial sequence
+    /// number.
+    ///
+    /// # Arguments
+    /// * `key` - The key of the segment.
+    /// * `flags` - The flags of the segment.
+    /// * `sequence_number` - The sequence number of the segment.
+    /// * `create` - Builds a new stream from its index and client-to-server key.
+    pub(crate) fn find_or_start_tcp(&mut self, key: FlowKey, flags: TcpFlags, sequence_number: u32,
+                                    create: impl FnOnce(usize, FlowKey) -> T) -> (usize, Direction) {
+        let syn = flags.contains(TcpFlags::SYN);
+        let opening = syn && !flags.contains(TcpFlags::ACK);
+        let found = self.find(&key).filter(|(index, direction)| {
+            let reused = opening
+                && (*direction == Direction::ServerToClient
+                    || self.flows[*index].client_isn.is_some_and(|isn| isn != sequence_number));
+            !reused
+        });
+        let (index, direction) = match found {
+            Some(found) => found,
+            None => {
+                // A SYN/ACK comes from the server, so its receiver opened the connection.
+                let client = if syn && !opening { key.reversed() } else { key };
+                let index = self.flows.len();
+                self.insert(client, create(index, client));
+                (index, if client == key { Direction::ClientToServer } else { Direction::ServerToClient })
+            }
+        };
+        if opening && direction == Direction::ClientToServer {
+            self.flows[index].client_isn = Some(sequence_number);
+        }
+        (index, direction)
+    }
+
+    pub(crate) fn get(&self, index: usize) -> Option<&T> {
+        self.flows.get(index).map(|flow| &flow.value)
+    }
+
+    pub(crate) fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.flows.get_mut(index).map(|flow| &mut flow.value)
+    }
+
+    /// Returns the conversations in stream index order.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &T> {
+        self.flows.iter().map(|flow| &flow.value)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.flows.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(source: &str, destination: &str) -> FlowKey {
+        FlowKey { protocol: IPProtocol::TCP, source: source.parse().unwrap(), destination: destination.parse().unwrap() }
+    }
+
+    fn segment(table: &mut FlowTable<FlowKey>, key: FlowKey, flags: TcpFlags, sequence: u32) -> (usize, Direction) {
+        table.find_or_start_tcp(key, flags, sequence, |_, client| client)
+    }
+
+    const CLIENT: &str = "10.0.0.1:40000";
+    const SERVER: &str = "10.0.0.2:80";
+
+    #[test]
+    fn handshake_opens_one_stream() {
+        let mut table = FlowTable::new();
+        let syn_ack = TcpFlags::SYN | TcpFlags::ACK;
+        assert_eq!(segment(&mut table, key(CLIENT, SERVER), TcpFlags::SYN, 100), (0, Direction::ClientToServer));
+        assert_eq!(segment(&mut table, key(SERVER, CLIENT), syn_ack, 500), (0, Direction::ServerToClient));
+        assert_eq!(segment(&mut table, key(CLIENT, SERVER), TcpFlags::ACK, 101), (0, Direction::ClientToServer));
+        assert_eq!(table.len(), 1);
+    }
+
+    #[test]
+    fn syn_ack_first_makes_its_receiver_the_client() {
+        let mut table = FlowTable::new();
+        let syn_ack = TcpFlags::SYN | TcpFlags::ACK;
+        assert_eq!(segment(&mut table, key(SERVER, CLIENT), syn_ack, 500), (0, Direction::ServerToClient));
+        assert_eq!(table.get(0), Some(&key(CLIENT, SERVER)));
+        // A retransmitted SYN/ACK and the client's ACK stay in the stream.
+        assert_eq!(segment(&mut table, key(SERVER, CLIENT), syn_ack, 500), (0, Direction::ServerToClient));
+        assert_eq!(segment(&mut table, key(CLIENT, SERVER), TcpFlags::ACK, 101), (0, Direction::ClientToServer));
+        assert_eq!(table.len(), 1);
+    }
+
+    #[test]
+    fn mid_stream_segments_make_their_sender_the_client() {
+        let mut table = FlowTable::new();
+        assert_eq!(segment(&mut table, key(SERVER, CLIENT), TcpFlags::ACK, 500), (0, Direction::ClientToServer));
+        assert_eq!(segment(&mut table, key(CLIENT, SERVER), TcpFlags::ACK, 100), (0, Direction::ServerToClient));
+        // A SYN from the endpoint taken for the server opens a new stream.
+        assert_eq!(segment(&mut table, key(CLIENT, SERVER), TcpFlags::SYN, 100), (1, Direction::ClientToServer));
+    }
+
+    #[test]
+    fn port_reuse_starts_a_new_stream() {
+        let mut table = FlowTable::new();
+        segment(&mut table, key(CLIENT, SERVER), TcpFlags::SYN, 100);
+        // A retransmitted SYN keeps its stream, one with a new initial sequence number does not.
+        assert_eq!(segment(&mut table, key(CLIENT, SERVER), TcpFlags::SYN, 100), (0, Direction::ClientToServer));
+        assert_eq!(segment(&mut table, key(CLIENT, SERVER), TcpFlags::SYN, 9000), (1, Direction::ClientToServer));
+        assert_eq!(segment(&mut table, key(SERVER, CLIENT), TcpFlags::ACK, 500), (1, Direction::ServerToClient));
+    }
+}
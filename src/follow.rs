@@ -0,0 +1,274 @@
+use std::fmt;
+use std::net::SocketAddr;
+use std::str::FromStr;
+
+use crate::{Direction, IPProtocol, StreamChunk, TcpStream, UdpStream};
+
+/// How the payload of a followed stream is shown.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FollowFormat {
+    /// Printable characters and line breaks, with any other byte, carriage returns included, shown
+    /// as a dot.
+    Ascii,
+    /// A hex dump with offsets and an ASCII column.
+    Hex,
+    /// The bytes themselves, shown as one hex string per chunk.
+    Raw,
+}
+
+impl FollowFormat {
+    /// Returns the format with the given name: `ascii`, `hex` or `raw`.
+    pub fn from_name(name: &str) -> Option<FollowFormat> {
+        match name {
+            "ascii" => Some(FollowFormat::Ascii),
+            "hex" => Some(FollowFormat::Hex),
+            "raw" => Some(FollowFormat::Raw),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the format, as accepted by `from_name`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            FollowFormat::Ascii => "ascii",
+            FollowFormat::Hex => "hex",
+            FollowFormat::Raw => "raw",
+        }
+    }
+}
+
+/// Selects the stream to follow, by its stream index or by its two endpoints in either order.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StreamSelector {
+    Index(usize),
+    Endpoints(SocketAddr, SocketAddr),
+}
+
+impl StreamSelector {
+    /// Parses a selector from the start of `args`, returning it with the number of arguments used.
+    ///
+    /// A stream index is a number, as in `3`. Endpoints are given as `address:port`, with IPv6
+    /// addresses in brackets, either as two arguments or as one with a comma between them, as in
+    /// `10.0.0.1:49152,10.0.0.2:80`.
+    pub fn parse(args: &[String]) -> Option<(StreamSelector, usize)> {
+        let first = args.first()?;
+        if let Ok(index) = usize::from_str(first) {
+            return Some((StreamSelector::Index(index), 1));
+        }
+        if let Some((a, b)) = first.split_once(',') {
+            let endpoints = StreamSelector::Endpoints(SocketAddr::from_str(a).ok()?, SocketAddr::from_str(b).ok()?);
+            return Some((endpoints, 1));
+        }
+        let a = SocketAddr::from_str(first).ok()?;
+        let b = SocketAddr::from_str(args.get(1)?).ok()?;
+        Some((StreamSelector::Endpoints(a, b), 2))
+    }
+
+    /// Returns whether the stream with the given index and endpoints is the one selected.
+    pub fn matches(&self, index: usize, client: SocketAddr, server: SocketAddr) -> bool {
+        match *self {
+            StreamSelector::Index(selected) => selected == index,
+            StreamSelector::Endpoints(a, b) => (a == client && b == server) || (a == server && b == client),
+        }
+    }
+}
+
+impl fmt::Display for StreamSelector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StreamSelector::Index(index) => write!(f, "stream index {}", index),
+            StreamSelector::Endpoints(a, b) => write!(f, "{} <-> {}", a, b),
+        }
+    }
+}
+
+/// The payload of a TCP or UDP stream, laid out as Wireshark's Follow Stream shows it.
+///
+/// Each chunk is shown after its length, except in the raw format. Chunks sent by the server are
+/// indented by a tab, so the two directions can be told apart.
+pub struct FollowedStream<'a> {
+    pub protocol: IPProtocol,
+    pub index: usize,
+    pub client: SocketAddr,
+    pub server: SocketAddr,
+    /// Each chunk of the conversation in order, with its payload.
+    pub chunks: Vec<(StreamChunk, &'a [u8])>,
+    pub format: FollowFormat,
+}
+
+impl<'a> FollowedStream<'a> {
+    /// Follows the reassembled data of a TCP stream.
+    pub fn tcp(stream: &'a TcpStream, format: FollowFormat) -> FollowedStream<'a> {
+        FollowedStream {
+            protocol: IPProtocol::TCP,
+            index: stream.index,
+            client: stream.client,
+            server: stream.server,
+            chunks: stream.chunks.iter().map(|chunk| (*chunk, stream.chunk_data(chunk))).collect(),
+            format,
+        }
+    }
+
+    /// Follows the datagrams of a UDP stream.
+    pub fn udp(stream: &'a UdpStream, format: FollowFormat) -> FollowedStream<'a> {
+        FollowedStream {
+            protocol: IPProtocol::UDP,
+            index: stream.index,
+            client: stream.client,
+            server: stream.server,
+            chunks: stream.chunks.iter().map(|chunk| (*chunk, stream.chunk_data(chunk))).collect(),
+            format,
+        }
+    }
+
+    /// Returns the payload sent in one direction of the conversation, in the order it was sent.
+    pub fn raw_bytes(&self, direction: Direction) -> Vec<u8> {
+        self.chunks
+            .iter()
+            .filter(|(chunk, _)| chunk.direction == direction)
+            .flat_map(|(_, data)| data.iter().copied())
+            .collect()
+    }
+}
+
+impl fmt::Display for FollowedStream<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let protocol = if self.protocol == IPProtocol::UDP { "udp" } else { "tcp" };
+        writeln!(f, "{}", "=".repeat(67))?;
+        writeln!(f, "Follow: {},{}", protocol, self.format.name())?;
+        writeln!(f, "Filter: {}.stream eq {}", protocol, self.index)?;
+        writeln!(f, "Node 0: {}", self.client)?;
+        writeln!(f, "Node 1: {}", self.server)?;
+        for (chunk, data) in &self.chunks {
+            let indent = if chunk.direction == Direction::ServerToClient { "\t" } else { "" };
+            if self.format != FollowFormat::Raw {
+                writeln!(f, "{}{}", indent, data.len())?;
+            }
+            match self.format {
+                FollowFormat::Ascii => {
+                    let text: String = data.iter().map(|b| if *b == b'\n' { '\n' } else { printable(*b) }).collect();
+                    for line in text.split_terminator('\n') {
+                        writeln!(f, "{}{}", indent, line)?;
+                    }
+                }
+                FollowFormat::Hex => {
+                    for (row, bytes) in data.chunks(16).enumerate() {
+                        writeln!(f, "{}{:08X}  {}", indent, chunk.offset + row * 16, hex_row(bytes))?;
+                    }
+                }
+                FollowFormat::Raw => {
+                    let hex: String = data.iter().map(|b| format!("{:02x}", b)).collect();
+                    writeln!(f, "{}{}", indent, hex)?;
+                }
+            }
+        }
+        write!(f, "{}", "=".repeat(67))
+    }
+}
+
+/// Formats up to 16 bytes as a hex dump row: the bytes in two groups of eight, then as text.
+fn hex_row(bytes: &[u8]) -> String {
+    let mut hex = String::new();
+    let mut text = String::new();
+    for (position, byte) in bytes.iter().enumerate() {
+        if position == 8 {
+            hex.push(' ');
+            text.push(' ');
+        }
+        hex.push_str(&format!("{:02x} ", byte));
+        text.push(printable(*byte));
+    }
+    format!("{:<49} {}", hex, text)
+}
+
+/// Returns the byte as a character if it is printable ASCII, or a dot otherwise.
+fn printable(byte: u8) -> char {
+    if byte.is_ascii_graphic() || byte == b' ' {
+        byte as char
+    } else {
+        '.'
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn chunk(direction: Direction, offset: usize, length: usize) -> StreamChunk {
+        StreamChunk { direction, offset, length, packet_number: 1, timestamp: Duration::ZERO }
+    }
+
+    fn followed(format: FollowFormat) -> FollowedStream<'static> {
+        FollowedStream {
+            protocol: IPProtocol::TCP,
+            index: 3,
+            client: "10.0.0.1:40000".parse().unwrap(),
+            server: "10.0.0.2:80".parse().unwrap(),
+            chunks: vec![
+                (chunk(Direction::ClientToServer, 0, 7), b"GET /\r\n"),
+                (chunk(Direction::ServerToClient, 0, 4), b"OK\r\n"),
+                (chunk(Direction::ClientToServer, 7, 2), b"\x00A"),
+            ],
+            format,
+        }
+    }
+
+    /// Returns the lines of the followed stream between its header and closing rule.
+    fn body(followed: &FollowedStream) -> Vec<String> {
+        let text = followed.to_string();
+        let lines: Vec<&str> = text.lines().collect();
+        lines[5..lines.len() - 1].iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn ascii_shows_carriage_returns_as_dots() {
+        assert_eq!(body(&followed(FollowFormat::Ascii)), ["7", "GET /.", "\t4", "\tOK.", "2", ".A"]);
+    }
+
+    #[test]
+    fn hex_shows_each_chunk_after_its_length() {
+        assert_eq!(body(&followed(FollowFormat::Hex)), [
+            "7",
+            "00000000  47 45 54 20 2f 0d 0a                              GET /..",
+            "\t4",
+            "\t00000000  4f 4b 0d 0a                                       OK..",
+            "2",
+            "00000007  00 41                                             .A",
+        ]);
+    }
+
+    #[test]
+    fn raw_shows_each_chunk_as_hex() {
+        assert_eq!(body(&followed(FollowFormat::Raw)), ["474554202f0d0a", "\t4f4b0d0a", "0041"]);
+    }
+
+    #[test]
+    fn raw_bytes_keep_the_directions_apart() {
+        let followed = followed(FollowFormat::Raw);
+        assert_eq!(followed.raw_bytes(Direction::ClientToServer), b"GET /\r\n\x00A");
+        assert_eq!(followed.raw_bytes(Direction::ServerToClient), b"OK\r\n");
+    }
+
+    #[test]
+    fn header_names_the_stream_and_its_nodes() {
+        let text = followed(FollowFormat::Ascii).to_string();
+        let header: Vec<&str> = text.lines().take(5).collect();
+        assert_eq!(header[1..], ["Follow: tcp,ascii", "Filter: tcp.stream eq 3", "Node 0: 10.0.0.1:40000",
+                                 "Node 1: 10.0.0.2:80"]);
+    }
+
+    #[test]
+    fn selectors_parse_indexes_and_endpoints() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<String>>();
+        let (a, b) = ("10.0.0.1:40000".parse().unwrap(), "10.0.0.2:80".parse().unwrap());
+        assert_eq!(StreamSelector::parse(&args(&["3", "hex"])), Some((StreamSelector::Index(3), 1)));
+        assert_eq!(StreamSelector::parse(&args(&["10.0.0.1:40000", "10.0.0.2:80"])),
+                   Some((StreamSelector::Endpoints(a, b), 2)));
+        assert_eq!(StreamSelector::parse(&args(&["10.0.0.2:80,10.0.0.1:40000"])),
+                   Some((StreamSelector::Endpoints(b, a), 1)));
+        assert!(StreamSelector::Endpoints(b, a).matches(0, a, b));
+        assert_eq!(StreamSelector::parse(&args(&["10.0.0.1"])), None);
+    }
+}
@@ -0,0 +1,530 @@
+use std::fmt;
+use std::net::Ipv6Addr;
+
+use crate::parser::{array, require};
+use crate::{ChecksumStatus, Layer, ParseError};
+
+/// An ICMPv6 message, decoded according to its type.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ICMPv6Message {
+    /// Echo Request (128) or Echo Reply (129).
+    Echo { identifier: u16, sequence_number: u16, data: Vec<u8> },
+    /// Destination Unreachable (1), with as much of the invoking packet as fits.
+    DestinationUnreachable { invoking_packet: Vec<u8> },
+    /// Packet Too Big (2), with the MTU of the next-hop link.
+    PacketTooBig { mtu: u32, invoking_packet: Vec<u8> },
+    /// Time Exceeded (3).
+    TimeExceeded { invoking_packet: Vec<u8> },
+    /// Parameter Problem (4), with the offset of the erroneous field in the invoking packet.
+    ParameterProblem { pointer: u32, invoking_packet: Vec<u8> },
+    /// Router Solicitation (133).
+    RouterSolicitation { options: Vec<NdOption> },
+    /// Router Advertisement (134). Lifetimes are in seconds, times in milliseconds.
+    RouterAdvertisement {
+        hop_limit: u8,
+        managed: bool,
+        other_configuration: bool,
+        router_lifetime: u16,
+        reachable_time: u32,
+        retransmit_timer: u32,
+        options: Vec<NdOption>,
+    },
+    /// Neighbor Solicitation (135).
+    NeighborSolicitation { target: [u8; 16], options: Vec<NdOption> },
+    /// Neighbor Advertisement (136).
+    NeighborAdvertisement { router: bool, solicited: bool, override_entry: bool, target: [u8; 16], options: Vec<NdOption> },
+    /// Redirect (137).
+    Redirect { target: [u8; 16], destination: [u8; 16], options: Vec<NdOption> },
+    /// Multicast Listener Query (130). MLDv2 queries also carry a source list.
+    MulticastListenerQuery { maximum_response_delay: u16, multicast_address: [u8; 16], sources: Option<Vec<[u8; 16]>> },
+    /// MLDv1 Multicast Listener Report (131).
+    MulticastListenerReport { multicast_address: [u8; 16] },
+    /// MLDv1 Multicast Listener Done (132).
+    MulticastListenerDone { multicast_address: [u8; 16] },
+    /// MLDv2 Multicast Listener Report (143).
+    MulticastListenerReportV2 { records: Vec<MulticastAddressRecord> },
+    /// Any other type, with the bytes following the checksum.
+    Other(Vec<u8>),
+}
+
+/// A Neighbor Discovery option.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NdOption {
+    /// Link-layer address of the sender, 6 bytes on Ethernet.
+    SourceLinkLayerAddress(Vec<u8>),
+    TargetLinkLayerAddress(Vec<u8>),
+    /// Prefix Information. Lifetimes are in seconds.
+    PrefixInformation {
+        prefix_length: u8,
+        on_link: bool,
+        autonomous: bool,
+        valid_lifetime: u32,
+        preferred_lifetime: u32,
+        prefix: [u8; 16],
+    },
+    /// Redirected Header, with the start of the redirected packet.
+    RedirectedHeader(Vec<u8>),
+    Mtu(u32),
+    /// Recursive DNS Server, with the lifetime in seconds.
+    RecursiveDnsServer { lifetime: u32, servers: Vec<[u8; 16]> },
+    Unknown { option_type: u8, data: Vec<u8> },
+}
+
+impl NdOption {
+    /// Parses the Neighbor Discovery options that fill `data`.
+    ///
+    /// # Errors
+    /// Returns `ParseError::Truncated` if an option runs past the end of `data`, or
+    /// `ParseError::BadHeaderLength` if an option has a length of zero.
+    pub fn parse_all(data: &[u8]) -> Result<Vec<NdOption>, ParseError> {
+        let mut options = vec![];
+        let mut current = 0;
+        while current < data.len() {
+            require(data, current + 2, Layer::ICMPv6)?;
+            let option_type = data[current];
+            let length = data[current + 1] as usize * 8;
+            if length == 0 {
+                return Err(ParseError::BadHeaderLength { layer: Layer::ICMPv6, length });
+            }
+            require(data, current + length, Layer::ICMPv6)?;
+            let body = &data[current + 2..current + length];
+            options.push(match option_type {
+                1 => NdOption::SourceLinkLayerAddress(body.to_vec()),
+                2 => NdOption::TargetLinkLayerAddress(body.to_vec()),
+                3 if body.len() == 30 => NdOption::PrefixInformation {
+                    prefix_length: body[0],
+                    on_link: body[1] & 0x80 != 0,
+                    autonomous: body[1] & 0x40 != 0,
+                    valid_lifetime: u32::from_be_bytes(array(body, 2)),
+                    preferred_lifetime: u32::from_be_bytes(array(body, 6)),
+                    prefix: array(body, 14),
+                },
+                // The redirected packet follows 6 reserved bytes.
+                4 => NdOption::RedirectedHeader(body[6..].to_vec()),
+                5 if body.len() == 6 => NdOption::Mtu(u32::from_be_bytes(array(body, 2))),
+                25 if body.len() >= 6 => NdOption::RecursiveDnsServer {
+                    lifetime: u32::from_be_bytes(array(body, 2)),
+                    servers: body[6..].chunks_exact(16).map(|server| array(server, 0)).collect(),
+                },
+                _ => NdOption::Unknown { option_type, data: body.to_vec() },
+            });
+            current += length;
+        }
+        Ok(options)
+    }
+}
+
+impl fmt::Display for NdOption {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NdOption::SourceLinkLayerAddress(address) => write!(f, "Source link-layer address = {}", mac(address)),
+            NdOption::TargetLinkLayerAddress(address) => write!(f, "Target link-layer address = {}", mac(address)),
+            NdOption::PrefixInformation { prefix_length, on_link, autonomous, valid_lifetime, preferred_lifetime, prefix } => {
+                write!(f, "Prefix information = {}/{}, on-link {}, autonomous {}, valid {} s, preferred {} s",
+                       Ipv6Addr::from(*prefix), prefix_length, *on_link as u8, *autonomous as u8, valid_lifetime,
+                       preferred_lifetime)
+            }
+            NdOption::RedirectedHeader(packet) => write!(f, "Redirected header = {} bytes", packet.len()),
+            NdOption::Mtu(mtu) => write!(f, "MTU = {}", mtu),
+            NdOption::RecursiveDnsServer { lifetime, servers } => {
+                let servers: Vec<String> = servers.iter().map(|server| Ipv6Addr::from(*server).to_string()).collect();
+                write!(f, "Recursive DNS servers = {}, lifetime {} s", servers.join(", "), lifetime)
+            }
+            NdOption::Unknown { option_type, data } => write!(f, "Option type {} = {} bytes", option_type, data.len()),
+        }
+    }
+}
+
+/// One multicast address record of an MLDv2 report.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MulticastAddressRecord {
+    /// Record type: 1 and 2 report the current mode, 3 to 6 a change of filter or sources.
+    pub record_type: u8,
+    pub multicast_address: [u8; 16],
+    pub sources: Vec<[u8; 16]>,
+}
+
+impl MulticastAddressRecord {
+    /// Parses the `count` address records of an MLDv2 report.
+    ///
+    /// # Errors
+    /// Returns `ParseError::Truncated` if a record runs past the end of `data`.
+    pub fn parse_all(data: &[u8], count: u16) -> Result<Vec<MulticastAddressRecord>, ParseError> {
+        let mut records = vec![];
+        let mut current = 0;
+        for _ in 0..count {
+            require(data, current + 20, Layer::ICMPv6)?;
+            let auxiliary_length = data[current + 1] as usize * 4;
+            let source_count = u16::from_be_bytes(array(data, current + 2)) as usize;
+            let length = 20 + source_count * 16 + auxiliary_length;
+            require(data, current + length, Layer::ICMPv6)?;
+            records.push(MulticastAddressRecord {
+                record_type: data[current],
+                multicast_address: array(data, current + 4),
+                sources: addresses(&data[current + 20..], source_count),
+            });
+            current += length;
+        }
+        Ok(records)
+    }
+}
+
+/// Reads `count` IPv6 addresses from the start of `data`, which must be long enough.
+fn addresses(data: &[u8], count: usize) -> Vec<[u8; 16]> {
+    data.chunks_exact(16).take(count).map(|address| array(address, 0)).collect()
+}
+
+pub struct ICMPv6Packet {
+    pub packet_type: u8,
+    pub code: u8,
+    pub checksum: [u8; 2],
+    pub checksum_status: ChecksumStatus,
+    pub message: ICMPv6Message,
+}
+
+impl Default for ICMPv6Packet {
+    fn default() -> Self {
+        ICMPv6Packet::new()
+    }
+}
+
+impl ICMPv6Packet {
+    pub fn new() -> ICMPv6Packet {
+        ICMPv6Packet {
+            packet_type: 0,
+            code: 0,
+            checksum: [0, 0],
+            checksum_status: ChecksumStatus::Unverified,
+            message: ICMPv6Message::Other(vec![]),
+        }
+    }
+
+    /// Parses an ICMPv6 message, including the options of Neighbor Discovery messages.
+    ///
+    /// # Errors
+    /// Returns `ParseError::Truncated` if `data` is shorter than the fixed part of the message, or
+    /// the error of a malformed option or MLDv2 record.
+    pub fn parse(data: &[u8]) -> Result<ICMPv6Packet, ParseError> {
+        require(data, 4, Layer::ICMPv6)?;
+        let mut icmp = ICMPv6Packet::new();
+        icmp.packet_type = data[0];
+        icmp.code = data[1];
+        icmp.checksum = array(data, 2);
+
+        let fixed_length = match icmp.packet_type {
+            1..=4 | 128 | 129 | 133 | 143 => 8,
+            134 => 16,
+            130..=132 | 135 | 136 => 24,
+            137 => 40,
+            _ => 4,
+        };
+        require(data, fixed_length, Layer::ICMPv6)?;
+        let word = || u32::from_be_bytes(array(data, 4));
+        let rest = data[fixed_length..].to_vec();
+        icmp.message = match icmp.packet_type {
+            128 | 129 => ICMPv6Message::Echo {
+                identifier: u16::from_be_bytes(array(data, 4)),
+                sequence_number: u16::from_be_bytes(array(data, 6)),
+                data: rest,
+            },
+            1 => ICMPv6Message::DestinationUnreachable { invoking_packet: rest },
+            2 => ICMPv6Message::PacketTooBig { mtu: word(), invoking_packet: rest },
+            3 => ICMPv6Message::TimeExceeded { invoking_packet: rest },
+            4 => ICMPv6Message::ParameterProblem { pointer: word(), invoking_packet: rest },
+            133 => ICMPv6Message::RouterSolicitation { options: NdOption::parse_all(&rest)? },
+            134 => ICMPv6Message::RouterAdvertisement {
+                hop_limit: data[4],
+                managed: data[5] & 0x80 != 0,
+                other_configuration: data[5] & 0x40 != 0,
+                router_lifetime: u16::from_be_bytes(array(data, 6)),
+                reachable_time: u32::from_be_bytes(array(data, 8)),
+                retransmit_timer: u32::from_be_bytes(array(data, 12)),
+                options: NdOption::parse_all(&rest)?,
+            },
+            135 => ICMPv6Message::NeighborSolicitation { target: array(data, 8), options: NdOption::parse_all(&rest)? },
+            136 => ICMPv6Message::NeighborAdvertisement {
+                router: data[4] & 0x80 != 0,
+                solicited: data[4] & 0x40 != 0,
+                override_entry: data[4] & 0x20 != 0,
+                target: array(data, 8),
+                options: NdOption::parse_all(&rest)?,
+            },
+            137 => ICMPv6Message::Redirect {
+                target: array(data, 8),
+                destination: array(data, 24),
+                options: NdOption::parse_all(&rest)?,
+            },
+            130 => {
+                // An MLDv2 query is told apart from an MLDv1 one by its length.
+                let sources = if data.len() >= 28 {
+                    let count = u16::from_be_bytes(array(data, 26)) as usize;
+                    require(data, 28 + count * 16, Layer::ICMPv6)?;
+                    Some(addresses(&data[28..], count))
+                } else {
+                    None
+                };
+                ICMPv6Message::MulticastListenerQuery {
+                    maximum_response_delay: u16::from_be_bytes(array(data, 4)),
+                    multicast_address: array(data, 8),
+                    sources,
+                }
+            }
+            131 => ICMPv6Message::MulticastListenerReport { multicast_address: array(data, 8) },
+            132 => ICMPv6Message::MulticastListenerDone { multicast_address: array(data, 8) },
+            143 => {
+                let count = u16::from_be_bytes(array(data, 6));
+                ICMPv6Message::MulticastListenerReportV2 { records: MulticastAddressRecord::parse_all(&rest, count)? }
+            }
+            _ => ICMPv6Message::Other(data[4..].to_vec()),
+        };
+        Ok(icmp)
+    }
+
+    /// Returns the name of the message type.
+    pub fn type_name(&self) -> &'static str {
+        match self.packet_type {
+            1 => "Destination Unreachable",
+            2 => "Packet Too Big",
+            3 => "Time Exceeded",
+            4 => "Parameter Problem",
+            128 => "Echo Request",
+            129 => "Echo Reply",
+            130 => "Multicast Listener Query",
+            131 => "Multicast Listener Report",
+            132 => "Multicast Listener Done",
+            133 => "Router Solicitation",
+            134 => "Router Advertisement",
+            135 => "Neighbor Solicitation",
+            136 => "Neighbor Advertisement",
+            137 => "Redirect",
+            143 => "Multicast Listener Report v2",
+            _ => "Unknown",
+        }
+    }
+}
+
+impl fmt::Display for ICMPv6Packet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "ICMPv6: -----ICMPv6 Header-----")?;
+        writeln!(f, "ICMPv6:")?;
+        writeln!(f, "ICMPv6: Type     = {} ({})", self.packet_type, self.type_name())?;
+        writeln!(f, "ICMPv6: Code     = {}", self.code)?;
+        writeln!(f, "ICMPv6: Checksum = 0x{:04x} {}", u16::from_be_bytes(self.checksum), self.checksum_status)?;
+        let mut options: &[NdOption] = &[];
+        match &self.message {
+            ICMPv6Message::Echo { identifier, sequence_number, data } => {
+                writeln!(f, "ICMPv6: Identifier = 0x{:04x}", identifier)?;
+                writeln!(f, "ICMPv6: Sequence number = {}", sequence_number)?;
+                writeln!(f, "ICMPv6: Data     = {} bytes", data.len())?;
+            }
+            ICMPv6Message::DestinationUnreachable { invoking_packet } | ICMPv6Message::TimeExceeded { invoking_packet } => {
+                writeln!(f, "ICMPv6: Invoking packet = {} bytes", invoking_packet.len())?;
+            }
+            ICMPv6Message::PacketTooBig { mtu, invoking_packet } => {
+                writeln!(f, "ICMPv6: MTU      = {}", mtu)?;
+                writeln!(f, "ICMPv6: Invoking packet = {} bytes", invoking_packet.len())?;
+            }
+            ICMPv6Message::ParameterProblem { pointer, invoking_packet } => {
+                writeln!(f, "ICMPv6: Pointer  = {}", pointer)?;
+                writeln!(f, "ICMPv6: Invoking packet = {} bytes", invoking_packet.len())?;
+            }
+            ICMPv6Message::RouterSolicitation { options: nd_options } => options = nd_options,
+            ICMPv6Message::RouterAdvertisement {
+                hop_limit, managed, other_configuration, router_lifetime, reachable_time, retransmit_timer,
+                options: nd_options,
+            } => {
+                writeln!(f, "ICMPv6: Cur hop limit    = {}", hop_limit)?;
+                writeln!(f, "ICMPv6: Managed address configuration = {}", *managed as u8)?;
+                writeln!(f, "ICMPv6: Other configuration = {}", *other_configuration as u8)?;
+                writeln!(f, "ICMPv6: Router lifetime  = {} s", router_lifetime)?;
+                writeln!(f, "ICMPv6: Reachable time   = {} ms", reachable_time)?;
+                writeln!(f, "ICMPv6: Retransmit timer = {} ms", retransmit_timer)?;
+                options = nd_options;
+            }
+            ICMPv6Message::NeighborSolicitation { target, options: nd_options } => {
+                writeln!(f, "ICMPv6: Target address = {}", Ipv6Addr::from(*target))?;
+                options = nd_options;
+            }
+            ICMPv6Message::NeighborAdvertisement { router, solicited, override_entry, target, options: nd_options } => {
+                writeln!(f, "ICMPv6: Flags    = router {}, solicited {}, override {}", *router as u8, *solicited as u8,
+                         *override_entry as u8)?;
+                writeln!(f, "ICMPv6: Target address = {}", Ipv6Addr::from(*target))?;
+                options = nd_options;
+            }
+            ICMPv6Message::Redirect { target, destination, options: nd_options } => {
+                writeln!(f, "ICMPv6: Target address = {}", Ipv6Addr::from(*target))?;
+                writeln!(f, "ICMPv6: Destination address = {}", Ipv6Addr::from(*destination))?;
+                options = nd_options;
+            }
+            ICMPv6Message::MulticastListenerQuery { maximum_response_delay, multicast_address, sources } => {
+                writeln!(f, "ICMPv6: Maximum response delay = {} ms", maximum_response_delay)?;
+                writeln!(f, "ICMPv6: Multicast address = {}", Ipv6Addr::from(*multicast_address))?;
+                if let Some(sources) = sources {
+                    writeln!(f, "ICMPv6: Sources  = {}", address_list(sources))?;
+                }
+            }
+            ICMPv6Message::MulticastListenerReport { multicast_address }
+            | ICMPv6Message::MulticastListenerDone { multicast_address } => {
+                writeln!(f, "ICMPv6: Multicast address = {}", Ipv6Addr::from(*multicast_address))?;
+            }
+            ICMPv6Message::MulticastListenerReportV2 { records } => {
+                for record in records {
+                    writeln!(f, "ICMPv6: Record   = type {}, {}, sources {}", record.record_type,
+                             Ipv6Addr::from(record.multicast_address), address_list(&record.sources))?;
+                }
+            }
+            ICMPv6Message::Other(data) => writeln!(f, "ICMPv6: Data     = {} bytes", data.len())?,
+        }
+        for option in options {
+            writeln!(f, "ICMPv6: {}", option)?;
+        }
+        write!(f, "ICMPv6:")
+    }
+}
+
+/// Formats a list of IPv6 addresses, or `none` for an empty one.
+fn address_list(addresses: &[[u8; 16]]) -> String {
+    if addresses.is_empty() {
+        return "none".to_string();
+    }
+    let addresses: Vec<String> = addresses.iter().map(|address| Ipv6Addr::from(*address).to_string()).collect();
+    addresses.join(", ")
+}
+
+/// Formats a link-layer address as colon-separated hex bytes.
+fn mac(address: &[u8]) -> String {
+    let bytes: Vec<String> = address.iter().map(|b| format!("{:02x}", b)).collect();
+    bytes.join(":")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(text: &str) -> [u8; 16] {
+        text.parse::<Ipv6Addr>().unwrap().octets()
+    }
+
+    #[test]
+    fn nd_options_are_read_by_type() {
+        let data = [
+            [1, 1, 0x02, 0, 0, 0, 0, 1].as_slice(),
+            // Prefix 2001:db8::/64, on-link and autonomous.
+            &[3, 4, 64, 0xc0, 0, 0, 0x0e, 0x10, 0, 0, 0x07, 0x08, 0, 0, 0, 0],
+            &address("2001:db8::"),
+            &[5, 1, 0, 0, 0, 0, 0x05, 0xdc],
+            &[25, 5, 0, 0, 0, 0, 0x01, 0x2c],
+            &address("2001:db8::53"),
+            &address("2001:db8::54"),
+            &[14, 1, 1, 2, 3, 4, 5, 6],
+        ].concat();
+        let options = NdOption::parse_all(&data).unwrap();
+        assert_eq!(options, [
+            NdOption::SourceLinkLayerAddress(vec![0x02, 0, 0, 0, 0, 1]),
+            NdOption::PrefixInformation {
+                prefix_length: 64,
+                on_link: true,
+                autonomous: true,
+                valid_lifetime: 3600,
+                preferred_lifetime: 1800,
+                prefix: address("2001:db8::"),
+            },
+            NdOption::Mtu(1500),
+            NdOption::RecursiveDnsServer { lifetime: 300, servers: vec![address("2001:db8::53"), address("2001:db8::54")] },
+            NdOption::Unknown { option_type: 14, data: vec![1, 2, 3, 4, 5, 6] },
+        ]);
+        assert_eq!(options[0].to_string(), "Source link-layer address = 02:00:00:00:00:01");
+        assert_eq!(options[1].to_string(),
+                   "Prefix information = 2001:db8::/64, on-link 1, autonomous 1, valid 3600 s, preferred 1800 s");
+    }
+
+    #[test]
+    fn malformed_nd_options_are_errors() {
+        // A zero length would never advance.
+        assert!(matches!(NdOption::parse_all(&[1, 0, 0, 0, 0, 0, 0, 0]),
+                         Err(ParseError::BadHeaderLength { layer: Layer::ICMPv6, length: 0 })));
+        assert!(matches!(NdOption::parse_all(&[1, 2, 0, 0, 0, 0, 0, 0]),
+                         Err(ParseError::Truncated { layer: Layer::ICMPv6, needed: 16, available: 8 })));
+        assert!(matches!(NdOption::parse_all(&[1]), Err(ParseError::Truncated { needed: 2, .. })));
+    }
+
+    #[test]
+    fn neighbor_discovery_messages_carry_their_options() {
+        let mut data = vec![136, 0, 0, 0, 0xe0, 0, 0, 0];
+        data.extend_from_slice(&address("fe80::1"));
+        data.extend_from_slice(&[2, 1, 0x02, 0, 0, 0, 0, 1]);
+        let icmp = ICMPv6Packet::parse(&data).unwrap();
+        assert_eq!(icmp.type_name(), "Neighbor Advertisement");
+        assert_eq!(icmp.message, ICMPv6Message::NeighborAdvertisement {
+            router: true,
+            solicited: true,
+            override_entry: true,
+            target: address("fe80::1"),
+            options: vec![NdOption::TargetLinkLayerAddress(vec![0x02, 0, 0, 0, 0, 1])],
+        });
+
+        let data = [134, 0, 0, 0, 64, 0x80, 0x07, 0x08, 0, 0, 0x75, 0x30, 0, 0, 0x03, 0xe8, 5, 1, 0, 0, 0, 0, 0x05, 0xdc];
+        let icmp = ICMPv6Packet::parse(&data).unwrap();
+        assert_eq!(icmp.message, ICMPv6Message::RouterAdvertisement {
+            hop_limit: 64,
+            managed: true,
+            other_configuration: false,
+            router_lifetime: 1800,
+            reachable_time: 30000,
+            retransmit_timer: 1000,
+            options: vec![NdOption::Mtu(1500)],
+        });
+
+        // A Neighbor Solicitation must hold its target address.
+        assert!(matches!(ICMPv6Packet::parse(&[135, 0, 0, 0, 0, 0, 0, 0]),
+                         Err(ParseError::Truncated { needed: 24, available: 8, .. })));
+    }
+
+    #[test]
+    fn mld_queries_are_told_apart_by_length() {
+        let mut query = vec![130, 0, 0, 0, 0x27, 0x10, 0, 0];
+        query.extend_from_slice(&address("ff02::fb"));
+        let icmp = ICMPv6Packet::parse(&query).unwrap();
+        assert_eq!(icmp.message, ICMPv6Message::MulticastListenerQuery {
+            maximum_response_delay: 10000,
+            multicast_address: address("ff02::fb"),
+            sources: None,
+        });
+
+        query.extend_from_slice(&[0x02, 125, 0, 1]);
+        query.extend_from_slice(&address("2001:db8::1"));
+        let icmp = ICMPv6Packet::parse(&query).unwrap();
+        let ICMPv6Message::MulticastListenerQuery { sources, .. } = icmp.message else { panic!("not a query") };
+        assert_eq!(sources, Some(vec![address("2001:db8::1")]));
+
+        // The source count claims more addresses than were sent.
+        query[27] = 2;
+        assert!(matches!(ICMPv6Packet::parse(&query), Err(ParseError::Truncated { needed: 60, .. })));
+    }
+
+    #[test]
+    fn mldv2_reports_list_their_records() {
+        let mut report = vec![143, 0, 0, 0, 0, 0, 0, 2];
+        // CHANGE_TO_EXCLUDE with no sources, then ALLOW_NEW_SOURCES with one source and 4 bytes of
+        // auxiliary data.
+        report.extend_from_slice(&[4, 0, 0, 0]);
+        report.extend_from_slice(&address("ff02::fb"));
+        report.extend_from_slice(&[5, 1, 0, 1]);
+        report.extend_from_slice(&address("ff3e::1234"));
+        report.extend_from_slice(&address("2001:db8::1"));
+        report.extend_from_slice(&[0xaa; 4]);
+        let icmp = ICMPv6Packet::parse(&report).unwrap();
+        assert_eq!(icmp.message, ICMPv6Message::MulticastListenerReportV2 { records: vec![
+            MulticastAddressRecord { record_type: 4, multicast_address: address("ff02::fb"), sources: vec![] },
+            MulticastAddressRecord {
+                record_type: 5,
+                multicast_address: address("ff3e::1234"),
+                sources: vec![address("2001:db8::1")],
+            },
+        ]});
+        assert!(icmp.to_string().contains("ICMPv6: Record   = type 5, ff3e::1234, sources 2001:db8::1"));
+
+        // The auxiliary data is part of the record, so cutting it off is an error.
+        assert!(matches!(ICMPv6Packet::parse(&report[..report.len() - 1]), Err(ParseError::Truncated { .. })));
+    }
+}
@@ -1,34 +1,56 @@
 use std::fmt;
-use crate::{IPProtocol, IPVersion, ProtocolDatagram};
-
-
-
-pub(crate) struct IPacket {
-    pub(crate) version: IPVersion,
-    pub(crate) ihl: u8,
-    //pub(crate) header length
-    pub(crate) tos: u8,
-    pub(crate) precedence: u8,
-    pub(crate) delay: u8,
-    pub(crate) throughput: u8,
-    pub(crate) reliability: u8,
-    pub(crate) total_length: [u8; 2],
-    pub(crate) identification: [u8; 2],
-    pub(crate) reserved_flag: u8,
-    pub(crate) do_not_fragment_flag: u8,
-    pub(crate) last_fragment_flag: u8,
-    pub(crate) fragment_offset: u16,
-    pub(crate) ttl: u8,
-    pub(crate) protocol: IPProtocol,
-    pub(crate) header_checksum: [u8; 2],
-    pub(crate) source_add: [u8; 4],
-    pub(crate) destination_add: [u8; 4],
-    pub(crate) options: Option<Vec<u8>>,
-    pub(crate) datagram: ProtocolDatagram,
+use bitreader::BitReader;
+use crate::checksum::ipv4_pseudo_header;
+use crate::parser::{array, require};
+use crate::{ChecksumStatus, IPProtocol, IPVersion, Ipv4Option, Layer, ParseError, ProtocolDatagram};
+
+/// Length of an IPv4 header without options.
+const MIN_HEADER_LENGTH: usize = 20;
+
+
+
+pub struct IPacket {
+    pub version: IPVersion,
+    pub ihl: u8,
+    //pub header length
+    pub tos: u8,
+    pub precedence: u8,
+    pub delay: u8,
+    pub throughput: u8,
+    pub reliability: u8,
+    pub total_length: [u8; 2],
+    pub identification: [u8; 2],
+    pub reserved_flag: u8,
+    pub do_not_fragment_flag: u8,
+    pub last_fragment_flag: u8,
+    pub fragment_offset: u16,
+    pub ttl: u8,
+    pub protocol: IPProtocol,
+    /// The protocol field as sent, which `protocol` maps to `IPProtocol::Default` for the protocols
+    /// that are not decoded.
+    pub protocol_number: u8,
+    pub header_checksum: [u8; 2],
+    pub header_checksum_status: ChecksumStatus,
+    pub source_add: [u8; 4],
+    pub destination_add: [u8; 4],
+    pub options: Vec<Ipv4Option>,
+    /// The datagram carried by the packet, or `ProtocolDatagram::Fragment` for a fragment that has
+    /// not been reassembled.
+    pub datagram: ProtocolDatagram,
+    /// Number of fragments `datagram` was reassembled from, 0 when the packet was not fragmented.
+    pub reassembled_fragments: usize,
+    /// Whether the capture cut the packet short of its total length, so the payload is incomplete.
+    pub truncated: bool,
+}
+
+impl Default for IPacket {
+    fn default() -> Self {
+        IPacket::new()
+    }
 }
 
 impl IPacket {
-    pub(crate) fn new() -> IPacket {
+    pub fn new() -> IPacket {
         IPacket {
             version: IPVersion::V4,
             ihl: 0,
@@ -44,43 +66,168 @@ impl IPacket {
             fragment_offset: 0,
             ttl: 0,
             protocol: IPProtocol::Default,
+            protocol_number: 0,
             header_checksum: [0, 0],
+            header_checksum_status: ChecksumStatus::Unverified,
             source_add: [0, 0, 0, 0],
             destination_add: [0, 0, 0, 0],
-            options: None,
-            datagram: ProtocolDatagram::Default("placeholder".parse().unwrap()),
+            options: vec![],
+            datagram: ProtocolDatagram::new(),
             last_fragment_flag: 0,
+            reassembled_fragments: 0,
+            truncated: false,
         }
     }
+
+    /// Returns whether the packet carries a loose or strict source route option.
+    pub fn is_source_routed(&self) -> bool {
+        self.options.iter().any(Ipv4Option::is_source_route)
+    }
+
+    /// Parses an IPv4 packet and the TCP, UDP or ICMP datagram it carries.
+    ///
+    /// The payload of a fragment is kept undecoded in `ProtocolDatagram::Fragment`; an
+    /// `Ipv4Reassembler` decodes it once all the fragments have arrived.
+    ///
+    /// # Arguments
+    /// * `data` - The raw bytes of the packet, starting at the IP header.
+    ///
+    /// # Errors
+    /// Returns `ParseError::BadVersion` if the version field is not 4, `ParseError::BadHeaderLength`
+    /// if the header length field is below 20 bytes, or `ParseError::Truncated` if the header or
+    /// the datagram header ends early. A malformed option is kept as `Ipv4Option::Malformed`.
+    pub fn parse(data: &[u8]) -> Result<IPacket, ParseError> {
+        require(data, MIN_HEADER_LENGTH, Layer::IPv4)?;
+
+        let ipv = data[0] >> 4;
+        let ihl = data[0] & 0x0f;
+
+        if ipv != 4 {
+            return Err(ParseError::BadVersion { layer: Layer::IPv4, version: ipv });
+        }
+        let header_length = ihl as usize * 4;
+        if header_length < MIN_HEADER_LENGTH {
+            return Err(ParseError::BadHeaderLength { layer: Layer::IPv4, length: header_length });
+        }
+        require(data, header_length, Layer::IPv4)?;
+
+        let temp = [data[1]];
+        let mut type_of_service = BitReader::new(&temp);
+        let precedence = type_of_service.read_u8(3).unwrap();
+        let delay = type_of_service.read_u8(1).unwrap();
+        let throughput = type_of_service.read_u8(1).unwrap();
+        let reliability = type_of_service.read_u8(1).unwrap();
+        let tos = type_of_service.read_u8(2).unwrap();
+
+        let total_length: [u8; 2] = array(data, 2);
+        let identification: [u8; 2] = array(data, 4);
+
+        let temp = [data[6], data[7]];
+        let mut flags = BitReader::new(&temp);
+        let reserved_flag = flags.read_u8(1).unwrap();
+        let do_not_fragment_flag = flags.read_u8(1).unwrap();
+        let last_fragment_flag = flags.read_u8(1).unwrap();
+        //Here first 3 bits are flags and rest 13 are Fragment offset, in units of 8 bytes
+        let fragment_offset = flags.read_u16(13).unwrap() * 8;
+
+        let ttl = data[8];
+        let protocol = IPProtocol::from(data[9]);
+        let header_checksum: [u8; 2] = array(data, 10);
+        let header_checksum_status = ChecksumStatus::verify(&[], &data[..header_length], 10);
+
+        let source_add: [u8; 4] = array(data, 12);
+        let destination_add: [u8; 4] = array(data, 16);
+
+        let options = Ipv4Option::parse_all(&data[MIN_HEADER_LENGTH..header_length]);
+
+        // Ethernet pads short frames, so the payload ends at the total length when it is sane.
+        let mut end = data.len();
+        let total = u16::from_be_bytes(total_length) as usize;
+        if total >= header_length && total < end {
+            end = total;
+        }
+        let payload = &data[header_length..end];
+        // Only a whole datagram can be dissected, so fragments are kept as they are until reassembly.
+        let fragmented = last_fragment_flag == 1 || fragment_offset != 0;
+        let datagram = if fragmented {
+            ProtocolDatagram::Fragment(payload.to_vec())
+        } else {
+            let mut datagram = ProtocolDatagram::parse(&protocol, payload)?;
+            // The checksum covers the whole datagram, so it can only be verified when nothing was
+            // cut off by the snap length.
+            if total >= header_length && end == total {
+                datagram.verify_checksum(payload, &ipv4_pseudo_header(source_add, destination_add, data[9], payload.len()));
+            }
+            datagram
+        };
+
+        Ok(IPacket {
+            version: IPVersion::V4,
+            ihl,
+            tos,
+            precedence,
+            delay,
+            throughput,
+            reliability,
+            total_length,
+            identification,
+            reserved_flag,
+            do_not_fragment_flag,
+            last_fragment_flag,
+            fragment_offset,
+            ttl,
+            protocol,
+            protocol_number: data[9],
+            header_checksum,
+            header_checksum_status,
+            source_add,
+            destination_add,
+            options,
+            datagram,
+            reassembled_fragments: 0,
+            truncated: total > data.len(),
+        })
+    }
 }
 
 impl fmt::Display for IPacket {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "IP: -----IP Header-----\n")?;
-        write!(f, "IP:\n")?;
-        write!(f, "IP: Version         = {}\n", self.version)?;
-        write!(f, "IP: Header length   = {} bytes\n", (self.ihl * 4))?;
-        write!(f, "IP: Type of service = 0x{:x}\n", self.tos)?;
-        write!(f, "IP:     xxx. ....   = {}(precedence)\n", self.precedence)?;
-        write!(f, "IP:     ...{} ....  = {} delay \n", self.delay, if self.delay == 0 {"normal"} else { "low"})?;
-        write!(f, "IP:     .... {}...  = {} throughput\n", self.throughput, if self.throughput == 0 {"normal"} else { "high"})?;
-        write!(f, "IP:     .... .{}..  = {} reliability\n", self.reliability, if self.reliability == 0 {"normal"} else { "high"})?;
-        write!(f, "IP: Total length    = {} bytes\n", u16::from_be_bytes(self.total_length))?;
-        write!(f, "IP: Identification  = {}\n", u16::from_be_bytes(self.identification))?;
-        write!(f, "IP: Flags: \n")?;
-        write!(f, "IP:     {}... ....  = {}\n", self.reserved_flag, if self.reserved_flag == 0 {"reserved"} else {"not reserved"})?;
-        write!(f, "IP:     .{}.. ....  = {}fragment\n", self.do_not_fragment_flag, if self.do_not_fragment_flag == 1 {"do not "} else {""})?;
-        write!(f, "IP:     ..{}. ....  = last fragment\n", self.last_fragment_flag)?;
-        write!(f, "IP: Fragment offset = {} bytes\n", self.fragment_offset)?;
-        write!(f, "IP: Time to live    = {} seconds/hops\n", self.ttl)?;
-        write!(f, "IP: Protocol        = {}\n", self.protocol)?;
-        write!(f, "IP: Header checksum = 0x{:x}{:x}\n", self.header_checksum[0], self.header_checksum[1])?;
-        write!(f, "IP: Source address  = {}\n", format!("{}.{}.{}.{}", self.source_add[0], self.source_add[1], self.source_add[2], self.source_add[3]))?;
-        write!(f, "IP: Destination address= {}\n", format!("{}.{}.{}.{}", self.destination_add[0], self.destination_add[1], self.destination_add[2], self.destination_add[3]))?;
-        match self.options.clone() {
-            None => write!(f, "No options\n")?,
-            Some(op) => write!(f, "Options: {}\n", op.len())?
-        };
+        writeln!(f, "IP: -----IP Header-----")?;
+        writeln!(f, "IP:")?;
+        writeln!(f, "IP: Version         = {}", self.version)?;
+        writeln!(f, "IP: Header length   = {} bytes", (self.ihl * 4))?;
+        writeln!(f, "IP: Type of service = 0x{:x}", self.tos)?;
+        writeln!(f, "IP:     xxx. ....   = {}(precedence)", self.precedence)?;
+        writeln!(f, "IP:     ...{} ....  = {} delay ", self.delay, if self.delay == 0 {"normal"} else { "low"})?;
+        writeln!(f, "IP:     .... {}...  = {} throughput", self.throughput, if self.throughput == 0 {"normal"} else { "high"})?;
+        writeln!(f, "IP:     .... .{}..  = {} reliability", self.reliability, if self.reliability == 0 {"normal"} else { "high"})?;
+        writeln!(f, "IP: Total length    = {} bytes", u16::from_be_bytes(self.total_length))?;
+        writeln!(f, "IP: Identification  = {}", u16::from_be_bytes(self.identification))?;
+        writeln!(f, "IP: Flags: ")?;
+        writeln!(f, "IP:     {}... ....  = {}", self.reserved_flag, if self.reserved_flag == 0 {"reserved"} else {"not reserved"})?;
+        writeln!(f, "IP:     .{}.. ....  = {}fragment", self.do_not_fragment_flag, if self.do_not_fragment_flag == 1 {"do not "} else {""})?;
+        writeln!(f, "IP:     ..{}. ....  = {}", self.last_fragment_flag, if self.last_fragment_flag == 1 {"more fragments"} else {"last fragment"})?;
+        writeln!(f, "IP: Fragment offset = {} bytes", self.fragment_offset)?;
+        writeln!(f, "IP: Time to live    = {} seconds/hops", self.ttl)?;
+        writeln!(f, "IP: Protocol        = {}", self.protocol)?;
+        writeln!(f, "IP: Header checksum = 0x{:04x} {}", u16::from_be_bytes(self.header_checksum),
+                 self.header_checksum_status)?;
+        writeln!(f, "IP: Source address  = {}.{}.{}.{}", self.source_add[0], self.source_add[1], self.source_add[2], self.source_add[3])?;
+        writeln!(f, "IP: Destination address= {}.{}.{}.{}", self.destination_add[0], self.destination_add[1], self.destination_add[2], self.destination_add[3])?;
+        if self.options.is_empty() {
+            writeln!(f, "IP: No options")?;
+        } else {
+            writeln!(f, "IP: Options         = {} bytes", self.ihl as usize * 4 - MIN_HEADER_LENGTH)?;
+            for option in &self.options {
+                writeln!(f, "IP:     {}", option)?;
+            }
+            if self.is_source_routed() {
+                writeln!(f, "IP: [Source routed packet]")?;
+            }
+        }
+        if self.reassembled_fragments > 0 {
+            writeln!(f, "IP: [Reassembled from {} fragments]", self.reassembled_fragments)?;
+        }
         write!(f, "{}", self.datagram)
     }
 }
\ No newline at end of file
@@ -1,16 +1,87 @@
 use std::fmt;
+use crate::checksum::{self, IPV4_PSEUDO_HEADER_LENGTH};
+use crate::parser::{array, require};
+use crate::{ChecksumStatus, ICMPv6Packet, IPProtocol, Layer, ParseError, TcpAnalysis, TcpFlags, TcpOption};
 
-pub(crate) enum ProtocolDatagram {
+pub enum ProtocolDatagram {
     TCP(TCPPacket),
     UDP(UDPPacket),
     ICMP(ICMPPacket),
+    ICMPv6(ICMPv6Packet),
+    /// Part of a fragmented datagram, which cannot be dissected on its own.
+    Fragment(Vec<u8>),
     Default(String),
 }
 
+impl Default for ProtocolDatagram {
+    fn default() -> Self {
+        ProtocolDatagram::new()
+    }
+}
+
 impl ProtocolDatagram {
-    pub(crate) fn new() -> ProtocolDatagram {
+    pub fn new() -> ProtocolDatagram {
         ProtocolDatagram::Default("This is the default value".parse().unwrap())
     }
+
+    /// Parses the datagram carried by an IP packet according to its protocol.
+    ///
+    /// # Arguments
+    /// * `protocol` - The protocol field of the enclosing IP header.
+    /// * `data` - The raw bytes of the IP payload.
+    ///
+    /// # Errors
+    /// Returns the error of the TCP, UDP, ICMP or ICMPv6 decoder.
+    pub fn parse(protocol: &IPProtocol, data: &[u8]) -> Result<ProtocolDatagram, ParseError> {
+        match protocol {
+            IPProtocol::ICMP => Ok(ProtocolDatagram::ICMP(ICMPPacket::parse(data)?)),
+            IPProtocol::ICMPv6 => Ok(ProtocolDatagram::ICMPv6(ICMPv6Packet::parse(data)?)),
+            IPProtocol::TCP => Ok(ProtocolDatagram::TCP(TCPPacket::parse(data)?)),
+            IPProtocol::UDP => Ok(ProtocolDatagram::UDP(UDPPacket::parse(data)?)),
+            IPProtocol::Default => Ok(ProtocolDatagram::new()),
+        }
+    }
+
+    /// Verifies the checksum of the datagram and records the result on it.
+    ///
+    /// The IP layer calls this only when it holds the whole datagram, so that a datagram cut short
+    /// by the snap length or split into fragments stays unverified.
+    ///
+    /// # Arguments
+    /// * `data` - The raw bytes of the datagram, as passed to `parse`.
+    /// * `pseudo_header` - The pseudo-header of the enclosing IPv4 or IPv6 header.
+    pub fn verify_checksum(&mut self, data: &[u8], pseudo_header: &[u8]) {
+        match self {
+            ProtocolDatagram::TCP(tcp) => tcp.checksum_status = ChecksumStatus::verify(pseudo_header, data, 16),
+            ProtocolDatagram::UDP(udp) => {
+                // Over IPv4 a zero checksum means none was computed, over IPv6 it is always an error.
+                // A computed zero is sent as 0xffff, so that is the value it should hold then.
+                udp.checksum_status = match (udp.checksum, pseudo_header.len() == IPV4_PSEUDO_HEADER_LENGTH) {
+                    ([0, 0], true) => ChecksumStatus::Absent,
+                    ([0, 0], false) => match checksum::expected(pseudo_header, data, 6) {
+                        0 => ChecksumStatus::Incorrect { expected: 0xffff },
+                        expected => ChecksumStatus::Incorrect { expected },
+                    },
+                    _ => ChecksumStatus::verify(pseudo_header, data, 6),
+                };
+            }
+            // ICMP for IPv4 covers no pseudo-header.
+            ProtocolDatagram::ICMP(icmp) => icmp.checksum_status = ChecksumStatus::verify(&[], data, 2),
+            ProtocolDatagram::ICMPv6(icmp) => icmp.checksum_status = ChecksumStatus::verify(pseudo_header, data, 2),
+            ProtocolDatagram::Fragment(_) | ProtocolDatagram::Default(_) => {}
+        }
+    }
+
+    /// Returns the result of verifying the checksum of the datagram, if it has one.
+    pub fn checksum_status(&self) -> Option<ChecksumStatus> {
+        match self {
+            ProtocolDatagram::TCP(tcp) => Some(tcp.checksum_status),
+            ProtocolDatagram::UDP(udp) => Some(udp.checksum_status),
+            ProtocolDatagram::ICMP(icmp) => Some(icmp.checksum_status),
+            ProtocolDatagram::ICMPv6(icmp) => Some(icmp.checksum_status),
+            ProtocolDatagram::Fragment(_) | ProtocolDatagram::Default(_) => None,
+        }
+    }
 }
 
 impl fmt::Display for ProtocolDatagram {
@@ -19,30 +90,40 @@ impl fmt::Display for ProtocolDatagram {
             ProtocolDatagram::TCP(packet) => write!(f, "{}", packet),
             ProtocolDatagram::UDP(packet) => write!(f, "{}", packet),
             ProtocolDatagram::ICMP(packet) => write!(f, "{}", packet),
+            ProtocolDatagram::ICMPv6(packet) => write!(f, "{}", packet),
+            ProtocolDatagram::Fragment(data) => write!(f, "Fragment data = {} bytes", data.len()),
             ProtocolDatagram::Default(_) => write!(f, "This is a placeholder")
         }
     }
 }
 
 
-pub(crate) struct ICMPPacket {
-    pub(crate) packet_type: u8,
-    pub(crate) code: u8,
-    pub(crate) checksum: [u8; 2],
-    pub(crate) identifier_be: [u8; 2],
-    pub(crate) identifier_le: [u8; 2],
-    pub(crate) sequence_be: [u8; 2],
-    pub(crate) sequence_le: [u8; 2],
-    pub(crate) timestamp: [u8; 8],
-    pub(crate) data: Vec<u8>,
+pub struct ICMPPacket {
+    pub packet_type: u8,
+    pub code: u8,
+    pub checksum: [u8; 2],
+    pub checksum_status: ChecksumStatus,
+    pub identifier_be: [u8; 2],
+    pub identifier_le: [u8; 2],
+    pub sequence_be: [u8; 2],
+    pub sequence_le: [u8; 2],
+    pub timestamp: [u8; 8],
+    pub data: Vec<u8>,
+}
+
+impl Default for ICMPPacket {
+    fn default() -> Self {
+        ICMPPacket::new()
+    }
 }
 
 impl ICMPPacket {
-    pub(crate) fn new() -> ICMPPacket {
+    pub fn new() -> ICMPPacket {
         ICMPPacket {
             packet_type: 0,
             code: 0,
             checksum: [0, 0],
+            checksum_status: ChecksumStatus::Unverified,
             identifier_be: [0, 0],
             identifier_le: [0, 0],
             sequence_be: [0, 0],
@@ -51,100 +132,213 @@ impl ICMPPacket {
             data: vec![],
         }
     }
+
+    /// Parses an ICMP message.
+    ///
+    /// The 8-byte timestamp is only read when the message is long enough to carry one, as echo
+    /// messages sent by ping do; otherwise everything after the header is kept as data.
+    ///
+    /// # Errors
+    /// Returns `ParseError::Truncated` if `data` is shorter than the 8-byte ICMP header.
+    pub fn parse(data: &[u8]) -> Result<ICMPPacket, ParseError> {
+        require(data, 8, Layer::ICMP)?;
+        let mut icmp = ICMPPacket::new();
+        icmp.packet_type = data[0];
+        icmp.code = data[1];
+        icmp.checksum = array(data, 2);
+        icmp.identifier_be = array(data, 4);
+        icmp.identifier_le = array(data, 4);
+        icmp.sequence_be = array(data, 6);
+        icmp.sequence_le = array(data, 6);
+        if data.len() >= 16 {
+            icmp.timestamp = array(data, 8);
+            icmp.data = data[16..].to_vec();
+        } else {
+            icmp.data = data[8..].to_vec();
+        }
+        Ok(icmp)
+    }
 }
 
 impl fmt::Display for ICMPPacket {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "ICMP: -----ICMP Header-----\n")?;
-        write!(f, "ICMP:\n")?;
-        write!(f, "ICMP: type= {}\n", self.packet_type)?;
-        write!(f, "ICMP: Code= {}\n", self.code)?;
-        write!(f, "ICMP: checksum= 0x{:x}{:x}\n", self.checksum[0], self.checksum[1])?;
+        writeln!(f, "ICMP: -----ICMP Header-----")?;
+        writeln!(f, "ICMP:")?;
+        writeln!(f, "ICMP: type= {}", self.packet_type)?;
+        writeln!(f, "ICMP: Code= {}", self.code)?;
+        writeln!(f, "ICMP: checksum= 0x{:04x} {}", u16::from_be_bytes(self.checksum), self.checksum_status)?;
         write!(f, "ICMP:")
     }
 }
 
 
-pub(crate) struct UDPPacket {
-    pub(crate) source_port: [u8; 2],
-    pub(crate) destination_port: [u8; 2],
-    pub(crate) length: [u8; 2],
-    pub(crate) checksum: [u8; 2],
-    pub(crate) data: Vec<u8>,
+pub struct UDPPacket {
+    pub source_port: [u8; 2],
+    pub destination_port: [u8; 2],
+    pub length: [u8; 2],
+    pub checksum: [u8; 2],
+    pub checksum_status: ChecksumStatus,
+    pub data: Vec<u8>,
+}
+
+impl Default for UDPPacket {
+    fn default() -> Self {
+        UDPPacket::new()
+    }
 }
 
 impl UDPPacket {
-    pub(crate) fn new() -> UDPPacket {
+    pub fn new() -> UDPPacket {
         UDPPacket {
             source_port: [0, 0],
             destination_port: [0, 0],
             length: [0, 0],
             checksum: [0, 0],
+            checksum_status: ChecksumStatus::Unverified,
             data: vec![],
         }
     }
+
+    /// Parses a UDP datagram.
+    ///
+    /// # Errors
+    /// Returns `ParseError::Truncated` if `data` is shorter than the 8-byte UDP header.
+    pub fn parse(data: &[u8]) -> Result<UDPPacket, ParseError> {
+        require(data, 8, Layer::UDP)?;
+        let mut udp = UDPPacket::new();
+        udp.source_port = array(data, 0);
+        udp.destination_port = array(data, 2);
+        udp.length = array(data, 4);
+        udp.checksum = array(data, 6);
+        udp.data = data[8..].to_vec();
+        Ok(udp)
+    }
 }
 
 impl fmt::Display for UDPPacket {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "UDP: -----UDP Header-----\n")?;
-        write!(f, "UDP:\n")?;
-        write!(f, "UDP: Source port      = {}\n", u16::from_be_bytes(self.source_port))?;
-        write!(f, "UDP: Destination port = {}\n", u16::from_be_bytes(self.destination_port))?;
-        write!(f, "UDP: Length           = {}\n", u16::from_be_bytes(self.length))?;
-        write!(f, "UDP: Checksum         = 0x{:x}{:x}\n", self.checksum[0], self.checksum[1])?;
+        writeln!(f, "UDP: -----UDP Header-----")?;
+        writeln!(f, "UDP:")?;
+        writeln!(f, "UDP: Source port      = {}", u16::from_be_bytes(self.source_port))?;
+        writeln!(f, "UDP: Destination port = {}", u16::from_be_bytes(self.destination_port))?;
+        writeln!(f, "UDP: Length           = {}", u16::from_be_bytes(self.length))?;
+        writeln!(f, "UDP: Checksum         = 0x{:04x} {}", u16::from_be_bytes(self.checksum), self.checksum_status)?;
         write!(f, "UDP:")
     }
 }
 
 
-pub(crate) struct TCPPacket {
-    pub(crate) source_port: [u8; 2],
-    pub(crate) destination_port: [u8; 2],
-    pub(crate) sequence_number: [u8; 4],
-    pub(crate) acknowledgement_number: [u8; 4],
-    pub(crate) data_offset: u8, //Taken from same bit as flag
-    pub(crate) flags: u8, //Taken from the same bit as data_offset
-    pub(crate) window: [u8; 2],
-    pub(crate) checksum: [u8; 2],
-    pub(crate) urgent_pointer: [u8; 2],
-    pub(crate) options: Option<Vec<u8>>, //Can range from 0 to 40 bytes
+pub struct TCPPacket {
+    pub source_port: [u8; 2],
+    pub destination_port: [u8; 2],
+    pub sequence_number: [u8; 4],
+    pub acknowledgement_number: [u8; 4],
+    /// Header length in bytes.
+    pub data_offset: u8,
+    pub flags: TcpFlags,
+    pub window: [u8; 2],
+    pub checksum: [u8; 2],
+    pub checksum_status: ChecksumStatus,
+    pub urgent_pointer: [u8; 2],
+    /// Options between the fixed header and the data offset, 0 to 40 bytes.
+    pub options: Vec<TcpOption>,
+    pub payload: Vec<u8>,
+    /// Index of the connection the segment belongs to, once set by `TcpConnectionTracker`.
+    pub stream_index: Option<usize>,
+    /// Conditions found by `TcpConnectionTracker` when analysing the segment within its connection.
+    pub analysis: Vec<TcpAnalysis>,
+}
 
+impl Default for TCPPacket {
+    fn default() -> Self {
+        TCPPacket::new()
+    }
 }
 
 impl TCPPacket {
-    pub(crate) fn new() -> TCPPacket {
+    pub fn new() -> TCPPacket {
         TCPPacket {
             source_port: [0, 0],
             destination_port: [0, 0],
             sequence_number: [0, 0, 0, 0],
             acknowledgement_number: [0, 0, 0, 0],
             data_offset: 0,
-            flags: 0,
+            flags: TcpFlags::default(),
             window: [0, 0],
             checksum: [0, 0],
+            checksum_status: ChecksumStatus::Unverified,
             urgent_pointer: [0, 0],
-            options: None
+            options: vec![],
+            payload: vec![],
+            stream_index: None,
+            analysis: vec![],
         }
     }
+
+    /// Parses a TCP segment, decoding its options and keeping the payload that follows them.
+    ///
+    /// # Errors
+    /// Returns `ParseError::Truncated` if `data` is shorter than the 20-byte TCP header or the data
+    /// offset, or `ParseError::BadHeaderLength` if the data offset is below 20 bytes. A malformed
+    /// option is kept as `TcpOption::Malformed`.
+    pub fn parse(data: &[u8]) -> Result<TCPPacket, ParseError> {
+        require(data, 20, Layer::TCP)?;
+        let mut tcp = TCPPacket::new();
+        tcp.source_port = array(data, 0);
+        tcp.destination_port = array(data, 2);
+        tcp.sequence_number = array(data, 4);
+        tcp.acknowledgement_number = array(data, 8);
+
+        let data_offset = data[12] >> 4;
+        if data_offset < 5 {
+            return Err(ParseError::BadHeaderLength { layer: Layer::TCP, length: data_offset as usize * 4 });
+        }
+
+        tcp.data_offset = data_offset * 4;
+        tcp.flags = TcpFlags::from_header(data[12], data[13]);
+        tcp.window = array(data, 14);
+        tcp.checksum = array(data, 16);
+        tcp.urgent_pointer = array(data, 18);
+
+        let header_length = tcp.data_offset as usize;
+        require(data, header_length, Layer::TCP)?;
+        tcp.options = TcpOption::parse_all(&data[20..header_length]);
+        tcp.payload = data[header_length..].to_vec();
+        Ok(tcp)
+    }
 }
 
 impl fmt::Display for TCPPacket {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "TCP: -----TCP Header-----\n")?;
-        write!(f, "TCP:\n")?;
-        write!(f, "TCP: Source Port       = {}\n", u16::from_be_bytes(self.source_port))?;
-        write!(f, "TCP: Destination Port  = {}\n", u16::from_be_bytes(self.destination_port))?;
-        write!(f, "TCP: Sequence number   = {}\n", u32::from_be_bytes(self.sequence_number))?;
-        write!(f, "TCP: Acknowledgement number     = {}\n", u32::from_be_bytes(self.acknowledgement_number))?;
-        write!(f, "TCP: Data offset(header length) = {} bytes\n", self.data_offset)?;
-        write!(f, "TCP: Flags             = {}\n", self.flags)?;
-        write!(f, "TCP: Window            = {}\n", u16::from_be_bytes(self.window))?;
-        write!(f, "TCP: Checksum          = 0x{:x}{:x}\n", self.checksum[0], self.checksum[1])?;
-        write!(f, "TCP: Urgent pointer    = {}\n", u16::from_be_bytes(self.urgent_pointer))?;
-        match self.options.clone() {
-            None => write!(f, "No options\n"),
-            Some(op) => write!(f, "Options: {}\n", op.len())
+        writeln!(f, "TCP: -----TCP Header-----")?;
+        writeln!(f, "TCP:")?;
+        writeln!(f, "TCP: Source Port       = {}", u16::from_be_bytes(self.source_port))?;
+        writeln!(f, "TCP: Destination Port  = {}", u16::from_be_bytes(self.destination_port))?;
+        writeln!(f, "TCP: Sequence number   = {}", u32::from_be_bytes(self.sequence_number))?;
+        writeln!(f, "TCP: Acknowledgement number     = {}", u32::from_be_bytes(self.acknowledgement_number))?;
+        writeln!(f, "TCP: Data offset(header length) = {} bytes", self.data_offset)?;
+        writeln!(f, "TCP: Flags             = {}", self.flags)?;
+        for (flag, name) in TcpFlags::ALL {
+            writeln!(f, "TCP:         {} = {}", self.flags.bit_pattern(flag, name), name)?;
+        }
+        writeln!(f, "TCP: Window            = {}", u16::from_be_bytes(self.window))?;
+        writeln!(f, "TCP: Checksum          = 0x{:04x} {}", u16::from_be_bytes(self.checksum), self.checksum_status)?;
+        writeln!(f, "TCP: Urgent pointer    = {}", u16::from_be_bytes(self.urgent_pointer))?;
+        if self.options.is_empty() {
+            writeln!(f, "TCP: No options")?;
+        } else {
+            writeln!(f, "TCP: Options           = {} bytes", self.data_offset - 20)?;
+            for option in &self.options {
+                writeln!(f, "TCP:         {}", option)?;
+            }
+        }
+        writeln!(f, "TCP: Payload           = {} bytes", self.payload.len())?;
+        if let Some(index) = self.stream_index {
+            writeln!(f, "TCP: [Stream index {}]", index)?;
+        }
+        for analysis in &self.analysis {
+            writeln!(f, "TCP: [Expert: {}]", analysis)?;
         }
+        write!(f, "TCP:")
     }
 }
\ No newline at end of file
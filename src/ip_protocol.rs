@@ -1,10 +1,11 @@
 use std::fmt;
 
-#[derive(PartialOrd, PartialEq)]
-pub(crate) enum IPProtocol {
+#[derive(Copy, Clone, Debug, PartialOrd, PartialEq, Eq, Hash)]
+pub enum IPProtocol {
     ICMP,
     TCP,
     UDP,
+    ICMPv6,
     Default,
 }
 
@@ -14,7 +15,33 @@ impl fmt::Display for IPProtocol {
             IPProtocol::ICMP => { write!(f, "1 (ICMP)") }
             IPProtocol::TCP => { write!(f, "6 (TCP)") }
             IPProtocol::UDP => { write!(f, "17 (UDP)") }
+            IPProtocol::ICMPv6 => { write!(f, "58 (ICMPv6)") }
             IPProtocol::Default => { write!(f, "00 (Default)") }
         }
     }
-}
\ No newline at end of file
+}
+
+impl From<u8> for IPProtocol {
+    fn from(number: u8) -> IPProtocol {
+        match number {
+            1 => IPProtocol::ICMP,
+            6 => IPProtocol::TCP,
+            17 => IPProtocol::UDP,
+            58 => IPProtocol::ICMPv6,
+            _ => IPProtocol::Default,
+        }
+    }
+}
+
+impl From<IPProtocol> for u8 {
+    /// Returns the protocol number, or 0 for a protocol that is not decoded.
+    fn from(protocol: IPProtocol) -> u8 {
+        match protocol {
+            IPProtocol::ICMP => 1,
+            IPProtocol::TCP => 6,
+            IPProtocol::UDP => 17,
+            IPProtocol::ICMPv6 => 58,
+            IPProtocol::Default => 0,
+        }
+    }
+}
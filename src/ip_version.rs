@@ -0,0 +1,18 @@
+use std::fmt;
+
+/// Enum representing IP version, either IPv4 or IPv6.
+#[derive(Copy, Clone, Debug, PartialOrd, PartialEq)]
+pub enum IPVersion {
+    V4,
+    V6,
+}
+
+// Implement the Display trait for IPVersion for human-readable output.
+impl fmt::Display for IPVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            IPVersion::V4 => write!(f, "4"),
+            IPVersion::V6 => write!(f, "6")
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use std::fmt;
+use std::net::Ipv4Addr;
+
+use crate::parser::array;
+
+/// An IPv4 option, decoded according to its type.
+///
+/// Route options keep their pointer, the 1-based offset within the option of the next address to
+/// be filled or used.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Ipv4Option {
+    /// End of Option List (type 0). The padding that follows it is not kept.
+    EndOfOptionList,
+    /// No Operation (type 1).
+    NoOperation,
+    /// Record Route (type 7).
+    RecordRoute { pointer: u8, route: Vec<[u8; 4]> },
+    /// Internet Timestamp (type 68). Timestamps are in milliseconds since midnight UT.
+    Timestamp { pointer: u8, overflow: u8, flag: TimestampFlag, entries: Vec<(Option<[u8; 4]>, u32)> },
+    /// Loose Source and Record Route (type 131).
+    LooseSourceRoute { pointer: u8, route: Vec<[u8; 4]> },
+    /// Strict Source and Record Route (type 137).
+    StrictSourceRoute { pointer: u8, route: Vec<[u8; 4]> },
+    /// Router Alert (type 148), with 0 asking routers to examine the packet.
+    RouterAlert(u16),
+    /// Basic Security Option (type 130), with the protection authority flag bytes.
+    Security { classification: u8, protection_authority: Vec<u8> },
+    Unknown { option_type: u8, data: Vec<u8> },
+    /// An option with a length below 2 or one that runs past the end of the header, with the bytes
+    /// from its type to the end of the options, which are left undecoded.
+    Malformed { option_type: u8, data: Vec<u8> },
+}
+
+/// The flag of a Timestamp option, saying what each entry holds.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TimestampFlag {
+    /// Timestamps only (flag 0).
+    TimestampsOnly,
+    /// Each timestamp is preceded by the address of the router that recorded it (flag 1).
+    WithAddresses,
+    /// Only the routers at the prespecified addresses record a timestamp (flag 3).
+    Prespecified,
+    Unknown(u8),
+}
+
+impl Ipv4Option {
+    /// Parses the options between the fixed IPv4 header and the end of the header.
+    ///
+    /// A malformed option ends the list, kept as `Ipv4Option::Malformed` with the bytes that
+    /// follow, so the packet itself is still decoded.
+    pub fn parse_all(data: &[u8]) -> Vec<Ipv4Option> {
+        let mut options = vec![];
+        let mut current = 0;
+        while current < data.len() {
+            let option_type = data[current];
+            match option_type {
+                0 => {
+                    options.push(Ipv4Option::EndOfOptionList);
+                    break;
+                }
+                1 => {
+                    options.push(Ipv4Option::NoOperation);
+                    current += 1;
+                    continue;
+                }
+                _ => {}
+            }
+            let length = data.get(current + 1).map_or(0, |length| *length as usize);
+            if length < 2 || current + length > data.len() {
+                options.push(Ipv4Option::Malformed { option_type, data: data[current..].to_vec() });
+                break;
+            }
+            let body = &data[current + 2..current + length];
+            options.push(match (option_type, body.len()) {
+                (7, length) if length >= 1 => Ipv4Option::RecordRoute { pointer: body[0], route: addresses(&body[1..]) },
+                (131, length) if length >= 1 => {
+                    Ipv4Option::LooseSourceRoute { pointer: body[0], route: addresses(&body[1..]) }
+                }
+                (137, length) if length >= 1 => {
+                    Ipv4Option::StrictSourceRoute { pointer: body[0], route: addresses(&body[1..]) }
+                }
+                (68, length) if length >= 2 => {
+                    let flag = match body[1] & 0x0f {
+                        0 => TimestampFlag::TimestampsOnly,
+                        1 => TimestampFlag::WithAddresses,
+                        3 => TimestampFlag::Prespecified,
+                        flag => TimestampFlag::Unknown(flag),
+                    };
+                    let entries = match flag {
+                        TimestampFlag::TimestampsOnly => {
+                            body[2..].chunks_exact(4).map(|entry| (None, u32::from_be_bytes(array(entry, 0)))).collect()
+                        }
+                        TimestampFlag::WithAddresses | TimestampFlag::Prespecified => body[2..]
+                            .chunks_exact(8)
+                            .map(|entry| (Some(array(entry, 0)), u32::from_be_bytes(array(entry, 4))))
+                            .collect(),
+                        TimestampFlag::Unknown(_) => vec![],
+                    };
+                    Ipv4Option::Timestamp { pointer: body[0], overflow: body[1] >> 4, flag, entries }
+                }
+                (148, 2) => Ipv4Option::RouterAlert(u16::from_be_bytes(array(body, 0))),
+                (130, length) if length >= 1 => {
+                    Ipv4Option::Security { classification: body[0], protection_authority: body[1..].to_vec() }
+                }
+                _ => Ipv4Option::Unknown { option_type, data: body.to_vec() },
+            });
+            current += length;
+        }
+        options
+    }
+
+    /// Returns whether this option asks for the packet to be source routed.
+    pub fn is_source_route(&self) -> bool {
+        matches!(self, Ipv4Option::LooseSourceRoute { .. } | Ipv4Option::StrictSourceRoute { .. })
+    }
+}
+
+/// Splits route data into addresses, dropping a trailing partial one.
+fn addresses(data: &[u8]) -> Vec<[u8; 4]> {
+    data.chunks_exact(4).map(|address| array(address, 0)).collect()
+}
+
+/// Formats a route as a list of addresses.
+fn route_list(route: &[[u8; 4]]) -> String {
+    let route: Vec<String> = route.iter().map(|address| Ipv4Addr::from(*address).to_string()).collect();
+    route.join(", ")
+}
+
+impl fmt::Display for Ipv4Option {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Ipv4Option::EndOfOptionList => write!(f, "End of Option List"),
+            Ipv4Option::NoOperation => write!(f, "No Operation"),
+            Ipv4Option::RecordRoute { pointer, route } => {
+                write!(f, "Record route = {} (pointer {})", route_list(route), pointer)
+            }
+            Ipv4Option::LooseSourceRoute { pointer, route } => {
+                write!(f, "Loose source route = {} (pointer {})", route_list(route), pointer)
+            }
+            Ipv4Option::StrictSourceRoute { pointer, route } => {
+                write!(f, "Strict source route = {} (pointer {})", route_list(route), pointer)
+            }
+            Ipv4Option::Timestamp { pointer, overflow, flag, entries } => {
+                let flag = match flag {
+                    TimestampFlag::TimestampsOnly => "timestamps only".to_string(),
+                    TimestampFlag::WithAddresses => "with addresses".to_string(),
+                    TimestampFlag::Prespecified => "prespecified addresses".to_string(),
+                    TimestampFlag::Unknown(flag) => format!("unknown flag {}", flag),
+                };
+                let entries: Vec<String> = entries
+                    .iter()
+                    .map(|(address, timestamp)| match address {
+                        Some(address) => format!("{} at {} ms", Ipv4Addr::from(*address), timestamp),
+                        None => format!("{} ms", timestamp),
+                    })
+                    .collect();
+                write!(f, "Timestamp = {} [{}] (pointer {}, overflow {})", flag, entries.join(", "), pointer, overflow)
+            }
+            Ipv4Option::RouterAlert(value) => write!(f, "Router alert = {}", value),
+            Ipv4Option::Security { classification, protection_authority } => {
+                let level = match classification {
+                    0x3d => "Top Secret",
+                    0x5a => "Secret",
+                    0x96 => "Confidential",
+                    0xab => "Unclassified",
+                    _ => "reserved",
+                };
+                write!(f, "Security = classification 0x{:02x} ({}), {} bytes of protection authority", classification,
+                       level, protection_authority.len())
+            }
+            Ipv4Option::Unknown { option_type, data } => write!(f, "Option type {} = {} bytes", option_type, data.len()),
+            Ipv4Option::Malformed { option_type, data } => {
+                write!(f, "Malformed option type {} = {} bytes left undecoded", option_type, data.len())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn malformed_option_ends_the_list() {
+        // No Operation, then a Record Route claiming 39 bytes in a 4-byte option area.
+        let options = Ipv4Option::parse_all(&[1, 7, 39, 4]);
+        assert_eq!(options, vec![Ipv4Option::NoOperation, Ipv4Option::Malformed { option_type: 7, data: vec![7, 39, 4] }]);
+        let options = Ipv4Option::parse_all(&[148, 0, 0, 0]);
+        assert_eq!(options, vec![Ipv4Option::Malformed { option_type: 148, data: vec![148, 0, 0, 0] }]);
+    }
+
+    #[test]
+    fn options_before_the_malformed_one_are_kept() {
+        let options = Ipv4Option::parse_all(&[148, 4, 0, 0, 131]);
+        assert_eq!(options, vec![
+            Ipv4Option::RouterAlert(0),
+            Ipv4Option::Malformed { option_type: 131, data: vec![131] },
+        ]);
+    }
+}
@@ -0,0 +1,236 @@
+use std::fmt;
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use crate::checksum::ipv4_pseudo_header;
+use crate::reassembly::FragmentTable;
+use crate::{EthernetFrame, IPProtocol, OverlapPolicy, ParseError, ProtocolDatagram};
+
+/// Time a datagram may wait for its missing fragments, as Linux waits by default.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Identifies the fragments of one IPv4 datagram: source, destination, protocol number and
+/// identification.
+type FragmentKey = ([u8; 4], [u8; 4], u8, u16);
+
+/// Reassembles fragmented IPv4 datagrams across the frames of a capture.
+///
+/// Fragments are buffered until the datagram is complete. The datagram is then dissected into the
+/// frame holding the fragment that completed it, while the other fragments keep their raw data.
+pub struct Ipv4Reassembler {
+    table: FragmentTable<FragmentKey>,
+}
+
+impl Default for Ipv4Reassembler {
+    fn default() -> Self {
+        Ipv4Reassembler::new()
+    }
+}
+
+impl Ipv4Reassembler {
+    /// Creates a reassembler with a 30-second timeout that keeps the first copy of overlapping data.
+    pub fn new() -> Ipv4Reassembler {
+        Ipv4Reassembler { table: FragmentTable::new(DEFAULT_TIMEOUT, OverlapPolicy::First) }
+    }
+
+    /// Sets how long a datagram may wait for its missing fragments, in capture time.
+    pub fn with_timeout(mut self, timeout: Duration) -> Ipv4Reassembler {
+        self.table.timeout = timeout;
+        self
+    }
+
+    /// Sets how data covered by more than one fragment is resolved.
+    pub fn with_overlap_policy(mut self, policy: OverlapPolicy) -> Ipv4Reassembler {
+        self.table.policy = policy;
+        self
+    }
+
+    /// Adds the frame to the reassembly if it carries an IPv4 fragment.
+    ///
+    /// When the fragment completes its datagram, the datagram is dissected and replaces the
+    /// fragment in the frame's IPv4 packet.
+    ///
+    /// # Arguments
+    /// * `frame` - A decoded frame, left unchanged unless it completes a datagram.
+    /// * `timestamp` - The capture time of the frame.
+    ///
+    /// # Errors
+    /// Returns the error of the TCP, UDP or ICMP decoder if the reassembled datagram is malformed.
+    pub fn process(&mut self, frame: &mut EthernetFrame, timestamp: Duration) -> Result<(), ParseError> {
+        let packet = match frame.payload.ipv4_mut() {
+            Some(packet) => packet,
+            None => return Ok(()),
+        };
+        let fragment = match &packet.datagram {
+            ProtocolDatagram::Fragment(fragment) => fragment,
+            _ => return Ok(()),
+        };
+        let key = (packet.source_add, packet.destination_add, packet.protocol_number,
+                   u16::from_be_bytes(packet.identification));
+        let more_fragments = packet.last_fragment_flag == 1;
+        let offset = packet.fragment_offset as usize;
+        let reassembled = match self.table.add(key, offset, more_fragments, fragment, packet.truncated, timestamp) {
+            Some(reassembled) => reassembled,
+            None => return Ok(()),
+        };
+
+        let mut datagram = ProtocolDatagram::parse(&packet.protocol, &reassembled.payload)?;
+        let pseudo_header = ipv4_pseudo_header(packet.source_add, packet.destination_add, packet.protocol_number,
+                                               reassembled.payload.len());
+        datagram.verify_checksum(&reassembled.payload, &pseudo_header);
+        packet.datagram = datagram;
+        packet.reassembled_fragments = reassembled.fragments;
+        Ok(())
+    }
+
+    /// Returns whether no IPv4 fragment was seen.
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+}
+
+impl fmt::Display for Ipv4Reassembler {
+    /// Formats the reassembly counts and the datagrams still missing fragments, which are the
+    /// incomplete sets once the whole capture has been read.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let incomplete = self.table.incomplete();
+        writeln!(f, "IP: -----IP Reassembly Report-----")?;
+        writeln!(f, "IP:")?;
+        writeln!(f, "IP: Reassembled datagrams= {}", self.table.reassembled)?;
+        writeln!(f, "IP: Timed out datagrams  = {}", self.table.timed_out)?;
+        writeln!(f, "IP: Discarded datagrams  = {}", self.table.discarded)?;
+        writeln!(f, "IP: Incomplete datagrams = {}", incomplete.len())?;
+        for datagram in incomplete {
+            let (source, destination, protocol, identification) = datagram.key;
+            let total = match datagram.total_length {
+                Some(total) => format!("{} bytes expected", total),
+                None => "last fragment missing".to_string(),
+            };
+            // Protocols that are not decoded are shown by their number.
+            let protocol = match IPProtocol::from(*protocol) {
+                IPProtocol::Default => protocol.to_string(),
+                decoded => decoded.to_string(),
+            };
+            let truncated = match datagram.truncated {
+                0 => String::new(),
+                count => format!(", {} cut short by the snap length", count),
+            };
+            writeln!(f, "IP:   {} > {} {}, identification 0x{:04x}: {} fragments{}, {} bytes received, {}",
+                     Ipv4Addr::from(*source), Ipv4Addr::from(*destination), protocol, identification,
+                     datagram.fragments, truncated, datagram.received, total)?;
+        }
+        write!(f, "IP:")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_packet, LinkType};
+
+    /// A UDP datagram from port 1000 to port 2000 with a zero checksum, which is not verified.
+    fn udp_datagram(data: &[u8]) -> Vec<u8> {
+        let mut datagram = vec![0x03, 0xe8, 0x07, 0xd0];
+        datagram.extend_from_slice(&(8 + data.len() as u16).to_be_bytes());
+        datagram.extend_from_slice(&[0, 0]);
+        datagram.extend_from_slice(data);
+        datagram
+    }
+
+    /// Returns a frame carrying the bytes of `datagram` from `offset` to `end` as an IPv4 fragment,
+    /// keeping only `captured` bytes of its payload when given.
+    fn fragment(protocol: u8, datagram: &[u8], offset: usize, end: usize, captured: Option<usize>) -> EthernetFrame {
+        let payload = &datagram[offset..end];
+        let flags_and_offset = (offset / 8) as u16 | if end < datagram.len() { 0x2000 } else { 0 };
+        let mut packet = vec![0x45, 0];
+        packet.extend_from_slice(&(20 + payload.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&[0x12, 0x34]);
+        packet.extend_from_slice(&flags_and_offset.to_be_bytes());
+        packet.extend_from_slice(&[64, protocol, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2]);
+        packet.extend_from_slice(&payload[..captured.unwrap_or(payload.len())]);
+        parse_packet(LinkType::Raw, &packet).unwrap()
+    }
+
+    fn udp_payload(frame: &EthernetFrame) -> Option<Vec<u8>> {
+        match frame.payload.datagram() {
+            Some(ProtocolDatagram::UDP(udp)) => Some(udp.data.clone()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn in_order_fragments_are_dissected() {
+        let datagram = udp_datagram(b"0123456789abcdefghij");
+        let mut reassembler = Ipv4Reassembler::new();
+        let mut frames = [fragment(17, &datagram, 0, 16, None), fragment(17, &datagram, 16, 28, None)];
+        for frame in &mut frames {
+            reassembler.process(frame, Duration::ZERO).unwrap();
+        }
+        assert!(matches!(frames[0].payload.datagram(), Some(ProtocolDatagram::Fragment(_))));
+        assert_eq!(udp_payload(&frames[1]), Some(b"0123456789abcdefghij".to_vec()));
+        assert_eq!(frames[1].ipv4().unwrap().reassembled_fragments, 2);
+    }
+
+    #[test]
+    fn out_of_order_fragments_are_dissected() {
+        let datagram = udp_datagram(b"0123456789abcdefghij");
+        let mut reassembler = Ipv4Reassembler::new();
+        let mut frames = [
+            fragment(17, &datagram, 16, 28, None),
+            fragment(17, &datagram, 8, 16, None),
+            fragment(17, &datagram, 0, 8, None),
+        ];
+        for frame in &mut frames {
+            reassembler.process(frame, Duration::ZERO).unwrap();
+        }
+        assert_eq!(udp_payload(&frames[2]), Some(b"0123456789abcdefghij".to_vec()));
+        assert!(reassembler.table.incomplete().is_empty());
+    }
+
+    #[test]
+    fn overlapping_fragments_follow_the_policy() {
+        let datagram = udp_datagram(b"0123456789abcdefghij");
+        let mut altered = datagram.clone();
+        altered[8..16].copy_from_slice(b"ABCDEFGH");
+        for (policy, expected) in [(OverlapPolicy::First, Some(b"01234567")), (OverlapPolicy::Last, Some(b"ABCDEFGH")),
+                                   (OverlapPolicy::Drop, None)] {
+            let mut reassembler = Ipv4Reassembler::new().with_overlap_policy(policy);
+            let mut frames = [
+                fragment(17, &datagram, 0, 16, None),
+                fragment(17, &altered, 8, 16, None),
+                fragment(17, &datagram, 16, 28, None),
+            ];
+            for frame in &mut frames {
+                reassembler.process(frame, Duration::ZERO).unwrap();
+            }
+            let payload = udp_payload(&frames[2]);
+            assert_eq!(payload.as_ref().map(|payload| &payload[..8]), expected.map(|e| &e[..]), "{:?}", policy);
+        }
+    }
+
+    #[test]
+    fn fragments_of_undecoded_protocols_are_kept_apart() {
+        // GRE and ESP fragments with the same addresses and identification.
+        let datagram = [7; 24];
+        let mut reassembler = Ipv4Reassembler::new();
+        reassembler.process(&mut fragment(47, &datagram, 0, 16, None), Duration::ZERO).unwrap();
+        reassembler.process(&mut fragment(50, &datagram, 16, 24, None), Duration::ZERO).unwrap();
+        assert_eq!(reassembler.table.reassembled, 0);
+        assert_eq!(reassembler.table.incomplete().len(), 2);
+    }
+
+    #[test]
+    fn truncated_fragment_is_not_reassembled() {
+        let datagram = udp_datagram(b"0123456789abcdefghij");
+        let mut reassembler = Ipv4Reassembler::new();
+        let mut first = fragment(17, &datagram, 0, 16, None);
+        let mut last = fragment(17, &datagram, 16, 28, Some(6));
+        assert!(last.ipv4().unwrap().truncated);
+        reassembler.process(&mut first, Duration::ZERO).unwrap();
+        reassembler.process(&mut last, Duration::ZERO).unwrap();
+        assert!(matches!(last.payload.datagram(), Some(ProtocolDatagram::Fragment(_))));
+        let incomplete = reassembler.table.incomplete();
+        assert_eq!((incomplete.len(), incomplete[0].truncated, incomplete[0].total_length), (1, 1, None));
+        assert!(reassembler.to_string().contains("2 fragments, 1 cut short by the snap length"));
+    }
+}
@@ -0,0 +1,372 @@
+use std::fmt;
+use std::net::Ipv6Addr;
+
+use crate::checksum::ipv6_pseudo_header;
+use crate::parser::{array, require};
+use crate::{IPProtocol, IPVersion, Layer, ParseError, ProtocolDatagram};
+
+/// Length of the fixed IPv6 header.
+const HEADER_LENGTH: usize = 40;
+
+/// An IPv6 extension header, in the order it appears in the header chain.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Ipv6ExtensionHeader {
+    /// Hop-by-Hop Options, with the raw option bytes.
+    HopByHop { options: Vec<u8> },
+    /// Routing header, with the type-specific data following the fixed fields.
+    Routing { routing_type: u8, segments_left: u8, data: Vec<u8> },
+    /// Fragment header. `offset` is in bytes.
+    Fragment { offset: u16, more_fragments: bool, identification: u32 },
+    /// Destination Options, with the raw option bytes.
+    DestinationOptions { options: Vec<u8> },
+    /// Authentication Header.
+    Authentication { spi: u32, sequence_number: u32 },
+    /// Encapsulating Security Payload. Everything after it is encrypted.
+    Esp { spi: u32, sequence_number: u32 },
+}
+
+impl fmt::Display for Ipv6ExtensionHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Ipv6ExtensionHeader::HopByHop { options } => write!(f, "Hop-by-Hop Options, {} bytes of options", options.len()),
+            Ipv6ExtensionHeader::Routing { routing_type, segments_left, data } => {
+                write!(f, "Routing, type {}, {} segments left, {} bytes", routing_type, segments_left, data.len())
+            }
+            Ipv6ExtensionHeader::Fragment { offset, more_fragments, identification } => {
+                write!(f, "Fragment, offset {} bytes, {}, identification 0x{:08x}", offset,
+                       if *more_fragments { "more fragments" } else { "last fragment" }, identification)
+            }
+            Ipv6ExtensionHeader::DestinationOptions { options } => {
+                write!(f, "Destination Options, {} bytes of options", options.len())
+            }
+            Ipv6ExtensionHeader::Authentication { spi, sequence_number } => {
+                write!(f, "Authentication Header, SPI 0x{:08x}, sequence {}", spi, sequence_number)
+            }
+            Ipv6ExtensionHeader::Esp { spi, sequence_number } => {
+                write!(f, "Encapsulating Security Payload, SPI 0x{:08x}, sequence {}", spi, sequence_number)
+            }
+        }
+    }
+}
+
+pub struct Ipv6Packet {
+    pub version: IPVersion,
+    pub traffic_class: u8,
+    pub flow_label: u32,
+    pub payload_length: u16,
+    /// Next header field of the fixed header, naming the first extension header if there is one.
+    pub next_header: u8,
+    pub hop_limit: u8,
+    pub source_add: [u8; 16],
+    pub destination_add: [u8; 16],
+    pub extension_headers: Vec<Ipv6ExtensionHeader>,
+    /// Protocol number found at the end of the extension header chain.
+    pub upper_layer_header: u8,
+    pub protocol: IPProtocol,
+    /// The datagram carried by the packet, or `ProtocolDatagram::Fragment` for a fragment that has
+    /// not been reassembled.
+    pub datagram: ProtocolDatagram,
+    /// Number of fragments `datagram` was reassembled from, 0 when the packet was not fragmented.
+    pub reassembled_fragments: usize,
+    /// Whether the capture cut the packet short of its payload length, so the payload is incomplete.
+    pub truncated: bool,
+}
+
+impl Default for Ipv6Packet {
+    fn default() -> Self {
+        Ipv6Packet::new()
+    }
+}
+
+impl Ipv6Packet {
+    pub fn new() -> Ipv6Packet {
+        Ipv6Packet {
+            version: IPVersion::V6,
+            traffic_class: 0,
+            flow_label: 0,
+            payload_length: 0,
+            next_header: 0,
+            hop_limit: 0,
+            source_add: [0; 16],
+            destination_add: [0; 16],
+            extension_headers: vec![],
+            upper_layer_header: 0,
+            protocol: IPProtocol::Default,
+            datagram: ProtocolDatagram::new(),
+            reassembled_fragments: 0,
+            truncated: false,
+        }
+    }
+
+    /// Parses an IPv6 packet, walks its extension headers and decodes the upper-layer datagram.
+    ///
+    /// The datagram is left undecoded when the chain ends in ESP or in No Next Header, and is kept
+    /// as `ProtocolDatagram::Fragment` when the packet is a fragment, until it is reassembled.
+    ///
+    /// # Arguments
+    /// * `data` - The raw bytes of the packet, starting at the IPv6 header.
+    ///
+    /// # Errors
+    /// Returns `ParseError::BadVersion` if the version field is not 6, or `ParseError::Truncated`
+    /// if the header, an extension header or the datagram header ends early.
+    pub fn parse(data: &[u8]) -> Result<Ipv6Packet, ParseError> {
+        require(data, HEADER_LENGTH, Layer::IPv6)?;
+        let first_word = u32::from_be_bytes(array(data, 0));
+        let version = (first_word >> 28) as u8;
+        if version != 6 {
+            return Err(ParseError::BadVersion { layer: Layer::IPv6, version });
+        }
+
+        let mut packet = Ipv6Packet::new();
+        packet.traffic_class = (first_word >> 20) as u8;
+        packet.flow_label = first_word & 0x000f_ffff;
+        packet.payload_length = u16::from_be_bytes(array(data, 4));
+        packet.next_header = data[6];
+        packet.hop_limit = data[7];
+        packet.source_add = array(data, 8);
+        packet.destination_add = array(data, 24);
+
+        // Ethernet pads short frames, so the payload ends at the payload length when it is sane.
+        // A zero length is left alone since jumbograms carry their length in a Hop-by-Hop option.
+        let mut end = data.len();
+        let total = HEADER_LENGTH + packet.payload_length as usize;
+        if packet.payload_length != 0 && total < end {
+            end = total;
+        }
+        let complete = packet.payload_length != 0 && end == total;
+        packet.truncated = packet.payload_length != 0 && total > end;
+        let data = &data[..end];
+
+        packet.decode_chain(packet.next_header, &data[HEADER_LENGTH..], complete)?;
+        Ok(packet)
+    }
+
+    /// Walks the extension headers starting with `next_header` and decodes the upper-layer
+    /// datagram that ends the chain.
+    ///
+    /// A fragment is kept undecoded in `ProtocolDatagram::Fragment`, with `upper_layer_header` set
+    /// to the header its fragmentable part starts with. An atomic fragment, with offset 0 and no
+    /// more fragments, is a whole datagram and is decoded directly.
+    ///
+    /// # Arguments
+    /// * `next_header` - The type of the first header in `data`.
+    /// * `data` - The bytes following the fixed header, or a reassembled fragmentable part.
+    /// * `complete` - Whether `data` holds the whole payload, so the checksum can be verified.
+    ///
+    /// # Errors
+    /// Returns `ParseError::Truncated` if an extension header or the datagram header ends early.
+    pub(crate) fn decode_chain(&mut self, mut next_header: u8, data: &[u8], complete: bool) -> Result<(), ParseError> {
+        let mut current = 0;
+        let mut readable = true;
+        loop {
+            let extension = match next_header {
+                0 | 43 | 60 => {
+                    require(&data[current..], 2, Layer::IPv6Extension)?;
+                    let length = (data[current + 1] as usize + 1) * 8;
+                    require(&data[current..], length, Layer::IPv6Extension)?;
+                    let body = &data[current + 2..current + length];
+                    let extension = match next_header {
+                        0 => Ipv6ExtensionHeader::HopByHop { options: body.to_vec() },
+                        60 => Ipv6ExtensionHeader::DestinationOptions { options: body.to_vec() },
+                        _ => Ipv6ExtensionHeader::Routing {
+                            routing_type: body[0],
+                            segments_left: body[1],
+                            data: body[2..].to_vec(),
+                        },
+                    };
+                    next_header = data[current];
+                    current += length;
+                    extension
+                }
+                44 => {
+                    require(&data[current..], 8, Layer::IPv6Extension)?;
+                    let offset_and_flag = u16::from_be_bytes(array(data, current + 2));
+                    let offset = offset_and_flag & 0xfff8;
+                    let more_fragments = offset_and_flag & 1 == 1;
+                    next_header = data[current];
+                    current += 8;
+                    readable = offset == 0 && !more_fragments;
+                    Ipv6ExtensionHeader::Fragment {
+                        offset,
+                        more_fragments,
+                        identification: u32::from_be_bytes(array(data, current - 4)),
+                    }
+                }
+                51 => {
+                    require(&data[current..], 12, Layer::IPv6Extension)?;
+                    let length = (data[current + 1] as usize + 2) * 4;
+                    require(&data[current..], length, Layer::IPv6Extension)?;
+                    let extension = Ipv6ExtensionHeader::Authentication {
+                        spi: u32::from_be_bytes(array(data, current + 4)),
+                        sequence_number: u32::from_be_bytes(array(data, current + 8)),
+                    };
+                    next_header = data[current];
+                    current += length;
+                    extension
+                }
+                50 => {
+                    require(&data[current..], 8, Layer::IPv6Extension)?;
+                    self.extension_headers.push(Ipv6ExtensionHeader::Esp {
+                        spi: u32::from_be_bytes(array(data, current)),
+                        sequence_number: u32::from_be_bytes(array(data, current + 4)),
+                    });
+                    self.upper_layer_header = next_header;
+                    return Ok(());
+                }
+                _ => break,
+            };
+            self.extension_headers.push(extension);
+            if !readable {
+                self.upper_layer_header = next_header;
+                self.datagram = ProtocolDatagram::Fragment(data[current..].to_vec());
+                return Ok(());
+            }
+        }
+
+        self.upper_layer_header = next_header;
+        self.protocol = IPProtocol::from(next_header);
+        let payload = &data[current..];
+        self.datagram = ProtocolDatagram::parse(&self.protocol, payload)?;
+        if complete {
+            let pseudo_header = ipv6_pseudo_header(self.source_add, self.destination_add, next_header, payload.len());
+            self.datagram.verify_checksum(payload, &pseudo_header);
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Ipv6Packet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "IPv6: -----IPv6 Header-----")?;
+        writeln!(f, "IPv6:")?;
+        writeln!(f, "IPv6: Version         = {}", self.version)?;
+        writeln!(f, "IPv6: Traffic class   = 0x{:02x}", self.traffic_class)?;
+        writeln!(f, "IPv6: Flow label      = 0x{:05x}", self.flow_label)?;
+        writeln!(f, "IPv6: Payload length  = {} bytes", self.payload_length)?;
+        writeln!(f, "IPv6: Next header     = {}", self.next_header)?;
+        writeln!(f, "IPv6: Hop limit       = {}", self.hop_limit)?;
+        writeln!(f, "IPv6: Source address  = {}", Ipv6Addr::from(self.source_add))?;
+        writeln!(f, "IPv6: Destination address= {}", Ipv6Addr::from(self.destination_add))?;
+        for extension in &self.extension_headers {
+            writeln!(f, "IPv6: Extension header= {}", extension)?;
+        }
+        match self.protocol {
+            IPProtocol::Default => writeln!(f, "IPv6: Upper-layer protocol= {}", self.upper_layer_header)?,
+            _ => writeln!(f, "IPv6: Upper-layer protocol= {}", self.protocol)?,
+        }
+        if self.reassembled_fragments > 0 {
+            writeln!(f, "IPv6: [Reassembled from {} fragments]", self.reassembled_fragments)?;
+        }
+        write!(f, "{}", self.datagram)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A UDP header from port 1000 to port 53, with a zero checksum.
+    const UDP: [u8; 8] = [0x03, 0xe8, 0x00, 0x35, 0x00, 0x08, 0x00, 0x00];
+
+    /// Returns an IPv6 packet from 2001:db8::1 to 2001:db8::2 with the given chain of headers.
+    fn packet(next_header: u8, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x6a, 0xb1, 0x23, 0x45];
+        packet.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&[next_header, 64]);
+        packet.extend_from_slice(&[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        packet.extend_from_slice(&[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]);
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    #[test]
+    fn fixed_header_fields_are_split() {
+        let packet = Ipv6Packet::parse(&packet(17, &UDP)).unwrap();
+        assert_eq!(packet.traffic_class, 0xab);
+        assert_eq!(packet.flow_label, 0x12345);
+        assert_eq!((packet.payload_length, packet.next_header, packet.hop_limit), (8, 17, 64));
+        assert_eq!(Ipv6Addr::from(packet.destination_add), "2001:db8::2".parse::<Ipv6Addr>().unwrap());
+        assert!(packet.extension_headers.is_empty());
+        assert_eq!((packet.upper_layer_header, packet.protocol), (17, IPProtocol::UDP));
+        assert!(matches!(packet.datagram, ProtocolDatagram::UDP(_)));
+    }
+
+    #[test]
+    fn extension_headers_are_walked_in_order() {
+        let payload = [
+            // Hop-by-Hop Options holding a PadN option, then a type 0 Routing header with one address.
+            [43, 0, 1, 4, 0, 0, 0, 0].as_slice(),
+            &[60, 2, 0, 1, 0, 0, 0, 0],
+            &[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 9],
+            // Destination Options, then an Authentication Header with a 4-byte ICV.
+            &[51, 0, 1, 4, 0, 0, 0, 0],
+            &[17, 2, 0, 0, 0, 0, 0x10, 0x01, 0, 0, 0, 7, 0xaa, 0xbb, 0xcc, 0xdd],
+            &UDP,
+        ].concat();
+        let packet = Ipv6Packet::parse(&packet(0, &payload)).unwrap();
+        assert_eq!(packet.extension_headers, [
+            Ipv6ExtensionHeader::HopByHop { options: vec![1, 4, 0, 0, 0, 0] },
+            Ipv6ExtensionHeader::Routing {
+                routing_type: 0,
+                segments_left: 1,
+                data: [[0, 0, 0, 0].as_slice(), &[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 9]].concat(),
+            },
+            Ipv6ExtensionHeader::DestinationOptions { options: vec![1, 4, 0, 0, 0, 0] },
+            Ipv6ExtensionHeader::Authentication { spi: 0x1001, sequence_number: 7 },
+        ]);
+        assert_eq!((packet.next_header, packet.upper_layer_header, packet.protocol), (0, 17, IPProtocol::UDP));
+        assert!(matches!(packet.datagram, ProtocolDatagram::UDP(_)));
+    }
+
+    #[test]
+    fn esp_ends_the_chain() {
+        let payload = [[50, 0, 1, 4, 0, 0, 0, 0].as_slice(), &[0, 0, 0x20, 0x02, 0, 0, 0, 1, 0xde, 0xad]].concat();
+        let packet = Ipv6Packet::parse(&packet(0, &payload)).unwrap();
+        assert_eq!(packet.extension_headers.len(), 2);
+        assert_eq!(packet.extension_headers[1], Ipv6ExtensionHeader::Esp { spi: 0x2002, sequence_number: 1 });
+        assert_eq!((packet.upper_layer_header, packet.protocol), (50, IPProtocol::Default));
+    }
+
+    #[test]
+    fn a_later_fragment_is_not_decoded() {
+        // Offset 8 with more fragments to follow: the bytes after the header are not a UDP header.
+        let payload = [[17, 0, 0x00, 0x09, 0, 0, 0x12, 0x34].as_slice(), &[0xff; 8]].concat();
+        let packet = Ipv6Packet::parse(&packet(44, &payload)).unwrap();
+        assert_eq!(packet.extension_headers, [
+            Ipv6ExtensionHeader::Fragment { offset: 8, more_fragments: true, identification: 0x1234 },
+        ]);
+        assert_eq!(packet.upper_layer_header, 17);
+        assert_eq!(packet.protocol, IPProtocol::Default);
+    }
+
+    #[test]
+    fn no_next_header_leaves_nothing_to_decode() {
+        let packet = Ipv6Packet::parse(&packet(60, &[59, 0, 1, 4, 0, 0, 0, 0])).unwrap();
+        assert_eq!(packet.extension_headers.len(), 1);
+        assert_eq!((packet.upper_layer_header, packet.protocol), (59, IPProtocol::Default));
+    }
+
+    #[test]
+    fn padding_past_the_payload_length_is_dropped() {
+        let mut padded = packet(17, &UDP);
+        padded.extend_from_slice(&[0; 6]);
+        let packet = Ipv6Packet::parse(&padded).unwrap();
+        let ProtocolDatagram::UDP(udp) = &packet.datagram else { panic!("not UDP") };
+        assert!(udp.data.is_empty());
+    }
+
+    #[test]
+    fn short_or_foreign_headers_are_errors() {
+        let mut ipv4 = packet(17, &UDP);
+        ipv4[0] = 0x45;
+        assert!(matches!(Ipv6Packet::parse(&ipv4), Err(ParseError::BadVersion { layer: Layer::IPv6, version: 4 })));
+        assert!(matches!(Ipv6Packet::parse(&ipv4[..39]), Err(ParseError::Truncated { layer: Layer::IPv6, .. })));
+
+        // A Hop-by-Hop header claiming 16 bytes, with 8 captured.
+        let cut = packet(0, &[17, 1, 1, 4, 0, 0, 0, 0]);
+        assert!(matches!(Ipv6Packet::parse(&cut),
+                         Err(ParseError::Truncated { layer: Layer::IPv6Extension, needed: 16, available: 8 })));
+        let cut = packet(51, &[17, 1, 0, 0, 0, 0]);
+        assert!(matches!(Ipv6Packet::parse(&cut), Err(ParseError::Truncated { layer: Layer::IPv6Extension, .. })));
+    }
+}
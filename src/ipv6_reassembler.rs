@@ -0,0 +1,213 @@
+use std::fmt;
+use std::net::Ipv6Addr;
+use std::time::Duration;
+
+use crate::reassembly::FragmentTable;
+use crate::{EthernetFrame, Ipv6ExtensionHeader, OverlapPolicy, ParseError, ProtocolDatagram};
+
+/// Time a datagram may wait for its missing fragments, as RFC 8200 specifies.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Identifies the fragments of one IPv6 datagram: source, destination and identification.
+type FragmentKey = ([u8; 16], [u8; 16], u32);
+
+/// Reassembles IPv6 datagrams split with the Fragment extension header across the frames of a
+/// capture.
+///
+/// Fragments are buffered until the datagram is complete. Its fragmentable part is then decoded
+/// into the frame holding the fragment that completed it, while the other fragments keep their
+/// raw data. Atomic fragments are decoded as they are parsed and never reach the reassembler.
+pub struct Ipv6Reassembler {
+    table: FragmentTable<FragmentKey>,
+}
+
+impl Default for Ipv6Reassembler {
+    fn default() -> Self {
+        Ipv6Reassembler::new()
+    }
+}
+
+impl Ipv6Reassembler {
+    /// Creates a reassembler with a 60-second timeout that drops datagrams with overlapping
+    /// fragments, as RFC 5722 requires.
+    pub fn new() -> Ipv6Reassembler {
+        Ipv6Reassembler { table: FragmentTable::new(DEFAULT_TIMEOUT, OverlapPolicy::Drop) }
+    }
+
+    /// Sets how long a datagram may wait for its missing fragments, in capture time.
+    pub fn with_timeout(mut self, timeout: Duration) -> Ipv6Reassembler {
+        self.table.timeout = timeout;
+        self
+    }
+
+    /// Sets how data covered by more than one fragment is resolved.
+    pub fn with_overlap_policy(mut self, policy: OverlapPolicy) -> Ipv6Reassembler {
+        self.table.policy = policy;
+        self
+    }
+
+    /// Adds the frame to the reassembly if it carries an IPv6 fragment.
+    ///
+    /// When the fragment completes its datagram, the extension headers and datagram of the
+    /// fragmentable part are decoded into the frame's IPv6 packet.
+    ///
+    /// # Arguments
+    /// * `frame` - A decoded frame, left unchanged unless it completes a datagram.
+    /// * `timestamp` - The capture time of the frame.
+    ///
+    /// # Errors
+    /// Returns `ParseError::Truncated` if a header of the reassembled fragmentable part ends early.
+    pub fn process(&mut self, frame: &mut EthernetFrame, timestamp: Duration) -> Result<(), ParseError> {
+        let packet = match frame.payload.ipv6_mut() {
+            Some(packet) => packet,
+            None => return Ok(()),
+        };
+        let fragment = match &packet.datagram {
+            ProtocolDatagram::Fragment(fragment) => fragment,
+            _ => return Ok(()),
+        };
+        let header = packet.extension_headers.iter().rev().find_map(|header| match header {
+            Ipv6ExtensionHeader::Fragment { offset, more_fragments, identification } => {
+                Some((*offset, *more_fragments, *identification))
+            }
+            _ => None,
+        });
+        let (offset, more_fragments, identification) = match header {
+            Some(header) => header,
+            None => return Ok(()),
+        };
+        let key = (packet.source_add, packet.destination_add, identification);
+        let reassembled = match self.table.add(key, offset as usize, more_fragments, fragment, packet.truncated,
+                                               timestamp) {
+            Some(reassembled) => reassembled,
+            None => return Ok(()),
+        };
+
+        packet.decode_chain(packet.upper_layer_header, &reassembled.payload, true)?;
+        packet.reassembled_fragments = reassembled.fragments;
+        Ok(())
+    }
+
+    /// Returns whether no IPv6 fragment was seen.
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+}
+
+impl fmt::Display for Ipv6Reassembler {
+    /// Formats the reassembly counts and the datagrams still missing fragments, which are the
+    /// incomplete sets once the whole capture has been read.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let incomplete = self.table.incomplete();
+        writeln!(f, "IPv6: -----IPv6 Reassembly Report-----")?;
+        writeln!(f, "IPv6:")?;
+        writeln!(f, "IPv6: Reassembled datagrams= {}", self.table.reassembled)?;
+        writeln!(f, "IPv6: Timed out datagrams  = {}", self.table.timed_out)?;
+        writeln!(f, "IPv6: Discarded datagrams  = {}", self.table.discarded)?;
+        writeln!(f, "IPv6: Incomplete datagrams = {}", incomplete.len())?;
+        for datagram in incomplete {
+            let (source, destination, identification) = datagram.key;
+            let total = match datagram.total_length {
+                Some(total) => format!("{} bytes expected", total),
+                None => "last fragment missing".to_string(),
+            };
+            let truncated = match datagram.truncated {
+                0 => String::new(),
+                count => format!(", {} cut short by the snap length", count),
+            };
+            writeln!(f, "IPv6:   {} > {}, identification 0x{:08x}: {} fragments{}, {} bytes received, {}",
+                     Ipv6Addr::from(*source), Ipv6Addr::from(*destination), identification, datagram.fragments,
+                     truncated, datagram.received, total)?;
+        }
+        write!(f, "IPv6:")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_packet, LinkType};
+
+    /// A Destination Options header holding a PadN option, leading the fragmentable part into UDP.
+    const DESTINATION_OPTIONS: [u8; 8] = [17, 0, 1, 4, 0, 0, 0, 0];
+
+    /// A UDP datagram from port 1000 to port 2000 carrying 20 bytes, after `DESTINATION_OPTIONS`.
+    fn fragmentable_part() -> Vec<u8> {
+        [DESTINATION_OPTIONS.as_slice(), &[0x03, 0xe8, 0x07, 0xd0, 0, 28, 0, 0], b"0123456789abcdefghij"].concat()
+    }
+
+    /// Returns a frame carrying the bytes of `part` from `offset` to `end` after a Hop-by-Hop
+    /// Options header and a Fragment header.
+    fn fragment(part: &[u8], offset: usize, end: usize) -> EthernetFrame {
+        let payload = &part[offset..end];
+        let offset_and_flag = offset as u16 | (end < part.len()) as u16;
+        let mut packet = vec![0x60, 0, 0, 0];
+        packet.extend_from_slice(&(16 + payload.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&[0, 64]);
+        packet.extend_from_slice(&[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        packet.extend_from_slice(&[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]);
+        packet.extend_from_slice(&[44, 0, 1, 4, 0, 0, 0, 0]);
+        packet.extend_from_slice(&[60, 0]);
+        packet.extend_from_slice(&offset_and_flag.to_be_bytes());
+        packet.extend_from_slice(&0xdeadbeef_u32.to_be_bytes());
+        packet.extend_from_slice(payload);
+        parse_packet(LinkType::Raw, &packet).unwrap()
+    }
+
+    fn process_all(reassembler: &mut Ipv6Reassembler, frames: &mut [EthernetFrame]) {
+        for frame in frames {
+            reassembler.process(frame, Duration::ZERO).unwrap();
+        }
+    }
+
+    #[test]
+    fn the_fragmentable_part_is_decoded_after_the_unfragmentable_headers() {
+        let part = fragmentable_part();
+        let mut reassembler = Ipv6Reassembler::new();
+        let mut frames = [fragment(&part, 24, 36), fragment(&part, 0, 24)];
+        process_all(&mut reassembler, &mut frames);
+
+        let first = frames[0].ipv6().unwrap();
+        assert_eq!(first.upper_layer_header, 60);
+        assert!(matches!(first.datagram, ProtocolDatagram::Fragment(_)));
+
+        let packet = frames[1].ipv6().unwrap();
+        assert_eq!(packet.extension_headers, [
+            Ipv6ExtensionHeader::HopByHop { options: vec![1, 4, 0, 0, 0, 0] },
+            Ipv6ExtensionHeader::Fragment { offset: 0, more_fragments: true, identification: 0xdeadbeef },
+            Ipv6ExtensionHeader::DestinationOptions { options: vec![1, 4, 0, 0, 0, 0] },
+        ]);
+        assert_eq!(packet.upper_layer_header, 17);
+        let ProtocolDatagram::UDP(udp) = &packet.datagram else { panic!("not reassembled") };
+        assert_eq!(udp.data, b"0123456789abcdefghij");
+        assert_eq!(packet.reassembled_fragments, 2);
+    }
+
+    #[test]
+    fn atomic_fragments_are_decoded_without_reassembly() {
+        let part = fragmentable_part();
+        let mut reassembler = Ipv6Reassembler::new();
+        let mut frames = [fragment(&part, 0, part.len())];
+        assert!(matches!(frames[0].ipv6().unwrap().datagram, ProtocolDatagram::UDP(_)));
+        process_all(&mut reassembler, &mut frames);
+        assert!(reassembler.is_empty());
+        assert_eq!(frames[0].ipv6().unwrap().reassembled_fragments, 0);
+    }
+
+    #[test]
+    fn overlapping_fragments_are_dropped_by_default() {
+        let part = fragmentable_part();
+        let mut reassembler = Ipv6Reassembler::new();
+        let mut frames = [fragment(&part, 0, 24), fragment(&part, 16, 24), fragment(&part, 24, 36)];
+        process_all(&mut reassembler, &mut frames);
+        assert!(frames.iter().all(|frame| matches!(frame.ipv6().unwrap().datagram, ProtocolDatagram::Fragment(_))));
+        assert_eq!(reassembler.table.discarded, 1);
+        assert!(reassembler.to_string().contains("IPv6: Discarded datagrams  = 1"));
+
+        // The overlap policy still applies when asked for.
+        let mut reassembler = Ipv6Reassembler::new().with_overlap_policy(OverlapPolicy::First);
+        let mut frames = [fragment(&part, 0, 24), fragment(&part, 16, 24), fragment(&part, 24, 36)];
+        process_all(&mut reassembler, &mut frames);
+        assert!(matches!(frames[2].ipv6().unwrap().datagram, ProtocolDatagram::UDP(_)));
+    }
+}
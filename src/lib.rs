@@ -0,0 +1,86 @@
+//! Packet capture parsing library.
+//!
+//! Decodes the link-layer header, IPv4 or IPv6 header, and TCP, UDP or ICMP header data of captured
+//! packets.
+//! Use [`parse_frame`] to decode the raw bytes of a single frame, or [`CaptureReader`] to stream the
+//! packets of a pcap or pcapng capture.
+
+// Protocol names are kept in their conventional upper case spelling (TCP, UDP, ICMP).
+#![allow(clippy::upper_case_acronyms)]
+
+// Import various modules related to network packet processing.
+mod ip_protocol;
+mod ip_version;
+mod byte_order;
+mod link_layer;
+mod pcap_file_header;
+mod pcap_block;
+mod ethernet_frame;
+mod ether_payload;
+mod internet_packet;
+mod ipv4_option;
+mod ipv6_packet;
+mod icmpv6_packet;
+mod arp_packet;
+mod tcp_flags;
+mod tcp_option;
+mod pcap_file;
+mod internet_protocol_types;
+mod parse_error;
+mod pcap_reader;
+mod pcapng_header;
+mod pcapng_reader;
+mod capture_reader;
+mod parser;
+mod filter;
+mod bpf;
+mod bpf_compiler;
+mod checksum;
+mod reassembly;
+mod ipv4_reassembler;
+mod ipv6_reassembler;
+mod flow;
+mod tcp_stream;
+mod tcp_connection;
+mod tcp_analysis;
+mod tcp_rtt;
+mod udp_stream;
+mod follow;
+
+// Re-export the public structs and enums from the respective modules.
+pub use ip_protocol::IPProtocol;
+pub use ip_version::IPVersion;
+pub use byte_order::ByteOrder;
+pub use link_layer::{CookedHeader, LinkType};
+pub use pcap_file_header::{PcapFileHeader, TimestampResolution};
+pub use ethernet_frame::{EthernetFrame, VlanTag};
+pub use ether_payload::{ether_type_name, EtherPayload, LldpTlv, MplsLabel};
+pub use pcap_block::PcapBlock;
+pub use internet_protocol_types::{ProtocolDatagram, ICMPPacket, UDPPacket, TCPPacket};
+pub use internet_packet::IPacket;
+pub use ipv4_option::{Ipv4Option, TimestampFlag};
+pub use ipv6_packet::{Ipv6ExtensionHeader, Ipv6Packet};
+pub use tcp_flags::TcpFlags;
+pub use tcp_option::TcpOption;
+pub use arp_packet::{ArpMonitor, ArpPacket};
+pub use icmpv6_packet::{ICMPv6Message, ICMPv6Packet, MulticastAddressRecord, NdOption};
+pub use parse_error::{Layer, ParseError};
+pub use pcap_reader::PcapReader;
+pub use pcapng_header::{InterfaceDescription, PcapNgOption, SectionHeader};
+pub use pcapng_reader::PcapNgReader;
+pub use capture_reader::CaptureReader;
+pub use parser::{parse_frame, parse_packet};
+pub use filter::{EndpointQualifier, Filter, FilterError, FilterExpr, IpNetwork};
+pub use bpf::{BpfError, BpfInstruction, BpfProgram};
+pub use checksum::ChecksumStatus;
+pub use reassembly::OverlapPolicy;
+pub use ipv4_reassembler::Ipv4Reassembler;
+pub use ipv6_reassembler::Ipv6Reassembler;
+pub use flow::{Direction, FlowKey};
+pub use tcp_stream::{StreamChunk, StreamData, TcpStream, TcpStreamReassembler};
+pub use tcp_analysis::{AnalysisCounts, TcpAnalysis};
+pub use tcp_rtt::{HandshakeTimes, RttSample, RttSummary};
+pub use tcp_connection::{DirectionCounts, TcpConnection, TcpConnectionTracker, TcpState};
+pub use udp_stream::{UdpStream, UdpStreamTracker};
+pub use follow::{FollowFormat, FollowedStream, StreamSelector};
+pub use pcap_file::PcapFile;
@@ -0,0 +1,200 @@
+use std::fmt;
+
+use crate::parser::{array, require};
+use crate::{Layer, ParseError};
+
+/// Link-layer header type of a capture, from the pcap header or a pcapng interface.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LinkType {
+    /// BSD loopback, with the address family in host byte order (LINKTYPE_NULL).
+    Null,
+    /// Ethernet II (LINKTYPE_ETHERNET, DLT_EN10MB).
+    Ethernet,
+    /// Raw IPv4 or IPv6 packets, with no link-layer header (LINKTYPE_RAW, DLT_RAW).
+    Raw,
+    /// OpenBSD loopback, with the address family in network byte order (LINKTYPE_LOOP).
+    Loop,
+    /// Linux cooked capture, as taken on the `any` interface (LINKTYPE_LINUX_SLL).
+    LinuxSll,
+    /// Raw IPv4 packets (LINKTYPE_IPV4).
+    IPv4,
+    /// Raw IPv6 packets (LINKTYPE_IPV6).
+    IPv6,
+    /// Linux cooked capture v2 (LINKTYPE_LINUX_SLL2).
+    LinuxSll2,
+}
+
+impl LinkType {
+    /// Returns the link type with the given LINKTYPE_ number.
+    ///
+    /// DLT_RAW is accepted under its LINKTYPE_ value and the platform values 12 and 14 that
+    /// older captures were written with.
+    ///
+    /// # Errors
+    /// Returns `ParseError::UnsupportedLinkType` if the link type cannot be decoded.
+    pub fn from_number(number: u32) -> Result<LinkType, ParseError> {
+        match number {
+            0 => Ok(LinkType::Null),
+            1 => Ok(LinkType::Ethernet),
+            12 | 14 | 101 => Ok(LinkType::Raw),
+            108 => Ok(LinkType::Loop),
+            113 => Ok(LinkType::LinuxSll),
+            228 => Ok(LinkType::IPv4),
+            229 => Ok(LinkType::IPv6),
+            276 => Ok(LinkType::LinuxSll2),
+            _ => Err(ParseError::UnsupportedLinkType(number)),
+        }
+    }
+}
+
+impl fmt::Display for LinkType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LinkType::Null => write!(f, "0 (BSD loopback)"),
+            LinkType::Ethernet => write!(f, "1 (Ethernet)"),
+            LinkType::Raw => write!(f, "101 (Raw IP)"),
+            LinkType::Loop => write!(f, "108 (OpenBSD loopback)"),
+            LinkType::LinuxSll => write!(f, "113 (Linux cooked)"),
+            LinkType::IPv4 => write!(f, "228 (Raw IPv4)"),
+            LinkType::IPv6 => write!(f, "229 (Raw IPv6)"),
+            LinkType::LinuxSll2 => write!(f, "276 (Linux cooked v2)"),
+        }
+    }
+}
+
+/// The link-layer fields of a Linux cooked capture header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CookedHeader {
+    /// Direction of the packet: 0 to us, 1 broadcast, 2 multicast, 3 to another host, 4 sent by us.
+    pub packet_type: u16,
+    /// ARPHRD_ type of the interface, 1 for Ethernet.
+    pub hardware_type: u16,
+    /// Link-layer address of the sender, of up to 8 bytes.
+    pub source_address: Vec<u8>,
+    /// Index of the capturing interface, only recorded by the v2 header.
+    pub interface_index: Option<u32>,
+}
+
+impl CookedHeader {
+    /// Parses a 16-byte LINKTYPE_LINUX_SLL header, returning it with the protocol type it carries.
+    ///
+    /// # Errors
+    /// Returns `ParseError::Truncated` if `data` is shorter than the header.
+    pub fn parse_sll(data: &[u8]) -> Result<(CookedHeader, [u8; 2]), ParseError> {
+        require(data, 16, Layer::LinuxSll)?;
+        let address_length = (u16::from_be_bytes(array(data, 4)) as usize).min(8);
+        let header = CookedHeader {
+            packet_type: u16::from_be_bytes(array(data, 0)),
+            hardware_type: u16::from_be_bytes(array(data, 2)),
+            source_address: data[6..6 + address_length].to_vec(),
+            interface_index: None,
+        };
+        Ok((header, array(data, 14)))
+    }
+
+    /// Parses a 20-byte LINKTYPE_LINUX_SLL2 header, returning it with the protocol type it carries.
+    ///
+    /// # Errors
+    /// Returns `ParseError::Truncated` if `data` is shorter than the header.
+    pub fn parse_sll2(data: &[u8]) -> Result<(CookedHeader, [u8; 2]), ParseError> {
+        require(data, 20, Layer::LinuxSll)?;
+        let address_length = (data[11] as usize).min(8);
+        let header = CookedHeader {
+            packet_type: data[10] as u16,
+            hardware_type: u16::from_be_bytes(array(data, 8)),
+            source_address: data[12..12 + address_length].to_vec(),
+            interface_index: Some(u32::from_be_bytes(array(data, 4))),
+        };
+        Ok((header, array(data, 0)))
+    }
+}
+
+/// Maps the address family of a BSD loopback header to an EtherType.
+///
+/// IPv6 has a different family number on each BSD, so all of them are recognised.
+pub(crate) fn address_family_ether_type(family: u32) -> [u8; 2] {
+    match family {
+        2 => [0x08, 0x00],
+        10 | 24 | 28 | 30 => [0x86, 0xdd],
+        _ => [0, 0],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn link_type_numbers_map_to_decoders() {
+        let cases = [
+            (0, LinkType::Null),
+            (1, LinkType::Ethernet),
+            (12, LinkType::Raw),
+            (14, LinkType::Raw),
+            (101, LinkType::Raw),
+            (108, LinkType::Loop),
+            (113, LinkType::LinuxSll),
+            (228, LinkType::IPv4),
+            (229, LinkType::IPv6),
+            (276, LinkType::LinuxSll2),
+        ];
+        for (number, link_type) in cases {
+            assert_eq!(LinkType::from_number(number).unwrap(), link_type, "{}", number);
+        }
+        assert!(matches!(LinkType::from_number(105), Err(ParseError::UnsupportedLinkType(105))));
+    }
+
+    #[test]
+    fn sll_headers_give_the_sender_and_protocol() {
+        let header = [0, 4, 0, 1, 0, 6, 0x02, 0, 0, 0, 0, 1, 0xaa, 0xbb, 0x08, 0x00];
+        let (cooked, protocol) = CookedHeader::parse_sll(&header).unwrap();
+        assert_eq!(protocol, [0x08, 0x00]);
+        assert_eq!(cooked, CookedHeader {
+            packet_type: 4,
+            hardware_type: 1,
+            source_address: vec![0x02, 0, 0, 0, 0, 1],
+            interface_index: None,
+        });
+
+        // The address field holds 8 bytes, whatever length is claimed.
+        let mut long = header;
+        long[5] = 12;
+        assert_eq!(CookedHeader::parse_sll(&long).unwrap().0.source_address.len(), 8);
+
+        assert!(matches!(CookedHeader::parse_sll(&header[..15]),
+                         Err(ParseError::Truncated { layer: Layer::LinuxSll, needed: 16, available: 15 })));
+    }
+
+    #[test]
+    fn sll2_headers_also_give_the_interface() {
+        let header = [0x86, 0xdd, 0, 0, 0, 0, 0, 3, 0, 1, 0, 6, 0x02, 0, 0, 0, 0, 1, 0, 0];
+        let (cooked, protocol) = CookedHeader::parse_sll2(&header).unwrap();
+        assert_eq!(protocol, [0x86, 0xdd]);
+        assert_eq!(cooked, CookedHeader {
+            packet_type: 0,
+            hardware_type: 1,
+            source_address: vec![0x02, 0, 0, 0, 0, 1],
+            interface_index: Some(3),
+        });
+
+        // A tunnel interface has a 4-byte address.
+        let mut tunnel = header;
+        tunnel[8..12].copy_from_slice(&[0x03, 0x0a, 3, 4]);
+        let (cooked, _) = CookedHeader::parse_sll2(&tunnel).unwrap();
+        assert_eq!((cooked.hardware_type, cooked.packet_type), (778, 3));
+        assert_eq!(cooked.source_address, [0x02, 0, 0, 0]);
+
+        assert!(matches!(CookedHeader::parse_sll2(&header[..16]), Err(ParseError::Truncated { needed: 20, .. })));
+    }
+
+    #[test]
+    fn loopback_families_map_to_ether_types() {
+        assert_eq!(address_family_ether_type(2), [0x08, 0x00]);
+        // IPv6 is 10 on Linux, 24 on NetBSD and OpenBSD, 28 on FreeBSD and 30 on macOS.
+        for family in [10, 24, 28, 30] {
+            assert_eq!(address_family_ether_type(family), [0x86, 0xdd], "{}", family);
+        }
+        assert_eq!(address_family_ether_type(0x0200_0000), [0, 0]);
+        assert_eq!(address_family_ether_type(7), [0, 0]);
+    }
+}
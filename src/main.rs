@@ -1,416 +1,439 @@
-// Allow unused imports, variables, dead code, mutable bindings, and assignments in this module.
-// This is typically done during development or debugging.
-#![allow(unused_imports)]
-#![allow(unused_variables)]
-#![allow(dead_code)]
-#![allow(unused_mut)]
-#![allow(unused_assignments)]
-
-// Import various modules related to network packet processing.
-mod ip_protocol;
-mod pcap_file_header;
-mod pcap_block;
-mod ethernet_frame;
-mod internet_packet;
-// mod pcap_file;
-mod internet_protocol_types;
-
-// Standard library imports for environment handling, formatting, file I/O, networking, and string processing.
-use std::{env, fmt};
+// Standard library imports for environment handling and file I/O.
+use std::{env, fs, process};
 use std::fs::File;
-use std::io::Read;
-use std::net::UdpSocket;
+use std::io::{self, BufReader, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use bitreader::BitReader;
-
-// Import structs and enums from the respective modules.
-use ip_protocol::IPProtocol;
-use pcap_file_header::PcapFileHeader;
-use ethernet_frame::EthernetFrame;
-use pcap_block::PcapBlock;
-use internet_protocol_types::{ProtocolDatagram, ICMPPacket, UDPPacket, TCPPacket};
-use internet_packet::IPacket;
-// use pcap_file::PcapFile;
-
-/// Enum representing IP version, either IPv4 or IPv6.
-#[derive(Copy, Clone, PartialOrd, PartialEq)]
-enum IPVersion {
-    V4,
-    V6,
-}
 
-// Implement the Display trait for IPVersion for human-readable output.
-impl fmt::Display for IPVersion {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            IPVersion::V4 => write!(f, "4"),
-            IPVersion::V6 => write!(f, "6")
-        }
-    }
+// Import the parsing API from the packet_analyzer library.
+use packet_analyzer::{ArpMonitor, BpfProgram, CaptureReader, Direction, FilterExpr, FollowFormat, FollowedStream,
+                      IPProtocol, Ipv4Reassembler, Ipv6Reassembler, LinkType, ParseError, PcapBlock, StreamSelector,
+                      TcpConnectionTracker, TcpStreamReassembler, UdpStreamTracker};
+
+/// What is done with the decoded packets, chosen by the arguments following the file name.
+enum Command {
+    /// `[-c <count>] [--bpf | --bpf-file <file>] [expression]`: print the packets that match the
+    /// filter expression, stopping after `count` of them.
+    Print { filter: Option<FilterExpr>, count: Option<usize>, prefilter: Option<Prefilter> },
+    /// `-d <expression>` or `-ddd <expression>`: show the expression compiled to BPF, as
+    /// `tcpdump -d` or `tcpdump -ddd` do.
+    Compile { filter: FilterExpr, ddd: bool },
+    /// `export tcp <directory>`: write the reassembled TCP streams to files.
+    ExportTcp(String),
+    /// `export rtt <directory>`: write the round-trip time samples of each TCP connection to files.
+    ExportRtt(String),
+    /// `follow <tcp|udp> <stream> [ascii|hex|raw] [file]`: show the payload of one stream.
+    Follow { protocol: IPProtocol, selector: StreamSelector, format: FollowFormat, output: Option<String> },
 }
 
-/// Enum representing various filters that can be applied to network packets.
-#[derive(Debug, Clone)]
-enum Filter {
-    Host([u8; 4]),
-    Port([u8; 2]),
-    Ip,
-    Tcp,
-    Udp,
-    Icmp,
-    Net([u8; 4]),
-    Count(i32),
-    Default(String),
+/// A BPF program run on the raw bytes of each packet, before it is decoded.
+enum Prefilter {
+    /// `--bpf`: the filter expression, compiled for the link-layer type of each packet.
+    Compiled(FilterExpr),
+    /// `--bpf-file <file>`: a program loaded from `tcpdump -ddd` output.
+    Loaded(BpfProgram),
 }
 
-// Implement custom logic for converting a string vector to a Filter.
-impl Filter {
-    fn from_str(str: Vec<String>) -> Filter {
-        // Handle different filter types based on input string.
-        if str.len() ==1{
-            match str[0].as_str(){
-                "tcp" => {Filter::Tcp},
-                "udp" =>{Filter::Udp},
-                "icmp" => {Filter::Icmp},
-                "ip" => {Filter::Ip}
-                _ => {Filter::Default("Default".to_string())}
+/// Parses the command given by the arguments following the file name.
+///
+/// # Errors
+/// Returns a usage message if an `export` or `follow` command is incomplete or malformed, or the
+/// syntax error of a filter expression with the position it was found at.
+fn parse_command(args: &[String]) -> Result<Command, String> {
+    match args.first().map(String::as_str) {
+        Some("export") => match &args[1..] {
+            [protocol, directory] if protocol == "tcp" => Ok(Command::ExportTcp(directory.clone())),
+            [measure, directory] if measure == "rtt" => Ok(Command::ExportRtt(directory.clone())),
+            _ => Err("usage: export <tcp|rtt> <directory>".to_string()),
+        },
+        Some("follow") => {
+            let usage = "usage: follow <tcp|udp> <stream-index|address:port address:port> [ascii|hex|raw] [file]";
+            let protocol = match args.get(1).map(String::as_str) {
+                Some("tcp") => IPProtocol::TCP,
+                Some("udp") => IPProtocol::UDP,
+                _ => return Err(usage.to_string()),
+            };
+            let (selector, used) = StreamSelector::parse(&args[2..]).ok_or(usage)?;
+            let rest = &args[2 + used..];
+            let (format, rest) = match rest.first().and_then(|name| FollowFormat::from_name(name)) {
+                Some(format) => (format, &rest[1..]),
+                None => (FollowFormat::Ascii, rest),
+            };
+            match rest {
+                [] => Ok(Command::Follow { protocol, selector, format, output: None }),
+                [file] => Ok(Command::Follow { protocol, selector, format, output: Some(file.clone()) }),
+                _ => Err(usage.to_string()),
             }
         }
-        else {
-            match str[0].as_str() {
-                "host" => {
-                    let k: Vec<&str> = str[1].split(".").collect();
-                    Filter::Host([u8::from_str(k[0]).unwrap(), u8::from_str(k[1]).unwrap(),
-                        u8::from_str(k[2]).unwrap(), u8::from_str(k[3]).unwrap()])
-                }
-                "port" => {
-                    let num = u16::from_str(&*str[1]).unwrap();
-                    let b1 = (num >> 8) as u8;
-                    let b2 = num as u8;
-                    Filter::Port([b1, b2])
+        _ => {
+            let mut count = None;
+            let mut ddd = None;
+            let mut bpf = false;
+            let mut loaded = None;
+            let mut expression = args;
+            loop {
+                expression = match expression {
+                    [option, value, rest @ ..] if option == "-c" => {
+                        count = Some(usize::from_str(value).map_err(|_| format!("invalid packet count: {}", value))?);
+                        rest
+                    }
+                    [option, rest @ ..] if option == "-d" || option == "-ddd" => {
+                        ddd = Some(option == "-ddd");
+                        rest
+                    }
+                    [option, rest @ ..] if option == "--bpf" => {
+                        bpf = true;
+                        rest
+                    }
+                    [option, file, rest @ ..] if option == "--bpf-file" => {
+                        let text = fs::read_to_string(file).map_err(|e| format!("Cannot read {}: {}", file, e))?;
+                        let program = BpfProgram::from_ddd(&text).map_err(|e| format!("invalid BPF program {}: {}", file, e))?;
+                        loaded = Some(program);
+                        rest
+                    }
+                    _ => break,
+                };
+            }
+            let filter = if expression.is_empty() { None } else { Some(parse_filter(expression)?) };
+            match (filter, ddd, bpf, loaded) {
+                (Some(filter), Some(ddd), _, _) => {
+                    BpfProgram::compile(&filter, LinkType::Ethernet).map_err(|e| e.to_string())?;
+                    Ok(Command::Compile { filter, ddd })
                 }
-                "net" => {
-                    let k: Vec<&str> = str[1].split(".").collect();
-                    Filter::Net([u8::from_str(k[0]).unwrap(), u8::from_str(k[1]).unwrap(),
-                        u8::from_str(k[2]).unwrap(), u8::from_str(k[3]).unwrap()])
+                (None, Some(_), _, _) => Err("usage: -d|-ddd <expression>".to_string()),
+                (_, None, true, Some(_)) => Err("--bpf and --bpf-file cannot be given together".to_string()),
+                (Some(filter), None, true, None) => {
+                    // Reject what BPF cannot express before reading the capture.
+                    BpfProgram::compile(&filter, LinkType::Ethernet).map_err(|e| e.to_string())?;
+                    Ok(Command::Print { filter: None, count, prefilter: Some(Prefilter::Compiled(filter)) })
                 }
-                "-c" => { Filter::Count(str[1].parse::<i32>().unwrap()) }
-                &_ => { Filter::Default("default".to_string()) }
+                (None, None, true, None) => Err("usage: --bpf <expression>".to_string()),
+                (filter, None, false, loaded) => Ok(Command::Print { filter, count, prefilter: loaded.map(Prefilter::Loaded) }),
             }
         }
     }
 }
 
-/// Creates and returns an `EthernetFrame` from raw data bytes.
-/// 
-/// # Arguments
-/// * `data` - A vector of bytes representing the raw data of the Ethernet frame.
-fn create_and_return_ether(data: Vec<u8>) -> EthernetFrame {
-    let packet_size = data.len() as u32;
-    let destination_address: [u8; 6] = data[0..6].try_into().unwrap();
-    let source_address: [u8; 6] = data[6..12].try_into().unwrap();
-    let ether_type: [u8; 2] = data[12..14].try_into().unwrap();
-
-
-    let temp = [data[14].clone()]; //This let is important
-    let mut version_head_len_byte = BitReader::new(&temp);
-    let ipv = version_head_len_byte.read_u8(4).unwrap();
-    let ihl = version_head_len_byte.read_u8(4).unwrap();
-
-    let temp = [data[15].clone()];
-    let mut type_of_service = BitReader::new(&temp);
-    let precedence = type_of_service.read_u8(3).unwrap();
-    let delay = type_of_service.read_u8(1).unwrap();
-    let throughput = type_of_service.read_u8(1).unwrap();
-    let reliability = type_of_service.read_u8(1).unwrap();
-    let tos = type_of_service.read_u8(2).unwrap();
-
-    let total_length: [u8; 2] = data[16..18].try_into().unwrap();
-    let identification: [u8; 2] = data[18..20].try_into().unwrap();
-
-    let temp = [data[20].clone(), data[21].clone()];
-    let mut flags = BitReader::new(&temp);
-    let reserved_flag = flags.read_u8(1).unwrap();
-    let do_not_fragment_flag = flags.read_u8(1).unwrap();
-    let last_fragment_flag = flags.read_u8(1).unwrap();
-    let fragment_offset = flags.peek_u16(0).unwrap();
-
-
-    //Here first 3 bits are flags and rest 13 are Fragment offset
-
-    let ttl = data[22];
-    let temp = data[23];
-    let mut protocol = IPProtocol::Default;
-    match temp {
-        1 => { protocol = IPProtocol::ICMP }
-        6 => { protocol = IPProtocol::TCP }
-        17 => { protocol = IPProtocol::UDP }
-        _ => {}
-    }
-    let header_checksum: [u8; 2] = data[24..26].try_into().unwrap();
-
-    let source_add: [u8; 4] = data[26..30].try_into().unwrap();
-    let destination_add: [u8; 4] = data[30..34].try_into().unwrap();
-
-    let mut options = None;
-    let mut current: usize = 34;
-    if ihl > 5 {
-        current = (34 + ((ihl * 4) - 20)) as usize;
-        options = Some(data[34..(current)].to_vec());
-    }
-
-
-    let mut datagram: ProtocolDatagram = ProtocolDatagram::new();
-    match protocol {
-        IPProtocol::ICMP => {
-            let mut icmp = ICMPPacket::new();
-            icmp.packet_type = data[current];
-            icmp.code = data[current + 1];
-            icmp.checksum = data[current + 2..current + 4].try_into().unwrap();
-            icmp.identifier_be = data[(current + 4)..(current + 6)].try_into().unwrap();
-            icmp.identifier_le = data[(current + 4)..(current + 6)].try_into().unwrap();
-            icmp.sequence_be = data[(current + 6)..(current + 8)].try_into().unwrap();
-            icmp.sequence_le = data[(current + 6)..(current + 8)].try_into().unwrap();
-            icmp.timestamp = data[(current + 8)..(current + 16)].try_into().unwrap();
-            icmp.data = data[(current + 16)..].to_vec();
-            datagram = ProtocolDatagram::ICMP(icmp);
-        }
-        IPProtocol::UDP => {
-            let mut udp = UDPPacket::new();
-            udp.source_port = data[current..(current + 2)].try_into().unwrap();
-            udp.destination_port = data[(current + 2)..(current + 4)].try_into().unwrap();
-            udp.length = data[(current + 4)..(current + 6)].try_into().unwrap();
-            udp.checksum = data[(current + 6)..(current + 8)].try_into().unwrap();
-            udp.data = data[(current + 8)..].to_vec();
-            datagram = ProtocolDatagram::UDP(udp);
-        }
-        IPProtocol::TCP => {
-            let mut tcp = TCPPacket::new();
-            tcp.source_port = data[current..(current + 2)].try_into().unwrap();
-            tcp.destination_port = data[(current + 2)..(current + 4)].try_into().unwrap();
-            tcp.sequence_number = data[(current + 4)..(current + 8)].try_into().unwrap();
-            tcp.acknowledgement_number = data[(current + 8)..(current + 12)].try_into().unwrap();
-
-            let data_offset_and_flags = data[current + 12];
-            let data_offset = (data_offset_and_flags >> 4) & 0xF;
-            let flags = data_offset_and_flags & 0xF;
+/// Parses a filter expression given as one or more arguments.
+///
+/// # Errors
+/// Returns the syntax error with the expression and a caret under the position it was found at.
+fn parse_filter(args: &[String]) -> Result<FilterExpr, String> {
+    // The shell may split an expression into several arguments, or keep it as one.
+    let expression = args.join(" ");
+    FilterExpr::parse(&expression).map_err(|e| {
+        format!("invalid filter: {}\n  {}\n  {}^", e.message, expression,
+                " ".repeat(expression[..e.position].chars().count()))
+    })
+}
 
-            tcp.data_offset = data_offset * 4;
-            tcp.flags = flags;
-            tcp.window = data[(current + 14)..(current + 16)].try_into().unwrap();
-            tcp.checksum = data[(current + 16)..(current + 18)].try_into().unwrap();
-            tcp.urgent_pointer = data[(current + 18)..(current + 20)].try_into().unwrap();
-            datagram = ProtocolDatagram::TCP(tcp);
-        }
-        _ => {}
+/// Returns the link-layer type of the capture, reading up to its first packet if it is a pcapng
+/// capture whose interfaces are only described there. Ethernet is assumed if none is found.
+fn capture_link_type<R: Read>(reader: &mut CaptureReader<R>) -> LinkType {
+    if reader.link_type().is_none() {
+        reader.next();
     }
+    reader.link_type().unwrap_or(LinkType::Ethernet)
+}
 
-
-    let version = match ipv {
-        4 => { IPVersion::V4 }
-        _ => { IPVersion::V6 }
-    };
-
-    let packet = IPacket {
-        version,
-        ihl,
-        tos,
-        precedence,
-        delay,
-        throughput,
-        reliability,
-        total_length,
-        identification,
-        reserved_flag,
-        do_not_fragment_flag,
-        last_fragment_flag,
-        fragment_offset,
-        ttl,
-        protocol,
-        header_checksum,
-        source_add,
-        destination_add,
-        options,
-        datagram,
-
-    };
-
-    EthernetFrame {
-        packet_size,
-        destination_address,
-        source_address,
-        ether_type,
-        version,
-        packet,
+/// Returns a prefilter running the filter expression compiled to BPF, compiling it once for each
+/// link-layer type the packets of the capture have.
+fn compiled_prefilter(filter: FilterExpr) -> impl FnMut(&PcapBlock) -> bool {
+    let mut programs: Vec<(LinkType, BpfProgram)> = vec![];
+    move |block| {
+        let position = match programs.iter().position(|(link_type, _)| *link_type == block.link_type) {
+            Some(position) => position,
+            None => match BpfProgram::compile(&filter, block.link_type) {
+                Ok(program) => {
+                    programs.push((block.link_type, program));
+                    programs.len() - 1
+                }
+                Err(e) => {
+                    eprintln!("Cannot compile the filter for link type {}: {}", block.link_type, e);
+                    process::exit(1);
+                }
+            },
+        };
+        programs[position].1.matches(block)
     }
 }
 
-
-
-/// Prints the given PCAP block if it matches the specified filter.
+/// Prints the given PCAP block if it matches the specified filter, returning whether it did.
 /// 
 /// # Arguments
+/// * `out` - Where the block is printed.
 /// * `block` - The PCAP block to be printed.
-/// * `filter` - The filter to apply to the PCAP block.
-fn print_pcap(block: PcapBlock, filter: Filter) {
+/// * `filter` - The filter to apply to the PCAP block, if any.
+///
+/// # Errors
+/// Returns the error of writing to `out`.
+fn print_pcap(out: &mut impl Write, block: PcapBlock, filter: Option<&FilterExpr>) -> io::Result<bool> {
+    if filter.is_some_and(|filter| !filter.matches(&block.ether_frame)) {
+        return Ok(false);
+    }
+    writeln!(out, "{}", block)?;
+    writeln!(out, "{}", block.ether_frame)?;
+    writeln!(out, "{}\n\n", block.ether_frame.payload)?;
+    Ok(true)
+}
 
-    match filter {
-        Filter::Host(address) => {
-            if block.ether_frame.packet.source_add == address ||
-                block.ether_frame.packet.destination_add == address {
-                println!("{}", block.ether_frame);
-                println!("{}\n\n", block.ether_frame.packet);
-            }
-        }
-        Filter::Port(port) => {
-            if block.ether_frame.packet.protocol == IPProtocol::UDP ||
-                block.ether_frame.packet.protocol == IPProtocol::TCP {
-                let mut src = [0, 0];
-                let mut dst = [0, 0];
-                match block.ether_frame.packet.datagram {
-                    ProtocolDatagram::TCP(ref packet) => {
-                        src = packet.source_port.clone();
-                        dst = packet.destination_port.clone();
-                    }
-                    ProtocolDatagram::UDP(ref packet) => {
-                        src = packet.source_port.clone();
-                        dst = packet.destination_port.clone();
-                    }
-                    ProtocolDatagram::ICMP(_) => {}
-                    ProtocolDatagram::Default(_) => {}
-                }
-                if [src, dst].contains(&port) {
-                    println!("{}", block.ether_frame);
-                    println!("{}\n\n", block.ether_frame.packet);
-                }
-            }
-        }
-        Filter::Ip => {
-            if block.ether_frame.version == IPVersion::V4 {
-                println!("{}", block.ether_frame);
-                println!("{}\n\n", block.ether_frame.packet);
+/// Writes the bytes sent by each side of every TCP stream to a file of its own.
+///
+/// # Arguments
+/// * `reassembler` - The reassembled streams of the capture.
+/// * `directory` - The directory the files are written to, named `tcp-stream-<index>-client.bin`
+///   and `tcp-stream-<index>-server.bin` after the stream index and the sending side.
+fn export_tcp_streams(out: &mut impl Write, reassembler: &TcpStreamReassembler, directory: &str) -> io::Result<()> {
+    if let Err(e) = fs::create_dir_all(directory) {
+        eprintln!("Cannot create {}: {}", directory, e);
+        process::exit(1);
+    }
+    for stream in reassembler.streams() {
+        for (direction, side) in [(Direction::ClientToServer, "client"), (Direction::ServerToClient, "server")] {
+            let path = Path::new(directory).join(format!("tcp-stream-{}-{}.bin", stream.index, side));
+            if let Err(e) = fs::write(&path, stream.data(direction).data()) {
+                eprintln!("Cannot write {}: {}", path.display(), e);
+                process::exit(1);
             }
         }
-        Filter::Tcp => {
-            if block.ether_frame.packet.protocol == IPProtocol::TCP{
-                println!("{}", block.ether_frame);
-                println!("{}\n\n", block.ether_frame.packet);
-            }
+        writeln!(out, "{}", stream)?;
+    }
+    Ok(())
+}
+
+/// Writes the round-trip time samples of every TCP connection that has any to a CSV file of its
+/// own, named `tcp-stream-<index>-rtt.csv` after the stream index.
+///
+/// Each line gives the capture time of the acknowledging segment and the round-trip time, both in
+/// seconds, and the side that sent the acknowledged segment.
+fn export_rtt(out: &mut impl Write, tracker: &TcpConnectionTracker, directory: &str) -> io::Result<()> {
+    if let Err(e) = fs::create_dir_all(directory) {
+        eprintln!("Cannot create {}: {}", directory, e);
+        process::exit(1);
+    }
+    for connection in tracker.connections().filter(|connection| !connection.rtt_samples.is_empty()) {
+        let mut csv = String::from("time,rtt,sender\n");
+        for sample in &connection.rtt_samples {
+            let sender = if sample.direction == Direction::ClientToServer { "client" } else { "server" };
+            csv.push_str(&format!("{}.{:09},{}.{:09},{}\n", sample.timestamp.as_secs(), sample.timestamp.subsec_nanos(),
+                                  sample.rtt.as_secs(), sample.rtt.subsec_nanos(), sender));
         }
-        Filter::Udp => {
-            if block.ether_frame.packet.protocol == IPProtocol::UDP{
-                println!("{}", block.ether_frame);
-                println!("{}\n\n", block.ether_frame.packet);
-            }
+        let path = Path::new(directory).join(format!("tcp-stream-{}-rtt.csv", connection.index));
+        if let Err(e) = fs::write(&path, csv) {
+            eprintln!("Cannot write {}: {}", path.display(), e);
+            process::exit(1);
         }
-        Filter::Icmp => {
-            if block.ether_frame.packet.protocol == IPProtocol::ICMP{
-                println!("{}", block.ether_frame);
-                println!("{}\n\n", block.ether_frame.packet);
-            }
+        writeln!(out, "Wrote {} RTT samples of stream {} to {}", connection.rtt_samples.len(), connection.index,
+                 path.display())?;
+    }
+    Ok(())
+}
+
+/// Shows the payload of the selected TCP or UDP stream, or writes it to a file.
+///
+/// With a file, the other formats write the same text they print, while the raw format writes the
+/// bytes sent by each side to a file of its own, named after the given one with `-client` or
+/// `-server` added to its stem, as `stream-client.bin` and `stream-server.bin` for `stream.bin`.
+fn follow_stream(out: &mut impl Write, tcp_streams: &TcpStreamReassembler, udp_streams: &UdpStreamTracker,
+                 protocol: IPProtocol, selector: StreamSelector, format: FollowFormat, output: Option<&str>)
+                 -> io::Result<()> {
+    let followed = if protocol == IPProtocol::UDP {
+        udp_streams
+            .streams()
+            .find(|stream| selector.matches(stream.index, stream.client, stream.server))
+            .map(|stream| FollowedStream::udp(stream, format))
+    } else {
+        tcp_streams
+            .streams()
+            .find(|stream| selector.matches(stream.index, stream.client, stream.server))
+            .map(|stream| FollowedStream::tcp(stream, format))
+    };
+    let followed = match followed {
+        Some(followed) => followed,
+        None => {
+            let name = if protocol == IPProtocol::UDP { "UDP" } else { "TCP" };
+            eprintln!("No {} stream matches {}", name, selector);
+            process::exit(1);
         }
-        Filter::Net(address) => {
-            if block.ether_frame.packet.source_add == address ||
-                block.ether_frame.packet.destination_add == address {
-                println!("{}", block.ether_frame);
-                println!("{}\n\n", block.ether_frame.packet);
-            }
+    };
+    let path = match output {
+        Some(path) => path,
+        None => {
+            return writeln!(out, "{}", followed);
         }
-        Filter::Count(count) => {
-            if count > 0 {
-                println!("{}", block.ether_frame);
-                println!("{}\n\n", block.ether_frame.packet);
-            }
+    };
+    let files = match format {
+        FollowFormat::Raw => {
+            let path = Path::new(path);
+            let stem = path.file_stem().map(|stem| stem.to_string_lossy()).unwrap_or_default();
+            let extension = path.extension().map(|extension| format!(".{}", extension.to_string_lossy()));
+            [(Direction::ClientToServer, "client"), (Direction::ServerToClient, "server")]
+                .into_iter()
+                .map(|(direction, side)| {
+                    let name = format!("{}-{}{}", stem, side, extension.as_deref().unwrap_or(""));
+                    (path.with_file_name(name), followed.raw_bytes(direction))
+                })
+                .collect()
         }
-        Filter::Default(_) => {
-            println!("{}", block.ether_frame);
-            println!("{}\n\n", block.ether_frame.packet);
+        _ => vec![(PathBuf::from(path), format!("{}\n", followed).into_bytes())],
+    };
+    for (path, contents) in files {
+        if let Err(e) = fs::write(&path, &contents) {
+            eprintln!("Cannot write {}: {}", path.display(), e);
+            process::exit(1);
         }
+        writeln!(out, "Wrote {} bytes of stream {} to {}", contents.len(), followed.index, path.display())?;
     }
+    Ok(())
 }
 
-
 fn main() {
+    // A reader closing the output early, as `head` does, ends the program quietly.
+    if let Err(e) = run() {
+        if e.kind() != ErrorKind::BrokenPipe {
+            eprintln!("Cannot write the output: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+/// Reads the capture named by the arguments and carries out the command they give, writing the
+/// output through a locked standard output.
+///
+/// # Errors
+/// Returns the error of writing to standard output.
+fn run() -> io::Result<()> {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
     let args: Vec<String> = env::args().collect();
     let mut file_name = String::from("test.pcap");
     if args.len() > 1 {
         file_name = args[1].clone();
     }
-    let mut filter = Filter::Default("default".to_owned());
-    if args.len() > 2 {
-        filter = Filter::from_str(args[2..].to_vec())
+    let command = match parse_command(args.get(2..).unwrap_or(&[])) {
+        Ok(command) => command,
+        Err(usage) => {
+            eprintln!("{}", usage);
+            process::exit(1);
+        }
+    };
+    // Read from stdin when the file name is "-", otherwise open the capture file.
+    let source: Box<dyn Read> = if file_name == "-" {
+        Box::new(io::stdin().lock())
+    } else {
+        match File::open(&file_name) {
+            Ok(file) => Box::new(file),
+            Err(e) => {
+                eprintln!("Cannot open {}: {}", file_name, e);
+                process::exit(1);
+            }
+        }
+    };
+    let mut reader = match CaptureReader::new(BufReader::new(source)) { //Parsing the PCAP Header
+        Ok(reader) => reader,
+        Err(e) => {
+            eprintln!("Cannot read {}: {}", file_name, e);
+            process::exit(1);
+        }
+    };
+    match &command {
+        Command::Compile { filter, ddd } => {
+            let link_type = capture_link_type(&mut reader);
+            match BpfProgram::compile(filter, link_type) {
+                Ok(program) if *ddd => write!(out, "{}", program.to_ddd())?,
+                Ok(program) => writeln!(out, "{}", program)?,
+                Err(e) => {
+                    eprintln!("Cannot compile the filter for link type {}: {}", link_type, e);
+                    process::exit(1);
+                }
+            }
+            return Ok(());
+        }
+        Command::Print { prefilter: Some(Prefilter::Compiled(filter)), .. } => {
+            reader.set_prefilter(compiled_prefilter(filter.clone()))
+        }
+        Command::Print { prefilter: Some(Prefilter::Loaded(program)), .. } => {
+            let program = program.clone();
+            reader.set_prefilter(move |block| program.matches(block))
+        }
+        _ => {}
+    }
+    if let CaptureReader::PcapNg(ref pcapng) = reader {
+        writeln!(out, "{}\n", pcapng.section())?;
     }
-    let mut pcap_header: PcapFileHeader = PcapFileHeader::new(); //Initializing a Pcap Header Structure
-    let mut file: File = File::open(file_name).unwrap(); //reading the file
-    let file_size: u64 = file.metadata().unwrap().len(); //File size (bytes)
-    let mut byte_count: u64 = 0; //This counter is used to track the bytes reader has read from the file
-
-
-    //This is PCAP Header
-    file.read(&mut pcap_header.magic_number).unwrap();
-    file.read(&mut pcap_header.version_major).unwrap();
-    file.read(&mut pcap_header.version_minor).unwrap();
-    file.read(&mut pcap_header.time_zone).unwrap();
-    file.read(&mut pcap_header.timestamp_accuracy).unwrap();
-    file.read(&mut pcap_header.snap_length).unwrap();
-    file.read(&mut pcap_header.link_layer_type).unwrap();
-    byte_count += 24;  //Because the size of PCAP Header is 24 bytes
-
-    // let pcap_file = PcapFile::new_with_header(pcap_header); //Initializing the PCAP file with the header
-    let mut packet_count = 0; //Count of network packets in PCAP File
 
-    loop {
-        let my_filter = filter.clone();
-        filter = my_filter.clone();
-        match filter {
-            Filter::Host(_) => {}
-            Filter::Port(_) => {}
-            Filter::Ip => {}
-            Filter::Tcp => {}
-            Filter::Udp => {}
-            Filter::Icmp => {}
-            Filter::Net(_) => {}
-            Filter::Count(count) => {
-                filter = Filter::Count(count - 1)
+    let mut packet_count = 0; //Count of network packets in PCAP File that passed the BPF filter
+    let mut printed_count = 0; //Count of packets that passed the filter and were printed
+    let mut arp_monitor = ArpMonitor::new();
+    let mut ipv4_reassembler = Ipv4Reassembler::new();
+    let mut ipv6_reassembler = Ipv6Reassembler::new();
+    let mut tcp_connections = TcpConnectionTracker::new();
+    let mut tcp_streams = TcpStreamReassembler::new();
+    let mut udp_streams = UdpStreamTracker::new();
+
+    while let Some(pcap_block) = reader.next() {
+        // Packets skipped by the BPF filter keep their place in the numbering.
+        let packet_number = packet_count + 1 + reader.filtered();
+        match pcap_block {
+            Ok(mut block) => {
+                packet_count += 1;
+                let timestamp = block.timestamp();
+                let reassembly = ipv4_reassembler
+                    .process(&mut block.ether_frame, timestamp)
+                    .and_then(|_| ipv6_reassembler.process(&mut block.ether_frame, timestamp));
+                if let Err(e) = reassembly {
+                    writeln!(out, "Packet {}: malformed reassembled datagram, {}\n\n", packet_number, e)?;
+                }
+                tcp_connections.process(&mut block.ether_frame, timestamp);
+                if let Some(arp) = block.ether_frame.payload.arp() {
+                    arp_monitor.observe(arp, packet_number);
+                }
+                match &command {
+                    Command::Print { filter, count, .. } => {
+                        if count.is_none_or(|count| printed_count < count) && print_pcap(&mut out, block, filter.as_ref())? {
+                            printed_count += 1;
+                        }
+                    }
+                    Command::ExportRtt(_) | Command::Compile { .. } => {}
+                    Command::Follow { protocol: IPProtocol::UDP, .. } => {
+                        udp_streams.process(&block.ether_frame, packet_number, timestamp);
+                    }
+                    Command::ExportTcp(_) | Command::Follow { .. } => {
+                        tcp_streams.process(&block.ether_frame, packet_number, timestamp);
+                    }
+                }
+            }
+            // Reading cannot continue past a broken record, the reader stops after this error.
+            Err(ParseError::Io(e)) => eprintln!("Error reading {} after packet {}: {}", file_name, packet_number - 1, e),
+            Err(e) => {
+                packet_count += 1;
+                writeln!(out, "Packet {}: malformed packet, {}\n\n", packet_number, e)?;
             }
-            Filter::Default(_) => {}
         }
-        if byte_count + 20 > file_size {
-            println!("Total number of packets in the file(Without Filter): {}", packet_count);
-            break;
+    }
+    match &command {
+        Command::Print { .. } | Command::Compile { .. } => {}
+        Command::ExportTcp(directory) => export_tcp_streams(&mut out, &tcp_streams, directory)?,
+        Command::ExportRtt(directory) => export_rtt(&mut out, &tcp_connections, directory)?,
+        Command::Follow { protocol, selector, format, output } => {
+            follow_stream(&mut out, &tcp_streams, &udp_streams, *protocol, *selector, *format, output.as_deref())?
         }
-
-        packet_count += 1;
-        let mut pcap_block: PcapBlock = PcapBlock::new(); //Initializing a new PCAP Block
-        file.read(&mut pcap_block.timestamp_seconds).unwrap();
-        file.read(&mut pcap_block.timestamp_microseconds).unwrap();
-        file.read(&mut pcap_block.captured_length).unwrap();
-        file.read(&mut pcap_block.original_length).unwrap();
-
-        byte_count += 16;
-        byte_count += u32::from_ne_bytes(pcap_block.captured_length.clone()) as u64;
-
-        let mut pcap_block_data = vec![0_u8; u32::from_ne_bytes(pcap_block.captured_length) as usize];
-        file.read(&mut pcap_block_data).unwrap();
-
-        pcap_block.ether_frame = create_and_return_ether(pcap_block_data);
-        // pcap_file.data.push(pcap_block); //Instead of print, we can use this command to create
-        // the complete PCAP file struct with Pcap Blocks
-        print_pcap(pcap_block, my_filter);
     }
+    writeln!(out, "Total number of packets in the file(Without Filter): {}", packet_count + reader.filtered())?;
+    if reader.filtered() > 0 {
+        writeln!(out, "Packets skipped by the BPF filter: {}", reader.filtered())?;
+    }
+    if !arp_monitor.is_empty() {
+        writeln!(out, "\n{}", arp_monitor)?;
+    }
+    if !tcp_connections.is_empty() {
+        writeln!(out, "\n{}", tcp_connections)?;
+    }
+    if !ipv4_reassembler.is_empty() {
+        writeln!(out, "\n{}", ipv4_reassembler)?;
+    }
+    if !ipv6_reassembler.is_empty() {
+        writeln!(out, "\n{}", ipv6_reassembler)?;
+    }
+    Ok(())
 }
-
-// fn main(){
-//     let rcv_socket = UdpSocket::bind("0.0.0.0:4331").expect("could not bind to the receive socket");
-//     println!("Listening on: {}", rcv_socket.local_addr().unwrap());
-//     let mut buf = [0; 1024];
-
-//     let (number_of_bytes, src_addr) = rcv_socket.recv_from(&mut buf)
-//         .expect("Didn't receive data");
-//     let filled_buf =  buf[..number_of_bytes].to_vec();
-//     let ethernet_frame = create_and_return_ether(filled_buf);
-//     println!("{}", ethernet_frame);
-//     println!("{}", ethernet_frame.packet);
-
-// }
-
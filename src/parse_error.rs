@@ -0,0 +1,104 @@
+use std::error::Error;
+use std::{fmt, io};
+
+/// Protocol layer at which decoding failed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Layer {
+    PcapRecord,
+    PcapNgBlock,
+    Ethernet,
+    Loopback,
+    LinuxSll,
+    Vlan,
+    Mpls,
+    Pppoe,
+    Lldp,
+    Arp,
+    IPv4,
+    IPv6,
+    IPv6Extension,
+    TCP,
+    UDP,
+    ICMP,
+    ICMPv6,
+}
+
+impl fmt::Display for Layer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Layer::PcapRecord => write!(f, "pcap record"),
+            Layer::PcapNgBlock => write!(f, "pcapng block"),
+            Layer::Ethernet => write!(f, "Ethernet"),
+            Layer::Loopback => write!(f, "loopback"),
+            Layer::LinuxSll => write!(f, "Linux cooked"),
+            Layer::Vlan => write!(f, "VLAN"),
+            Layer::Mpls => write!(f, "MPLS"),
+            Layer::Pppoe => write!(f, "PPPoE"),
+            Layer::Lldp => write!(f, "LLDP"),
+            Layer::Arp => write!(f, "ARP"),
+            Layer::IPv4 => write!(f, "IPv4"),
+            Layer::IPv6 => write!(f, "IPv6"),
+            Layer::IPv6Extension => write!(f, "IPv6 extension"),
+            Layer::TCP => write!(f, "TCP"),
+            Layer::UDP => write!(f, "UDP"),
+            Layer::ICMP => write!(f, "ICMP"),
+            Layer::ICMPv6 => write!(f, "ICMPv6"),
+        }
+    }
+}
+
+/// Error returned when a capture or the raw bytes of a frame cannot be decoded.
+#[derive(Debug)]
+pub enum ParseError {
+    /// Reading the capture failed, or it ended in the middle of a record.
+    Io(io::Error),
+    /// The bytes ended before the header of `layer` was complete. `needed` and `available` are
+    /// counted from the start of that layer.
+    Truncated { layer: Layer, needed: usize, available: usize },
+    /// A length field of `layer` holds a value that cannot be valid.
+    BadHeaderLength { layer: Layer, length: usize },
+    /// The version field of an IP header does not match the protocol it was dispatched to.
+    BadVersion { layer: Layer, version: u8 },
+    /// The capture uses a link-layer type that cannot be decoded.
+    UnsupportedLinkType(u32),
+    /// The capture does not start with a known pcap or pcapng magic number.
+    BadMagic([u8; 4]),
+    /// A pcapng packet refers to an interface that no Interface Description Block described.
+    UnknownInterface(u32),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Io(e) => write!(f, "error reading capture: {}", e),
+            ParseError::Truncated { layer, needed, available } => {
+                write!(f, "truncated {} header: needed {} bytes, got {}", layer, needed, available)
+            }
+            ParseError::BadHeaderLength { layer, length } => {
+                write!(f, "bad {} header length: {}", layer, length)
+            }
+            ParseError::BadVersion { layer, version } => write!(f, "bad {} version: {}", layer, version),
+            ParseError::UnsupportedLinkType(link_type) => write!(f, "unsupported link-layer type {}", link_type),
+            ParseError::BadMagic(magic) => {
+                write!(f, "not a pcap or pcapng file: bad magic number 0x{:02x}{:02x}{:02x}{:02x}", magic[0], magic[1],
+                       magic[2], magic[3])
+            }
+            ParseError::UnknownInterface(interface_id) => write!(f, "packet refers to unknown interface {}", interface_id),
+        }
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ParseError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ParseError {
+    fn from(e: io::Error) -> ParseError {
+        ParseError::Io(e)
+    }
+}
@@ -0,0 +1,277 @@
+use crate::ether_payload::{ETHERTYPE_QINQ, ETHERTYPE_QINQ_LEGACY, ETHERTYPE_VLAN};
+use crate::link_layer::address_family_ether_type;
+use crate::{CookedHeader, EtherPayload, EthernetFrame, Layer, LinkType, ParseError, VlanTag};
+
+/// Length of an Ethernet header.
+const ETHERNET_HEADER_LENGTH: usize = 14;
+
+/// Parses raw frame bytes into an `EthernetFrame`.
+///
+/// The frame is expected to start with a 14-byte Ethernet header, optionally followed by VLAN tags.
+/// Its payload is decoded according to the EtherType, and the TCP, UDP and ICMP payloads of IP
+/// packets into the matching `ProtocolDatagram` variant.
+///
+/// # Arguments
+/// * `data` - The raw bytes of the Ethernet frame, as captured.
+///
+/// # Errors
+/// Returns `ParseError::Truncated` if a header ends early, or `ParseError::BadHeaderLength` if a
+/// header length field is invalid.
+pub fn parse_frame(data: &[u8]) -> Result<EthernetFrame, ParseError> {
+    parse_packet(LinkType::Ethernet, data)
+}
+
+/// Parses the raw bytes of a captured packet according to the link-layer type of the capture.
+///
+/// Whatever the link layer, the result is an `EthernetFrame` whose `ether_type` names the network
+/// protocol: loopback address families and raw IP versions are mapped to the matching EtherType.
+///
+/// # Arguments
+/// * `link_type` - The link-layer type of the capture or interface.
+/// * `data` - The raw bytes of the packet, as captured.
+///
+/// # Errors
+/// Same as `parse_frame`.
+pub fn parse_packet(link_type: LinkType, data: &[u8]) -> Result<EthernetFrame, ParseError> {
+    let mut frame = EthernetFrame::new();
+    frame.packet_size = data.len() as u32;
+    frame.link_type = link_type;
+
+    let mut header_length = match link_type {
+        LinkType::Ethernet => {
+            require(data, ETHERNET_HEADER_LENGTH, Layer::Ethernet)?;
+            frame.destination_address = array(data, 0);
+            frame.source_address = array(data, 6);
+            frame.ether_type = array(data, 12);
+            ETHERNET_HEADER_LENGTH
+        }
+        LinkType::Null | LinkType::Loop => {
+            require(data, 4, Layer::Loopback)?;
+            // LINKTYPE_NULL stores the family in the byte order of the capturing host. Families are
+            // small numbers, so a big-endian value starts with two zero bytes.
+            let family = if link_type == LinkType::Loop || data[0..2] == [0, 0] {
+                u32::from_be_bytes(array(data, 0))
+            } else {
+                u32::from_le_bytes(array(data, 0))
+            };
+            frame.ether_type = address_family_ether_type(family);
+            4
+        }
+        LinkType::Raw | LinkType::IPv4 | LinkType::IPv6 => {
+            require(data, 1, Layer::IPv4)?;
+            frame.ether_type = if data[0] >> 4 == 6 { [0x86, 0xdd] } else { [0x08, 0x00] };
+            0
+        }
+        LinkType::LinuxSll | LinkType::LinuxSll2 => {
+            let (cooked, protocol) = if link_type == LinkType::LinuxSll {
+                CookedHeader::parse_sll(data)?
+            } else {
+                CookedHeader::parse_sll2(data)?
+            };
+            if cooked.source_address.len() == 6 {
+                frame.source_address = array(&cooked.source_address, 0);
+            }
+            frame.ether_type = protocol;
+            frame.cooked = Some(cooked);
+            if link_type == LinkType::LinuxSll { 16 } else { 20 }
+        }
+    };
+
+    // Single and stacked VLAN tags sit between the link-layer header and the EtherType of the payload.
+    loop {
+        let tpid = u16::from_be_bytes(frame.ether_type);
+        if tpid != ETHERTYPE_VLAN && tpid != ETHERTYPE_QINQ && tpid != ETHERTYPE_QINQ_LEGACY {
+            break;
+        }
+        require(&data[header_length..], 4, Layer::Vlan)?;
+        frame.vlan_tags.push(VlanTag::from_tci(tpid, u16::from_be_bytes(array(data, header_length))));
+        frame.ether_type = array(data, header_length + 2);
+        header_length += 4;
+    }
+
+    frame.payload = EtherPayload::parse(u16::from_be_bytes(frame.ether_type), &data[header_length..])?;
+    Ok(frame)
+}
+
+/// Checks that `data` holds at least `needed` bytes of a `layer` header.
+///
+/// # Errors
+/// Returns `ParseError::Truncated` if `data` is shorter than `needed`.
+pub(crate) fn require(data: &[u8], needed: usize, layer: Layer) -> Result<(), ParseError> {
+    if data.len() < needed {
+        return Err(ParseError::Truncated { layer, needed, available: data.len() });
+    }
+    Ok(())
+}
+
+/// Copies `N` bytes of `data` starting at `start` into an array.
+///
+/// The caller must have checked the length with `require` first.
+pub(crate) fn array<const N: usize>(data: &[u8], start: usize) -> [u8; N] {
+    data[start..start + N].try_into().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 28-byte IPv4 packet carrying an empty UDP datagram.
+    const PACKET: [u8; 28] = [0x45, 0x00, 0x00, 0x1c, 0x00, 0x01, 0x00, 0x00, 0x40, 0x11, 0x00, 0x00, 10, 0, 0, 1, 10, 0,
+                              0, 2, 0x04, 0x00, 0x00, 0x35, 0x00, 0x08, 0x00, 0x00];
+
+    fn with_header(header: &[u8]) -> Vec<u8> {
+        [header, &PACKET].concat()
+    }
+
+    /// Builds an Ethernet frame holding an IPv4 packet with the given protocol and datagram.
+    fn ipv4_frame(protocol: u8, datagram: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 1, 0x08, 0x00];
+        frame.extend_from_slice(&[0x45, 0x00]);
+        frame.extend_from_slice(&(20 + datagram.len() as u16).to_be_bytes());
+        frame.extend_from_slice(&[0x00, 0x01, 0x00, 0x00, 0x40, protocol, 0x00, 0x00, 10, 0, 0, 1, 10, 0, 0, 2]);
+        frame.extend_from_slice(datagram);
+        frame
+    }
+
+    /// Frames with each kind of datagram, four bytes of payload each, and the length of their headers.
+    fn frames() -> [(Vec<u8>, usize); 3] {
+        let tcp = [0x9c, 0x40, 0x00, 0x50, 0, 0, 0, 1, 0, 0, 0, 0, 0x50, 0x10, 0xff, 0xff, 0, 0, 0, 0, 1, 2, 3, 4];
+        let udp = [0x04, 0x00, 0x00, 0x35, 0x00, 0x0c, 0x00, 0x00, 1, 2, 3, 4];
+        let icmp = [0x08, 0x00, 0x00, 0x00, 0x12, 0x34, 0x00, 0x01, 1, 2, 3, 4];
+        [(ipv4_frame(6, &tcp), 54), (ipv4_frame(17, &udp), 42), (ipv4_frame(1, &icmp), 42)]
+    }
+
+    #[test]
+    fn headers_cut_short_report_their_layer() {
+        let [(tcp, _), (udp, _), (icmp, _)] = frames();
+        let cases = [
+            (&tcp[..13], Layer::Ethernet, 14, 13),
+            (&tcp[..33], Layer::IPv4, 20, 19),
+            (&tcp[..50], Layer::TCP, 20, 16),
+            (&udp[..41], Layer::UDP, 8, 7),
+            (&icmp[..36], Layer::ICMP, 8, 2),
+        ];
+        for (data, layer, needed, available) in cases {
+            match parse_frame(data) {
+                Err(ParseError::Truncated { layer: l, needed: n, available: a }) => {
+                    assert_eq!((l, n, a), (layer, needed, available), "{} bytes", data.len());
+                }
+                other => panic!("{} bytes: {:?}", data.len(), other.map(|_| ())),
+            }
+        }
+
+        // An IPv4 header length claiming options that were not captured.
+        let mut options = udp.clone();
+        options[14] = 0x46;
+        assert!(matches!(parse_frame(&options[..36]),
+                         Err(ParseError::Truncated { layer: Layer::IPv4, needed: 24, available: 22 })));
+    }
+
+    #[test]
+    fn header_lengths_below_the_minimum_are_rejected() {
+        let [(mut tcp, _), ..] = frames();
+        tcp[46] = 0x40;
+        assert!(matches!(parse_frame(&tcp), Err(ParseError::BadHeaderLength { layer: Layer::TCP, length: 16 })));
+        tcp[14] = 0x44;
+        assert!(matches!(parse_frame(&tcp), Err(ParseError::BadHeaderLength { layer: Layer::IPv4, length: 16 })));
+    }
+
+    #[test]
+    fn every_prefix_of_a_frame_is_decoded_without_panicking() {
+        for (frame, header_length) in frames() {
+            for length in 0..=frame.len() {
+                match parse_frame(&frame[..length]) {
+                    Ok(_) => assert!(length >= header_length, "{} of {} bytes", length, frame.len()),
+                    Err(ParseError::Truncated { .. }) => {
+                        assert!(length < header_length, "{} of {} bytes", length, frame.len());
+                    }
+                    Err(error) => panic!("{} of {} bytes: {}", length, frame.len(), error),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn null_families_are_read_in_the_byte_order_of_the_capturing_host() {
+        for header in [[2, 0, 0, 0], [0, 0, 0, 2]] {
+            let frame = parse_packet(LinkType::Null, &with_header(&header)).unwrap();
+            assert_eq!(frame.link_type, LinkType::Null);
+            assert_eq!(frame.ether_type, [0x08, 0x00], "{:?}", header);
+            assert_eq!(frame.packet_size, 32);
+        }
+    }
+
+    #[test]
+    fn loop_families_are_always_big_endian() {
+        let frame = parse_packet(LinkType::Loop, &with_header(&[0, 0, 0, 2])).unwrap();
+        assert_eq!(frame.ether_type, [0x08, 0x00]);
+        assert!(matches!(parse_packet(LinkType::Loop, &[0, 0, 2]),
+                         Err(ParseError::Truncated { layer: Layer::Loopback, needed: 4, available: 3 })));
+    }
+
+    #[test]
+    fn raw_packets_have_no_link_layer_header() {
+        for link_type in [LinkType::Raw, LinkType::IPv4] {
+            let frame = parse_packet(link_type, &PACKET).unwrap();
+            assert_eq!(frame.ether_type, [0x08, 0x00]);
+            assert_eq!(frame.source_address, [0; 6]);
+            assert!(frame.cooked.is_none());
+        }
+        assert!(matches!(parse_packet(LinkType::Raw, &[]), Err(ParseError::Truncated { needed: 1, available: 0, .. })));
+    }
+
+    #[test]
+    fn cooked_headers_are_kept_with_the_frame() {
+        let sll = with_header(&[0, 0, 0, 1, 0, 6, 0x02, 0, 0, 0, 0, 1, 0, 0, 0x08, 0x00]);
+        let frame = parse_packet(LinkType::LinuxSll, &sll).unwrap();
+        assert_eq!(frame.ether_type, [0x08, 0x00]);
+        assert_eq!(frame.source_address, [0x02, 0, 0, 0, 0, 1]);
+        assert_eq!(frame.cooked.map(|cooked| cooked.interface_index), Some(None));
+
+        // Only a 6-byte sender address fills in the Ethernet source address.
+        let sll2 = with_header(&[0x08, 0x00, 0, 0, 0, 0, 0, 2, 0x03, 0x0a, 0, 4, 10, 0, 0, 1, 0, 0, 0, 0]);
+        let frame = parse_packet(LinkType::LinuxSll2, &sll2).unwrap();
+        assert_eq!(frame.ether_type, [0x08, 0x00]);
+        assert_eq!(frame.source_address, [0; 6]);
+        assert_eq!(frame.cooked.map(|cooked| cooked.interface_index), Some(Some(2)));
+    }
+
+    #[test]
+    fn vlan_tags_are_peeled_off_before_the_payload() {
+        // A QinQ service tag with priority 5 and VID 100, then a customer tag with DEI set and VID 20.
+        let frame = [[0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 1, 0x88, 0xa8, 0xa0, 0x64, 0x81, 0x00].as_slice(),
+                     &[0x10, 0x14, 0x08, 0x00], &PACKET].concat();
+        let frame = parse_frame(&frame).unwrap();
+        assert_eq!(frame.vlan_tags, [
+            VlanTag { tpid: 0x88a8, priority: 5, drop_eligible: false, vlan_id: 100 },
+            VlanTag { tpid: 0x8100, priority: 0, drop_eligible: true, vlan_id: 20 },
+        ]);
+        assert_eq!(frame.ether_type, [0x08, 0x00]);
+        assert!(frame.ipv4().is_some());
+
+        // The legacy QinQ TPID is recognised, and tags follow a cooked header too.
+        let sll = [[0, 0, 0, 1, 0, 6, 0x02, 0, 0, 0, 0, 1, 0, 0, 0x91, 0x00].as_slice(), &[0x00, 0x07, 0x08, 0x00],
+                   &PACKET].concat();
+        let frame = parse_packet(LinkType::LinuxSll, &sll).unwrap();
+        assert_eq!(frame.vlan_tags.iter().map(|tag| (tag.tpid, tag.vlan_id)).collect::<Vec<_>>(), [(0x9100, 7)]);
+        assert!(frame.ipv4().is_some());
+
+        let cut = [0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 1, 0x81, 0x00, 0x00];
+        assert!(matches!(parse_frame(&cut), Err(ParseError::Truncated { layer: Layer::Vlan, needed: 4, available: 1 })));
+    }
+
+    #[test]
+    fn ipv6_is_found_by_version_and_address_family() {
+        let mut packet = vec![0x60, 0, 0, 0, 0, 8, 17, 64];
+        packet.extend_from_slice(&[0; 32]);
+        packet.extend_from_slice(&PACKET[20..]);
+        for link_type in [LinkType::Raw, LinkType::IPv6] {
+            let frame = parse_packet(link_type, &packet).unwrap();
+            assert_eq!(frame.ether_type, [0x86, 0xdd]);
+            assert!(frame.ipv6().is_some());
+        }
+        // macOS numbers IPv6 30, written here by a little-endian host.
+        let frame = parse_packet(LinkType::Null, &[[30, 0, 0, 0].as_slice(), &packet].concat()).unwrap();
+        assert!(frame.ipv6().is_some());
+    }
+}
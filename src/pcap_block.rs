@@ -1,21 +1,79 @@
-use crate::EthernetFrame;
+use std::fmt;
+use std::time::Duration;
+use crate::{parse_packet, EthernetFrame, LinkType, ParseError, PcapNgOption};
 
-pub(crate) struct PcapBlock {
-    pub(crate) timestamp_seconds: [u8; 4],
-    pub(crate) timestamp_microseconds: [u8; 4],
-    pub(crate) captured_length: [u8; 4],
-    pub(crate) original_length: [u8; 4],
-    pub(crate) ether_frame: EthernetFrame,
+/// A single captured packet: the record header fields and the frame decoded from its bytes.
+pub struct PcapBlock {
+    pub timestamp_seconds: u32,
+    /// Sub-second part of the timestamp, converted to nanoseconds whatever the capture resolution.
+    pub timestamp_nanoseconds: u32,
+    pub captured_length: u32,
+    pub original_length: u32,
+    /// Link-layer type of the capture or interface, which selects how `data` is decoded.
+    pub link_type: LinkType,
+    /// Interface the packet was captured on, always 0 for pcap captures.
+    pub interface_id: u32,
+    /// Comments attached to the packet in a pcapng capture.
+    pub comments: Vec<String>,
+    /// Options of the pcapng packet block, including the comments.
+    pub options: Vec<PcapNgOption>,
+    pub data: Vec<u8>,
+    pub ether_frame: EthernetFrame,
+}
+
+impl Default for PcapBlock {
+    fn default() -> Self {
+        PcapBlock::new()
+    }
 }
 
 impl PcapBlock {
-    pub(crate) fn new() -> PcapBlock {
+    pub fn new() -> PcapBlock {
         PcapBlock {
-            timestamp_seconds: [0, 0, 0, 0],
-            timestamp_microseconds: [0, 0, 0, 0],
-            captured_length: [0, 0, 0, 0],
-            original_length: [0, 0, 0, 0],
+            timestamp_seconds: 0,
+            timestamp_nanoseconds: 0,
+            captured_length: 0,
+            original_length: 0,
+            link_type: LinkType::Ethernet,
+            interface_id: 0,
+            comments: vec![],
+            options: vec![],
+            data: vec![],
             ether_frame: EthernetFrame::new(),
         }
     }
-}
\ No newline at end of file
+
+    /// Decodes the captured bytes in `data` into `ether_frame`, according to `link_type`.
+    ///
+    /// # Errors
+    /// Returns the error of `parse_packet` if the packet is malformed.
+    pub fn decode(&mut self) -> Result<(), ParseError> {
+        self.ether_frame = parse_packet(self.link_type, &self.data)?;
+        Ok(())
+    }
+
+    /// Returns the capture time of the packet as a duration since the Unix epoch.
+    pub fn timestamp(&self) -> Duration {
+        Duration::new(self.timestamp_seconds as u64, self.timestamp_nanoseconds)
+    }
+}
+
+impl fmt::Display for PcapBlock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "PCAP: -----Record Header-----")?;
+        writeln!(f, "PCAP:")?;
+        writeln!(f, "PCAP: Timestamp       = {}.{:09} seconds", self.timestamp_seconds, self.timestamp_nanoseconds)?;
+        writeln!(f, "PCAP: Captured length = {} bytes", self.captured_length)?;
+        writeln!(f, "PCAP: Original length = {} bytes", self.original_length)?;
+        if self.interface_id != 0 {
+            writeln!(f, "PCAP: Interface       = {}", self.interface_id)?;
+        }
+        for comment in &self.comments {
+            writeln!(f, "PCAP: Comment         = {}", comment)?;
+        }
+        for option in self.options.iter().filter(|o| o.code != 1) {
+            writeln!(f, "PCAP: Option {:<9} = {} bytes", option.code, option.value.len())?;
+        }
+        write!(f, "PCAP:")
+    }
+}
@@ -1,21 +1,46 @@
-use crate::{PcapBlock, PcapFileHeader};
+use std::io::Read;
 
-pub(crate) struct PcapFile {
-    header: PcapFileHeader,
-    data: Vec<PcapBlock>,
+use crate::{ParseError, PcapBlock, PcapFileHeader, PcapReader};
+
+/// A complete pcap capture held in memory.
+pub struct PcapFile {
+    pub header: PcapFileHeader,
+    pub data: Vec<PcapBlock>,
+}
+
+impl Default for PcapFile {
+    fn default() -> Self {
+        PcapFile::new()
+    }
 }
 
 impl PcapFile {
-    fn new() -> PcapFile {
+    pub fn new() -> PcapFile {
         PcapFile {
             header: PcapFileHeader::new(),
             data: Vec::new(),
         }
     }
-    fn new_with_header(header: PcapFileHeader) -> PcapFile {
+    pub fn new_with_header(header: PcapFileHeader) -> PcapFile {
         PcapFile {
             header,
             data: Vec::new(),
         }
     }
-}
\ No newline at end of file
+
+    /// Reads a whole capture into memory.
+    ///
+    /// # Arguments
+    /// * `reader` - The source of the capture bytes.
+    ///
+    /// # Errors
+    /// Returns the first error produced while reading or decoding the capture.
+    pub fn read<R: Read>(reader: R) -> Result<PcapFile, ParseError> {
+        let mut pcap_reader = PcapReader::new(reader)?;
+        let mut pcap_file = PcapFile::new_with_header(pcap_reader.header().clone());
+        for block in &mut pcap_reader {
+            pcap_file.data.push(block?);
+        }
+        Ok(pcap_file)
+    }
+}
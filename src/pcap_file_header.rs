@@ -1,23 +1,133 @@
-pub(crate) struct PcapFileHeader {
-    pub(crate) magic_number: [u8; 4],
-    pub(crate) version_major: [u8; 2],
-    pub(crate) version_minor: [u8; 2],
-    pub(crate) time_zone: [u8; 4],
-    pub(crate) timestamp_accuracy: [u8; 4],
-    pub(crate) snap_length: [u8; 4],
-    pub(crate) link_layer_type: [u8; 4],
+use crate::parser::array;
+use crate::{ByteOrder, ParseError};
+
+/// Resolution of the record timestamps of a pcap capture.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TimestampResolution {
+    Microsecond,
+    Nanosecond,
+}
+
+/// The 24-byte global header of a pcap capture, with every field decoded in the byte order given
+/// by the magic number.
+#[derive(Clone)]
+pub struct PcapFileHeader {
+    pub magic_number: [u8; 4],
+    pub byte_order: ByteOrder,
+    pub resolution: TimestampResolution,
+    pub version_major: u16,
+    pub version_minor: u16,
+    pub time_zone: i32,
+    pub timestamp_accuracy: u32,
+    pub snap_length: u32,
+    pub link_layer_type: u32,
+}
+
+impl Default for PcapFileHeader {
+    fn default() -> Self {
+        PcapFileHeader::new()
+    }
 }
 
 impl PcapFileHeader {
-    pub(crate) fn new() -> PcapFileHeader {
+    pub fn new() -> PcapFileHeader {
         PcapFileHeader {
             magic_number: [0; 4],
-            version_major: [0; 2],
-            version_minor: [0; 2],
-            time_zone: [0; 4],
-            timestamp_accuracy: [0; 4],
-            snap_length: [0; 4],
-            link_layer_type: [0; 4],
+            byte_order: ByteOrder::LittleEndian,
+            resolution: TimestampResolution::Microsecond,
+            version_major: 0,
+            version_minor: 0,
+            time_zone: 0,
+            timestamp_accuracy: 0,
+            snap_length: 0,
+            link_layer_type: 0,
+        }
+    }
+
+    /// Parses the global header.
+    ///
+    /// The magic number selects both the byte order of the file and whether its timestamps count
+    /// microseconds (0xa1b2c3d4) or nanoseconds (0xa1b23c4d).
+    ///
+    /// # Errors
+    /// Returns `ParseError::BadMagic` if the header does not start with a pcap magic number.
+    pub fn parse(data: &[u8; 24]) -> Result<PcapFileHeader, ParseError> {
+        let magic_number: [u8; 4] = array(data, 0);
+        let (byte_order, resolution) = match magic_number {
+            [0xa1, 0xb2, 0xc3, 0xd4] => (ByteOrder::BigEndian, TimestampResolution::Microsecond),
+            [0xd4, 0xc3, 0xb2, 0xa1] => (ByteOrder::LittleEndian, TimestampResolution::Microsecond),
+            [0xa1, 0xb2, 0x3c, 0x4d] => (ByteOrder::BigEndian, TimestampResolution::Nanosecond),
+            [0x4d, 0x3c, 0xb2, 0xa1] => (ByteOrder::LittleEndian, TimestampResolution::Nanosecond),
+            _ => return Err(ParseError::BadMagic(magic_number)),
+        };
+
+        Ok(PcapFileHeader {
+            magic_number,
+            byte_order,
+            resolution,
+            version_major: byte_order.u16(array(data, 4)),
+            version_minor: byte_order.u16(array(data, 6)),
+            time_zone: byte_order.u32(array(data, 8)) as i32,
+            timestamp_accuracy: byte_order.u32(array(data, 12)),
+            snap_length: byte_order.u32(array(data, 16)),
+            link_layer_type: byte_order.u32(array(data, 20)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a global header for version 2.4 with a snap length of 65535 and Ethernet frames,
+    /// laid out in the byte order the magic number stands for.
+    fn header(magic_number: [u8; 4], byte_order: ByteOrder) -> [u8; 24] {
+        let mut data = [0_u8; 24];
+        data[0..4].copy_from_slice(&magic_number);
+        let fields: [(usize, u32); 4] = [(8, (-3600_i32) as u32), (12, 0), (16, 65535), (20, 1)];
+        match byte_order {
+            ByteOrder::LittleEndian => {
+                data[4..6].copy_from_slice(&2_u16.to_le_bytes());
+                data[6..8].copy_from_slice(&4_u16.to_le_bytes());
+                for (offset, value) in fields {
+                    data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+                }
+            }
+            ByteOrder::BigEndian => {
+                data[4..6].copy_from_slice(&2_u16.to_be_bytes());
+                data[6..8].copy_from_slice(&4_u16.to_be_bytes());
+                for (offset, value) in fields {
+                    data[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+                }
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn magic_numbers_select_byte_order_and_resolution() {
+        let variants = [
+            ([0xa1, 0xb2, 0xc3, 0xd4], ByteOrder::BigEndian, TimestampResolution::Microsecond),
+            ([0xd4, 0xc3, 0xb2, 0xa1], ByteOrder::LittleEndian, TimestampResolution::Microsecond),
+            ([0xa1, 0xb2, 0x3c, 0x4d], ByteOrder::BigEndian, TimestampResolution::Nanosecond),
+            ([0x4d, 0x3c, 0xb2, 0xa1], ByteOrder::LittleEndian, TimestampResolution::Nanosecond),
+        ];
+        for (magic_number, byte_order, resolution) in variants {
+            let parsed = PcapFileHeader::parse(&header(magic_number, byte_order)).unwrap();
+            assert_eq!(parsed.magic_number, magic_number);
+            assert_eq!(parsed.byte_order, byte_order);
+            assert_eq!(parsed.resolution, resolution);
+            assert_eq!((parsed.version_major, parsed.version_minor), (2, 4));
+            assert_eq!(parsed.time_zone, -3600);
+            assert_eq!(parsed.snap_length, 65535);
+            assert_eq!(parsed.link_layer_type, 1);
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn other_magic_numbers_are_rejected() {
+        // A pcapng section header block starts with its block type instead.
+        let data = header([0x0a, 0x0d, 0x0d, 0x0a], ByteOrder::LittleEndian);
+        assert!(matches!(PcapFileHeader::parse(&data), Err(ParseError::BadMagic([0x0a, 0x0d, 0x0d, 0x0a]))));
+    }
+}
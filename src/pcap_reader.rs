@@ -0,0 +1,256 @@
+use std::io::{self, Read};
+
+use crate::parser::array;
+use crate::{Layer, LinkType, ParseError, PcapBlock, PcapFileHeader, TimestampResolution};
+
+/// Largest captured length accepted for a single record.
+const MAX_CAPTURED_LENGTH: usize = 0x0400_0000;
+
+/// Streaming reader over a pcap capture.
+///
+/// The 24-byte global header is parsed once on construction, then each call to `next` reads one
+/// record and decodes its frame. Records are read sequentially, so any `Read` source works,
+/// including pipes and stdin, without knowing the capture size up front.
+///
+/// A prefilter, such as a `BpfProgram`, can be set to skip records before their frame is decoded.
+pub struct PcapReader<R: Read> {
+    reader: R,
+    header: PcapFileHeader,
+    link_type: LinkType,
+    prefilter: Option<Prefilter>,
+    /// Number of records the prefilter skipped.
+    filtered: usize,
+    done: bool,
+}
+
+impl<R: Read> PcapReader<R> {
+    /// Creates a reader and parses the global pcap header.
+    ///
+    /// # Arguments
+    /// * `reader` - The source of the capture bytes.
+    ///
+    /// # Errors
+    /// Returns `ParseError::Io` if the header cannot be read, `ParseError::BadMagic` if the input is
+    /// not a pcap capture, or `ParseError::UnsupportedLinkType` if its link layer cannot be decoded.
+    pub fn new(mut reader: R) -> Result<PcapReader<R>, ParseError> {
+        let mut magic_number = [0_u8; 4];
+        reader.read_exact(&mut magic_number)?;
+        PcapReader::with_magic(magic_number, reader)
+    }
+
+    /// Creates a reader from a source whose 4-byte magic number has already been consumed.
+    ///
+    /// # Errors
+    /// Same as `PcapReader::new`.
+    pub fn with_magic(magic_number: [u8; 4], mut reader: R) -> Result<PcapReader<R>, ParseError> {
+        let mut header_bytes = [0_u8; 24];
+        header_bytes[0..4].copy_from_slice(&magic_number);
+        // Check the magic number before reading on, so that short non-pcap input reports BadMagic.
+        PcapFileHeader::parse(&header_bytes)?;
+        reader.read_exact(&mut header_bytes[4..])?;
+        let header = PcapFileHeader::parse(&header_bytes)?;
+        let link_type = LinkType::from_number(header.link_layer_type)?;
+        Ok(PcapReader { reader, header, link_type, prefilter: None, filtered: 0, done: false })
+    }
+
+    /// Returns the global header of the capture.
+    pub fn header(&self) -> &PcapFileHeader {
+        &self.header
+    }
+
+    /// Returns the link-layer type of the capture.
+    pub fn link_type(&self) -> LinkType {
+        self.link_type
+    }
+
+    /// Sets a test run on each record before its frame is decoded; records it rejects are skipped.
+    ///
+    /// # Arguments
+    /// * `prefilter` - Returns whether to keep a record, given with its captured bytes and lengths
+    ///   but no decoded frame.
+    pub fn set_prefilter(&mut self, prefilter: impl FnMut(&PcapBlock) -> bool + 'static) {
+        self.prefilter = Some(Box::new(prefilter));
+    }
+
+    /// Returns the number of records the prefilter skipped so far.
+    pub fn filtered(&self) -> usize {
+        self.filtered
+    }
+
+    /// Reads the next record without decoding its frame.
+    ///
+    /// Returns `Ok(None)` when the capture ends cleanly on a record boundary.
+    fn read_block(&mut self) -> Result<Option<PcapBlock>, ParseError> {
+        let mut record_header = [0_u8; 16];
+        if !read_or_eof(&mut self.reader, &mut record_header)? {
+            return Ok(None);
+        }
+
+        let mut pcap_block: PcapBlock = PcapBlock::new(); //Initializing a new PCAP Block
+        pcap_block.link_type = self.link_type;
+        let byte_order = self.header.byte_order;
+        pcap_block.timestamp_seconds = byte_order.u32(array(&record_header, 0));
+        pcap_block.timestamp_nanoseconds = byte_order.u32(array(&record_header, 4));
+        if self.header.resolution == TimestampResolution::Microsecond {
+            pcap_block.timestamp_nanoseconds = pcap_block.timestamp_nanoseconds.saturating_mul(1000);
+        }
+        pcap_block.captured_length = byte_order.u32(array(&record_header, 8));
+        pcap_block.original_length = byte_order.u32(array(&record_header, 12));
+
+        let captured_length = pcap_block.captured_length as usize;
+        if captured_length > MAX_CAPTURED_LENGTH {
+            return Err(ParseError::BadHeaderLength { layer: Layer::PcapRecord, length: captured_length });
+        }
+        pcap_block.data = vec![0_u8; captured_length];
+        self.reader.read_exact(&mut pcap_block.data)?;
+        Ok(Some(pcap_block))
+    }
+}
+
+impl<R: Read> Iterator for PcapReader<R> {
+    type Item = Result<PcapBlock, ParseError>;
+
+    /// Reads and decodes the next record that passes the prefilter.
+    ///
+    /// A frame that fails to decode is returned as an error and reading continues with the next
+    /// record. A read failure or an invalid record header ends the iteration.
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            match self.read_block() {
+                Ok(Some(block)) if self.prefilter.as_mut().is_some_and(|prefilter| !prefilter(&block)) => {
+                    self.filtered += 1;
+                }
+                Ok(Some(mut block)) => return Some(block.decode().map(|_| block)),
+                Ok(None) => self.done = true,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// A test run on a packet before its frame is decoded.
+pub(crate) type Prefilter = Box<dyn FnMut(&PcapBlock) -> bool>;
+
+/// Fills `buf` from `reader`, or returns `Ok(false)` if the input ends before the first byte.
+///
+/// # Errors
+/// Returns `ParseError::Io` if reading fails or the input ends part way through `buf`.
+pub(crate) fn read_or_eof<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<bool, ParseError> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ByteOrder;
+
+    /// A 28-byte IPv4 packet carrying an empty UDP datagram, as stored with the Raw link type.
+    const PACKET: [u8; 28] = [0x45, 0x00, 0x00, 0x1c, 0x00, 0x01, 0x00, 0x00, 0x40, 0x11, 0x00, 0x00, 10, 0, 0, 1, 10, 0,
+                              0, 2, 0x04, 0x00, 0x00, 0x35, 0x00, 0x08, 0x00, 0x00];
+
+    fn put(capture: &mut Vec<u8>, byte_order: ByteOrder, value: u32) {
+        match byte_order {
+            ByteOrder::LittleEndian => capture.extend_from_slice(&value.to_le_bytes()),
+            ByteOrder::BigEndian => capture.extend_from_slice(&value.to_be_bytes()),
+        }
+    }
+
+    /// Builds a Raw IP capture with the given magic number, holding one record per timestamp, each
+    /// with `PACKET` as the captured part of a 60-byte packet.
+    fn capture(magic_number: [u8; 4], byte_order: ByteOrder, timestamps: &[(u32, u32)]) -> Vec<u8> {
+        let mut capture = magic_number.to_vec();
+        put(&mut capture, byte_order, match byte_order {
+            ByteOrder::LittleEndian => 0x0004_0002,
+            ByteOrder::BigEndian => 0x0002_0004,
+        });
+        for value in [0, 0, 65535, 101] {
+            put(&mut capture, byte_order, value);
+        }
+        for &(seconds, fraction) in timestamps {
+            for value in [seconds, fraction, PACKET.len() as u32, 60] {
+                put(&mut capture, byte_order, value);
+            }
+            capture.extend_from_slice(&PACKET);
+        }
+        capture
+    }
+
+    #[test]
+    fn microsecond_timestamps_are_converted_to_nanoseconds() {
+        let capture = capture([0xd4, 0xc3, 0xb2, 0xa1], ByteOrder::LittleEndian, &[(1_700_000_000, 250_000)]);
+        let mut reader = PcapReader::new(&capture[..]).unwrap();
+        assert_eq!(reader.link_type(), LinkType::Raw);
+        assert_eq!(reader.header().version_major, 2);
+        let block = reader.next().unwrap().unwrap();
+        assert_eq!(block.timestamp_seconds, 1_700_000_000);
+        assert_eq!(block.timestamp_nanoseconds, 250_000_000);
+        assert_eq!((block.captured_length, block.original_length), (28, 60));
+        assert!(block.ether_frame.ipv4().is_some());
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn nanosecond_timestamps_are_kept() {
+        let capture = capture([0x4d, 0x3c, 0xb2, 0xa1], ByteOrder::LittleEndian, &[(1, 999_999_999), (2, 5)]);
+        let blocks: Vec<PcapBlock> = PcapReader::new(&capture[..]).unwrap().map(Result::unwrap).collect();
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].timestamp().as_nanos(), 1_999_999_999);
+        assert_eq!(blocks[1].timestamp().as_nanos(), 2_000_000_005);
+    }
+
+    #[test]
+    fn big_endian_records_are_decoded() {
+        for magic_number in [[0xa1, 0xb2, 0xc3, 0xd4], [0xa1, 0xb2, 0x3c, 0x4d]] {
+            let capture = capture(magic_number, ByteOrder::BigEndian, &[(0x0102_0304, 7)]);
+            let mut reader = PcapReader::new(&capture[..]).unwrap();
+            assert_eq!(reader.header().byte_order, ByteOrder::BigEndian);
+            assert_eq!(reader.header().snap_length, 65535);
+            let block = reader.next().unwrap().unwrap();
+            assert_eq!(block.timestamp_seconds, 0x0102_0304);
+            assert_eq!((block.captured_length, block.original_length), (28, 60));
+            assert_eq!(block.data, PACKET);
+        }
+    }
+
+    #[test]
+    fn oversized_records_end_the_capture() {
+        let mut capture = capture([0xd4, 0xc3, 0xb2, 0xa1], ByteOrder::LittleEndian, &[(1, 0)]);
+        for value in [2, 0, MAX_CAPTURED_LENGTH as u32 + 1, MAX_CAPTURED_LENGTH as u32 + 1] {
+            put(&mut capture, ByteOrder::LittleEndian, value);
+        }
+        let mut reader = PcapReader::new(&capture[..]).unwrap();
+        assert!(reader.next().unwrap().is_ok());
+        assert!(matches!(reader.next(), Some(Err(ParseError::BadHeaderLength { layer: Layer::PcapRecord, .. }))));
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn records_cut_short_are_read_errors() {
+        let capture = capture([0xd4, 0xc3, 0xb2, 0xa1], ByteOrder::LittleEndian, &[(1, 0)]);
+        let mut reader = PcapReader::new(&capture[..capture.len() - 4]).unwrap();
+        assert!(matches!(reader.next(), Some(Err(ParseError::Io(_)))));
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn other_input_is_bad_magic() {
+        assert!(matches!(PcapReader::new(&b"GET / HTTP/1.1\r\n"[..]).err(), Some(ParseError::BadMagic(_))));
+        // Input too short for a whole header still reports the magic number rather than a read error.
+        assert!(matches!(PcapReader::new(&b"text"[..]).err(), Some(ParseError::BadMagic(_))));
+        assert!(matches!(PcapReader::new(&[0xd4, 0xc3][..]).err(), Some(ParseError::Io(_))));
+    }
+}
@@ -0,0 +1,278 @@
+use std::fmt;
+
+use crate::parser::{array, require};
+use crate::{ByteOrder, Layer, ParseError};
+
+/// Option code marking the end of an option list.
+const OPT_ENDOFOPT: u16 = 0;
+/// Option code of a UTF-8 comment, valid in every block.
+const OPT_COMMENT: u16 = 1;
+
+/// A pcapng option, as found at the end of section, interface and packet blocks.
+#[derive(Clone, Debug)]
+pub struct PcapNgOption {
+    pub code: u16,
+    pub value: Vec<u8>,
+}
+
+impl PcapNgOption {
+    /// Parses an option list up to the end-of-options marker or the end of `data`.
+    ///
+    /// An option whose length runs past the end of the block ends the list.
+    pub fn parse_all(data: &[u8], byte_order: ByteOrder) -> Vec<PcapNgOption> {
+        let mut options = vec![];
+        let mut current = 0;
+        while current + 4 <= data.len() {
+            let code = byte_order.u16(array(data, current));
+            let length = byte_order.u16(array(data, current + 2)) as usize;
+            if code == OPT_ENDOFOPT || current + 4 + length > data.len() {
+                break;
+            }
+            options.push(PcapNgOption { code, value: data[current + 4..current + 4 + length].to_vec() });
+            // Option values are padded to 32 bits.
+            current += 4 + ((length + 3) & !3);
+        }
+        options
+    }
+
+    /// Returns the value of the option as text.
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.value).into_owned()
+    }
+}
+
+/// Returns the comments found in an option list.
+pub(crate) fn comments(options: &[PcapNgOption]) -> Vec<String> {
+    options.iter().filter(|o| o.code == OPT_COMMENT).map(|o| o.text()).collect()
+}
+
+/// Returns the first option with the given code as text.
+fn text_option(options: &[PcapNgOption], code: u16) -> Option<String> {
+    options.iter().find(|o| o.code == code).map(|o| o.text())
+}
+
+/// The Section Header Block that starts every pcapng section.
+#[derive(Clone, Debug)]
+pub struct SectionHeader {
+    pub byte_order: ByteOrder,
+    pub version_major: u16,
+    pub version_minor: u16,
+    /// Length of the section in bytes, or -1 if it was not recorded.
+    pub section_length: i64,
+    pub comments: Vec<String>,
+    pub hardware: Option<String>,
+    pub os: Option<String>,
+    pub user_application: Option<String>,
+    pub options: Vec<PcapNgOption>,
+}
+
+impl SectionHeader {
+    /// Parses the body of a Section Header Block, starting at the byte-order magic.
+    ///
+    /// # Errors
+    /// Returns `ParseError::Truncated` if the body is shorter than the fixed fields.
+    pub fn parse(body: &[u8], byte_order: ByteOrder) -> Result<SectionHeader, ParseError> {
+        require(body, 16, Layer::PcapNgBlock)?;
+        let section_length = match byte_order {
+            ByteOrder::LittleEndian => i64::from_le_bytes(array(body, 8)),
+            ByteOrder::BigEndian => i64::from_be_bytes(array(body, 8)),
+        };
+        let options = PcapNgOption::parse_all(&body[16..], byte_order);
+        Ok(SectionHeader {
+            byte_order,
+            version_major: byte_order.u16(array(body, 4)),
+            version_minor: byte_order.u16(array(body, 6)),
+            section_length,
+            comments: comments(&options),
+            hardware: text_option(&options, 2),
+            os: text_option(&options, 3),
+            user_application: text_option(&options, 4),
+            options,
+        })
+    }
+}
+
+impl fmt::Display for SectionHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "PCAPNG: -----Section Header-----")?;
+        writeln!(f, "PCAPNG:")?;
+        writeln!(f, "PCAPNG: Version     = {}.{}", self.version_major, self.version_minor)?;
+        if let Some(hardware) = &self.hardware {
+            writeln!(f, "PCAPNG: Hardware    = {}", hardware)?;
+        }
+        if let Some(os) = &self.os {
+            writeln!(f, "PCAPNG: OS          = {}", os)?;
+        }
+        if let Some(application) = &self.user_application {
+            writeln!(f, "PCAPNG: Application = {}", application)?;
+        }
+        for comment in &self.comments {
+            writeln!(f, "PCAPNG: Comment     = {}", comment)?;
+        }
+        write!(f, "PCAPNG:")
+    }
+}
+
+/// An Interface Description Block, describing the interface packets of a section were captured on.
+#[derive(Clone, Debug)]
+pub struct InterfaceDescription {
+    pub link_type: u32,
+    pub snap_length: u32,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    /// Number of timestamp units per second, from `if_tsresol` (microseconds by default).
+    pub timestamp_resolution: u64,
+    /// Seconds to add to every timestamp, from `if_tsoffset`.
+    pub timestamp_offset: i64,
+    pub options: Vec<PcapNgOption>,
+}
+
+impl InterfaceDescription {
+    /// Parses the body of an Interface Description Block.
+    ///
+    /// # Errors
+    /// Returns `ParseError::Truncated` if the body is shorter than the fixed fields.
+    pub fn parse(body: &[u8], byte_order: ByteOrder) -> Result<InterfaceDescription, ParseError> {
+        require(body, 8, Layer::PcapNgBlock)?;
+        let options = PcapNgOption::parse_all(&body[8..], byte_order);
+
+        let mut timestamp_resolution = 1_000_000;
+        if let Some(tsresol) = options.iter().find(|o| o.code == 9 && o.value.len() == 1) {
+            // The high bit selects a power of two, otherwise the value is a power of ten.
+            let exponent = (tsresol.value[0] & 0x7f) as u32;
+            let resolution = if tsresol.value[0] & 0x80 == 0 {
+                10_u64.checked_pow(exponent)
+            } else {
+                2_u64.checked_pow(exponent)
+            };
+            timestamp_resolution = resolution.unwrap_or(timestamp_resolution);
+        }
+        let mut timestamp_offset = 0;
+        if let Some(tsoffset) = options.iter().find(|o| o.code == 14 && o.value.len() == 8) {
+            timestamp_offset = match byte_order {
+                ByteOrder::LittleEndian => i64::from_le_bytes(array(&tsoffset.value, 0)),
+                ByteOrder::BigEndian => i64::from_be_bytes(array(&tsoffset.value, 0)),
+            };
+        }
+
+        Ok(InterfaceDescription {
+            link_type: byte_order.u16(array(body, 0)) as u32,
+            snap_length: byte_order.u32(array(body, 4)),
+            name: text_option(&options, 2),
+            description: text_option(&options, 3),
+            timestamp_resolution,
+            timestamp_offset,
+            options,
+        })
+    }
+
+    /// Converts a timestamp in interface units to seconds and nanoseconds since the Unix epoch.
+    pub fn timestamp(&self, units: u64) -> (u32, u32) {
+        let seconds = (units / self.timestamp_resolution) as i64 + self.timestamp_offset;
+        let fraction = (units % self.timestamp_resolution) as u128;
+        let nanoseconds = fraction * 1_000_000_000 / self.timestamp_resolution as u128;
+        (seconds as u32, nanoseconds as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds an option with its value padded to 32 bits.
+    fn option(code: u16, value: &[u8]) -> Vec<u8> {
+        let mut option = code.to_le_bytes().to_vec();
+        option.extend_from_slice(&(value.len() as u16).to_le_bytes());
+        option.extend_from_slice(value);
+        option.resize(option.len().div_ceil(4) * 4, 0);
+        option
+    }
+
+    /// Builds a little-endian Interface Description Block body with the given options.
+    fn interface(link_type: u16, options: &[Vec<u8>]) -> Vec<u8> {
+        let mut body = link_type.to_le_bytes().to_vec();
+        body.extend_from_slice(&[0, 0]);
+        body.extend_from_slice(&262_144_u32.to_le_bytes());
+        body.extend(options.concat());
+        body.extend_from_slice(&[0, 0, 0, 0]);
+        body
+    }
+
+    #[test]
+    fn section_headers_are_read_in_either_byte_order() {
+        let mut little = vec![0x4d, 0x3c, 0x2b, 0x1a, 0x01, 0x00, 0x00, 0x00];
+        little.extend_from_slice(&(-1_i64).to_le_bytes());
+        let options = [option(2, b"x86_64"), option(3, b"Linux"), option(4, b"dumpcap"), option(1, b"first"),
+                       option(1, b"second")];
+        little.extend(options.concat());
+        let section = SectionHeader::parse(&little, ByteOrder::LittleEndian).unwrap();
+        assert_eq!((section.version_major, section.version_minor), (1, 0));
+        assert_eq!(section.section_length, -1);
+        assert_eq!(section.hardware.as_deref(), Some("x86_64"));
+        assert_eq!(section.os.as_deref(), Some("Linux"));
+        assert_eq!(section.user_application.as_deref(), Some("dumpcap"));
+        assert_eq!(section.comments, ["first", "second"]);
+        assert_eq!(section.options.len(), 5);
+
+        let mut big = vec![0x1a, 0x2b, 0x3c, 0x4d, 0x00, 0x01, 0x00, 0x00];
+        big.extend_from_slice(&4096_i64.to_be_bytes());
+        big.extend_from_slice(&[0x00, 0x01, 0x00, 0x02, b'h', b'i', 0x00, 0x00]);
+        let section = SectionHeader::parse(&big, ByteOrder::BigEndian).unwrap();
+        assert_eq!((section.version_major, section.version_minor), (1, 0));
+        assert_eq!(section.section_length, 4096);
+        assert_eq!(section.comments, ["hi"]);
+
+        assert!(matches!(SectionHeader::parse(&big[..12], ByteOrder::BigEndian), Err(ParseError::Truncated { .. })));
+    }
+
+    #[test]
+    fn option_lists_end_at_the_marker_or_a_bad_length() {
+        let mut data = [option(1, b"abcde"), option(0, b""), option(1, b"after")].concat();
+        let options = PcapNgOption::parse_all(&data, ByteOrder::LittleEndian);
+        assert_eq!(options.len(), 1);
+        assert_eq!(options[0].text(), "abcde");
+
+        // An option running past the end of the block is dropped with the rest of the list.
+        data = option(2, b"eth0");
+        data.extend_from_slice(&[0x01, 0x00, 0x40, 0x00, b'x', 0, 0, 0]);
+        let options = PcapNgOption::parse_all(&data, ByteOrder::LittleEndian);
+        assert_eq!(options.len(), 1);
+        assert_eq!(options[0].code, 2);
+    }
+
+    #[test]
+    fn timestamp_resolution_defaults_to_microseconds() {
+        let interface = InterfaceDescription::parse(&interface(1, &[option(2, b"eth0")]), ByteOrder::LittleEndian).unwrap();
+        assert_eq!(interface.link_type, 1);
+        assert_eq!(interface.snap_length, 262_144);
+        assert_eq!(interface.name.as_deref(), Some("eth0"));
+        assert_eq!(interface.timestamp_resolution, 1_000_000);
+        assert_eq!(interface.timestamp(1_700_000_000_250_000), (1_700_000_000, 250_000_000));
+    }
+
+    #[test]
+    fn timestamp_resolution_can_be_a_power_of_ten_or_two() {
+        let nanoseconds = interface(101, &[option(9, &[9])]);
+        let interface_description = InterfaceDescription::parse(&nanoseconds, ByteOrder::LittleEndian).unwrap();
+        assert_eq!(interface_description.timestamp_resolution, 1_000_000_000);
+        assert_eq!(interface_description.timestamp(5_000_000_007), (5, 7));
+
+        let binary = interface(101, &[option(9, &[0x80 | 10])]);
+        let interface_description = InterfaceDescription::parse(&binary, ByteOrder::LittleEndian).unwrap();
+        assert_eq!(interface_description.timestamp_resolution, 1024);
+        assert_eq!(interface_description.timestamp(3 * 1024 + 512), (3, 500_000_000));
+
+        // An exponent too large for 64 bits keeps the default.
+        let overflow = interface(101, &[option(9, &[0x80 | 70])]);
+        let interface_description = InterfaceDescription::parse(&overflow, ByteOrder::LittleEndian).unwrap();
+        assert_eq!(interface_description.timestamp_resolution, 1_000_000);
+    }
+
+    #[test]
+    fn timestamp_offsets_are_added_to_the_seconds() {
+        let body = interface(1, &[option(9, &[3]), option(14, &1_000_i64.to_le_bytes())]);
+        let interface = InterfaceDescription::parse(&body, ByteOrder::LittleEndian).unwrap();
+        assert_eq!(interface.timestamp_offset, 1_000);
+        assert_eq!(interface.timestamp(2_500), (1_002, 500_000_000));
+    }
+}
@@ -0,0 +1,460 @@
+use std::io::Read;
+
+use crate::parser::{array, require};
+use crate::pcap_reader::{read_or_eof, Prefilter};
+use crate::pcapng_header::comments;
+use crate::{ByteOrder, InterfaceDescription, Layer, LinkType, ParseError, PcapBlock, PcapNgOption, SectionHeader};
+
+/// Block type of a Section Header Block, which is also the magic number of pcapng files.
+pub(crate) const SECTION_HEADER_BLOCK: [u8; 4] = [0x0a, 0x0d, 0x0d, 0x0a];
+const INTERFACE_DESCRIPTION_BLOCK: u32 = 1;
+const SIMPLE_PACKET_BLOCK: u32 = 3;
+const ENHANCED_PACKET_BLOCK: u32 = 6;
+
+/// Largest block accepted, large enough for any packet the pcap reader accepts.
+const MAX_BLOCK_LENGTH: usize = 0x0401_0000;
+
+/// Streaming reader over a pcapng capture.
+///
+/// Section Header and Interface Description Blocks are consumed as they appear, Enhanced and
+/// Simple Packet Blocks are returned as `PcapBlock`s, and every other block type is skipped.
+///
+/// A prefilter, such as a `BpfProgram`, can be set to skip packets before their frame is decoded.
+pub struct PcapNgReader<R: Read> {
+    reader: R,
+    section: SectionHeader,
+    interfaces: Vec<InterfaceDescription>,
+    prefilter: Option<Prefilter>,
+    /// Number of packets the prefilter skipped.
+    filtered: usize,
+    done: bool,
+}
+
+impl<R: Read> PcapNgReader<R> {
+    /// Creates a reader and parses the leading Section Header Block.
+    ///
+    /// # Arguments
+    /// * `reader` - The source of the capture bytes.
+    ///
+    /// # Errors
+    /// Returns `ParseError::Io` if the section header cannot be read, or `ParseError::BadMagic` if
+    /// the input is not a pcapng capture.
+    pub fn new(mut reader: R) -> Result<PcapNgReader<R>, ParseError> {
+        let mut block_type = [0_u8; 4];
+        reader.read_exact(&mut block_type)?;
+        PcapNgReader::with_magic(block_type, reader)
+    }
+
+    /// Creates a reader from a source whose first 4 bytes have already been consumed.
+    ///
+    /// # Errors
+    /// Same as `PcapNgReader::new`.
+    pub fn with_magic(block_type: [u8; 4], mut reader: R) -> Result<PcapNgReader<R>, ParseError> {
+        if block_type != SECTION_HEADER_BLOCK {
+            return Err(ParseError::BadMagic(block_type));
+        }
+        let mut length = [0_u8; 4];
+        reader.read_exact(&mut length)?;
+        let section = read_section_header(&mut reader, length)?;
+        Ok(PcapNgReader { reader, section, interfaces: vec![], prefilter: None, filtered: 0, done: false })
+    }
+
+    /// Returns the header of the current section.
+    pub fn section(&self) -> &SectionHeader {
+        &self.section
+    }
+
+    /// Returns the interfaces described so far in the current section.
+    pub fn interfaces(&self) -> &[InterfaceDescription] {
+        &self.interfaces
+    }
+
+    /// Sets a test run on each packet before its frame is decoded; packets it rejects are skipped.
+    ///
+    /// # Arguments
+    /// * `prefilter` - Returns whether to keep a packet, given with its captured bytes, lengths
+    ///   and link-layer type but no decoded frame.
+    pub fn set_prefilter(&mut self, prefilter: impl FnMut(&PcapBlock) -> bool + 'static) {
+        self.prefilter = Some(Box::new(prefilter));
+    }
+
+    /// Returns the number of packets the prefilter skipped so far.
+    pub fn filtered(&self) -> usize {
+        self.filtered
+    }
+
+    /// Reads the next block, returning its type and body.
+    ///
+    /// A new Section Header Block is consumed here since its length can only be decoded once its
+    /// byte-order magic is known. Returns `Ok(None)` at the end of the capture.
+    fn read_block(&mut self) -> Result<Option<(u32, Vec<u8>)>, ParseError> {
+        let mut block_header = [0_u8; 8];
+        loop {
+            if !read_or_eof(&mut self.reader, &mut block_header)? {
+                return Ok(None);
+            }
+            if array(&block_header, 0) != SECTION_HEADER_BLOCK {
+                break;
+            }
+            self.section = read_section_header(&mut self.reader, array(&block_header, 4))?;
+            self.interfaces.clear();
+        }
+
+        let byte_order = self.section.byte_order;
+        let length = byte_order.u32(array(&block_header, 4)) as usize;
+        check_block_length(length, 12)?;
+        let mut body = vec![0_u8; length - 8];
+        self.reader.read_exact(&mut body)?;
+        // Drop the trailing copy of the block length.
+        body.truncate(length - 12);
+        Ok(Some((byte_order.u32(array(&block_header, 0)), body)))
+    }
+
+    /// Returns the interface a packet block refers to.
+    fn interface(&self, interface_id: u32) -> Result<&InterfaceDescription, ParseError> {
+        self.interfaces.get(interface_id as usize).ok_or(ParseError::UnknownInterface(interface_id))
+    }
+
+    /// Builds a `PcapBlock` from the body of an Enhanced Packet Block.
+    fn enhanced_packet(&self, body: &[u8]) -> Result<PcapBlock, ParseError> {
+        require(body, 20, Layer::PcapNgBlock)?;
+        let byte_order = self.section.byte_order;
+        let mut pcap_block = PcapBlock::new();
+        pcap_block.interface_id = byte_order.u32(array(body, 0));
+        let interface = self.interface(pcap_block.interface_id)?;
+
+        let units = ((byte_order.u32(array(body, 4)) as u64) << 32) | byte_order.u32(array(body, 8)) as u64;
+        (pcap_block.timestamp_seconds, pcap_block.timestamp_nanoseconds) = interface.timestamp(units);
+        pcap_block.captured_length = byte_order.u32(array(body, 12));
+        pcap_block.original_length = byte_order.u32(array(body, 16));
+
+        let captured_length = pcap_block.captured_length as usize;
+        if captured_length > body.len() - 20 {
+            return Err(ParseError::BadHeaderLength { layer: Layer::PcapNgBlock, length: captured_length });
+        }
+        pcap_block.data = body[20..20 + captured_length].to_vec();
+        let options_start = (20 + captured_length + 3) & !3;
+        if options_start < body.len() {
+            pcap_block.options = PcapNgOption::parse_all(&body[options_start..], byte_order);
+            pcap_block.comments = comments(&pcap_block.options);
+        }
+        pcap_block.link_type = LinkType::from_number(interface.link_type)?;
+        Ok(pcap_block)
+    }
+
+    /// Builds a `PcapBlock` from the body of a Simple Packet Block.
+    ///
+    /// Simple packets belong to the first interface and carry no timestamp; their captured length
+    /// is the original length cut to the snap length of that interface.
+    fn simple_packet(&self, body: &[u8]) -> Result<PcapBlock, ParseError> {
+        require(body, 4, Layer::PcapNgBlock)?;
+        let interface = self.interface(0)?;
+        let mut pcap_block = PcapBlock::new();
+        pcap_block.original_length = self.section.byte_order.u32(array(body, 0));
+
+        let mut captured_length = (pcap_block.original_length as usize).min(body.len() - 4);
+        if interface.snap_length > 0 {
+            captured_length = captured_length.min(interface.snap_length as usize);
+        }
+        pcap_block.captured_length = captured_length as u32;
+        pcap_block.data = body[4..4 + captured_length].to_vec();
+        pcap_block.link_type = LinkType::from_number(interface.link_type)?;
+        Ok(pcap_block)
+    }
+}
+
+impl<R: Read> Iterator for PcapNgReader<R> {
+    type Item = Result<PcapBlock, ParseError>;
+
+    /// Reads blocks until the next packet that passes the prefilter and decodes it.
+    ///
+    /// A packet that fails to decode is returned as an error and reading continues with the next
+    /// block. A read failure or an invalid block length ends the iteration.
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            let (block_type, body) = match self.read_block() {
+                Ok(Some(block)) => block,
+                Ok(None) => {
+                    self.done = true;
+                    return None;
+                }
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            };
+            let packet = match block_type {
+                INTERFACE_DESCRIPTION_BLOCK => {
+                    match InterfaceDescription::parse(&body, self.section.byte_order) {
+                        Ok(interface) => self.interfaces.push(interface),
+                        Err(e) => return Some(Err(e)),
+                    }
+                    continue;
+                }
+                ENHANCED_PACKET_BLOCK => self.enhanced_packet(&body),
+                SIMPLE_PACKET_BLOCK => self.simple_packet(&body),
+                _ => continue,
+            };
+            if let (Ok(block), Some(prefilter)) = (&packet, self.prefilter.as_mut()) {
+                if !prefilter(block) {
+                    self.filtered += 1;
+                    continue;
+                }
+            }
+            return Some(packet.and_then(|mut block| block.decode().map(|_| block)));
+        }
+        None
+    }
+}
+
+/// Reads the rest of a Section Header Block whose type and length fields have been consumed.
+fn read_section_header<R: Read>(reader: &mut R, length: [u8; 4]) -> Result<SectionHeader, ParseError> {
+    let mut byte_order_magic = [0_u8; 4];
+    reader.read_exact(&mut byte_order_magic)?;
+    let byte_order = match byte_order_magic {
+        [0x1a, 0x2b, 0x3c, 0x4d] => ByteOrder::BigEndian,
+        [0x4d, 0x3c, 0x2b, 0x1a] => ByteOrder::LittleEndian,
+        _ => return Err(ParseError::BadMagic(byte_order_magic)),
+    };
+    let length = byte_order.u32(length) as usize;
+    check_block_length(length, 28)?;
+
+    let mut body = vec![0_u8; length - 8];
+    body[0..4].copy_from_slice(&byte_order_magic);
+    reader.read_exact(&mut body[4..])?;
+    body.truncate(length - 12);
+    SectionHeader::parse(&body, byte_order)
+}
+
+/// Checks that a block length is a multiple of 4 between `minimum` and `MAX_BLOCK_LENGTH`.
+fn check_block_length(length: usize, minimum: usize) -> Result<(), ParseError> {
+    if length < minimum || length % 4 != 0 || length > MAX_BLOCK_LENGTH {
+        return Err(ParseError::BadHeaderLength { layer: Layer::PcapNgBlock, length });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 36-byte IPv4 packet carrying a UDP datagram with 8 bytes of payload.
+    const PACKET: [u8; 36] = [0x45, 0x00, 0x00, 0x24, 0x00, 0x01, 0x00, 0x00, 0x40, 0x11, 0x00, 0x00, 10, 0, 0, 1, 10, 0,
+                              0, 2, 0x04, 0x00, 0x00, 0x35, 0x00, 0x10, 0x00, 0x00, 1, 2, 3, 4, 5, 6, 7, 8];
+
+    /// Lays out blocks and their fields in one byte order.
+    struct Capture {
+        byte_order: ByteOrder,
+        bytes: Vec<u8>,
+    }
+
+    impl Capture {
+        fn new(byte_order: ByteOrder) -> Capture {
+            let mut capture = Capture { byte_order, bytes: vec![] };
+            capture.section(&[]);
+            capture
+        }
+
+        fn u16(&self, value: u16) -> [u8; 2] {
+            match self.byte_order {
+                ByteOrder::LittleEndian => value.to_le_bytes(),
+                ByteOrder::BigEndian => value.to_be_bytes(),
+            }
+        }
+
+        fn u32(&self, value: u32) -> [u8; 4] {
+            match self.byte_order {
+                ByteOrder::LittleEndian => value.to_le_bytes(),
+                ByteOrder::BigEndian => value.to_be_bytes(),
+            }
+        }
+
+        /// Builds an option with its value padded to 32 bits.
+        fn option(&self, code: u16, value: &[u8]) -> Vec<u8> {
+            let mut option = [self.u16(code), self.u16(value.len() as u16)].concat();
+            option.extend_from_slice(value);
+            option.resize(option.len().div_ceil(4) * 4, 0);
+            option
+        }
+
+        /// Appends a block, padding its body to 32 bits.
+        fn block(&mut self, block_type: u32, body: &[u8]) {
+            let length = self.u32(12 + body.len().div_ceil(4) as u32 * 4);
+            self.bytes.extend_from_slice(&self.u32(block_type));
+            self.bytes.extend_from_slice(&length);
+            self.bytes.extend_from_slice(body);
+            self.bytes.resize(self.bytes.len().next_multiple_of(4), 0);
+            self.bytes.extend_from_slice(&length);
+        }
+
+        fn section(&mut self, options: &[Vec<u8>]) {
+            let mut body = self.u32(0x1a2b_3c4d).to_vec();
+            body.extend_from_slice(&[self.u16(1), self.u16(0)].concat());
+            body.extend_from_slice(&[0xff; 8]);
+            body.extend(options.concat());
+            self.block(0x0a0d_0d0a, &body);
+        }
+
+        fn interface(&mut self, link_type: u16, snap_length: u32, options: &[Vec<u8>]) {
+            let mut body = [self.u16(link_type), [0, 0]].concat();
+            body.extend_from_slice(&self.u32(snap_length));
+            body.extend(options.concat());
+            self.block(INTERFACE_DESCRIPTION_BLOCK, &body);
+        }
+
+        fn enhanced_packet(&mut self, interface_id: u32, units: u64, data: &[u8], options: &[Vec<u8>]) {
+            let mut body = self.u32(interface_id).to_vec();
+            body.extend_from_slice(&self.u32((units >> 32) as u32));
+            body.extend_from_slice(&self.u32(units as u32));
+            body.extend_from_slice(&self.u32(data.len() as u32));
+            body.extend_from_slice(&self.u32(data.len() as u32 + 4));
+            body.extend_from_slice(data);
+            body.resize(body.len().next_multiple_of(4), 0);
+            body.extend(options.concat());
+            self.block(ENHANCED_PACKET_BLOCK, &body);
+        }
+
+        fn simple_packet(&mut self, original_length: u32, data: &[u8]) {
+            let mut body = self.u32(original_length).to_vec();
+            body.extend_from_slice(data);
+            self.block(SIMPLE_PACKET_BLOCK, &body);
+        }
+
+        fn read(&self) -> Vec<PcapBlock> {
+            PcapNgReader::new(&self.bytes[..]).unwrap().map(Result::unwrap).collect()
+        }
+    }
+
+    /// Wraps `PACKET` in an Ethernet header.
+    fn ethernet() -> Vec<u8> {
+        let mut frame = vec![0x02, 0, 0, 0, 0, 2, 0x02, 0, 0, 0, 0, 1, 0x08, 0x00];
+        frame.extend_from_slice(&PACKET);
+        frame
+    }
+
+    #[test]
+    fn sections_are_read_in_either_byte_order() {
+        for byte_order in [ByteOrder::LittleEndian, ByteOrder::BigEndian] {
+            let mut capture = Capture { byte_order, bytes: vec![] };
+            let options = [capture.option(4, b"dumpcap"), capture.option(1, b"capture comment")];
+            capture.section(&options);
+            capture.interface(101, 0, &[]);
+            capture.enhanced_packet(0, 1_700_000_000_250_000, &PACKET, &[]);
+
+            let mut reader = PcapNgReader::new(&capture.bytes[..]).unwrap();
+            assert_eq!(reader.section().byte_order, byte_order);
+            assert_eq!(reader.section().section_length, -1);
+            assert_eq!(reader.section().user_application.as_deref(), Some("dumpcap"));
+            assert_eq!(reader.section().comments, ["capture comment"]);
+            let block = reader.next().unwrap().unwrap();
+            assert_eq!((block.timestamp_seconds, block.timestamp_nanoseconds), (1_700_000_000, 250_000_000));
+            assert_eq!((block.captured_length, block.original_length), (36, 40));
+            assert_eq!(block.data, PACKET);
+            assert!(block.ether_frame.ipv4().is_some());
+            assert!(reader.next().is_none());
+        }
+    }
+
+    #[test]
+    fn interface_resolutions_scale_timestamps() {
+        let mut capture = Capture::new(ByteOrder::LittleEndian);
+        let nanoseconds = capture.option(9, &[9]);
+        let binary = capture.option(9, &[0x80 | 10]);
+        capture.interface(101, 0, &[nanoseconds]);
+        capture.interface(101, 0, &[binary]);
+        capture.enhanced_packet(0, 5_000_000_007, &PACKET, &[]);
+        capture.enhanced_packet(1, 3 * 1024 + 256, &PACKET, &[]);
+
+        let blocks = capture.read();
+        assert_eq!((blocks[0].timestamp_seconds, blocks[0].timestamp_nanoseconds), (5, 7));
+        assert_eq!((blocks[1].timestamp_seconds, blocks[1].timestamp_nanoseconds), (3, 250_000_000));
+    }
+
+    #[test]
+    fn each_interface_keeps_its_link_type() {
+        let mut capture = Capture::new(ByteOrder::BigEndian);
+        capture.interface(101, 0, &[]);
+        capture.interface(1, 0, &[]);
+        capture.enhanced_packet(1, 0, &ethernet(), &[]);
+        capture.enhanced_packet(0, 0, &PACKET, &[]);
+
+        let mut reader = PcapNgReader::new(&capture.bytes[..]).unwrap();
+        let blocks: Vec<PcapBlock> = reader.by_ref().map(Result::unwrap).collect();
+        assert_eq!(reader.interfaces().len(), 2);
+        assert_eq!((blocks[0].interface_id, blocks[0].link_type), (1, LinkType::Ethernet));
+        assert_eq!((blocks[1].interface_id, blocks[1].link_type), (0, LinkType::Raw));
+        assert!(blocks.iter().all(|block| block.ether_frame.ipv4().is_some()));
+    }
+
+    #[test]
+    fn packets_keep_their_comments_and_options() {
+        let mut capture = Capture::new(ByteOrder::LittleEndian);
+        capture.interface(101, 0, &[]);
+        let options = [capture.option(2, &capture.u32(1)), capture.option(1, b"retransmitted"), capture.option(0, b"")];
+        capture.enhanced_packet(0, 0, &PACKET[..33], &options);
+
+        let blocks = capture.read();
+        assert_eq!(blocks[0].captured_length, 33);
+        assert_eq!(blocks[0].comments, ["retransmitted"]);
+        assert_eq!(blocks[0].options.len(), 2);
+        assert_eq!(blocks[0].options[0].code, 2);
+    }
+
+    #[test]
+    fn simple_packets_belong_to_the_first_interface() {
+        let mut capture = Capture::new(ByteOrder::LittleEndian);
+        capture.interface(101, 32, &[]);
+        capture.interface(1, 0, &[]);
+        capture.simple_packet(36, &PACKET);
+        capture.simple_packet(30, &PACKET[..30]);
+
+        let blocks = capture.read();
+        assert_eq!(blocks.len(), 2);
+        // The snap length of the interface cuts the first packet, and padding is not part of the data.
+        assert_eq!((blocks[0].captured_length, blocks[0].original_length), (32, 36));
+        assert_eq!(blocks[0].data, PACKET[..32]);
+        assert_eq!((blocks[1].captured_length, blocks[1].original_length), (30, 30));
+        assert!(blocks.iter().all(|block| block.link_type == LinkType::Raw && block.timestamp_seconds == 0));
+    }
+
+    #[test]
+    fn unknown_blocks_are_skipped() {
+        let mut capture = Capture::new(ByteOrder::LittleEndian);
+        capture.block(4, &[0, 0, 0, 0]);
+        capture.interface(101, 0, &[]);
+        capture.block(0x0bad_0001, &[0xaa; 10]);
+        capture.enhanced_packet(0, 0, &PACKET, &[]);
+        assert_eq!(capture.read().len(), 1);
+    }
+
+    #[test]
+    fn a_new_section_starts_without_interfaces() {
+        let mut capture = Capture::new(ByteOrder::LittleEndian);
+        capture.interface(101, 0, &[]);
+        capture.enhanced_packet(0, 0, &PACKET, &[]);
+        let mut next = Capture::new(ByteOrder::BigEndian);
+        next.enhanced_packet(0, 0, &PACKET, &[]);
+        next.interface(1, 0, &[]);
+        next.enhanced_packet(0, 0, &ethernet(), &[]);
+        capture.bytes.extend(next.bytes);
+
+        let mut reader = PcapNgReader::new(&capture.bytes[..]).unwrap();
+        assert_eq!(reader.next().unwrap().unwrap().link_type, LinkType::Raw);
+        assert!(matches!(reader.next(), Some(Err(ParseError::UnknownInterface(0)))));
+        assert_eq!(reader.section().byte_order, ByteOrder::BigEndian);
+        assert_eq!(reader.next().unwrap().unwrap().link_type, LinkType::Ethernet);
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn bad_block_lengths_end_the_capture() {
+        let mut capture = Capture::new(ByteOrder::LittleEndian);
+        capture.bytes.extend_from_slice(&[6, 0, 0, 0, 10, 0, 0, 0]);
+        let mut reader = PcapNgReader::new(&capture.bytes[..]).unwrap();
+        assert!(matches!(reader.next(), Some(Err(ParseError::BadHeaderLength { length: 10, .. }))));
+        assert!(reader.next().is_none());
+
+        assert!(matches!(PcapNgReader::new(&[0xd4, 0xc3, 0xb2, 0xa1, 0, 0, 0, 0][..]).err(),
+                         Some(ParseError::BadMagic([0xd4, 0xc3, 0xb2, 0xa1]))));
+    }
+}
@@ -0,0 +1,269 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::Duration;
+
+/// Largest datagram that can be rebuilt from IPv4 or IPv6 fragments.
+const MAX_DATAGRAM_LENGTH: usize = 65535;
+
+/// How bytes covered by more than one fragment are resolved.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum OverlapPolicy {
+    /// Keep the bytes of the fragment that arrived first, as BSD and Windows stacks do.
+    #[default]
+    First,
+    /// Keep the bytes of the fragment that arrived last.
+    Last,
+    /// Drop the whole datagram, as RFC 5722 requires for IPv6.
+    Drop,
+}
+
+/// The fragments received so far for one datagram.
+pub(crate) struct FragmentBuffer {
+    data: Vec<u8>,
+    filled: Vec<bool>,
+    /// Length of the datagram, known once the fragment without the more-fragments flag arrives.
+    total_length: Option<usize>,
+    fragments: usize,
+    /// Number of fragments cut short by the snap length, whose data was left out.
+    truncated: usize,
+    first_seen: Duration,
+}
+
+impl FragmentBuffer {
+    fn new(timestamp: Duration) -> FragmentBuffer {
+        FragmentBuffer {
+            data: vec![],
+            filled: vec![],
+            total_length: None,
+            fragments: 0,
+            truncated: 0,
+            first_seen: timestamp,
+        }
+    }
+
+    /// Returns the number of bytes received so far.
+    fn received(&self) -> usize {
+        self.filled.iter().filter(|b| **b).count()
+    }
+
+    /// Returns whether every byte up to the total length has been received.
+    fn is_complete(&self) -> bool {
+        self.total_length.is_some_and(|total| self.filled.len() >= total && self.filled[..total].iter().all(|b| *b))
+    }
+}
+
+/// A datagram rebuilt from its fragments.
+pub(crate) struct Reassembled {
+    pub(crate) payload: Vec<u8>,
+    pub(crate) fragments: usize,
+}
+
+/// A datagram still missing fragments, as reported at the end of a capture.
+pub(crate) struct Incomplete<'a, K> {
+    pub(crate) key: &'a K,
+    pub(crate) fragments: usize,
+    pub(crate) received: usize,
+    pub(crate) total_length: Option<usize>,
+    pub(crate) truncated: usize,
+}
+
+/// Buffers the fragments of datagrams, keyed by whatever identifies a datagram at the IP layer,
+/// until every byte of a datagram has arrived.
+pub(crate) struct FragmentTable<K> {
+    pending: HashMap<K, FragmentBuffer>,
+    pub(crate) timeout: Duration,
+    pub(crate) policy: OverlapPolicy,
+    /// Number of datagrams rebuilt so far.
+    pub(crate) reassembled: usize,
+    /// Number of datagrams given up on because their fragments stopped arriving.
+    pub(crate) timed_out: usize,
+    /// Number of datagrams dropped for overlapping or inconsistent fragments.
+    pub(crate) discarded: usize,
+}
+
+impl<K: Hash + Eq + Clone> FragmentTable<K> {
+    pub(crate) fn new(timeout: Duration, policy: OverlapPolicy) -> FragmentTable<K> {
+        FragmentTable { pending: HashMap::new(), timeout, policy, reassembled: 0, timed_out: 0, discarded: 0 }
+    }
+
+    /// Adds a fragment, returning the whole datagram if this fragment completes it.
+    ///
+    /// Datagrams that have waited longer than the timeout before `timestamp` are discarded first.
+    /// The data of a truncated fragment is left out, since the missing bytes would corrupt the
+    /// datagram and, in the last fragment, its length; the datagram stays incomplete unless a
+    /// whole copy of the fragment arrives.
+    ///
+    /// # Arguments
+    /// * `key` - Identifies the datagram the fragment belongs to.
+    /// * `offset` - Offset of the fragment in the datagram, in bytes.
+    /// * `more_fragments` - Whether fragments follow this one.
+    /// * `payload` - The fragment's part of the datagram.
+    /// * `truncated` - Whether the capture cut the fragment short, so `payload` is incomplete.
+    /// * `timestamp` - The capture time of the fragment.
+    pub(crate) fn add(&mut self, key: K, offset: usize, more_fragments: bool, payload: &[u8], truncated: bool,
+                      timestamp: Duration) -> Option<Reassembled> {
+        self.expire(timestamp);
+        let end = offset + payload.len();
+        let buffer = self.pending.entry(key.clone()).or_insert_with(|| FragmentBuffer::new(timestamp));
+        buffer.fragments += 1;
+        if truncated {
+            buffer.truncated += 1;
+            return None;
+        }
+
+        let overlaps = buffer.filled.get(offset..end.min(buffer.filled.len())).is_some_and(|f| f.iter().any(|b| *b));
+        // A fragment past the end of the datagram, or a second end, means the set cannot be rebuilt.
+        let bad_length = end > MAX_DATAGRAM_LENGTH
+            || buffer.total_length.is_some_and(|total| end > total || (!more_fragments && end != total))
+            || (!more_fragments && buffer.filled.len() > end);
+        if bad_length || (overlaps && self.policy == OverlapPolicy::Drop) {
+            self.pending.remove(&key);
+            self.discarded += 1;
+            return None;
+        }
+
+        if buffer.data.len() < end {
+            buffer.data.resize(end, 0);
+            buffer.filled.resize(end, false);
+        }
+        for (index, byte) in payload.iter().enumerate() {
+            let position = offset + index;
+            if !buffer.filled[position] || self.policy == OverlapPolicy::Last {
+                buffer.data[position] = *byte;
+                buffer.filled[position] = true;
+            }
+        }
+        if !more_fragments {
+            buffer.total_length = Some(end);
+        }
+
+        if !buffer.is_complete() {
+            return None;
+        }
+        let mut buffer = self.pending.remove(&key).unwrap();
+        let total = buffer.total_length.unwrap();
+        buffer.data.truncate(total);
+        self.reassembled += 1;
+        Some(Reassembled { payload: buffer.data, fragments: buffer.fragments })
+    }
+
+    /// Discards the datagrams whose first fragment arrived more than the timeout before `now`.
+    pub(crate) fn expire(&mut self, now: Duration) {
+        let timeout = self.timeout;
+        let before = self.pending.len();
+        self.pending.retain(|_, buffer| now.saturating_sub(buffer.first_seen) <= timeout);
+        self.timed_out += before - self.pending.len();
+    }
+
+    /// Returns whether no fragment has been added yet.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.pending.is_empty() && self.reassembled == 0 && self.timed_out == 0 && self.discarded == 0
+    }
+
+    /// Returns the datagrams still waiting for fragments, oldest first.
+    pub(crate) fn incomplete(&self) -> Vec<Incomplete<'_, K>> {
+        let mut buffers: Vec<(&K, &FragmentBuffer)> = self.pending.iter().collect();
+        buffers.sort_by_key(|(_, buffer)| buffer.first_seen);
+        buffers
+            .into_iter()
+            .map(|(key, buffer)| Incomplete {
+                key,
+                fragments: buffer.fragments,
+                received: buffer.received(),
+                total_length: buffer.total_length,
+                truncated: buffer.truncated,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: u32 = 1;
+
+    fn table(policy: OverlapPolicy) -> FragmentTable<u32> {
+        FragmentTable::new(Duration::from_secs(30), policy)
+    }
+
+    fn add(table: &mut FragmentTable<u32>, offset: usize, more_fragments: bool, payload: &[u8]) -> Option<Vec<u8>> {
+        table.add(KEY, offset, more_fragments, payload, false, Duration::ZERO).map(|datagram| datagram.payload)
+    }
+
+    #[test]
+    fn in_order_fragments() {
+        let mut table = table(OverlapPolicy::First);
+        assert_eq!(add(&mut table, 0, true, b"abcdefgh"), None);
+        assert_eq!(add(&mut table, 8, true, b"ijklmnop"), None);
+        assert_eq!(add(&mut table, 16, false, b"qr"), Some(b"abcdefghijklmnopqr".to_vec()));
+        assert_eq!(table.reassembled, 1);
+        assert!(table.incomplete().is_empty());
+    }
+
+    #[test]
+    fn out_of_order_fragments() {
+        let mut table = table(OverlapPolicy::First);
+        assert_eq!(add(&mut table, 16, false, b"qr"), None);
+        assert_eq!(add(&mut table, 0, true, b"abcdefgh"), None);
+        assert_eq!(table.incomplete()[0].total_length, Some(18));
+        assert_eq!(add(&mut table, 8, true, b"ijklmnop"), Some(b"abcdefghijklmnopqr".to_vec()));
+    }
+
+    #[test]
+    fn overlap_keeps_the_first_copy() {
+        let mut table = table(OverlapPolicy::First);
+        add(&mut table, 0, true, b"abcdefgh");
+        add(&mut table, 0, true, b"ABCDEFGHIJKLMNOP");
+        assert_eq!(add(&mut table, 16, false, b"qr"), Some(b"abcdefghIJKLMNOPqr".to_vec()));
+    }
+
+    #[test]
+    fn overlap_keeps_the_last_copy() {
+        let mut table = table(OverlapPolicy::Last);
+        add(&mut table, 0, true, b"abcdefgh");
+        add(&mut table, 0, true, b"ABCDEFGHIJKLMNOP");
+        assert_eq!(add(&mut table, 16, false, b"qr"), Some(b"ABCDEFGHIJKLMNOPqr".to_vec()));
+    }
+
+    #[test]
+    fn overlap_drops_the_datagram() {
+        let mut table = table(OverlapPolicy::Drop);
+        add(&mut table, 0, true, b"abcdefgh");
+        assert_eq!(add(&mut table, 0, true, b"ABCDEFGHIJKLMNOP"), None);
+        assert_eq!(table.discarded, 1);
+        // What follows starts a new datagram, which never completes.
+        assert_eq!(add(&mut table, 16, false, b"qr"), None);
+        assert_eq!(table.reassembled, 0);
+    }
+
+    #[test]
+    fn truncated_fragment_leaves_the_datagram_incomplete() {
+        let mut table = table(OverlapPolicy::First);
+        add(&mut table, 0, true, b"abcdefgh");
+        // The last fragment lost a byte to the snap length, which must not set the total length.
+        assert!(table.add(KEY, 8, false, b"ijklmnop", true, Duration::ZERO).is_none());
+        let incomplete = table.incomplete();
+        assert_eq!(incomplete.len(), 1);
+        assert_eq!((incomplete[0].fragments, incomplete[0].truncated), (2, 1));
+        assert_eq!((incomplete[0].received, incomplete[0].total_length), (8, None));
+        // A whole copy of the fragment still completes it.
+        assert_eq!(add(&mut table, 8, false, b"ijklmnopq"), Some(b"abcdefghijklmnopq".to_vec()));
+    }
+
+    #[test]
+    fn second_end_discards_the_datagram() {
+        let mut table = table(OverlapPolicy::First);
+        add(&mut table, 8, false, b"ijklmnop");
+        assert_eq!(add(&mut table, 16, false, b"qr"), None);
+        assert_eq!(table.discarded, 1);
+    }
+
+    #[test]
+    fn stale_datagrams_time_out() {
+        let mut table = table(OverlapPolicy::First);
+        add(&mut table, 0, true, b"abcdefgh");
+        assert!(table.add(KEY, 8, false, b"ij", false, Duration::from_secs(31)).is_none());
+        assert_eq!(table.timed_out, 1);
+    }
+}
@@ -0,0 +1,381 @@
+use std::fmt;
+use std::time::Duration;
+
+use crate::{TcpFlags, TcpOption, TCPPacket};
+
+/// Longest time a segment filling a gap may arrive after the gap was seen to be counted as out of
+/// order rather than retransmitted, as Wireshark assumes when no round-trip time is known.
+const OUT_OF_ORDER_THRESHOLD: Duration = Duration::from_millis(3);
+
+/// Most gaps kept per direction; the oldest is forgotten when a burst of losses opens more.
+const MAX_GAPS: usize = 64;
+
+/// A condition found by analysing a TCP segment against the rest of its connection, as Wireshark's
+/// expert analysis reports.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TcpAnalysis {
+    /// The segment resends data that was already sent.
+    Retransmission,
+    /// The segment resends the data asked for by at least two duplicate ACKs.
+    FastRetransmission,
+    /// The segment arrived after later data, shortly after the gap it fills was seen.
+    OutOfOrder,
+    /// The segment starts past the next expected sequence number, so data before it was not
+    /// captured.
+    PreviousSegmentNotCaptured,
+    /// The segment acknowledges the same data with the same non-zero window as the previous one,
+    /// or with any non-zero window when it carries SACK blocks; the value counts the duplicates in
+    /// a row.
+    DuplicateAck(u32),
+    /// The sender advertises a zero window and cannot receive more data.
+    ZeroWindow,
+    /// The segment fills the receiver's advertised window.
+    WindowFull,
+    /// The segment carries at most one byte from just before the next expected sequence number, to
+    /// check the peer is still there.
+    KeepAlive,
+}
+
+impl fmt::Display for TcpAnalysis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TcpAnalysis::Retransmission => write!(f, "retransmission"),
+            TcpAnalysis::FastRetransmission => write!(f, "fast retransmission"),
+            TcpAnalysis::OutOfOrder => write!(f, "out-of-order"),
+            TcpAnalysis::PreviousSegmentNotCaptured => write!(f, "previous segment not captured"),
+            TcpAnalysis::DuplicateAck(count) => write!(f, "duplicate ACK #{}", count),
+            TcpAnalysis::ZeroWindow => write!(f, "zero window"),
+            TcpAnalysis::WindowFull => write!(f, "window full"),
+            TcpAnalysis::KeepAlive => write!(f, "keep-alive"),
+        }
+    }
+}
+
+/// The number of times each analysis condition was found in a connection.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct AnalysisCounts {
+    pub retransmissions: u64,
+    pub fast_retransmissions: u64,
+    pub out_of_order: u64,
+    pub lost_segments: u64,
+    pub duplicate_acks: u64,
+    pub zero_windows: u64,
+    pub window_full: u64,
+    pub keep_alives: u64,
+}
+
+impl AnalysisCounts {
+    /// Counts one occurrence of a condition.
+    pub fn add(&mut self, analysis: TcpAnalysis) {
+        let count = match analysis {
+            TcpAnalysis::Retransmission => &mut self.retransmissions,
+            TcpAnalysis::FastRetransmission => &mut self.fast_retransmissions,
+            TcpAnalysis::OutOfOrder => &mut self.out_of_order,
+            TcpAnalysis::PreviousSegmentNotCaptured => &mut self.lost_segments,
+            TcpAnalysis::DuplicateAck(_) => &mut self.duplicate_acks,
+            TcpAnalysis::ZeroWindow => &mut self.zero_windows,
+            TcpAnalysis::WindowFull => &mut self.window_full,
+            TcpAnalysis::KeepAlive => &mut self.keep_alives,
+        };
+        *count += 1;
+    }
+
+    /// Returns whether no condition was found.
+    pub fn is_empty(&self) -> bool {
+        *self == AnalysisCounts::default()
+    }
+}
+
+impl fmt::Display for AnalysisCounts {
+    /// Formats the conditions that were found with their counts, as in `2 retransmissions, 3
+    /// duplicate ACKs`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let counts = [
+            (self.retransmissions, "retransmissions"),
+            (self.fast_retransmissions, "fast retransmissions"),
+            (self.out_of_order, "out-of-order segments"),
+            (self.lost_segments, "lost segments"),
+            (self.duplicate_acks, "duplicate ACKs"),
+            (self.zero_windows, "zero windows"),
+            (self.window_full, "window full"),
+            (self.keep_alives, "keep-alives"),
+        ];
+        let counts: Vec<String> =
+            counts.iter().filter(|(count, _)| *count > 0).map(|(count, name)| format!("{} {}", count, name)).collect();
+        if counts.is_empty() {
+            write!(f, "none")
+        } else {
+            write!(f, "{}", counts.join(", "))
+        }
+    }
+}
+
+/// What is known of the segments sent in one direction of a connection.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct SequenceState {
+    /// One past the highest sequence number sent so far.
+    next_sequence: Option<u32>,
+    /// Acknowledgement number of the last segment with the ACK flag.
+    last_ack: Option<u32>,
+    /// Window of the last segment, before scaling.
+    last_window: u16,
+    /// Number of duplicate ACKs sent in a row.
+    duplicate_acks: u32,
+    /// Window scale shift announced in the SYN.
+    window_scale: Option<u8>,
+    /// Sequence ranges skipped over by a later segment, with the time the gap was seen. Only gaps
+    /// seen less than the out-of-order threshold ago are kept, since a later fill is a
+    /// retransmission.
+    gaps: Vec<(u32, u32, Duration)>,
+}
+
+/// Returns whether sequence number `a` comes before `b`, allowing for wraparound.
+pub(crate) fn before(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+/// Analyses a segment against the earlier segments of both directions of its connection and
+/// records it in the sender's state.
+///
+/// # Arguments
+/// * `sender` - The state of the direction the segment was sent in.
+/// * `receiver` - The state of the opposite direction.
+/// * `tcp` - The segment.
+/// * `timestamp` - The capture time of the segment.
+pub(crate) fn analyze(sender: &mut SequenceState, receiver: &SequenceState, tcp: &TCPPacket, timestamp: Duration)
+                      -> Vec<TcpAnalysis> {
+    let mut analysis = vec![];
+    let flags = tcp.flags;
+    let sequence_number = u32::from_be_bytes(tcp.sequence_number);
+    let acknowledgement_number = u32::from_be_bytes(tcp.acknowledgement_number);
+    let window = u16::from_be_bytes(tcp.window);
+    let payload = tcp.payload.len() as u32;
+    let control = flags.intersects(TcpFlags::SYN | TcpFlags::FIN | TcpFlags::RST);
+    // SYN and FIN each take up a sequence number.
+    let length = payload + flags.contains(TcpFlags::SYN) as u32 + flags.contains(TcpFlags::FIN) as u32;
+    let end = sequence_number.wrapping_add(length);
+
+    if window == 0 && !control {
+        analysis.push(TcpAnalysis::ZeroWindow);
+    }
+    sender.gaps.retain(|(_, _, seen)| timestamp.saturating_sub(*seen) < OUT_OF_ORDER_THRESHOLD);
+    if let Some(next) = sender.next_sequence {
+        if payload <= 1 && !control && sequence_number == next.wrapping_sub(1) {
+            analysis.push(TcpAnalysis::KeepAlive);
+        } else if before(next, sequence_number) {
+            analysis.push(TcpAnalysis::PreviousSegmentNotCaptured);
+            if sender.gaps.len() == MAX_GAPS {
+                sender.gaps.remove(0);
+            }
+            sender.gaps.push((next, sequence_number, timestamp));
+        } else if length > 0 && before(sequence_number, next) {
+            let gap = sender.gaps.iter().position(|(start, gap_end, _)| {
+                !before(sequence_number, *start) && before(sequence_number, *gap_end)
+            });
+            if gap.is_some() {
+                analysis.push(TcpAnalysis::OutOfOrder);
+            } else if receiver.duplicate_acks >= 2 && receiver.last_ack == Some(sequence_number) {
+                analysis.push(TcpAnalysis::FastRetransmission);
+            } else {
+                analysis.push(TcpAnalysis::Retransmission);
+            }
+            // Whatever the segment leaves unfilled on either side stays a gap.
+            if let Some(gap) = gap {
+                let (start, gap_end, seen) = sender.gaps[gap];
+                let left = Some((start, sequence_number, seen)).filter(|_| before(start, sequence_number));
+                let right = Some((end, gap_end, seen)).filter(|_| before(end, gap_end));
+                sender.gaps.splice(gap..=gap, left.into_iter().chain(right));
+            }
+        }
+    }
+
+    // Windows are only scaled once both sides have announced a scale in their SYN.
+    let scaled = sender.window_scale.is_some() && receiver.window_scale.is_some();
+    if payload > 0 {
+        if let Some(ack) = receiver.last_ack {
+            let shift = if scaled { receiver.window_scale.unwrap_or(0).min(14) } else { 0 };
+            let receive_window = (receiver.last_window as u32) << shift;
+            if end == ack.wrapping_add(receive_window) {
+                analysis.push(TcpAnalysis::WindowFull);
+            }
+        }
+    }
+
+    if flags.contains(TcpFlags::ACK) {
+        let keep_alive = analysis.contains(&TcpAnalysis::KeepAlive);
+        // As in Wireshark, a zero window never makes a duplicate ACK, and SACK blocks make one even
+        // when the window changed.
+        let sack = tcp.options.iter().any(|option| matches!(option, TcpOption::Sack(_)));
+        if payload == 0 && !control && !keep_alive && sender.last_ack == Some(acknowledgement_number) && window != 0
+            && (sender.last_window == window || sack) {
+            sender.duplicate_acks += 1;
+            analysis.push(TcpAnalysis::DuplicateAck(sender.duplicate_acks));
+        } else if sender.last_ack != Some(acknowledgement_number) || sender.last_window != window {
+            sender.duplicate_acks = 0;
+        }
+        sender.last_ack = Some(acknowledgement_number);
+    }
+    sender.last_window = window;
+    if flags.contains(TcpFlags::SYN) {
+        sender.window_scale = tcp.options.iter().find_map(|option| match option {
+            TcpOption::WindowScale(shift) => Some(*shift),
+            _ => None,
+        });
+    }
+    sender.next_sequence = match sender.next_sequence {
+        Some(next) if before(end, next) => Some(next),
+        _ => Some(end),
+    };
+    analysis
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tcp_connection::tests::segment;
+    use crate::{Direction, EthernetFrame, ProtocolDatagram, TcpConnectionTracker};
+
+    const CLIENT: Direction = Direction::ClientToServer;
+    const SERVER: Direction = Direction::ServerToClient;
+
+    /// Runs the segments, each with its capture time in milliseconds, through a connection
+    /// tracker and returns the analysis of each.
+    fn analyze_all(segments: Vec<(u64, EthernetFrame)>) -> Vec<Vec<TcpAnalysis>> {
+        let mut tracker = TcpConnectionTracker::new();
+        segments
+            .into_iter()
+            .map(|(time, mut frame)| {
+                tracker.process(&mut frame, Duration::from_millis(time));
+                match frame.payload.datagram() {
+                    Some(ProtocolDatagram::TCP(tcp)) => tcp.analysis.clone(),
+                    _ => panic!("not a TCP segment"),
+                }
+            })
+            .collect()
+    }
+
+    fn data(direction: Direction, sequence_number: u32, length: usize) -> EthernetFrame {
+        segment(direction, TcpFlags::ACK | TcpFlags::PSH, sequence_number, 5000, 1000, &vec![b'x'; length])
+    }
+
+    fn ack(direction: Direction, acknowledgement_number: u32, window: u16) -> EthernetFrame {
+        segment(direction, TcpFlags::ACK, 5000, acknowledgement_number, window, b"")
+    }
+
+    /// A bare segment for driving `analyze` directly.
+    fn tcp(sequence_number: u32, length: usize) -> TCPPacket {
+        let mut tcp = TCPPacket::new();
+        tcp.flags = TcpFlags::ACK;
+        tcp.sequence_number = sequence_number.to_be_bytes();
+        tcp.window = 1000_u16.to_be_bytes();
+        tcp.payload = vec![0; length];
+        tcp
+    }
+
+    #[test]
+    fn duplicate_acks_are_counted_in_a_row() {
+        let analysis = analyze_all(vec![
+            (0, data(CLIENT, 1000, 10)),
+            (1, ack(SERVER, 1010, 1000)),
+            (2, ack(SERVER, 1010, 1000)),
+            (3, ack(SERVER, 1010, 1000)),
+            // A window update is not a duplicate.
+            (4, ack(SERVER, 1010, 2000)),
+        ]);
+        assert_eq!(analysis[1], []);
+        assert_eq!(analysis[2], [TcpAnalysis::DuplicateAck(1)]);
+        assert_eq!(analysis[3], [TcpAnalysis::DuplicateAck(2)]);
+        assert_eq!(analysis[4], []);
+    }
+
+    #[test]
+    fn retransmission_after_two_duplicate_acks_is_fast() {
+        let analysis = analyze_all(vec![
+            (0, data(CLIENT, 1000, 10)),
+            (0, data(CLIENT, 1020, 10)),
+            (10, ack(SERVER, 1010, 1000)),
+            (11, ack(SERVER, 1010, 1000)),
+            (12, ack(SERVER, 1010, 1000)),
+            (20, data(CLIENT, 1010, 10)),
+            (30, data(CLIENT, 1010, 10)),
+        ]);
+        assert_eq!(analysis[1], [TcpAnalysis::PreviousSegmentNotCaptured]);
+        assert_eq!(analysis[5], [TcpAnalysis::FastRetransmission]);
+        assert_eq!(analysis[6], [TcpAnalysis::FastRetransmission]);
+        let analysis = analyze_all(vec![(0, data(CLIENT, 1000, 10)), (50, data(CLIENT, 1000, 10))]);
+        assert_eq!(analysis[1], [TcpAnalysis::Retransmission]);
+    }
+
+    #[test]
+    fn keep_alive_resends_the_last_byte() {
+        let analysis = analyze_all(vec![
+            (0, data(CLIENT, 1000, 10)),
+            (1, ack(SERVER, 1010, 1000)),
+            (1000, data(CLIENT, 1009, 1)),
+            (1001, data(CLIENT, 1009, 0)),
+            (1002, ack(SERVER, 1010, 1000)),
+        ]);
+        assert_eq!(analysis[2], [TcpAnalysis::KeepAlive]);
+        assert_eq!(analysis[3], [TcpAnalysis::KeepAlive]);
+        // The answer to a keep-alive repeats the last ACK.
+        assert_eq!(analysis[4], [TcpAnalysis::DuplicateAck(1)]);
+    }
+
+    #[test]
+    fn zero_window_acks_are_not_duplicates() {
+        let analysis = analyze_all(vec![
+            (0, data(CLIENT, 1000, 10)),
+            (1, ack(SERVER, 1010, 0)),
+            // A zero window probe and its answer.
+            (200, data(CLIENT, 1010, 1)),
+            (201, ack(SERVER, 1010, 0)),
+            (400, data(CLIENT, 1010, 1)),
+            (401, ack(SERVER, 1010, 0)),
+        ]);
+        assert_eq!(analysis[1], [TcpAnalysis::ZeroWindow]);
+        assert_eq!(analysis[3], [TcpAnalysis::ZeroWindow]);
+        assert_eq!(analysis[5], [TcpAnalysis::ZeroWindow]);
+    }
+
+    #[test]
+    fn gap_filled_shortly_after_is_out_of_order() {
+        let analysis = analyze_all(vec![
+            (0, data(CLIENT, 1000, 10)),
+            (0, data(CLIENT, 1020, 10)),
+            (1, data(CLIENT, 1010, 10)),
+            (2, data(CLIENT, 1010, 10)),
+        ]);
+        assert_eq!(analysis[2], [TcpAnalysis::OutOfOrder]);
+        // The gap is gone once filled, so a second copy is a retransmission.
+        assert_eq!(analysis[3], [TcpAnalysis::Retransmission]);
+    }
+
+    #[test]
+    fn partial_fills_split_the_gap() {
+        let mut sender = SequenceState::default();
+        let receiver = SequenceState::default();
+        analyze(&mut sender, &receiver, &tcp(1000, 10), Duration::ZERO);
+        analyze(&mut sender, &receiver, &tcp(1040, 10), Duration::ZERO);
+        assert_eq!(sender.gaps, [(1010, 1040, Duration::ZERO)]);
+        let analysis = analyze(&mut sender, &receiver, &tcp(1020, 10), Duration::from_millis(1));
+        assert_eq!(analysis, [TcpAnalysis::OutOfOrder]);
+        assert_eq!(sender.gaps, [(1010, 1020, Duration::ZERO), (1030, 1040, Duration::ZERO)]);
+        analyze(&mut sender, &receiver, &tcp(1010, 10), Duration::from_millis(1));
+        analyze(&mut sender, &receiver, &tcp(1030, 10), Duration::from_millis(1));
+        assert!(sender.gaps.is_empty());
+    }
+
+    #[test]
+    fn gaps_are_bounded() {
+        let mut sender = SequenceState::default();
+        let receiver = SequenceState::default();
+        // Every other segment is lost.
+        for segment in 0..1000 {
+            analyze(&mut sender, &receiver, &tcp(segment * 20, 10), Duration::ZERO);
+        }
+        assert_eq!(sender.gaps.len(), MAX_GAPS);
+        // Gaps older than the out-of-order threshold are forgotten.
+        analyze(&mut sender, &receiver, &tcp(19990, 10), Duration::from_secs(1));
+        assert!(sender.gaps.is_empty());
+    }
+}
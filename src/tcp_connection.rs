@@ -0,0 +1,503 @@
+use std::fmt;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use crate::flow::FlowTable;
+use crate::tcp_analysis::{analyze, SequenceState};
+use crate::tcp_rtt::{milliseconds, RttState};
+use crate::{AnalysisCounts, Direction, EthernetFrame, FlowKey, HandshakeTimes, ProtocolDatagram, RttSample, RttSummary, TcpAnalysis,
+            TcpFlags};
+
+/// The state of a TCP connection, as far as its captured segments show.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TcpState {
+    /// The client sent a SYN that was not answered.
+    SynSent,
+    /// The server answered with a SYN/ACK, but the client's final ACK was not seen.
+    SynReceived,
+    /// The handshake completed, or the connection was already open when the capture started.
+    Established,
+    /// One side sent a FIN and the other has not yet.
+    HalfClosed,
+    /// Both sides sent a FIN.
+    Closed,
+    /// A RST ended the connection.
+    Reset,
+}
+
+impl fmt::Display for TcpState {
+    /// Formats the state as the outcome of the connection at the end of the capture.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TcpState::SynSent => write!(f, "never completed"),
+            TcpState::SynReceived => write!(f, "half-open"),
+            TcpState::Established => write!(f, "established"),
+            TcpState::HalfClosed => write!(f, "half-closed"),
+            TcpState::Closed => write!(f, "closed"),
+            TcpState::Reset => write!(f, "reset"),
+        }
+    }
+}
+
+/// Packet and payload byte counts for one direction of a connection.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct DirectionCounts {
+    pub packets: u64,
+    /// TCP payload bytes, retransmissions included.
+    pub bytes: u64,
+}
+
+/// A TCP connection followed from its handshake to its teardown.
+pub struct TcpConnection {
+    /// Position of the connection in the order connections were first seen, counting from 0.
+    pub index: usize,
+    /// The endpoint that opened the connection, or that sent the first captured segment.
+    pub client: SocketAddr,
+    pub server: SocketAddr,
+    pub state: TcpState,
+    /// Whether the handshake was captured, rather than the connection being picked up midway.
+    pub handshake_seen: bool,
+    /// Initial sequence number of the client, when its SYN was seen.
+    pub client_isn: Option<u32>,
+    pub client_fin: bool,
+    pub server_fin: bool,
+    /// Capture time of the first segment.
+    pub start: Duration,
+    /// Capture time of the last segment.
+    pub end: Duration,
+    pub client_to_server: DirectionCounts,
+    pub server_to_client: DirectionCounts,
+    /// Totals of the conditions found by analysing the segments of both directions.
+    pub analysis: AnalysisCounts,
+    pub handshake: HandshakeTimes,
+    /// Round-trip times measured from the acknowledgements of both directions, in capture order.
+    pub rtt_samples: Vec<RttSample>,
+    client_sequence: SequenceState,
+    server_sequence: SequenceState,
+    rtt: RttState,
+}
+
+impl TcpConnection {
+    pub fn new(index: usize, client: SocketAddr, server: SocketAddr, timestamp: Duration) -> TcpConnection {
+        TcpConnection {
+            index,
+            client,
+            server,
+            state: TcpState::Established,
+            handshake_seen: false,
+            client_isn: None,
+            client_fin: false,
+            server_fin: false,
+            start: timestamp,
+            end: timestamp,
+            client_to_server: DirectionCounts::default(),
+            server_to_client: DirectionCounts::default(),
+            analysis: AnalysisCounts::default(),
+            handshake: HandshakeTimes::default(),
+            rtt_samples: vec![],
+            client_sequence: SequenceState::default(),
+            server_sequence: SequenceState::default(),
+            rtt: RttState::default(),
+        }
+    }
+
+    /// Returns the time from the first to the last segment of the connection.
+    pub fn duration(&self) -> Duration {
+        self.end.saturating_sub(self.start)
+    }
+
+    /// Returns the counts of the given direction.
+    pub fn counts(&self, direction: Direction) -> &DirectionCounts {
+        match direction {
+            Direction::ClientToServer => &self.client_to_server,
+            Direction::ServerToClient => &self.server_to_client,
+        }
+    }
+
+    /// Returns the minimum, average, 95th percentile and maximum of the round-trip time samples.
+    pub fn rtt_summary(&self) -> Option<RttSummary> {
+        RttSummary::from_samples(&self.rtt_samples)
+    }
+
+    /// Records the capture time of a handshake segment.
+    fn record_handshake(&mut self, direction: Direction, flags: TcpFlags, timestamp: Duration) {
+        let syn = flags.contains(TcpFlags::SYN);
+        let ack = flags.contains(TcpFlags::ACK);
+        let handshake = &mut self.handshake;
+        match direction {
+            Direction::ClientToServer if syn && !ack && handshake.syn_ack.is_none() => handshake.syn = Some(timestamp),
+            Direction::ServerToClient if syn && ack && handshake.syn.is_some() && handshake.syn_ack.is_none() => {
+                handshake.syn_ack = Some(timestamp)
+            }
+            Direction::ClientToServer if ack && !syn && handshake.syn_ack.is_some() && handshake.ack.is_none() => {
+                handshake.ack = Some(timestamp)
+            }
+            _ => {}
+        }
+    }
+
+    /// Moves the connection to its next state after a segment with the given flags.
+    fn update(&mut self, direction: Direction, flags: TcpFlags, sequence_number: u32, first: bool) {
+        let syn = flags.contains(TcpFlags::SYN);
+        let ack = flags.contains(TcpFlags::ACK);
+        if first && syn {
+            self.handshake_seen = true;
+            self.state = if ack { TcpState::SynReceived } else { TcpState::SynSent };
+        }
+        if syn && !ack && direction == Direction::ClientToServer {
+            self.client_isn = Some(sequence_number);
+        }
+        if self.state == TcpState::Reset {
+            return;
+        }
+        if flags.contains(TcpFlags::RST) {
+            self.state = TcpState::Reset;
+            return;
+        }
+        match (self.state, direction) {
+            (TcpState::SynSent, Direction::ServerToClient) if syn && ack => self.state = TcpState::SynReceived,
+            (TcpState::SynReceived, Direction::ClientToServer) if ack && !syn => self.state = TcpState::Established,
+            _ => {}
+        }
+        if flags.contains(TcpFlags::FIN) {
+            match direction {
+                Direction::ClientToServer => self.client_fin = true,
+                Direction::ServerToClient => self.server_fin = true,
+            }
+            // A FIN before the handshake completed still tears the connection down.
+            self.state = if self.client_fin && self.server_fin { TcpState::Closed } else { TcpState::HalfClosed };
+        }
+    }
+}
+
+impl fmt::Display for TcpConnection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let handshake = if self.handshake_seen { "" } else { " (no handshake captured)" };
+        writeln!(f, "TCP: Stream {}        = {} <-> {}, {}{}", self.index, self.client, self.server, self.state,
+                 handshake)?;
+        writeln!(f, "TCP:   Start         = {}.{:09} seconds", self.start.as_secs(), self.start.subsec_nanos())?;
+        writeln!(f, "TCP:   End           = {}.{:09} seconds", self.end.as_secs(), self.end.subsec_nanos())?;
+        writeln!(f, "TCP:   Duration      = {}.{:09} seconds", self.duration().as_secs(),
+                 self.duration().subsec_nanos())?;
+        writeln!(f, "TCP:   Client to server= {} packets, {} bytes", self.client_to_server.packets,
+                 self.client_to_server.bytes)?;
+        writeln!(f, "TCP:   Server to client= {} packets, {} bytes", self.server_to_client.packets,
+                 self.server_to_client.bytes)?;
+        write!(f, "TCP:   Analysis      = {}", self.analysis)?;
+        if let (Some(total), Some(server), Some(client)) =
+            (self.handshake.total(), self.handshake.server_latency(), self.handshake.client_latency()) {
+            write!(f, "\nTCP:   Handshake     = {} (SYN to SYN/ACK {}, SYN/ACK to ACK {})", milliseconds(total),
+                   milliseconds(server), milliseconds(client))?;
+        }
+        if let Some(summary) = self.rtt_summary() {
+            write!(f, "\nTCP:   RTT           = {}", summary)?;
+        }
+        Ok(())
+    }
+}
+
+/// Links the TCP segments of a capture into connections, follows the state of each, analyses
+/// each segment for retransmissions, duplicate ACKs, window problems and lost segments, and
+/// measures handshake latency and round-trip times.
+///
+/// Connections are numbered in the order they are first seen, in the same way as the streams of
+/// `TcpStreamReassembler`.
+pub struct TcpConnectionTracker {
+    connections: FlowTable<TcpConnection>,
+}
+
+impl Default for TcpConnectionTracker {
+    fn default() -> Self {
+        TcpConnectionTracker::new()
+    }
+}
+
+impl TcpConnectionTracker {
+    pub fn new() -> TcpConnectionTracker {
+        TcpConnectionTracker { connections: FlowTable::new() }
+    }
+
+    /// Adds the frame's TCP segment to its connection and sets the segment's stream index and
+    /// analysis, returning the index of the connection.
+    ///
+    /// # Arguments
+    /// * `frame` - A decoded frame; frames without a TCP segment are ignored.
+    /// * `timestamp` - The capture time of the frame.
+    pub fn process(&mut self, frame: &mut EthernetFrame, timestamp: Duration) -> Option<usize> {
+        let key = FlowKey::from_frame(frame)?;
+        let tcp = match frame.payload.datagram_mut()? {
+            ProtocolDatagram::TCP(tcp) => tcp,
+            _ => return None,
+        };
+        let sequence_number = u32::from_be_bytes(tcp.sequence_number);
+        let count = self.connections.len();
+        let (index, direction) = self.connections.find_or_start_tcp(key, tcp.flags, sequence_number,
+            |index, client| TcpConnection::new(index, client.source, client.destination, timestamp));
+
+        let connection = self.connections.get_mut(index)?;
+        connection.end = timestamp;
+        let counts = match direction {
+            Direction::ClientToServer => &mut connection.client_to_server,
+            Direction::ServerToClient => &mut connection.server_to_client,
+        };
+        counts.packets += 1;
+        counts.bytes += tcp.payload.len() as u64;
+        connection.update(direction, tcp.flags, sequence_number, index == count);
+        let (sender, receiver) = match direction {
+            Direction::ClientToServer => (&mut connection.client_sequence, &connection.server_sequence),
+            Direction::ServerToClient => (&mut connection.server_sequence, &connection.client_sequence),
+        };
+        tcp.analysis = analyze(sender, receiver, tcp, timestamp);
+        for analysis in &tcp.analysis {
+            connection.analysis.add(*analysis);
+        }
+        let retransmission =
+            tcp.analysis.iter().any(|analysis| matches!(analysis, TcpAnalysis::Retransmission | TcpAnalysis::FastRetransmission));
+        if let Some(sample) = connection.rtt.observe(direction, tcp, retransmission, timestamp) {
+            connection.rtt_samples.push(sample);
+        }
+        connection.record_handshake(direction, tcp.flags, timestamp);
+        tcp.stream_index = Some(index);
+        Some(index)
+    }
+
+    /// Returns the connection with the given index.
+    pub fn connection(&self, index: usize) -> Option<&TcpConnection> {
+        self.connections.get(index)
+    }
+
+    /// Returns the connections in the order they were first seen.
+    pub fn connections(&self) -> impl Iterator<Item = &TcpConnection> {
+        self.connections.iter()
+    }
+
+    /// Returns whether no TCP segment was seen.
+    pub fn is_empty(&self) -> bool {
+        self.connections.len() == 0
+    }
+}
+
+impl fmt::Display for TcpConnectionTracker {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "TCP: -----TCP Connection Report-----")?;
+        writeln!(f, "TCP:")?;
+        writeln!(f, "TCP: Connections       = {}", self.connections.len())?;
+        for connection in self.connections() {
+            writeln!(f, "{}", connection)?;
+        }
+        write!(f, "TCP:")
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{parse_packet, LinkType, TcpStreamReassembler};
+
+    /// Returns a frame carrying a TCP segment between 10.0.0.1 port 40000, the client, and
+    /// 10.0.0.2 port 80.
+    pub(crate) fn segment(direction: Direction, flags: TcpFlags, sequence_number: u32, acknowledgement_number: u32,
+                          window: u16, payload: &[u8]) -> EthernetFrame {
+        let (source, destination) = match direction {
+            Direction::ClientToServer => ([10, 0, 0, 1, 0x9c, 0x40], [10, 0, 0, 2, 0, 80]),
+            Direction::ServerToClient => ([10, 0, 0, 2, 0, 80], [10, 0, 0, 1, 0x9c, 0x40]),
+        };
+        let mut packet = vec![0x45, 0];
+        packet.extend_from_slice(&(40 + payload.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&[0, 0, 0x40, 0, 64, 6, 0, 0]);
+        packet.extend_from_slice(&source[..4]);
+        packet.extend_from_slice(&destination[..4]);
+        packet.extend_from_slice(&source[4..]);
+        packet.extend_from_slice(&destination[4..]);
+        packet.extend_from_slice(&sequence_number.to_be_bytes());
+        packet.extend_from_slice(&acknowledgement_number.to_be_bytes());
+        packet.extend_from_slice(&[0x50 | (flags.bits() >> 8) as u8, flags.bits() as u8]);
+        packet.extend_from_slice(&window.to_be_bytes());
+        packet.extend_from_slice(&[0, 0, 0, 0]);
+        packet.extend_from_slice(payload);
+        parse_packet(LinkType::Raw, &packet).unwrap()
+    }
+
+    /// Runs the tracker and the reassembler over the segments, returning the stream index each
+    /// gave every segment.
+    fn stream_indexes(segments: &mut [EthernetFrame]) -> Vec<(Option<usize>, Option<usize>)> {
+        let mut tracker = TcpConnectionTracker::new();
+        let mut reassembler = TcpStreamReassembler::new();
+        segments
+            .iter_mut()
+            .enumerate()
+            .map(|(number, frame)| {
+                (tracker.process(frame, Duration::ZERO), reassembler.process(frame, number + 1, Duration::ZERO))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tracker_and_reassembler_number_streams_alike() {
+        let client = Direction::ClientToServer;
+        let server = Direction::ServerToClient;
+        let syn_ack = TcpFlags::SYN | TcpFlags::ACK;
+        let mut segments = [
+            // Picked up at the SYN/ACK, then a SYN with ACK from the client side, which is not an
+            // opening SYN and so stays in the stream.
+            segment(server, syn_ack, 500, 101, 1000, b""),
+            segment(client, syn_ack, 100, 501, 1000, b""),
+            segment(client, TcpFlags::ACK, 101, 501, 1000, b"data"),
+            // No initial sequence number of the client is known, so a SYN does not start a stream.
+            segment(client, TcpFlags::SYN, 5000, 0, 1000, b""),
+            // The server opens a connection back to the client's port.
+            segment(server, TcpFlags::SYN, 3000, 0, 1000, b""),
+            segment(client, syn_ack, 8000, 3001, 1000, b""),
+            // The client reuses its port with a new initial sequence number.
+            segment(client, TcpFlags::SYN, 7000, 0, 1000, b""),
+            segment(server, TcpFlags::ACK, 3001, 7001, 1000, b""),
+        ];
+        for (tracker, reassembler) in stream_indexes(&mut segments) {
+            assert_eq!(tracker, reassembler);
+        }
+        let indexes: Vec<usize> = segments.iter().filter_map(|frame| match frame.payload.datagram() {
+            Some(ProtocolDatagram::TCP(tcp)) => tcp.stream_index,
+            _ => None,
+        }).collect();
+        assert_eq!(indexes, [0, 0, 0, 0, 1, 1, 2, 2]);
+    }
+
+    /// Runs a tracker over the segments, one millisecond apart.
+    fn track(segments: Vec<EthernetFrame>) -> TcpConnectionTracker {
+        let mut tracker = TcpConnectionTracker::new();
+        for (number, mut frame) in segments.into_iter().enumerate() {
+            tracker.process(&mut frame, Duration::from_millis(number as u64));
+        }
+        tracker
+    }
+
+    /// Returns the opening segments of a connection, up to `steps` of SYN, SYN/ACK and ACK.
+    fn handshake(steps: usize) -> Vec<EthernetFrame> {
+        let segments = vec![
+            segment(Direction::ClientToServer, TcpFlags::SYN, 100, 0, 1000, b""),
+            segment(Direction::ServerToClient, TcpFlags::SYN | TcpFlags::ACK, 500, 101, 1000, b""),
+            segment(Direction::ClientToServer, TcpFlags::ACK, 101, 501, 1000, b""),
+        ];
+        segments.into_iter().take(steps).collect()
+    }
+
+    #[test]
+    fn an_unanswered_syn_never_completed() {
+        let tracker = track(handshake(1));
+        let connection = tracker.connection(0).unwrap();
+        assert_eq!(connection.state, TcpState::SynSent);
+        assert_eq!(connection.state.to_string(), "never completed");
+        assert!(connection.handshake_seen);
+        assert_eq!(connection.client_isn, Some(100));
+        assert_eq!(connection.client.to_string(), "10.0.0.1:40000");
+    }
+
+    #[test]
+    fn a_missing_final_ack_is_half_open() {
+        let tracker = track(handshake(2));
+        let connection = tracker.connection(0).unwrap();
+        assert_eq!(connection.state, TcpState::SynReceived);
+        assert_eq!(connection.state.to_string(), "half-open");
+    }
+
+    #[test]
+    fn a_completed_handshake_is_established() {
+        let tracker = track(handshake(3));
+        let connection = tracker.connection(0).unwrap();
+        assert_eq!(connection.state, TcpState::Established);
+        assert!(!connection.to_string().contains("no handshake captured"));
+
+        // A connection picked up midway is taken as established.
+        let tracker = track(vec![segment(Direction::ServerToClient, TcpFlags::ACK, 900, 200, 1000, b"data")]);
+        let connection = tracker.connection(0).unwrap();
+        assert_eq!(connection.state, TcpState::Established);
+        assert!(!connection.handshake_seen);
+        assert_eq!(connection.client.to_string(), "10.0.0.2:80");
+        assert!(connection.to_string().contains("established (no handshake captured)"));
+    }
+
+    #[test]
+    fn fins_half_close_then_close() {
+        let mut segments = handshake(3);
+        segments.push(segment(Direction::ServerToClient, TcpFlags::FIN | TcpFlags::ACK, 501, 101, 1000, b""));
+        let tracker = track(segments);
+        let connection = tracker.connection(0).unwrap();
+        assert_eq!(connection.state, TcpState::HalfClosed);
+        assert!(connection.server_fin && !connection.client_fin);
+
+        let mut segments = handshake(3);
+        segments.push(segment(Direction::ServerToClient, TcpFlags::FIN | TcpFlags::ACK, 501, 101, 1000, b""));
+        segments.push(segment(Direction::ClientToServer, TcpFlags::FIN | TcpFlags::ACK, 101, 502, 1000, b""));
+        segments.push(segment(Direction::ServerToClient, TcpFlags::ACK, 502, 102, 1000, b""));
+        let tracker = track(segments);
+        assert_eq!(tracker.connection(0).unwrap().state, TcpState::Closed);
+    }
+
+    #[test]
+    fn a_fin_before_the_handshake_completed_still_closes() {
+        let mut segments = handshake(1);
+        segments.push(segment(Direction::ClientToServer, TcpFlags::FIN, 101, 0, 1000, b""));
+        let tracker = track(segments);
+        assert_eq!(tracker.connection(0).unwrap().state, TcpState::HalfClosed);
+
+        let mut segments = handshake(2);
+        segments.push(segment(Direction::ClientToServer, TcpFlags::FIN | TcpFlags::ACK, 101, 501, 1000, b""));
+        segments.push(segment(Direction::ServerToClient, TcpFlags::FIN | TcpFlags::ACK, 501, 102, 1000, b""));
+        let tracker = track(segments);
+        assert_eq!(tracker.connection(0).unwrap().state, TcpState::Closed);
+    }
+
+    #[test]
+    fn a_reset_is_final() {
+        let mut segments = handshake(3);
+        segments.push(segment(Direction::ServerToClient, TcpFlags::RST, 501, 0, 0, b""));
+        segments.push(segment(Direction::ClientToServer, TcpFlags::ACK, 101, 501, 1000, b"late"));
+        segments.push(segment(Direction::ClientToServer, TcpFlags::FIN | TcpFlags::ACK, 105, 501, 1000, b""));
+        let tracker = track(segments);
+        let connection = tracker.connection(0).unwrap();
+        assert_eq!(connection.state, TcpState::Reset);
+        // Segments after the RST are counted but no longer move the state.
+        assert!(!connection.client_fin);
+        assert_eq!(connection.counts(Direction::ClientToServer).packets, 4);
+
+        // A RST answering the SYN resets a connection that never opened.
+        let mut segments = handshake(1);
+        segments.push(segment(Direction::ServerToClient, TcpFlags::RST | TcpFlags::ACK, 0, 101, 0, b""));
+        let tracker = track(segments);
+        assert_eq!(tracker.connection(0).unwrap().state, TcpState::Reset);
+    }
+
+    #[test]
+    fn packets_and_bytes_are_counted_per_direction() {
+        let mut segments = handshake(3);
+        segments.push(segment(Direction::ClientToServer, TcpFlags::ACK | TcpFlags::PSH, 101, 501, 1000, b"GET /"));
+        // A retransmission counts again.
+        segments.push(segment(Direction::ClientToServer, TcpFlags::ACK | TcpFlags::PSH, 101, 501, 1000, b"GET /"));
+        segments.push(segment(Direction::ServerToClient, TcpFlags::ACK | TcpFlags::PSH, 501, 106, 1000, b"200 OK\r\n"));
+        let tracker = track(segments);
+        let connection = tracker.connection(0).unwrap();
+        assert_eq!(*connection.counts(Direction::ClientToServer), DirectionCounts { packets: 4, bytes: 10 });
+        assert_eq!(*connection.counts(Direction::ServerToClient), DirectionCounts { packets: 2, bytes: 8 });
+        assert_eq!(connection.duration(), Duration::from_millis(5));
+    }
+
+    #[test]
+    fn handshake_segments_are_timed() {
+        let tracker = track(handshake(2));
+        let handshake_times = tracker.connection(0).unwrap().handshake;
+        assert_eq!(handshake_times.server_latency(), Some(Duration::from_millis(1)));
+        assert_eq!(handshake_times.total(), None);
+
+        // A retransmitted SYN restarts the timing, and later ACKs do not move its end.
+        let mut segments = handshake(1);
+        segments.extend(handshake(3));
+        segments.push(segment(Direction::ClientToServer, TcpFlags::ACK, 101, 501, 1000, b"data"));
+        let tracker = track(segments);
+        let connection = tracker.connection(0).unwrap();
+        assert_eq!(connection.handshake.syn, Some(Duration::from_millis(1)));
+        assert_eq!(connection.handshake.server_latency(), Some(Duration::from_millis(1)));
+        assert_eq!(connection.handshake.client_latency(), Some(Duration::from_millis(1)));
+        let report = connection.to_string();
+        assert!(report.contains("Handshake     = 2.000 ms (SYN to SYN/ACK 1.000 ms, SYN/ACK to ACK 1.000 ms)"), "{}", report);
+    }
+}
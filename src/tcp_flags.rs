@@ -0,0 +1,157 @@
+use std::fmt;
+use std::ops::{BitAnd, BitOr};
+
+/// The nine control flags of a TCP header, from the NS bit of byte 12 and the whole of byte 13.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct TcpFlags(u16);
+
+impl TcpFlags {
+    pub const FIN: TcpFlags = TcpFlags(0x001);
+    pub const SYN: TcpFlags = TcpFlags(0x002);
+    pub const RST: TcpFlags = TcpFlags(0x004);
+    pub const PSH: TcpFlags = TcpFlags(0x008);
+    pub const ACK: TcpFlags = TcpFlags(0x010);
+    pub const URG: TcpFlags = TcpFlags(0x020);
+    pub const ECE: TcpFlags = TcpFlags(0x040);
+    pub const CWR: TcpFlags = TcpFlags(0x080);
+    pub const NS: TcpFlags = TcpFlags(0x100);
+
+    /// Every flag with its name, from the most significant bit down.
+    pub const ALL: [(TcpFlags, &'static str); 9] = [
+        (TcpFlags::NS, "NS"),
+        (TcpFlags::CWR, "CWR"),
+        (TcpFlags::ECE, "ECE"),
+        (TcpFlags::URG, "URG"),
+        (TcpFlags::ACK, "ACK"),
+        (TcpFlags::PSH, "PSH"),
+        (TcpFlags::RST, "RST"),
+        (TcpFlags::SYN, "SYN"),
+        (TcpFlags::FIN, "FIN"),
+    ];
+
+    /// Builds the flags from bytes 12 and 13 of a TCP header.
+    pub fn from_header(byte_12: u8, byte_13: u8) -> TcpFlags {
+        TcpFlags((((byte_12 & 1) as u16) << 8) | byte_13 as u16)
+    }
+
+    /// Returns the flags as a 9-bit value.
+    pub fn bits(&self) -> u16 {
+        self.0
+    }
+
+    /// Returns whether every flag of `other` is set.
+    pub fn contains(&self, other: TcpFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns whether any flag of `other` is set.
+    pub fn intersects(&self, other: TcpFlags) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    /// Returns the flag with the given name, as in `syn`, `SYN` or the tcpdump form `tcp-syn`.
+    pub fn from_name(name: &str) -> Option<TcpFlags> {
+        let name = name.to_ascii_uppercase();
+        let name = name.strip_prefix("TCP-").unwrap_or(&name);
+        let name = if name == "PUSH" { "PSH" } else { name };
+        TcpFlags::ALL.iter().find(|(_, flag_name)| *flag_name == name).map(|(flag, _)| *flag)
+    }
+
+    /// Draws the position of `flag` in the 12 bits following the data offset, Wireshark style:
+    /// `.... .... ..S.` when SYN is set, `.... .... ..0.` when it is not.
+    pub fn bit_pattern(&self, flag: TcpFlags, name: &str) -> String {
+        let symbol = if self.contains(flag) { name.chars().next().unwrap_or('1') } else { '0' };
+        let mut pattern = String::new();
+        for bit in (0..12).rev() {
+            pattern.push(if 1 << bit == flag.0 { symbol } else { '.' });
+            if bit % 4 == 0 && bit != 0 {
+                pattern.push(' ');
+            }
+        }
+        pattern
+    }
+}
+
+impl BitOr for TcpFlags {
+    type Output = TcpFlags;
+
+    fn bitor(self, other: TcpFlags) -> TcpFlags {
+        TcpFlags(self.0 | other.0)
+    }
+}
+
+impl BitAnd for TcpFlags {
+    type Output = TcpFlags;
+
+    fn bitand(self, other: TcpFlags) -> TcpFlags {
+        TcpFlags(self.0 & other.0)
+    }
+}
+
+impl fmt::Display for TcpFlags {
+    /// Formats the flags as a value followed by the names of the set flags, as in `0x012 (SYN, ACK)`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<&str> =
+            TcpFlags::ALL.iter().rev().filter(|(flag, _)| self.contains(*flag)).map(|(_, name)| *name).collect();
+        if names.is_empty() {
+            write!(f, "0x{:03x}", self.0)
+        } else {
+            write!(f, "0x{:03x} ({})", self.0, names.join(", "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ns_comes_from_the_low_bit_of_byte_12() {
+        // Byte 12 holds the data offset in its high nibble, which must not leak into the flags.
+        let flags = TcpFlags::from_header(0x51, 0x12);
+        assert_eq!(flags, TcpFlags::NS | TcpFlags::SYN | TcpFlags::ACK);
+        assert_eq!(flags.bits(), 0x112);
+        assert_eq!(TcpFlags::from_header(0xfe, 0xff).bits(), 0x0ff);
+        assert_eq!(TcpFlags::from_header(0x50, 0x00), TcpFlags::default());
+    }
+
+    #[test]
+    fn contains_needs_every_flag_and_intersects_any() {
+        let flags = TcpFlags::SYN | TcpFlags::ACK;
+        assert!(flags.contains(TcpFlags::SYN));
+        assert!(!flags.contains(TcpFlags::SYN | TcpFlags::FIN));
+        assert!(flags.intersects(TcpFlags::SYN | TcpFlags::FIN));
+        assert!(!flags.intersects(TcpFlags::RST | TcpFlags::FIN));
+        assert_eq!(flags & TcpFlags::ACK, TcpFlags::ACK);
+    }
+
+    #[test]
+    fn bit_patterns_mark_the_position_of_one_flag() {
+        let flags = TcpFlags::NS | TcpFlags::SYN;
+        assert_eq!(flags.bit_pattern(TcpFlags::SYN, "SYN"), ".... .... ..S.");
+        assert_eq!(flags.bit_pattern(TcpFlags::ACK, "ACK"), ".... ...0 ....");
+        assert_eq!(flags.bit_pattern(TcpFlags::NS, "NS"), "...N .... ....");
+        assert_eq!(flags.bit_pattern(TcpFlags::FIN, "FIN"), ".... .... ...0");
+        assert_eq!(TcpFlags::CWR.bit_pattern(TcpFlags::CWR, "CWR"), ".... C... ....");
+    }
+
+    #[test]
+    fn names_are_read_in_any_case_and_in_tcpdump_form() {
+        assert_eq!(TcpFlags::from_name("syn"), Some(TcpFlags::SYN));
+        assert_eq!(TcpFlags::from_name("RST"), Some(TcpFlags::RST));
+        assert_eq!(TcpFlags::from_name("tcp-syn"), Some(TcpFlags::SYN));
+        assert_eq!(TcpFlags::from_name("tcp-rst"), Some(TcpFlags::RST));
+        assert_eq!(TcpFlags::from_name("tcp-push"), Some(TcpFlags::PSH));
+        assert_eq!(TcpFlags::from_name("TCP-ECE"), Some(TcpFlags::ECE));
+        assert_eq!(TcpFlags::from_name("ns"), Some(TcpFlags::NS));
+        assert_eq!(TcpFlags::from_name("tcp-"), None);
+        assert_eq!(TcpFlags::from_name("udp-syn"), None);
+    }
+
+    #[test]
+    fn display_lists_the_set_flags_from_fin_up() {
+        assert_eq!((TcpFlags::SYN | TcpFlags::ACK).to_string(), "0x012 (SYN, ACK)");
+        assert_eq!(TcpFlags::from_header(0x01, 0x11).to_string(), "0x111 (FIN, ACK, NS)");
+        assert_eq!(TcpFlags::default().to_string(), "0x000");
+    }
+}
@@ -0,0 +1,152 @@
+use std::fmt;
+
+use crate::parser::array;
+
+/// A TCP option, decoded according to its kind.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TcpOption {
+    /// End of Option List (kind 0). The padding that follows it is not kept.
+    EndOfOptionList,
+    /// No-Operation (kind 1), used to align the options that follow.
+    NoOperation,
+    /// Maximum Segment Size (kind 2).
+    MaximumSegmentSize(u16),
+    /// Window Scale (kind 3), the shift count applied to the window.
+    WindowScale(u8),
+    /// SACK Permitted (kind 4).
+    SackPermitted,
+    /// Selective Acknowledgement (kind 5), with the left and right edge of each block.
+    Sack(Vec<(u32, u32)>),
+    /// Timestamps (kind 8).
+    Timestamps { value: u32, echo_reply: u32 },
+    /// TCP Fast Open (kind 34, or the experimental kind 254 with magic 0xf989), with the cookie,
+    /// which is empty in a cookie request.
+    FastOpen(Vec<u8>),
+    /// Multipath TCP (kind 30), with the subtype and the option bytes following the length, which
+    /// start with the subtype.
+    Mptcp { subtype: u8, data: Vec<u8> },
+    Unknown { kind: u8, data: Vec<u8> },
+    /// An option with a length below 2 or one that runs past the end of the options, with the
+    /// bytes from its kind to the end of the options, which are left undecoded.
+    Malformed { kind: u8, data: Vec<u8> },
+}
+
+impl TcpOption {
+    /// Parses the options between the fixed TCP header and the end of the header.
+    ///
+    /// A malformed option ends the list, kept as `TcpOption::Malformed` with the bytes that
+    /// follow, so the segment itself is still decoded.
+    pub fn parse_all(data: &[u8]) -> Vec<TcpOption> {
+        let mut options = vec![];
+        let mut current = 0;
+        while current < data.len() {
+            let kind = data[current];
+            match kind {
+                0 => {
+                    options.push(TcpOption::EndOfOptionList);
+                    break;
+                }
+                1 => {
+                    options.push(TcpOption::NoOperation);
+                    current += 1;
+                    continue;
+                }
+                _ => {}
+            }
+            let length = data.get(current + 1).map_or(0, |length| *length as usize);
+            if length < 2 || current + length > data.len() {
+                options.push(TcpOption::Malformed { kind, data: data[current..].to_vec() });
+                break;
+            }
+            let body = &data[current + 2..current + length];
+            options.push(match (kind, body.len()) {
+                (2, 2) => TcpOption::MaximumSegmentSize(u16::from_be_bytes(array(body, 0))),
+                (3, 1) => TcpOption::WindowScale(body[0]),
+                (4, 0) => TcpOption::SackPermitted,
+                (5, length) if length % 8 == 0 => TcpOption::Sack(
+                    body.chunks_exact(8)
+                        .map(|block| (u32::from_be_bytes(array(block, 0)), u32::from_be_bytes(array(block, 4))))
+                        .collect(),
+                ),
+                (8, 8) => TcpOption::Timestamps {
+                    value: u32::from_be_bytes(array(body, 0)),
+                    echo_reply: u32::from_be_bytes(array(body, 4)),
+                },
+                (34, _) => TcpOption::FastOpen(body.to_vec()),
+                (254, length) if length >= 2 && body[0..2] == [0xf9, 0x89] => TcpOption::FastOpen(body[2..].to_vec()),
+                (30, length) if length >= 1 => TcpOption::Mptcp { subtype: body[0] >> 4, data: body.to_vec() },
+                _ => TcpOption::Unknown { kind, data: body.to_vec() },
+            });
+            current += length;
+        }
+        options
+    }
+}
+
+impl fmt::Display for TcpOption {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TcpOption::EndOfOptionList => write!(f, "End of Option List"),
+            TcpOption::NoOperation => write!(f, "No-Operation"),
+            TcpOption::MaximumSegmentSize(mss) => write!(f, "Maximum segment size = {} bytes", mss),
+            TcpOption::WindowScale(shift) => {
+                write!(f, "Window scale = {} (multiply by {})", shift, 1_u32.checked_shl(*shift as u32).unwrap_or(0))
+            }
+            TcpOption::SackPermitted => write!(f, "SACK permitted"),
+            TcpOption::Sack(blocks) => {
+                let blocks: Vec<String> = blocks.iter().map(|(left, right)| format!("{}-{}", left, right)).collect();
+                write!(f, "SACK = {}", blocks.join(", "))
+            }
+            TcpOption::Timestamps { value, echo_reply } => {
+                write!(f, "Timestamps = value {}, echo reply {}", value, echo_reply)
+            }
+            TcpOption::FastOpen(cookie) if cookie.is_empty() => write!(f, "TCP Fast Open = cookie request"),
+            TcpOption::FastOpen(cookie) => {
+                let cookie: String = cookie.iter().map(|b| format!("{:02x}", b)).collect();
+                write!(f, "TCP Fast Open = cookie {}", cookie)
+            }
+            TcpOption::Mptcp { subtype, data } => {
+                let name = match subtype {
+                    0 => "MP_CAPABLE",
+                    1 => "MP_JOIN",
+                    2 => "DSS",
+                    3 => "ADD_ADDR",
+                    4 => "REMOVE_ADDR",
+                    5 => "MP_PRIO",
+                    6 => "MP_FAIL",
+                    7 => "MP_FASTCLOSE",
+                    8 => "MP_TCPRST",
+                    _ => "unknown",
+                };
+                write!(f, "Multipath TCP = {} ({}), {} bytes", subtype, name, data.len())
+            }
+            TcpOption::Unknown { kind, data } => write!(f, "Option kind {} = {} bytes", kind, data.len()),
+            TcpOption::Malformed { kind, data } => {
+                write!(f, "Malformed option kind {} = {} bytes left undecoded", kind, data.len())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn malformed_option_ends_the_list() {
+        // MSS, then a SACK Permitted whose length of 1 is below the minimum.
+        let options = TcpOption::parse_all(&[2, 4, 0x05, 0xb4, 4, 1, 0, 0]);
+        assert_eq!(options, vec![
+            TcpOption::MaximumSegmentSize(1460),
+            TcpOption::Malformed { kind: 4, data: vec![4, 1, 0, 0] },
+        ]);
+    }
+
+    #[test]
+    fn option_running_past_the_header_is_malformed() {
+        let options = TcpOption::parse_all(&[1, 8, 10, 0]);
+        assert_eq!(options, vec![TcpOption::NoOperation, TcpOption::Malformed { kind: 8, data: vec![8, 10, 0] }]);
+        let options = TcpOption::parse_all(&[1, 1, 1, 3]);
+        assert_eq!(options.last(), Some(&TcpOption::Malformed { kind: 3, data: vec![3] }));
+    }
+}
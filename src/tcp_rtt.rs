@@ -0,0 +1,315 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::Duration;
+
+use crate::tcp_analysis::before;
+use crate::{Direction, TcpFlags, TcpOption, TCPPacket};
+
+/// Capture times of the three segments of a connection's handshake.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct HandshakeTimes {
+    /// The client's SYN; the last one when the SYN was retransmitted.
+    pub syn: Option<Duration>,
+    /// The server's first SYN/ACK.
+    pub syn_ack: Option<Duration>,
+    /// The client's ACK of the SYN/ACK.
+    pub ack: Option<Duration>,
+}
+
+impl HandshakeTimes {
+    /// Returns the time from the SYN to the SYN/ACK, the round trip between the capture point and
+    /// the server.
+    pub fn server_latency(&self) -> Option<Duration> {
+        Some(self.syn_ack?.saturating_sub(self.syn?))
+    }
+
+    /// Returns the time from the SYN/ACK to the ACK, the round trip between the capture point and
+    /// the client.
+    pub fn client_latency(&self) -> Option<Duration> {
+        Some(self.ack?.saturating_sub(self.syn_ack?))
+    }
+
+    /// Returns the time from the SYN to the ACK that completes the handshake.
+    pub fn total(&self) -> Option<Duration> {
+        Some(self.ack?.saturating_sub(self.syn?))
+    }
+}
+
+/// The time taken for a segment to be acknowledged.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RttSample {
+    /// Capture time of the acknowledging segment.
+    pub timestamp: Duration,
+    pub rtt: Duration,
+    /// Direction of the acknowledged segment.
+    pub direction: Direction,
+}
+
+/// Minimum, average, 95th percentile and maximum of a set of round-trip times.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RttSummary {
+    pub samples: usize,
+    pub min: Duration,
+    pub average: Duration,
+    /// The 95th percentile, by the nearest-rank method.
+    pub p95: Duration,
+    pub max: Duration,
+}
+
+impl RttSummary {
+    /// Summarises the given samples, or returns `None` when there are none.
+    pub fn from_samples(samples: &[RttSample]) -> Option<RttSummary> {
+        let mut rtts: Vec<Duration> = samples.iter().map(|sample| sample.rtt).collect();
+        rtts.sort();
+        let count = rtts.len();
+        let total: Duration = rtts.iter().sum();
+        Some(RttSummary {
+            samples: count,
+            min: *rtts.first()?,
+            average: total / count as u32,
+            p95: rtts[(count * 95).div_ceil(100) - 1],
+            max: rtts[count - 1],
+        })
+    }
+}
+
+/// Formats a duration in milliseconds.
+pub(crate) fn milliseconds(duration: Duration) -> String {
+    format!("{:.3} ms", duration.as_secs_f64() * 1000.0)
+}
+
+impl fmt::Display for RttSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} samples, min {}, avg {}, p95 {}, max {}", self.samples, milliseconds(self.min),
+               milliseconds(self.average), milliseconds(self.p95), milliseconds(self.max))
+    }
+}
+
+/// A segment sent in one direction that has not been acknowledged yet.
+#[derive(Clone, Debug)]
+struct Outstanding {
+    /// Sequence number following the segment, which the acknowledging segment carries.
+    end: u32,
+    sent: Duration,
+    /// Whether the segment was sent more than once, which makes its round-trip time ambiguous.
+    retransmitted: bool,
+}
+
+/// What is waiting to be acknowledged in one direction of a connection.
+#[derive(Clone, Debug, Default)]
+struct SenderState {
+    outstanding: VecDeque<Outstanding>,
+    /// TSval of the segments sent, with the time each value was first sent.
+    timestamp_values: VecDeque<(u32, Duration)>,
+    /// Highest acknowledgement number received for this direction.
+    highest_ack: Option<u32>,
+}
+
+/// Round-trip time measurement for both directions of a connection.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RttState {
+    client: SenderState,
+    server: SenderState,
+}
+
+impl RttState {
+    /// Records a segment and returns a round-trip time sample if it acknowledges earlier data.
+    ///
+    /// The sample is taken from the TSecr of the segment when it echoes a TSval seen earlier, and
+    /// otherwise from the segment with the highest sequence number the acknowledgement covers. Per
+    /// Karn's algorithm, no sample is taken from a retransmitted segment.
+    ///
+    /// # Arguments
+    /// * `direction` - The direction of the segment.
+    /// * `tcp` - The segment.
+    /// * `retransmission` - Whether the segment resends data that was already sent.
+    /// * `timestamp` - The capture time of the segment.
+    pub(crate) fn observe(&mut self, direction: Direction, tcp: &TCPPacket, retransmission: bool, timestamp: Duration)
+                          -> Option<RttSample> {
+        let (sender, receiver) = match direction {
+            Direction::ClientToServer => (&mut self.client, &mut self.server),
+            Direction::ServerToClient => (&mut self.server, &mut self.client),
+        };
+        let sequence_number = u32::from_be_bytes(tcp.sequence_number);
+        let length = tcp.payload.len() as u32 + tcp.flags.contains(TcpFlags::SYN) as u32
+            + tcp.flags.contains(TcpFlags::FIN) as u32;
+        let end = sequence_number.wrapping_add(length);
+        let timestamps = tcp.options.iter().find_map(|option| match option {
+            TcpOption::Timestamps { value, echo_reply } => Some((*value, *echo_reply)),
+            _ => None,
+        });
+
+        if length > 0 {
+            if retransmission {
+                for segment in sender.outstanding.iter_mut() {
+                    if before(sequence_number, segment.end) && !before(end, segment.end) {
+                        segment.retransmitted = true;
+                    }
+                }
+            } else {
+                sender.outstanding.push_back(Outstanding { end, sent: timestamp, retransmitted: false });
+            }
+        }
+        if let Some((value, _)) = timestamps {
+            if sender.timestamp_values.back().is_none_or(|(last, _)| *last != value) {
+                sender.timestamp_values.push_back((value, timestamp));
+            }
+        }
+
+        if !tcp.flags.contains(TcpFlags::ACK) {
+            return None;
+        }
+        let ack = u32::from_be_bytes(tcp.acknowledgement_number);
+        if receiver.highest_ack.is_some_and(|highest| !before(highest, ack)) {
+            return None;
+        }
+        receiver.highest_ack = Some(ack);
+
+        let mut acknowledged = None;
+        while let Some(segment) = receiver.outstanding.front() {
+            if before(ack, segment.end) {
+                break;
+            }
+            acknowledged = receiver.outstanding.pop_front();
+        }
+        let echoed = timestamps.and_then(|(_, echo_reply)| {
+            let position = receiver.timestamp_values.iter().position(|(value, _)| *value == echo_reply)?;
+            let sent = receiver.timestamp_values[position].1;
+            receiver.timestamp_values.drain(..=position);
+            Some(sent)
+        });
+        let sent = match (echoed, acknowledged) {
+            (Some(sent), _) => sent,
+            (None, Some(segment)) if !segment.retransmitted => segment.sent,
+            _ => return None,
+        };
+        let direction = match direction {
+            Direction::ClientToServer => Direction::ServerToClient,
+            Direction::ServerToClient => Direction::ClientToServer,
+        };
+        Some(RttSample { timestamp, rtt: timestamp.saturating_sub(sent), direction })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLIENT: Direction = Direction::ClientToServer;
+    const SERVER: Direction = Direction::ServerToClient;
+
+    fn ms(milliseconds: u64) -> Duration {
+        Duration::from_millis(milliseconds)
+    }
+
+    /// Builds a segment with `length` bytes of payload and, if given, a TSval and TSecr.
+    fn segment(flags: TcpFlags, sequence_number: u32, acknowledgement_number: u32, length: usize,
+               timestamps: Option<(u32, u32)>) -> TCPPacket {
+        let mut tcp = TCPPacket::new();
+        tcp.flags = flags;
+        tcp.sequence_number = sequence_number.to_be_bytes();
+        tcp.acknowledgement_number = acknowledgement_number.to_be_bytes();
+        tcp.payload = vec![0; length];
+        if let Some((value, echo_reply)) = timestamps {
+            tcp.options.push(TcpOption::Timestamps { value, echo_reply });
+        }
+        tcp
+    }
+
+    fn summary(rtts: impl Iterator<Item = u64>) -> RttSummary {
+        let samples: Vec<RttSample> =
+            rtts.map(|rtt| RttSample { timestamp: ms(0), rtt: ms(rtt), direction: CLIENT }).collect();
+        RttSummary::from_samples(&samples).unwrap()
+    }
+
+    #[test]
+    fn p95_is_taken_by_nearest_rank() {
+        assert_eq!(RttSummary::from_samples(&[]), None);
+
+        let one = summary([7].into_iter());
+        assert_eq!((one.samples, one.min, one.average, one.p95, one.max), (1, ms(7), ms(7), ms(7), ms(7)));
+
+        // Samples are sorted first, so the order they were taken in does not matter.
+        let twenty = summary((1..=20).rev());
+        assert_eq!((twenty.min, twenty.p95, twenty.max), (ms(1), ms(19), ms(20)));
+        assert_eq!(twenty.average, Duration::from_micros(10_500));
+
+        let hundred = summary(1..=100);
+        assert_eq!((hundred.samples, hundred.p95, hundred.max), (100, ms(95), ms(100)));
+        assert_eq!(hundred.to_string(), "100 samples, min 1.000 ms, avg 50.500 ms, p95 95.000 ms, max 100.000 ms");
+    }
+
+    #[test]
+    fn a_cumulative_ack_times_the_last_segment_it_covers() {
+        let mut state = RttState::default();
+        assert_eq!(state.observe(CLIENT, &segment(TcpFlags::ACK, 1, 1, 100, None), false, ms(0)), None);
+        assert_eq!(state.observe(CLIENT, &segment(TcpFlags::ACK, 101, 1, 100, None), false, ms(5)), None);
+        let sample = state.observe(SERVER, &segment(TcpFlags::ACK, 1, 201, 0, None), false, ms(20)).unwrap();
+        assert_eq!(sample, RttSample { timestamp: ms(20), rtt: ms(15), direction: CLIENT });
+    }
+
+    #[test]
+    fn acks_that_do_not_advance_are_skipped() {
+        let mut state = RttState::default();
+        state.observe(CLIENT, &segment(TcpFlags::ACK, 1, 1, 100, None), false, ms(0));
+        assert!(state.observe(SERVER, &segment(TcpFlags::ACK, 1, 101, 0, None), false, ms(10)).is_some());
+        state.observe(CLIENT, &segment(TcpFlags::ACK, 101, 1, 100, None), false, ms(20));
+        // A duplicate and an older ACK take no sample, and leave the new segment outstanding.
+        assert_eq!(state.observe(SERVER, &segment(TcpFlags::ACK, 1, 101, 0, None), false, ms(25)), None);
+        assert_eq!(state.observe(SERVER, &segment(TcpFlags::ACK, 1, 51, 0, None), false, ms(26)), None);
+        let sample = state.observe(SERVER, &segment(TcpFlags::ACK, 1, 201, 0, None), false, ms(30)).unwrap();
+        assert_eq!(sample.rtt, ms(10));
+    }
+
+    #[test]
+    fn retransmitted_segments_are_not_timed() {
+        let mut state = RttState::default();
+        state.observe(CLIENT, &segment(TcpFlags::ACK, 1, 1, 100, None), false, ms(0));
+        state.observe(CLIENT, &segment(TcpFlags::ACK, 1, 1, 100, None), true, ms(100));
+        assert_eq!(state.observe(SERVER, &segment(TcpFlags::ACK, 1, 101, 0, None), false, ms(150)), None);
+
+        // The next segment was sent once, so its ACK is timed again.
+        state.observe(CLIENT, &segment(TcpFlags::ACK, 101, 1, 100, None), false, ms(200));
+        let sample = state.observe(SERVER, &segment(TcpFlags::ACK, 1, 201, 0, None), false, ms(240)).unwrap();
+        assert_eq!(sample.rtt, ms(40));
+    }
+
+    #[test]
+    fn timestamps_time_the_ack_of_a_retransmission() {
+        let mut state = RttState::default();
+        state.observe(CLIENT, &segment(TcpFlags::ACK, 1, 1, 100, Some((1000, 0))), false, ms(0));
+        state.observe(CLIENT, &segment(TcpFlags::ACK, 1, 1, 100, Some((1010, 0))), true, ms(100));
+        // The echoed TSval tells which copy the ACK answers.
+        let sample = state.observe(SERVER, &segment(TcpFlags::ACK, 1, 101, 0, Some((5000, 1010))), false, ms(120));
+        assert_eq!(sample.map(|sample| sample.rtt), Some(ms(20)));
+    }
+
+    #[test]
+    fn echoed_timestamps_are_matched_once() {
+        let mut state = RttState::default();
+        state.observe(CLIENT, &segment(TcpFlags::ACK, 1, 1, 100, Some((1000, 0))), false, ms(0));
+        // A repeated TSval keeps the time it was first sent.
+        state.observe(CLIENT, &segment(TcpFlags::ACK, 101, 1, 100, Some((1000, 0))), false, ms(5));
+        state.observe(CLIENT, &segment(TcpFlags::ACK, 201, 1, 100, Some((1001, 0))), false, ms(10));
+        let sample = state.observe(SERVER, &segment(TcpFlags::ACK, 1, 101, 0, Some((5000, 1000))), false, ms(30));
+        assert_eq!(sample.map(|sample| sample.rtt), Some(ms(30)));
+        let sample = state.observe(SERVER, &segment(TcpFlags::ACK, 1, 301, 0, Some((5001, 1001))), false, ms(35));
+        assert_eq!(sample.map(|sample| sample.rtt), Some(ms(25)));
+
+        // Matching 1001 dropped 1000 too, so echoing it again falls back to the acknowledged segment.
+        state.observe(CLIENT, &segment(TcpFlags::ACK, 301, 1, 100, Some((1002, 0))), false, ms(40));
+        let sample = state.observe(SERVER, &segment(TcpFlags::ACK, 1, 401, 0, Some((5002, 1000))), false, ms(48));
+        assert_eq!(sample.map(|sample| sample.rtt), Some(ms(8)));
+    }
+
+    #[test]
+    fn handshake_times_need_both_ends() {
+        let mut handshake = HandshakeTimes { syn: Some(ms(100)), ..HandshakeTimes::default() };
+        assert_eq!((handshake.server_latency(), handshake.client_latency(), handshake.total()), (None, None, None));
+        handshake.syn_ack = Some(ms(130));
+        handshake.ack = Some(ms(135));
+        assert_eq!(handshake.server_latency(), Some(ms(30)));
+        assert_eq!(handshake.client_latency(), Some(ms(5)));
+        assert_eq!(handshake.total(), Some(ms(35)));
+    }
+}
@@ -0,0 +1,358 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use crate::flow::FlowTable;
+use crate::{Direction, EthernetFrame, FlowKey, IPProtocol, ProtocolDatagram, TcpFlags};
+
+/// The bytes sent in one direction of a TCP stream, put back in sequence order.
+///
+/// Sequence numbers are taken relative to the first byte of data, the one following the SYN when
+/// the SYN was captured, and are unwrapped so that streams longer than 4 GiB stay in order. Bytes
+/// received more than once keep their first copy.
+pub struct StreamData {
+    /// Sequence number of the first byte of data.
+    base: Option<u32>,
+    syn_seen: bool,
+    /// Bytes delivered in order so far.
+    data: Vec<u8>,
+    /// Segments received ahead of a gap, by offset from the first byte.
+    pending: BTreeMap<u64, Vec<u8>>,
+    /// Number of bytes received again after they had already been delivered or buffered.
+    pub retransmitted_bytes: u64,
+    /// Whether a FIN was sent in this direction.
+    pub fin: bool,
+}
+
+impl Default for StreamData {
+    fn default() -> Self {
+        StreamData::new()
+    }
+}
+
+impl StreamData {
+    pub fn new() -> StreamData {
+        StreamData { base: None, syn_seen: false, data: vec![], pending: BTreeMap::new(), retransmitted_bytes: 0, fin: false }
+    }
+
+    /// Returns the initial sequence number of this direction, if its SYN was seen.
+    pub fn initial_sequence_number(&self) -> Option<u32> {
+        self.base.filter(|_| self.syn_seen).map(|base| base.wrapping_sub(1))
+    }
+
+    /// Returns the bytes delivered in order so far.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns the number of bytes that never arrived before the segments still waiting for them.
+    pub fn missing_bytes(&self) -> u64 {
+        let mut position = self.data.len() as u64;
+        let mut missing = 0;
+        for (offset, segment) in &self.pending {
+            missing += offset.saturating_sub(position);
+            position = position.max(offset + segment.len() as u64);
+        }
+        missing
+    }
+
+    /// Returns the number of bytes received after a gap, which cannot be delivered until the gap
+    /// is filled.
+    pub fn pending_bytes(&self) -> u64 {
+        self.pending.values().map(|segment| segment.len() as u64).sum()
+    }
+
+    /// Adds the payload of a segment, returning how many bytes it made available in order.
+    ///
+    /// # Arguments
+    /// * `sequence_number` - The sequence number of the segment.
+    /// * `syn` - Whether the segment has the SYN flag, which takes up one sequence number.
+    /// * `payload` - The data carried by the segment.
+    fn add(&mut self, sequence_number: u32, syn: bool, payload: &[u8]) -> usize {
+        let start_sequence = if syn { sequence_number.wrapping_add(1) } else { sequence_number };
+        if syn && !self.syn_seen {
+            self.syn_seen = true;
+            self.base = Some(start_sequence);
+        }
+        if payload.is_empty() {
+            return 0;
+        }
+        let base = *self.base.get_or_insert(start_sequence);
+
+        // Place the segment within 2^31 bytes of the next expected byte, across wraparounds.
+        let next = self.data.len() as u64;
+        let delta = start_sequence.wrapping_sub(base).wrapping_sub(next as u32) as i32 as i64;
+        let start = next as i64 + delta;
+        // Data from before the first captured byte cannot be placed.
+        let (start, payload) = if start < 0 {
+            let skip = start.unsigned_abs() as usize;
+            if skip >= payload.len() {
+                return 0;
+            }
+            (0, &payload[skip..])
+        } else {
+            (start as u64, payload)
+        };
+
+        if start > next {
+            match self.pending.get(&start) {
+                Some(segment) if segment.len() >= payload.len() => self.retransmitted_bytes += payload.len() as u64,
+                _ => {
+                    self.pending.insert(start, payload.to_vec());
+                }
+            }
+            return 0;
+        }
+        let before = self.data.len();
+        self.append(start, payload);
+        while let Some(entry) = self.pending.first_entry() {
+            if *entry.key() > self.data.len() as u64 {
+                break;
+            }
+            let start = *entry.key();
+            let segment = entry.remove();
+            self.append(start, &segment);
+        }
+        self.data.len() - before
+    }
+
+    /// Appends the part of a segment starting at or before the next expected byte that has not
+    /// been delivered yet.
+    fn append(&mut self, start: u64, segment: &[u8]) {
+        let overlap = (self.data.len() as u64 - start).min(segment.len() as u64) as usize;
+        self.retransmitted_bytes += overlap as u64;
+        self.data.extend_from_slice(&segment[overlap..]);
+    }
+}
+
+/// A run of bytes delivered in order to one direction of a stream.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct StreamChunk {
+    pub direction: Direction,
+    /// Offset of the run in the data of its direction.
+    pub offset: usize,
+    pub length: usize,
+    /// Number of the packet, counting from 1, whose segment made the run available.
+    pub packet_number: usize,
+    pub timestamp: Duration,
+}
+
+/// Both directions of a TCP connection.
+pub struct TcpStream {
+    /// Position of the stream in the order streams were first seen, counting from 0.
+    pub index: usize,
+    /// The endpoint that opened the connection, or that sent the first captured segment.
+    pub client: SocketAddr,
+    pub server: SocketAddr,
+    /// Bytes sent by the client.
+    pub client_data: StreamData,
+    /// Bytes sent by the server.
+    pub server_data: StreamData,
+    /// The runs of data of both directions, in the order they became available.
+    pub chunks: Vec<StreamChunk>,
+}
+
+impl TcpStream {
+    pub fn new(index: usize, client: SocketAddr, server: SocketAddr) -> TcpStream {
+        TcpStream { index, client, server, client_data: StreamData::new(), server_data: StreamData::new(), chunks: vec![] }
+    }
+
+    /// Returns the bytes sent in the given direction.
+    pub fn data(&self, direction: Direction) -> &StreamData {
+        match direction {
+            Direction::ClientToServer => &self.client_data,
+            Direction::ServerToClient => &self.server_data,
+        }
+    }
+
+    /// Returns the bytes of a chunk of this stream.
+    pub fn chunk_data(&self, chunk: &StreamChunk) -> &[u8] {
+        &self.data(chunk.direction).data()[chunk.offset..chunk.offset + chunk.length]
+    }
+}
+
+impl fmt::Display for TcpStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "TCP stream {}: {} <-> {}, {} bytes from client, {} bytes from server", self.index, self.client,
+               self.server, self.client_data.data().len(), self.server_data.data().len())?;
+        let missing = self.client_data.missing_bytes() + self.server_data.missing_bytes();
+        if missing > 0 {
+            write!(f, " [{} bytes missing]", missing)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reassembles the byte streams of the TCP connections in a capture.
+///
+/// Streams are numbered in the order they are first seen. A SYN with a new initial sequence
+/// number between the endpoints of an earlier stream starts a new stream, as happens when a client
+/// reuses its port, so the numbering matches that of `TcpConnectionTracker`.
+pub struct TcpStreamReassembler {
+    streams: FlowTable<TcpStream>,
+}
+
+impl Default for TcpStreamReassembler {
+    fn default() -> Self {
+        TcpStreamReassembler::new()
+    }
+}
+
+impl TcpStreamReassembler {
+    pub fn new() -> TcpStreamReassembler {
+        TcpStreamReassembler { streams: FlowTable::new() }
+    }
+
+    /// Adds the frame's TCP segment to its stream, returning the index of the stream.
+    ///
+    /// # Arguments
+    /// * `frame` - A decoded frame; frames without a TCP segment are ignored.
+    /// * `packet_number` - The number of the packet in the capture, counting from 1.
+    /// * `timestamp` - The capture time of the frame.
+    pub fn process(&mut self, frame: &EthernetFrame, packet_number: usize, timestamp: Duration) -> Option<usize> {
+        let key = FlowKey::from_frame(frame)?;
+        let tcp = match frame.payload.datagram()? {
+            ProtocolDatagram::TCP(tcp) if key.protocol == IPProtocol::TCP => tcp,
+            _ => return None,
+        };
+        let sequence_number = u32::from_be_bytes(tcp.sequence_number);
+        let syn = tcp.flags.contains(TcpFlags::SYN);
+        let (index, direction) = self.streams.find_or_start_tcp(key, tcp.flags, sequence_number,
+            |index, client| TcpStream::new(index, client.source, client.destination));
+
+        let stream = self.streams.get_mut(index)?;
+        let data = match direction {
+            Direction::ClientToServer => &mut stream.client_data,
+            Direction::ServerToClient => &mut stream.server_data,
+        };
+        let offset = data.data.len();
+        let length = data.add(sequence_number, syn, &tcp.payload);
+        if tcp.flags.contains(TcpFlags::FIN) {
+            data.fin = true;
+        }
+        if length > 0 {
+            stream.chunks.push(StreamChunk { direction, offset, length, packet_number, timestamp });
+        }
+        Some(index)
+    }
+
+    /// Returns the stream with the given index.
+    pub fn stream(&self, index: usize) -> Option<&TcpStream> {
+        self.streams.get(index)
+    }
+
+    /// Returns the streams in the order they were first seen.
+    pub fn streams(&self) -> impl Iterator<Item = &TcpStream> {
+        self.streams.iter()
+    }
+
+    /// Returns whether no TCP segment was seen.
+    pub fn is_empty(&self) -> bool {
+        self.streams.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Starts a direction with a SYN at sequence number 1000, so its data starts at 1001.
+    fn opened() -> StreamData {
+        let mut data = StreamData::new();
+        assert_eq!(data.add(1000, true, &[]), 0);
+        data
+    }
+
+    #[test]
+    fn in_order_segments_are_appended() {
+        let mut data = opened();
+        assert_eq!(data.add(1001, false, b"abc"), 3);
+        assert_eq!(data.add(1004, false, b"def"), 3);
+        assert_eq!(data.data(), b"abcdef");
+        assert_eq!(data.pending_bytes(), 0);
+        assert_eq!(data.retransmitted_bytes, 0);
+        assert_eq!(data.initial_sequence_number(), Some(1000));
+    }
+
+    #[test]
+    fn reversed_segments_wait_for_the_first_one() {
+        let mut data = opened();
+        assert_eq!(data.add(1007, false, b"ghi"), 0);
+        assert_eq!(data.add(1004, false, b"def"), 0);
+        assert_eq!(data.data(), b"");
+        assert_eq!(data.pending_bytes(), 6);
+        assert_eq!(data.add(1001, false, b"abc"), 9);
+        assert_eq!(data.data(), b"abcdefghi");
+        assert_eq!(data.pending_bytes(), 0);
+        assert_eq!(data.retransmitted_bytes, 0);
+    }
+
+    #[test]
+    fn overlapping_bytes_keep_their_first_copy() {
+        let mut data = opened();
+        data.add(1001, false, b"abcd");
+        assert_eq!(data.add(1003, false, b"XXef"), 2);
+        assert_eq!(data.data(), b"abcdef");
+        assert_eq!(data.pending_bytes(), 0);
+        assert_eq!(data.retransmitted_bytes, 2);
+    }
+
+    #[test]
+    fn duplicates_are_counted_as_retransmitted() {
+        let mut data = opened();
+        data.add(1001, false, b"abc");
+        assert_eq!(data.add(1001, false, b"abc"), 0);
+        assert_eq!(data.retransmitted_bytes, 3);
+
+        // A duplicate of a segment still waiting for a gap is not buffered twice.
+        data.add(1007, false, b"ghi");
+        assert_eq!(data.add(1007, false, b"ghi"), 0);
+        assert_eq!(data.data(), b"abc");
+        assert_eq!(data.pending_bytes(), 3);
+        assert_eq!(data.retransmitted_bytes, 6);
+    }
+
+    #[test]
+    fn a_gap_is_delivered_once_filled() {
+        let mut data = opened();
+        data.add(1001, false, b"abc");
+        data.add(1007, false, b"ghi");
+        assert_eq!(data.data(), b"abc");
+        assert_eq!(data.pending_bytes(), 3);
+        assert_eq!(data.missing_bytes(), 3);
+
+        assert_eq!(data.add(1004, false, b"def"), 6);
+        assert_eq!(data.data(), b"abcdefghi");
+        assert_eq!(data.pending_bytes(), 0);
+        assert_eq!(data.missing_bytes(), 0);
+        assert_eq!(data.retransmitted_bytes, 0);
+    }
+
+    #[test]
+    fn data_before_the_first_captured_byte_is_dropped() {
+        // Without a SYN the first segment with data sets where the stream starts.
+        let mut data = StreamData::new();
+        data.add(5000, false, b"def");
+        assert_eq!(data.initial_sequence_number(), None);
+        assert_eq!(data.add(4990, false, b"xy"), 0);
+        assert_eq!(data.add(4997, false, b"abcdefgh"), 2);
+        assert_eq!(data.data(), b"defgh");
+        assert_eq!(data.pending_bytes(), 0);
+        assert_eq!(data.retransmitted_bytes, 3);
+    }
+
+    #[test]
+    fn sequence_numbers_wrap_around() {
+        let mut data = StreamData::new();
+        data.add(u32::MAX - 1, true, &[]);
+        assert_eq!(data.initial_sequence_number(), Some(u32::MAX - 1));
+        assert_eq!(data.add(u32::MAX, false, b"a"), 1);
+        assert_eq!(data.add(2, false, b"d"), 0);
+        assert_eq!(data.pending_bytes(), 1);
+        assert_eq!(data.add(0, false, b"bc"), 3);
+        assert_eq!(data.data(), b"abcd");
+        assert_eq!(data.pending_bytes(), 0);
+        assert_eq!(data.retransmitted_bytes, 0);
+    }
+}
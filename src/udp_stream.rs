@@ -0,0 +1,113 @@
+use std::fmt;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use crate::flow::FlowTable;
+use crate::{Direction, EthernetFrame, FlowKey, ProtocolDatagram, StreamChunk};
+
+/// The datagrams exchanged between two UDP endpoints.
+pub struct UdpStream {
+    /// Position of the stream in the order streams were first seen, counting from 0.
+    pub index: usize,
+    /// The endpoint that sent the first captured datagram.
+    pub client: SocketAddr,
+    pub server: SocketAddr,
+    /// Payloads sent by the client, one after the other.
+    client_data: Vec<u8>,
+    /// Payloads sent by the server, one after the other.
+    server_data: Vec<u8>,
+    /// One chunk per datagram of either direction, in capture order.
+    pub chunks: Vec<StreamChunk>,
+}
+
+impl UdpStream {
+    pub fn new(index: usize, client: SocketAddr, server: SocketAddr) -> UdpStream {
+        UdpStream { index, client, server, client_data: vec![], server_data: vec![], chunks: vec![] }
+    }
+
+    /// Returns the payloads sent in the given direction, one after the other.
+    pub fn data(&self, direction: Direction) -> &[u8] {
+        match direction {
+            Direction::ClientToServer => &self.client_data,
+            Direction::ServerToClient => &self.server_data,
+        }
+    }
+
+    /// Returns the payload of a datagram of this stream.
+    pub fn chunk_data(&self, chunk: &StreamChunk) -> &[u8] {
+        &self.data(chunk.direction)[chunk.offset..chunk.offset + chunk.length]
+    }
+}
+
+impl fmt::Display for UdpStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "UDP stream {}: {} <-> {}, {} datagrams, {} bytes from client, {} bytes from server", self.index,
+               self.client, self.server, self.chunks.len(), self.client_data.len(), self.server_data.len())
+    }
+}
+
+/// Groups the UDP datagrams of a capture by the pair of endpoints exchanging them.
+///
+/// Streams are numbered in the order they are first seen, separately from TCP streams.
+pub struct UdpStreamTracker {
+    streams: FlowTable<UdpStream>,
+}
+
+impl Default for UdpStreamTracker {
+    fn default() -> Self {
+        UdpStreamTracker::new()
+    }
+}
+
+impl UdpStreamTracker {
+    pub fn new() -> UdpStreamTracker {
+        UdpStreamTracker { streams: FlowTable::new() }
+    }
+
+    /// Adds the frame's UDP datagram to its stream, returning the index of the stream.
+    ///
+    /// # Arguments
+    /// * `frame` - A decoded frame; frames without a UDP datagram are ignored.
+    /// * `packet_number` - The number of the packet in the capture, counting from 1.
+    /// * `timestamp` - The capture time of the frame.
+    pub fn process(&mut self, frame: &EthernetFrame, packet_number: usize, timestamp: Duration) -> Option<usize> {
+        let key = FlowKey::from_frame(frame)?;
+        let udp = match frame.payload.datagram()? {
+            ProtocolDatagram::UDP(udp) => udp,
+            _ => return None,
+        };
+        let (index, direction) = match self.streams.find(&key) {
+            Some(found) => found,
+            None => {
+                let index = self.streams.len();
+                self.streams.insert(key, UdpStream::new(index, key.source, key.destination));
+                (index, Direction::ClientToServer)
+            }
+        };
+
+        let stream = self.streams.get_mut(index)?;
+        let data = match direction {
+            Direction::ClientToServer => &mut stream.client_data,
+            Direction::ServerToClient => &mut stream.server_data,
+        };
+        let offset = data.len();
+        data.extend_from_slice(&udp.data);
+        stream.chunks.push(StreamChunk { direction, offset, length: udp.data.len(), packet_number, timestamp });
+        Some(index)
+    }
+
+    /// Returns the stream with the given index.
+    pub fn stream(&self, index: usize) -> Option<&UdpStream> {
+        self.streams.get(index)
+    }
+
+    /// Returns the streams in the order they were first seen.
+    pub fn streams(&self) -> impl Iterator<Item = &UdpStream> {
+        self.streams.iter()
+    }
+
+    /// Returns whether no UDP datagram was seen.
+    pub fn is_empty(&self) -> bool {
+        self.streams.len() == 0
+    }
+}